@@ -62,6 +62,7 @@ fn main() {
                 is_app: true,
                 error_code: quiche::h3::WireErrorCode::NoError as u64,
                 reason: vec![],
+                frame_type: None,
             },
         },
     ];
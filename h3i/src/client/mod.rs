@@ -386,6 +386,7 @@ pub(crate) fn execute_action(
                 is_app,
                 error_code,
                 reason,
+                ..
             } = error;
 
             log::info!("connection_close={error:?}");
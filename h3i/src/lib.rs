@@ -107,6 +107,7 @@
 //!                is_app: true,
 //!                error_code: quiche::h3::WireErrorCode::NoError as u64,
 //!                reason: vec![],
+//!                frame_type: None,
 //!            },
 //!        },
 //!    ];
@@ -446,6 +446,7 @@ pub fn prompt_connection_close() -> InquireResult<Action> {
             is_app: matches!(error_space, ErrorSpace::ApplicationError),
             error_code,
             reason: reason.as_bytes().to_vec(),
+            frame_type: None,
         },
     })
 }
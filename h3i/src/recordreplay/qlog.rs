@@ -405,6 +405,7 @@ impl From<&PacketSent> for H3Actions {
                                     .as_ref()
                                     .map(|s| s.as_bytes().to_vec())
                                     .unwrap_or_default(),
+                                frame_type: None,
                             },
                         })
                     },
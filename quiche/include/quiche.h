@@ -127,6 +127,15 @@ enum quiche_error {
 
     // The peer sent more data in CRYPTO frames than we can buffer.
     QUICHE_ERR_CRYPTO_BUFFER_EXCEEDED = -20,
+
+    // The provided packet carries an invalid or unknown connection ID.
+    QUICHE_ERR_INVALID_CONN_ID = -21,
+
+    // The confidentiality or integrity limit of the AEAD was reached.
+    QUICHE_ERR_AEAD_LIMIT_REACHED = -22,
+
+    // The TLS handshake failed and a TLS alert was sent to the peer.
+    QUICHE_ERR_TLS_ALERT = -23,
 };
 
 // Returns a human readable string with the quiche version number.
@@ -150,6 +159,31 @@ int quiche_config_load_cert_chain_from_pem_file(quiche_config *config,
 int quiche_config_load_priv_key_from_pem_file(quiche_config *config,
                                               const char *path);
 
+// Stores a certificate chain and private key.
+typedef struct quiche_certificate quiche_certificate;
+
+// Loads a certificate chain and private key from the given files. Returns NULL
+// on failure.
+quiche_certificate *quiche_certificate_from_pem_files(const char *cert_path,
+                                                      const char *key_path);
+
+// Configures the OCSP response to staple when presenting the certificate.
+int quiche_certificate_set_ocsp_response(quiche_certificate *cert,
+                                         const uint8_t *response,
+                                         size_t response_len);
+
+// Frees the certificate object.
+void quiche_certificate_free(quiche_certificate *cert);
+
+// Configures a callback selecting the certificate to present for a given
+// server name. The callback returns NULL to use the default certificate, and
+// the returned certificate must not be freed while the config is in use.
+void quiche_config_set_certificate_selector(quiche_config *config,
+    const quiche_certificate *(*cb)(const uint8_t *server_name,
+                                    size_t server_name_len,
+                                    void *argp),
+    void *argp);
+
 // Specifies a file where trusted CA certificates are stored for the purposes of certificate verification.
 int quiche_config_load_verify_locations_from_file(quiche_config *config,
                                                   const char *path);
@@ -161,9 +195,18 @@ int quiche_config_load_verify_locations_from_directory(quiche_config *config,
 // Configures whether to verify the peer's certificate.
 void quiche_config_verify_peer(quiche_config *config, bool v);
 
+// Configures whether the server requires clients to present a certificate.
+void quiche_config_require_client_cert(quiche_config *config, bool v);
+
 // Configures whether to send GREASE.
 void quiche_config_grease(quiche_config *config, bool v);
 
+// Configures whether to grease the QUIC bit.
+void quiche_config_grease_quic_bit(quiche_config *config, bool v);
+
+// Configures whether to maintain the latency spin bit.
+void quiche_config_enable_spin_bit(quiche_config *config, bool v);
+
 // Configures whether to do path MTU discovery.
 void quiche_config_discover_pmtu(quiche_config *config, bool v);
 
@@ -173,6 +216,24 @@ void quiche_config_log_keys(quiche_config *config);
 // Enables sending or receiving early data.
 void quiche_config_enable_early_data(quiche_config *config);
 
+// Configures the OCSP response to staple in the server's handshake.
+int quiche_config_set_ocsp_response(quiche_config *config,
+                                    const uint8_t *response,
+                                    size_t response_len);
+
+// Enables requesting a stapled OCSP response from the server.
+void quiche_config_enable_ocsp_stapling(quiche_config *config);
+
+// Configures the list of allowed TLS 1.3 cipher suites, separated by ':'.
+int quiche_config_set_cipher_suites(quiche_config *config, const char *v);
+
+// Configures the list of supported key exchange groups, separated by ':'.
+int quiche_config_set_groups(quiche_config *config, const char *v);
+
+// Configures the list of supported signature algorithms, separated by ':'.
+int quiche_config_set_signature_algorithms(quiche_config *config,
+                                           const char *v);
+
 // Configures the list of supported application protocols.
 int quiche_config_set_application_protos(quiche_config *config,
                                          const uint8_t *protos,
@@ -185,6 +246,13 @@ void quiche_config_set_max_amplification_factor(quiche_config *config, size_t v)
 // no timeout.
 void quiche_config_set_max_idle_timeout(quiche_config *config, uint64_t v);
 
+// Configures whether to send keep-alive PING frames.
+void quiche_config_enable_keep_alive(quiche_config *config, bool v);
+
+// Sets the fraction of the idle timeout after which a keep-alive PING frame is
+// sent, default is 0.5.
+void quiche_config_set_keep_alive_ratio(quiche_config *config, double v);
+
 // Sets the `max_udp_payload_size transport` parameter.
 void quiche_config_set_max_recv_udp_payload_size(quiche_config *config, size_t v);
 
@@ -224,6 +292,18 @@ int quiche_config_set_cc_algorithm_name(quiche_config *config, const char *algo)
 // Sets the initial cwnd for the connection in terms of packet count.
 void quiche_config_set_initial_congestion_window_packets(quiche_config *config, size_t packets);
 
+// Sets the minimum congestion window size in terms of packet count. Values
+// smaller than 1 are treated as 1.
+void quiche_config_set_minimum_congestion_window_packets(quiche_config *config, size_t packets);
+
+// Sets the factor the probe timeout is multiplied by after each consecutive
+// timeout.
+void quiche_config_set_pto_backoff_multiplier(quiche_config *config, double v);
+
+// Sets the maximum number of consecutive timeouts the probe timeout is backed
+// off for.
+void quiche_config_set_max_pto_backoffs(quiche_config *config, uint32_t v);
+
 enum quiche_cc_algorithm {
     QUICHE_CC_RENO = 0,
     QUICHE_CC_CUBIC = 1,
@@ -257,6 +337,9 @@ void quiche_config_set_max_stream_window(quiche_config *config, uint64_t v);
 // Sets the limit of active connection IDs.
 void quiche_config_set_active_connection_id_limit(quiche_config *config, uint64_t v);
 
+// Sets the length of the local Source Connection IDs.
+int quiche_config_set_local_connection_id_len(quiche_config *config, size_t v);
+
 // Sets the initial stateless reset token. |v| must contain 16 bytes, otherwise the behaviour is undefined.
 void quiche_config_set_stateless_reset_token(quiche_config *config, const uint8_t *v);
 
@@ -299,6 +382,15 @@ ssize_t quiche_negotiate_version(const uint8_t *scid, size_t scid_len,
                                  const uint8_t *dcid, size_t dcid_len,
                                  uint8_t *out, size_t out_len);
 
+// Writes a version negotiation packet advertising the given versions.
+ssize_t quiche_negotiate_version_with_versions(const uint8_t *scid,
+                                               size_t scid_len,
+                                               const uint8_t *dcid,
+                                               size_t dcid_len,
+                                               const uint32_t *versions,
+                                               size_t versions_len,
+                                               uint8_t *out, size_t out_len);
+
 // Writes a retry packet.
 ssize_t quiche_retry(const uint8_t *scid, size_t scid_len,
                      const uint8_t *dcid, size_t dcid_len,
@@ -306,6 +398,15 @@ ssize_t quiche_retry(const uint8_t *scid, size_t scid_len,
                      const uint8_t *token, size_t token_len,
                      uint32_t version, uint8_t *out, size_t out_len);
 
+// Writes a stateless reset packet.
+ssize_t quiche_stateless_reset(const uint8_t *reset_token, size_t pkt_len,
+                               uint8_t *out, size_t out_len);
+
+// Derives the 16-byte stateless reset token for a connection ID from a key.
+void quiche_stateless_reset_token(const uint8_t *key, size_t key_len,
+                                  const uint8_t *cid, size_t cid_len,
+                                  uint8_t *out);
+
 // Returns true if the given protocol version is supported.
 bool quiche_version_is_supported(uint32_t version);
 
@@ -400,6 +501,16 @@ enum quiche_shutdown {
     QUICHE_SHUTDOWN_WRITE = 1,
 };
 
+// Opens a new locally-initiated bidirectional stream, and returns its ID.
+//
+// On error a value lower than 0 is returned.
+int64_t quiche_conn_stream_open_bidi(quiche_conn *conn);
+
+// Opens a new locally-initiated unidirectional stream, and returns its ID.
+//
+// On error a value lower than 0 is returned.
+int64_t quiche_conn_stream_open_uni(quiche_conn *conn);
+
 // Sets the priority for a stream.
 int quiche_conn_stream_priority(quiche_conn *conn, uint64_t stream_id,
                                 uint8_t urgency, bool incremental);
@@ -482,6 +593,17 @@ void quiche_conn_application_proto(const quiche_conn *conn, const uint8_t **out,
 // Returns the peer's leaf certificate (if any) as a DER-encoded buffer.
 void quiche_conn_peer_cert(const quiche_conn *conn, const uint8_t **out, size_t *out_len);
 
+// Returns the OCSP response stapled by the server (if any).
+void quiche_conn_peer_ocsp_response(const quiche_conn *conn,
+                                    const uint8_t **out, size_t *out_len);
+
+// Derives out_len bytes of keying material from the TLS handshake, using the
+// given label and context.
+int quiche_conn_export_keying_material(const quiche_conn *conn,
+                                       const uint8_t *label, size_t label_len,
+                                       const uint8_t *context, size_t context_len,
+                                       uint8_t *out, size_t out_len);
+
 // Returns the serialized cryptographic session for the connection.
 void quiche_conn_session(const quiche_conn *conn, const uint8_t **out, size_t *out_len);
 
@@ -523,6 +645,11 @@ bool quiche_conn_peer_error(const quiche_conn *conn,
                             const uint8_t **reason,
                             size_t *reason_len);
 
+// Returns true if a transport error was received, and updates the provided
+// parameter with the type of the frame that triggered it.
+bool quiche_conn_peer_error_frame_type(const quiche_conn *conn,
+                                       uint64_t *frame_type);
+
 // Returns true if a connection error was queued or sent, and updates the provided
 // parameters accordingly.
 bool quiche_conn_local_error(const quiche_conn *conn,
@@ -686,6 +813,21 @@ typedef struct {
 
     // The most recent data delivery rate estimate in bytes/s.
     uint64_t delivery_rate;
+
+    // The most recent round-trip time sample (in nanoseconds).
+    uint64_t latest_rtt;
+
+    // The slow start threshold of the path in bytes.
+    size_t ssthresh;
+
+    // The number of bytes in flight on this path.
+    size_t bytes_in_flight;
+
+    // The number of times the probe timeout expired on this path.
+    size_t pto_count;
+
+    // The number of QUIC packets declared lost that were later acked.
+    size_t spurious_lost;
 } quiche_path_stats;
 
 
@@ -734,6 +876,9 @@ bool quiche_conn_is_dgram_send_queue_full(const quiche_conn *conn);
 // Returns whether or not the DATAGRAM recv queue is full.
 bool quiche_conn_is_dgram_recv_queue_full(const quiche_conn *conn);
 
+// Configures whether to send keep-alive PING frames on this connection.
+void quiche_conn_set_keep_alive(quiche_conn *conn, bool v);
+
 // Schedule an ack-eliciting packet on the active path.
 ssize_t quiche_conn_send_ack_eliciting(quiche_conn *conn);
 
@@ -0,0 +1,343 @@
+// Copyright (C) 2024, Cloudflare, Inc.
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//
+//     * Redistributions in binary form must reproduce the above copyright
+//       notice, this list of conditions and the following disclaimer in the
+//       documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS
+// IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO,
+// THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR
+// PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::frame;
+
+/// State of the ACK frequency extension.
+///
+/// This tracks both the acknowledgement behavior requested by the peer via
+/// ACK_FREQUENCY and IMMEDIATE_ACK frames, and the frames the local endpoint
+/// needs to send to request a different behavior from the peer.
+#[derive(Default, Debug)]
+pub struct AckFrequency {
+    /// The largest sequence number of the ACK_FREQUENCY frames received.
+    largest_recv_seq_num: Option<u64>,
+
+    /// The number of ack-eliciting packets that can be received without
+    /// sending an ACK.
+    ack_eliciting_threshold: u64,
+
+    /// The maximum amount of time an ACK can be delayed for.
+    max_ack_delay: Duration,
+
+    /// The number of out-of-order packets that trigger an immediate ACK.
+    reordering_threshold: u64,
+
+    /// The number of ack-eliciting packets received since the last ACK was
+    /// sent.
+    unacked_count: u64,
+
+    /// The smallest packet number found missing since the last ACK was sent.
+    first_missing: Option<u64>,
+
+    /// Whether an IMMEDIATE_ACK frame was received.
+    immediate_ack_recvd: bool,
+
+    /// When the delayed ACK needs to be sent.
+    ack_timer: Option<Instant>,
+
+    /// The sequence number of the next ACK_FREQUENCY frame to send.
+    next_send_seq_num: u64,
+
+    /// The parameters of the last ACK_FREQUENCY frame requested by the
+    /// application, as ack-eliciting threshold, max ACK delay in
+    /// microseconds and reordering threshold.
+    send_params: Option<(u64, u64, u64)>,
+
+    /// Whether an ACK_FREQUENCY frame needs to be sent.
+    send_pending: bool,
+
+    /// Whether an IMMEDIATE_ACK frame needs to be sent.
+    immediate_ack_pending: bool,
+}
+
+impl AckFrequency {
+    /// Processes an ACK_FREQUENCY frame received from the peer.
+    ///
+    /// Frames with a sequence number that is not larger than the ones
+    /// previously received are ignored.
+    pub fn on_frame_received(
+        &mut self, seq_num: u64, ack_eliciting_threshold: u64,
+        max_ack_delay: Duration, reordering_threshold: u64,
+    ) {
+        if self.largest_recv_seq_num.map_or(false, |s| seq_num <= s) {
+            return;
+        }
+
+        self.largest_recv_seq_num = Some(seq_num);
+
+        self.ack_eliciting_threshold = ack_eliciting_threshold;
+        self.max_ack_delay = max_ack_delay;
+        self.reordering_threshold = reordering_threshold;
+    }
+
+    /// Processes an IMMEDIATE_ACK frame received from the peer.
+    pub fn on_immediate_ack_received(&mut self) {
+        self.immediate_ack_recvd = true;
+    }
+
+    /// Processes an ack-eliciting packet received in the application packet
+    /// number space, where `largest_rx` is the largest packet number received
+    /// before it.
+    ///
+    /// Returns true if an ACK needs to be sent immediately. Otherwise the ACK
+    /// is delayed until the ACK timer expires.
+    pub fn on_packet_received(
+        &mut self, pn: u64, largest_rx: u64, now: Instant,
+    ) -> bool {
+        // Until the peer requests otherwise, every ack-eliciting packet is
+        // acknowledged immediately.
+        let immediate_ack = std::mem::take(&mut self.immediate_ack_recvd);
+
+        if self.largest_recv_seq_num.is_none() || immediate_ack {
+            return true;
+        }
+
+        self.unacked_count += 1;
+
+        if self.unacked_count > self.ack_eliciting_threshold {
+            return true;
+        }
+
+        if self.reordering_threshold > 0 {
+            // The packet fills a gap.
+            if pn < largest_rx {
+                return true;
+            }
+
+            if pn > largest_rx + 1 && self.first_missing.is_none() {
+                self.first_missing = Some(largest_rx + 1);
+            }
+
+            if let Some(missing) = self.first_missing {
+                if pn - missing >= self.reordering_threshold {
+                    return true;
+                }
+            }
+        }
+
+        if self.ack_timer.is_none() {
+            self.ack_timer = Some(now + self.max_ack_delay);
+        }
+
+        false
+    }
+
+    /// Resets the delayed ACK state after an ACK frame was sent.
+    pub fn on_ack_sent(&mut self) {
+        self.unacked_count = 0;
+        self.first_missing = None;
+        self.ack_timer = None;
+    }
+
+    /// Returns when the delayed ACK needs to be sent, if any.
+    pub fn ack_timer(&self) -> Option<Instant> {
+        self.ack_timer
+    }
+
+    /// Requests the peer to change its acknowledgement behavior.
+    pub fn request(
+        &mut self, ack_eliciting_threshold: u64, max_ack_delay: u64,
+        reordering_threshold: u64,
+    ) {
+        self.send_params =
+            Some((ack_eliciting_threshold, max_ack_delay, reordering_threshold));
+
+        self.send_pending = true;
+    }
+
+    /// Requests the peer to send an ACK immediately.
+    pub fn request_immediate_ack(&mut self) {
+        self.immediate_ack_pending = true;
+    }
+
+    /// Returns true if there are frames to be sent.
+    pub fn has_pending(&self) -> bool {
+        self.send_pending || self.immediate_ack_pending
+    }
+
+    /// Returns the ACK_FREQUENCY frame to send, if any.
+    pub fn frame(&self) -> Option<frame::Frame> {
+        if !self.send_pending {
+            return None;
+        }
+
+        let (ack_eliciting_threshold, request_max_ack_delay, reordering) =
+            self.send_params?;
+
+        Some(frame::Frame::AckFrequency {
+            seq_num: self.next_send_seq_num,
+            ack_eliciting_threshold,
+            request_max_ack_delay,
+            reordering_threshold: reordering,
+        })
+    }
+
+    /// Commits the ACK_FREQUENCY frame returned by [`frame()`] as sent.
+    ///
+    /// [`frame()`]: struct.AckFrequency.html#method.frame
+    pub fn on_frame_sent(&mut self) {
+        self.next_send_seq_num += 1;
+
+        self.send_pending = false;
+    }
+
+    /// Returns true if an IMMEDIATE_ACK frame needs to be sent.
+    pub fn should_send_immediate_ack(&self) -> bool {
+        self.immediate_ack_pending
+    }
+
+    /// Commits the IMMEDIATE_ACK frame as sent.
+    pub fn on_immediate_ack_sent(&mut self) {
+        self.immediate_ack_pending = false;
+    }
+
+    /// Returns true if the ACK_FREQUENCY frame with the given sequence number
+    /// is the last one sent.
+    pub fn is_latest_sent(&self, seq_num: u64) -> bool {
+        seq_num + 1 == self.next_send_seq_num
+    }
+
+    /// Schedules the ACK_FREQUENCY frame with the given sequence number for
+    /// retransmission, unless it was already superseded by a newer one.
+    pub fn on_frame_lost(&mut self, seq_num: u64) {
+        if self.is_latest_sent(seq_num) {
+            self.send_pending = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ack_immediately_by_default() {
+        let mut af = AckFrequency::default();
+
+        let now = Instant::now();
+
+        assert!(af.on_packet_received(1, 0, now));
+        assert!(af.on_packet_received(2, 1, now));
+        assert_eq!(af.ack_timer(), None);
+    }
+
+    #[test]
+    fn ack_eliciting_threshold() {
+        let mut af = AckFrequency::default();
+
+        let now = Instant::now();
+        let max_ack_delay = Duration::from_millis(50);
+
+        af.on_frame_received(0, 2, max_ack_delay, 0);
+
+        assert!(!af.on_packet_received(1, 0, now));
+        assert_eq!(af.ack_timer(), Some(now + max_ack_delay));
+
+        assert!(!af.on_packet_received(2, 1, now));
+        assert!(af.on_packet_received(3, 2, now));
+
+        af.on_ack_sent();
+        assert_eq!(af.ack_timer(), None);
+
+        assert!(!af.on_packet_received(4, 3, now));
+
+        // Stale frames are ignored.
+        af.on_frame_received(0, 0, max_ack_delay, 0);
+        assert!(!af.on_packet_received(5, 4, now));
+
+        af.on_immediate_ack_received();
+        assert!(af.on_packet_received(6, 5, now));
+    }
+
+    #[test]
+    fn reordering_threshold() {
+        let mut af = AckFrequency::default();
+
+        let now = Instant::now();
+
+        af.on_frame_received(0, 10, Duration::from_millis(50), 3);
+
+        assert!(!af.on_packet_received(1, 0, now));
+
+        // Packet 2 is missing.
+        assert!(!af.on_packet_received(3, 1, now));
+        assert!(!af.on_packet_received(4, 3, now));
+        assert!(af.on_packet_received(5, 4, now));
+
+        af.on_ack_sent();
+
+        // Out-of-order packet.
+        assert!(af.on_packet_received(2, 5, now));
+    }
+
+    #[test]
+    fn send_frame() {
+        let mut af = AckFrequency::default();
+
+        assert!(!af.has_pending());
+        assert_eq!(af.frame(), None);
+
+        af.request(10, 50_000, 1);
+        assert!(af.has_pending());
+
+        assert_eq!(
+            af.frame(),
+            Some(frame::Frame::AckFrequency {
+                seq_num: 0,
+                ack_eliciting_threshold: 10,
+                request_max_ack_delay: 50_000,
+                reordering_threshold: 1,
+            })
+        );
+
+        af.on_frame_sent();
+        assert!(!af.has_pending());
+        assert!(af.is_latest_sent(0));
+
+        af.request(20, 50_000, 1);
+        af.on_frame_sent();
+        assert!(af.is_latest_sent(1));
+
+        // Superseded frames are not retransmitted.
+        af.on_frame_lost(0);
+        assert!(!af.has_pending());
+
+        af.on_frame_lost(1);
+        assert_eq!(
+            af.frame(),
+            Some(frame::Frame::AckFrequency {
+                seq_num: 2,
+                ack_eliciting_threshold: 20,
+                request_max_ack_delay: 50_000,
+                reordering_threshold: 1,
+            })
+        );
+    }
+}
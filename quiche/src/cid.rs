@@ -696,6 +696,26 @@ impl ConnectionIdentifiers {
         self.dcids.iter().filter(|e| e.path_id.is_none()).count()
     }
 
+    /// Sets the stateless reset token of the initial destination Connection
+    /// ID, as advertised by the peer in its transport parameters.
+    ///
+    /// This is a no-op if the initial destination Connection ID was already
+    /// retired.
+    pub fn set_initial_dcid_reset_token(&mut self, reset_token: u128) {
+        if let Some(e) = self.dcids.get_mut(0) {
+            e.reset_token = Some(reset_token);
+        }
+    }
+
+    /// Returns an iterator over the stateless reset tokens of the destination
+    /// Connection IDs currently in use on a path.
+    pub fn active_reset_tokens(&self) -> impl Iterator<Item = u128> + '_ {
+        self.dcids
+            .iter()
+            .filter(|e| e.path_id.is_some())
+            .filter_map(|e| e.reset_token)
+    }
+
     /// Returns the oldest active source Connection ID of this connection.
     #[inline]
     pub fn oldest_scid(&self) -> &ConnectionIdEntry {
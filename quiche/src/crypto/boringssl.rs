@@ -156,10 +156,16 @@ pub(crate) struct PacketKey {
 
 impl PacketKey {
     pub fn new(
-        alg: Algorithm, key: Vec<u8>, iv: Vec<u8>, _enc: u32,
+        alg: Algorithm, mut key: Vec<u8>, iv: Vec<u8>, _enc: u32,
     ) -> Result<Self> {
+        let ctx = make_aead_ctx(alg, &key);
+
+        // The key is expanded into the AEAD context, so it's not needed
+        // anymore.
+        zeroize(&mut key);
+
         Ok(Self {
-            ctx: make_aead_ctx(alg, &key)?,
+            ctx: ctx?,
             nonce: iv,
         })
     }
@@ -180,6 +186,15 @@ impl PacketKey {
     }
 }
 
+impl Drop for PacketKey {
+    fn drop(&mut self) {
+        unsafe { EVP_AEAD_CTX_cleanup(&mut self.ctx) };
+
+        zeroize(&mut self.ctx.opaque);
+        zeroize(&mut self.nonce);
+    }
+}
+
 extern {
     fn EVP_aead_aes_128_gcm() -> *const EVP_AEAD;
 
@@ -193,6 +208,8 @@ extern {
         key_len: usize, tag_len: usize, engine: *mut c_void,
     ) -> c_int;
 
+    fn EVP_AEAD_CTX_cleanup(ctx: *mut EVP_AEAD_CTX);
+
     fn EVP_AEAD_CTX_open(
         ctx: *const EVP_AEAD_CTX, out: *mut u8, out_len: *mut usize,
         max_out_len: usize, nonce: *const u8, nonce_len: usize, inp: *const u8,
@@ -206,3 +223,29 @@ extern {
         extra_in_len: usize, ad: *const u8, ad_len: usize,
     ) -> c_int;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packet_key_zeroized_on_drop() {
+        let key = PacketKey::new(
+            Algorithm::AES128_GCM,
+            vec![0xba; 16],
+            vec![0xba; 12],
+            0,
+        )
+        .unwrap();
+
+        let mut key = std::mem::ManuallyDrop::new(key);
+
+        assert!(key.ctx.opaque.iter().any(|b| *b != 0));
+
+        // The AEAD context is stored inline, so its memory can still be
+        // inspected once the key is dropped.
+        unsafe { std::ptr::drop_in_place(&mut *key) };
+
+        assert!(key.ctx.opaque.iter().all(|b| *b == 0));
+    }
+}
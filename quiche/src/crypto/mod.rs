@@ -55,14 +55,18 @@ impl Level {
     }
 }
 
+/// An AEAD algorithm used for packet protection.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Algorithm {
+    /// AES-128 in GCM mode.
     #[allow(non_camel_case_types)]
     AES128_GCM,
 
+    /// AES-256 in GCM mode.
     #[allow(non_camel_case_types)]
     AES256_GCM,
 
+    /// ChaCha20 with Poly1305.
     #[allow(non_camel_case_types)]
     ChaCha20_Poly1305,
 }
@@ -86,6 +90,7 @@ impl Algorithm {
         }
     }
 
+    /// Returns the length of the algorithm's keys.
     pub fn key_len(self) -> usize {
         match self {
             Algorithm::AES128_GCM => 16,
@@ -94,6 +99,7 @@ impl Algorithm {
         }
     }
 
+    /// Returns the length of the algorithm's authentication tag.
     pub fn tag_len(self) -> usize {
         if cfg!(feature = "fuzzing") {
             return 0;
@@ -106,6 +112,7 @@ impl Algorithm {
         }
     }
 
+    /// Returns the length of the algorithm's nonce.
     pub fn nonce_len(self) -> usize {
         match self {
             Algorithm::AES128_GCM => 12,
@@ -113,6 +120,28 @@ impl Algorithm {
             Algorithm::ChaCha20_Poly1305 => 12,
         }
     }
+
+    /// Returns the maximum number of packets that can be protected with a
+    /// single key, as defined in RFC 9001.
+    pub fn confidentiality_limit(self) -> u64 {
+        match self {
+            Algorithm::AES128_GCM => 1 << 23,
+            Algorithm::AES256_GCM => 1 << 23,
+            // Larger than the number of possible packet numbers.
+            Algorithm::ChaCha20_Poly1305 => u64::MAX,
+        }
+    }
+
+    /// Returns the maximum number of received packets that can fail
+    /// authentication over the lifetime of a connection, as defined in RFC
+    /// 9001.
+    pub fn integrity_limit(self) -> u64 {
+        match self {
+            Algorithm::AES128_GCM => 1 << 52,
+            Algorithm::AES256_GCM => 1 << 52,
+            Algorithm::ChaCha20_Poly1305 => 1 << 36,
+        }
+    }
 }
 
 #[allow(non_camel_case_types)]
@@ -124,7 +153,7 @@ pub struct EVP_AEAD {
 pub struct Open {
     alg: Algorithm,
 
-    secret_prk: hkdf::Prk,
+    secret: Secret,
 
     header: HeaderProtectionKey,
 
@@ -138,7 +167,7 @@ impl Open {
 
     pub fn new(
         alg: Algorithm, key: Vec<u8>, iv: Vec<u8>, hp_key: Vec<u8>,
-        secret_prk: hkdf::Prk,
+        secret: Secret,
     ) -> Result<Open> {
         Ok(Open {
             alg,
@@ -147,19 +176,14 @@ impl Open {
 
             packet: PacketKey::new(alg, key, iv, Self::DECRYPT)?,
 
-            secret_prk,
+            secret,
         })
     }
 
     pub fn from_secret(aead: Algorithm, secret: &[u8]) -> Result<Open> {
-        let secret_prk = hkdf::Prk::new_less_safe(aead.get_ring_digest(), secret);
-
-        Self::from_secret_prk(aead, secret_prk)
-    }
+        let secret = Secret(secret.to_vec());
+        let secret_prk = secret.prk(aead);
 
-    pub fn from_secret_prk(
-        aead: Algorithm, secret_prk: hkdf::Prk,
-    ) -> Result<Open> {
         Ok(Open {
             alg: aead,
 
@@ -167,7 +191,7 @@ impl Open {
 
             packet: PacketKey::from_secret_prk(aead, &secret_prk, Self::DECRYPT)?,
 
-            secret_prk,
+            secret,
         })
     }
 
@@ -190,18 +214,18 @@ impl Open {
     }
 
     pub fn derive_next_packet_key(&self) -> Result<Open> {
-        let next_secret_prk = derive_next_secret(self.alg, &self.secret_prk)?;
+        let next_secret = derive_next_secret(self.alg, &self.secret)?;
 
         let next_packet_key = PacketKey::from_secret_prk(
             self.alg,
-            &next_secret_prk,
+            &next_secret.prk(self.alg),
             Self::DECRYPT,
         )?;
 
         Ok(Open {
             alg: self.alg,
 
-            secret_prk: next_secret_prk,
+            secret: next_secret,
 
             header: HeaderProtectionKey::new(
                 self.alg,
@@ -216,7 +240,7 @@ impl Open {
 pub struct Seal {
     alg: Algorithm,
 
-    secret_prk: hkdf::Prk,
+    secret: Secret,
 
     header: HeaderProtectionKey,
 
@@ -230,7 +254,7 @@ impl Seal {
 
     pub fn new(
         alg: Algorithm, key: Vec<u8>, iv: Vec<u8>, hp_key: Vec<u8>,
-        secret_prk: hkdf::Prk,
+        secret: Secret,
     ) -> Result<Seal> {
         Ok(Seal {
             alg,
@@ -239,19 +263,14 @@ impl Seal {
 
             packet: PacketKey::new(alg, key, iv, Self::ENCRYPT)?,
 
-            secret_prk,
+            secret,
         })
     }
 
     pub fn from_secret(aead: Algorithm, secret: &[u8]) -> Result<Seal> {
-        let secret_prk = hkdf::Prk::new_less_safe(aead.get_ring_digest(), secret);
+        let secret = Secret(secret.to_vec());
+        let secret_prk = secret.prk(aead);
 
-        Self::from_secret_prk(aead, secret_prk)
-    }
-
-    pub fn from_secret_prk(
-        aead: Algorithm, secret_prk: hkdf::Prk,
-    ) -> Result<Seal> {
         Ok(Seal {
             alg: aead,
 
@@ -259,7 +278,7 @@ impl Seal {
 
             packet: PacketKey::from_secret_prk(aead, &secret_prk, Self::ENCRYPT)?,
 
-            secret_prk,
+            secret,
         })
     }
 
@@ -282,18 +301,18 @@ impl Seal {
     }
 
     pub fn derive_next_packet_key(&self) -> Result<Seal> {
-        let next_secret_prk = derive_next_secret(self.alg, &self.secret_prk)?;
+        let next_secret = derive_next_secret(self.alg, &self.secret)?;
 
         let next_packet_key = PacketKey::from_secret_prk(
             self.alg,
-            &next_secret_prk,
+            &next_secret.prk(self.alg),
             Self::ENCRYPT,
         )?;
 
         Ok(Seal {
             alg: self.alg,
 
-            secret_prk: next_secret_prk,
+            secret: next_secret,
 
             header: HeaderProtectionKey::new(
                 self.alg,
@@ -329,28 +348,64 @@ impl HeaderProtectionKey {
     }
 }
 
-pub fn derive_initial_key_material(
-    cid: &[u8], version: u32, is_server: bool,
-) -> Result<(Open, Seal)> {
+impl Drop for HeaderProtectionKey {
+    fn drop(&mut self) {
+        zeroize(&mut self.hp_key);
+    }
+}
+
+/// A traffic secret, which is overwritten with zeros when dropped.
+///
+/// The secret is kept as raw bytes rather than as an `hkdf::Prk`, as ring
+/// doesn't provide a way to clear the latter.
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    fn prk(&self, aead: Algorithm) -> hkdf::Prk {
+        hkdf::Prk::new_less_safe(aead.get_ring_digest(), &self.0)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        zeroize(&mut self.0);
+    }
+}
+
+pub fn derive_initial_secrets(
+    cid: &[u8], version: u32,
+) -> Result<([u8; 32], [u8; 32])> {
     let mut client_secret = [0; 32];
     let mut server_secret = [0; 32];
 
+    let initial_secret = derive_initial_secret(cid, version);
+
+    derive_client_initial_secret(&initial_secret, &mut client_secret)?;
+    derive_server_initial_secret(&initial_secret, &mut server_secret)?;
+
+    Ok((client_secret, server_secret))
+}
+
+pub fn derive_initial_key_material(
+    cid: &[u8], version: u32, is_server: bool,
+) -> Result<(Open, Seal)> {
     let aead = Algorithm::AES128_GCM;
 
     let key_len = aead.key_len();
     let nonce_len = aead.nonce_len();
 
-    let initial_secret = derive_initial_secret(cid, version);
+    let (mut client_secret_buf, mut server_secret_buf) =
+        derive_initial_secrets(cid, version)?;
 
     // Client.
     let mut client_key = vec![0; key_len];
     let mut client_iv = vec![0; nonce_len];
     let mut client_hp_key = vec![0; key_len];
 
-    derive_client_initial_secret(&initial_secret, &mut client_secret)?;
+    let client_secret = Secret(client_secret_buf.to_vec());
+    let client_secret_prk = client_secret.prk(aead);
 
-    let client_secret_prk =
-        hkdf::Prk::new_less_safe(aead.get_ring_digest(), &client_secret);
+    zeroize(&mut client_secret_buf);
 
     derive_pkt_key(aead, &client_secret_prk, &mut client_key)?;
     derive_pkt_iv(aead, &client_secret_prk, &mut client_iv)?;
@@ -361,10 +416,10 @@ pub fn derive_initial_key_material(
     let mut server_iv = vec![0; nonce_len];
     let mut server_hp_key = vec![0; key_len];
 
-    derive_server_initial_secret(&initial_secret, &mut server_secret)?;
+    let server_secret = Secret(server_secret_buf.to_vec());
+    let server_secret_prk = server_secret.prk(aead);
 
-    let server_secret_prk =
-        hkdf::Prk::new_less_safe(aead.get_ring_digest(), &server_secret);
+    zeroize(&mut server_secret_buf);
 
     derive_pkt_key(aead, &server_secret_prk, &mut server_key)?;
     derive_pkt_iv(aead, &server_secret_prk, &mut server_iv)?;
@@ -372,37 +427,13 @@ pub fn derive_initial_key_material(
 
     let (open, seal) = if is_server {
         (
-            Open::new(
-                aead,
-                client_key,
-                client_iv,
-                client_hp_key,
-                client_secret_prk,
-            )?,
-            Seal::new(
-                aead,
-                server_key,
-                server_iv,
-                server_hp_key,
-                server_secret_prk,
-            )?,
+            Open::new(aead, client_key, client_iv, client_hp_key, client_secret)?,
+            Seal::new(aead, server_key, server_iv, server_hp_key, server_secret)?,
         )
     } else {
         (
-            Open::new(
-                aead,
-                server_key,
-                server_iv,
-                server_hp_key,
-                server_secret_prk,
-            )?,
-            Seal::new(
-                aead,
-                client_key,
-                client_iv,
-                client_hp_key,
-                client_secret_prk,
-            )?,
+            Open::new(aead, server_key, server_iv, server_hp_key, server_secret)?,
+            Seal::new(aead, client_key, client_iv, client_hp_key, client_secret)?,
         )
     };
 
@@ -435,17 +466,14 @@ fn derive_server_initial_secret(prk: &hkdf::Prk, out: &mut [u8]) -> Result<()> {
     hkdf_expand_label(prk, LABEL, out)
 }
 
-fn derive_next_secret(aead: Algorithm, secret: &hkdf::Prk) -> Result<hkdf::Prk> {
+fn derive_next_secret(aead: Algorithm, secret: &Secret) -> Result<Secret> {
     const LABEL: &[u8] = b"quic ku";
 
-    let mut next_secret = [0u8; 32];
-
-    hkdf_expand_label(secret, LABEL, &mut next_secret)?;
+    let mut next_secret = Secret(vec![0u8; 32]);
 
-    let next_secret_prk =
-        hkdf::Prk::new_less_safe(aead.get_ring_digest(), &next_secret);
+    hkdf_expand_label(&secret.prk(aead), LABEL, &mut next_secret.0)?;
 
-    Ok(next_secret_prk)
+    Ok(next_secret)
 }
 
 pub fn derive_hdr_key(
@@ -508,6 +536,18 @@ fn hkdf_expand_label(
     Ok(())
 }
 
+/// Overwrites the given buffer with zeros.
+///
+/// Volatile writes are used so that the compiler can't optimize the zeroing
+/// away, even if the buffer is about to be dropped.
+pub(crate) fn zeroize(buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+        unsafe { std::ptr::write_volatile(b, 0) };
+    }
+
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
 fn make_nonce(iv: &[u8], counter: u64) -> [u8; aead::NONCE_LEN] {
     let mut nonce = [0; aead::NONCE_LEN];
     nonce.copy_from_slice(iv);
@@ -536,6 +576,43 @@ impl hkdf::KeyType for ArbitraryOutputLen {
 mod tests {
     use super::*;
 
+    #[test]
+    fn zeroize_buffer() {
+        let mut buf = vec![0xba; 32];
+
+        zeroize(&mut buf);
+        assert_eq!(buf, vec![0; 32]);
+
+        let mut hpk =
+            HeaderProtectionKey::new(Algorithm::AES128_GCM, vec![0xba; 16])
+                .unwrap();
+
+        zeroize(&mut hpk.hp_key);
+        assert_eq!(hpk.hp_key, vec![0; 16]);
+    }
+
+    #[test]
+    fn derive_initial_secrets_pair() {
+        let dcid = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+
+        let (client_secret, server_secret) =
+            derive_initial_secrets(&dcid, crate::PROTOCOL_VERSION_V1).unwrap();
+
+        let expected_client_initial_secret = [
+            0xc0, 0x0c, 0xf1, 0x51, 0xca, 0x5b, 0xe0, 0x75, 0xed, 0x0e, 0xbf,
+            0xb5, 0xc8, 0x03, 0x23, 0xc4, 0x2d, 0x6b, 0x7d, 0xb6, 0x78, 0x81,
+            0x28, 0x9a, 0xf4, 0x00, 0x8f, 0x1f, 0x6c, 0x35, 0x7a, 0xea,
+        ];
+        assert_eq!(&client_secret, &expected_client_initial_secret);
+
+        let expected_server_initial_secret = [
+            0x3c, 0x19, 0x98, 0x28, 0xfd, 0x13, 0x9e, 0xfd, 0x21, 0x6c, 0x15,
+            0x5a, 0xd8, 0x44, 0xcc, 0x81, 0xfb, 0x82, 0xfa, 0x8d, 0x74, 0x46,
+            0xfa, 0x7d, 0x78, 0xbe, 0x80, 0x3a, 0xcd, 0xda, 0x95, 0x1b,
+        ];
+        assert_eq!(&server_secret, &expected_server_initial_secret);
+    }
+
     #[test]
     fn derive_initial_secrets_v1() {
         let dcid = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
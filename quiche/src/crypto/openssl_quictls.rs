@@ -21,7 +21,7 @@ impl Drop for EVP_CIPHER_CTX {
 }
 
 impl Algorithm {
-    pub fn get_evp_aead(self) -> *const EVP_AEAD {
+    pub(crate) fn get_evp_aead(self) -> *const EVP_AEAD {
         match self {
             Algorithm::AES128_GCM => unsafe { EVP_aes_128_gcm() },
             Algorithm::AES256_GCM => unsafe { EVP_aes_256_gcm() },
@@ -328,6 +328,16 @@ impl PacketKey {
     }
 }
 
+impl Drop for PacketKey {
+    fn drop(&mut self) {
+        // Freeing the context also clears the expanded key schedule.
+        unsafe { EVP_CIPHER_CTX_free(self.ctx) };
+
+        zeroize(&mut self.key);
+        zeroize(&mut self.nonce);
+    }
+}
+
 unsafe impl std::marker::Send for PacketKey {}
 unsafe impl std::marker::Sync for PacketKey {}
 
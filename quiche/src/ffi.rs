@@ -28,6 +28,7 @@ use std::ffi;
 use std::ptr;
 use std::slice;
 use std::sync::atomic;
+use std::sync::Arc;
 
 use std::net::Ipv4Addr;
 use std::net::Ipv6Addr;
@@ -174,6 +175,93 @@ pub extern fn quiche_config_load_priv_key_from_pem_file(
     }
 }
 
+#[no_mangle]
+pub extern fn quiche_certificate_from_pem_files(
+    cert_path: *const c_char, key_path: *const c_char,
+) -> *const Certificate {
+    let cert_path = unsafe { ffi::CStr::from_ptr(cert_path).to_str().unwrap() };
+    let key_path = unsafe { ffi::CStr::from_ptr(key_path).to_str().unwrap() };
+
+    match Certificate::from_pem_files(cert_path, key_path) {
+        Ok(c) => Arc::into_raw(Arc::new(c)),
+
+        Err(_) => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern fn quiche_certificate_set_ocsp_response(
+    cert: *const Certificate, response: *const u8, response_len: size_t,
+) -> c_int {
+    let response = unsafe { slice::from_raw_parts(response, response_len) };
+
+    let mut cert = unsafe { Arc::from_raw(cert) };
+
+    let rc = match Arc::get_mut(&mut cert) {
+        Some(cert) => match cert.set_ocsp_response(response) {
+            Ok(_) => 0,
+
+            Err(e) => e.to_c() as c_int,
+        },
+
+        // The certificate was already handed out by a selector.
+        None => Error::InvalidState.to_c() as c_int,
+    };
+
+    let _ = Arc::into_raw(cert);
+
+    rc
+}
+
+#[no_mangle]
+pub extern fn quiche_certificate_free(cert: *const Certificate) {
+    drop(unsafe { Arc::from_raw(cert) });
+}
+
+struct CertificateSelector {
+    cb: extern fn(
+        server_name: *const u8,
+        server_name_len: size_t,
+        argp: *mut c_void,
+    ) -> *const Certificate,
+    argp: atomic::AtomicPtr<c_void>,
+}
+
+#[no_mangle]
+pub extern fn quiche_config_set_certificate_selector(
+    config: &mut Config,
+    cb: extern fn(
+        server_name: *const u8,
+        server_name_len: size_t,
+        argp: *mut c_void,
+    ) -> *const Certificate,
+    argp: *mut c_void,
+) {
+    let selector = CertificateSelector {
+        cb,
+        argp: atomic::AtomicPtr::new(argp),
+    };
+
+    config.set_certificate_selector(move |server_name| {
+        let cert = (selector.cb)(
+            server_name.as_ptr(),
+            server_name.len(),
+            selector.argp.load(atomic::Ordering::Relaxed),
+        );
+
+        if cert.is_null() {
+            return None;
+        }
+
+        // The application keeps its own reference to the certificate.
+        unsafe {
+            Arc::increment_strong_count(cert);
+
+            Some(Arc::from_raw(cert))
+        }
+    });
+}
+
 #[no_mangle]
 pub extern fn quiche_config_load_verify_locations_from_file(
     config: &mut Config, path: *const c_char,
@@ -205,11 +293,26 @@ pub extern fn quiche_config_verify_peer(config: &mut Config, v: bool) {
     config.verify_peer(v);
 }
 
+#[no_mangle]
+pub extern fn quiche_config_require_client_cert(config: &mut Config, v: bool) {
+    config.require_client_cert(v);
+}
+
 #[no_mangle]
 pub extern fn quiche_config_grease(config: &mut Config, v: bool) {
     config.grease(v);
 }
 
+#[no_mangle]
+pub extern fn quiche_config_grease_quic_bit(config: &mut Config, v: bool) {
+    config.grease_quic_bit(v);
+}
+
+#[no_mangle]
+pub extern fn quiche_config_enable_spin_bit(config: &mut Config, v: bool) {
+    config.enable_spin_bit(v);
+}
+
 #[no_mangle]
 pub extern fn quiche_config_discover_pmtu(config: &mut Config, v: bool) {
     config.discover_pmtu(v);
@@ -225,6 +328,66 @@ pub extern fn quiche_config_enable_early_data(config: &mut Config) {
     config.enable_early_data();
 }
 
+#[no_mangle]
+pub extern fn quiche_config_set_ocsp_response(
+    config: &mut Config, response: *const u8, response_len: size_t,
+) -> c_int {
+    let response = unsafe { slice::from_raw_parts(response, response_len) };
+
+    match config.set_ocsp_response(response) {
+        Ok(_) => 0,
+
+        Err(e) => e.to_c() as c_int,
+    }
+}
+
+#[no_mangle]
+pub extern fn quiche_config_enable_ocsp_stapling(config: &mut Config) {
+    config.enable_ocsp_stapling();
+}
+
+#[no_mangle]
+pub extern fn quiche_config_set_cipher_suites(
+    config: &mut Config, v: *const c_char,
+) -> c_int {
+    let v = unsafe { ffi::CStr::from_ptr(v).to_str().unwrap() };
+    let v: Vec<&str> = v.split(':').collect();
+
+    match config.set_cipher_suites(&v) {
+        Ok(_) => 0,
+
+        Err(e) => e.to_c() as c_int,
+    }
+}
+
+#[no_mangle]
+pub extern fn quiche_config_set_groups(
+    config: &mut Config, v: *const c_char,
+) -> c_int {
+    let v = unsafe { ffi::CStr::from_ptr(v).to_str().unwrap() };
+    let v: Vec<&str> = v.split(':').collect();
+
+    match config.set_groups(&v) {
+        Ok(_) => 0,
+
+        Err(e) => e.to_c() as c_int,
+    }
+}
+
+#[no_mangle]
+pub extern fn quiche_config_set_signature_algorithms(
+    config: &mut Config, v: *const c_char,
+) -> c_int {
+    let v = unsafe { ffi::CStr::from_ptr(v).to_str().unwrap() };
+    let v: Vec<&str> = v.split(':').collect();
+
+    match config.set_signature_algorithms(&v) {
+        Ok(_) => 0,
+
+        Err(e) => e.to_c() as c_int,
+    }
+}
+
 #[no_mangle]
 /// Corresponds to the `Config::set_application_protos_wire_format` Rust
 /// function.
@@ -252,6 +415,16 @@ pub extern fn quiche_config_set_max_idle_timeout(config: &mut Config, v: u64) {
     config.set_max_idle_timeout(v);
 }
 
+#[no_mangle]
+pub extern fn quiche_config_enable_keep_alive(config: &mut Config, v: bool) {
+    config.enable_keep_alive(v);
+}
+
+#[no_mangle]
+pub extern fn quiche_config_set_keep_alive_ratio(config: &mut Config, v: f64) {
+    config.set_keep_alive_ratio(v);
+}
+
 #[no_mangle]
 pub extern fn quiche_config_set_max_recv_udp_payload_size(
     config: &mut Config, v: size_t,
@@ -342,6 +515,25 @@ pub extern fn quiche_config_set_initial_congestion_window_packets(
     config.set_initial_congestion_window_packets(packets);
 }
 
+#[no_mangle]
+pub extern fn quiche_config_set_minimum_congestion_window_packets(
+    config: &mut Config, packets: size_t,
+) {
+    config.set_minimum_congestion_window_packets(packets);
+}
+
+#[no_mangle]
+pub extern fn quiche_config_set_pto_backoff_multiplier(
+    config: &mut Config, v: f64,
+) {
+    config.set_pto_backoff_multiplier(v);
+}
+
+#[no_mangle]
+pub extern fn quiche_config_set_max_pto_backoffs(config: &mut Config, v: u32) {
+    config.set_max_pto_backoffs(v);
+}
+
 #[no_mangle]
 pub extern fn quiche_config_enable_hystart(config: &mut Config, v: bool) {
     config.enable_hystart(v);
@@ -391,6 +583,17 @@ pub extern fn quiche_config_set_active_connection_id_limit(
     config.set_active_connection_id_limit(v);
 }
 
+#[no_mangle]
+pub extern fn quiche_config_set_local_connection_id_len(
+    config: &mut Config, v: size_t,
+) -> c_int {
+    match config.set_local_connection_id_len(v) {
+        Ok(_) => 0,
+
+        Err(e) => e.to_c() as c_int,
+    }
+}
+
 #[no_mangle]
 pub extern fn quiche_config_set_stateless_reset_token(
     config: &mut Config, v: *const u8,
@@ -433,7 +636,7 @@ pub extern fn quiche_header_info(
     scid: *mut u8, scid_len: *mut size_t, dcid: *mut u8, dcid_len: *mut size_t,
     token: *mut u8, token_len: *mut size_t,
 ) -> c_int {
-    let buf = unsafe { slice::from_raw_parts_mut(buf, buf_len) };
+    let buf = unsafe { slice::from_raw_parts(buf, buf_len) };
     let hdr = match Header::from_slice(buf, dcil) {
         Ok(v) => v,
 
@@ -564,6 +767,28 @@ pub extern fn quiche_negotiate_version(
     }
 }
 
+#[no_mangle]
+pub extern fn quiche_negotiate_version_with_versions(
+    scid: *const u8, scid_len: size_t, dcid: *const u8, dcid_len: size_t,
+    versions: *const u32, versions_len: size_t, out: *mut u8, out_len: size_t,
+) -> ssize_t {
+    let scid = unsafe { slice::from_raw_parts(scid, scid_len) };
+    let scid = ConnectionId::from_ref(scid);
+
+    let dcid = unsafe { slice::from_raw_parts(dcid, dcid_len) };
+    let dcid = ConnectionId::from_ref(dcid);
+
+    let versions = unsafe { slice::from_raw_parts(versions, versions_len) };
+
+    let out = unsafe { slice::from_raw_parts_mut(out, out_len) };
+
+    match negotiate_version_with_versions(&scid, &dcid, versions, out) {
+        Ok(v) => v as ssize_t,
+
+        Err(e) => e.to_c(),
+    }
+}
+
 #[no_mangle]
 pub extern fn quiche_version_is_supported(version: u32) -> bool {
     version_is_supported(version)
@@ -594,6 +819,40 @@ pub extern fn quiche_retry(
     }
 }
 
+#[no_mangle]
+pub extern fn quiche_stateless_reset(
+    reset_token: *const u8, pkt_len: size_t, out: *mut u8, out_len: size_t,
+) -> ssize_t {
+    let reset_token = unsafe { slice::from_raw_parts(reset_token, 16) };
+    let reset_token = match reset_token.try_into() {
+        Ok(rt) => rt,
+        Err(_) => unreachable!(),
+    };
+    let reset_token = u128::from_be_bytes(reset_token);
+
+    let out = unsafe { slice::from_raw_parts_mut(out, out_len) };
+
+    match stateless_reset(reset_token, pkt_len, out) {
+        Ok(v) => v as ssize_t,
+
+        Err(e) => e.to_c(),
+    }
+}
+
+#[no_mangle]
+pub extern fn quiche_stateless_reset_token(
+    key: *const u8, key_len: size_t, cid: *const u8, cid_len: size_t,
+    out: *mut u8,
+) {
+    let key = unsafe { slice::from_raw_parts(key, key_len) };
+    let cid = unsafe { slice::from_raw_parts(cid, cid_len) };
+    let out = unsafe { slice::from_raw_parts_mut(out, 16) };
+
+    let token = stateless_reset_token(key, cid);
+
+    out.copy_from_slice(&token.to_be_bytes());
+}
+
 #[no_mangle]
 pub extern fn quiche_conn_new_with_tls(
     scid: *const u8, scid_len: size_t, odcid: *const u8, odcid_len: size_t,
@@ -738,6 +997,7 @@ impl From<&RecvInfo<'_>> for crate::RecvInfo {
         crate::RecvInfo {
             from: std_addr_from_c(info.from, info.from_len),
             to: std_addr_from_c(info.to, info.to_len),
+            ecn: crate::Ecn::NotEct,
         }
     }
 }
@@ -875,6 +1135,24 @@ pub extern fn quiche_conn_stream_send(
     }
 }
 
+#[no_mangle]
+pub extern fn quiche_conn_stream_open_bidi(conn: &mut Connection) -> i64 {
+    match conn.stream_open_bidi() {
+        Ok(v) => v as i64,
+
+        Err(e) => e.to_c() as i64,
+    }
+}
+
+#[no_mangle]
+pub extern fn quiche_conn_stream_open_uni(conn: &mut Connection) -> i64 {
+    match conn.stream_open_uni() {
+        Ok(v) => v as i64,
+
+        Err(e) => e.to_c() as i64,
+    }
+}
+
 #[no_mangle]
 pub extern fn quiche_conn_stream_priority(
     conn: &mut Connection, stream_id: u64, urgency: u8, incremental: bool,
@@ -1103,6 +1381,39 @@ pub extern fn quiche_conn_peer_cert(
     }
 }
 
+#[no_mangle]
+pub extern fn quiche_conn_peer_ocsp_response(
+    conn: &Connection, out: &mut *const u8, out_len: &mut size_t,
+) {
+    match conn.peer_ocsp_response() {
+        Some(response) => {
+            *out = response.as_ptr();
+            *out_len = response.len();
+        },
+
+        None => *out_len = 0,
+    }
+}
+
+#[no_mangle]
+pub extern fn quiche_conn_export_keying_material(
+    conn: &Connection, label: *const u8, label_len: size_t, context: *const u8,
+    context_len: size_t, out: *mut u8, out_len: size_t,
+) -> c_int {
+    let label = unsafe { slice::from_raw_parts(label, label_len) };
+    let context = unsafe { slice::from_raw_parts(context, context_len) };
+    let out = unsafe { slice::from_raw_parts_mut(out, out_len) };
+
+    match conn.export_keying_material(label, context, out_len) {
+        Ok(v) => {
+            out.copy_from_slice(&v);
+            0
+        },
+
+        Err(e) => e.to_c() as c_int,
+    }
+}
+
 #[no_mangle]
 pub extern fn quiche_conn_session(
     conn: &Connection, out: &mut *const u8, out_len: &mut size_t,
@@ -1166,6 +1477,21 @@ pub extern fn quiche_conn_peer_error(
     }
 }
 
+#[no_mangle]
+pub extern fn quiche_conn_peer_error_frame_type(
+    conn: &Connection, frame_type: *mut u64,
+) -> bool {
+    match conn.peer_error.as_ref().and_then(|e| e.frame_type) {
+        Some(v) => {
+            unsafe { *frame_type = v };
+
+            true
+        },
+
+        None => false,
+    }
+}
+
 #[no_mangle]
 pub extern fn quiche_conn_local_error(
     conn: &Connection, is_app: *mut bool, error_code: *mut u64,
@@ -1310,6 +1636,11 @@ pub struct PathStats {
     stream_retrans_bytes: u64,
     pmtu: usize,
     delivery_rate: u64,
+    latest_rtt: u64,
+    ssthresh: usize,
+    bytes_in_flight: usize,
+    pto_count: usize,
+    spurious_lost: usize,
 }
 
 #[no_mangle]
@@ -1339,6 +1670,11 @@ pub extern fn quiche_conn_path_stats(
     out.stream_retrans_bytes = stats.stream_retrans_bytes;
     out.pmtu = stats.pmtu;
     out.delivery_rate = stats.delivery_rate;
+    out.latest_rtt = stats.latest_rtt.as_nanos() as u64;
+    out.ssthresh = stats.ssthresh;
+    out.bytes_in_flight = stats.bytes_in_flight;
+    out.pto_count = stats.pto_count;
+    out.spurious_lost = stats.spurious_lost;
 
     0
 }
@@ -1448,6 +1784,11 @@ pub extern fn quiche_conn_is_dgram_recv_queue_full(conn: &Connection) -> bool {
     conn.is_dgram_recv_queue_full()
 }
 
+#[no_mangle]
+pub extern fn quiche_conn_set_keep_alive(conn: &mut Connection, v: bool) {
+    conn.set_keep_alive(v);
+}
+
 #[no_mangle]
 pub extern fn quiche_conn_send_ack_eliciting(conn: &mut Connection) -> ssize_t {
     match conn.send_ack_eliciting() {
@@ -24,6 +24,11 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+//! QUIC frame parsing and serialization.
+//!
+//! This exposes the wire format of the frames supported by quiche, so that
+//! tools such as packet dissectors and fuzzers can reuse it.
+
 use std::convert::TryInto;
 
 use crate::Error;
@@ -42,152 +47,293 @@ use qlog::events::quic::QuicFrame;
 #[cfg(feature = "qlog")]
 use qlog::events::quic::StreamType;
 
-pub const MAX_CRYPTO_OVERHEAD: usize = 8;
-pub const MAX_DGRAM_OVERHEAD: usize = 2;
-pub const MAX_STREAM_OVERHEAD: usize = 12;
-pub const MAX_STREAM_SIZE: u64 = 1 << 62;
+pub use crate::ranges::RangeSet;
+pub use crate::stream::RangeBuf;
+
+pub(crate) const MAX_CRYPTO_OVERHEAD: usize = 8;
+pub(crate) const MAX_DGRAM_OVERHEAD: usize = 2;
+pub(crate) const MAX_STREAM_OVERHEAD: usize = 12;
+pub(crate) const MAX_STREAM_SIZE: u64 = 1 << 62;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// The ECN counts carried by an ACK frame.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct EcnCounts {
-    ect0_count: u64,
-    ect1_count: u64,
-    ecn_ce_count: u64,
+    /// The number of packets received with the ECT(0) codepoint.
+    pub ect0_count: u64,
+    /// The number of packets received with the ECT(1) codepoint.
+    pub ect1_count: u64,
+    /// The number of packets received with the ECN-CE codepoint.
+    pub ecn_ce_count: u64,
 }
 
+/// A QUIC frame.
 #[derive(Clone, PartialEq, Eq)]
 pub enum Frame {
+    /// A run of PADDING frames.
     Padding {
+        /// The number of padding bytes.
         len: usize,
     },
 
+    /// A PING frame.
     Ping {
-        // Attach metadata to the Ping frame. This doesn't appear on the wire,
-        // but does tell us if this frame was part of a PMTUD probe and how
-        // large the probe was. This will only show up on sent frames and be
-        // None otherwise. This is the total size of the QUIC packet in the
-        // probe.
+        /// The total size of the QUIC packet, if this frame was part of a
+        /// PMTUD probe.
+        ///
+        /// This doesn't appear on the wire, and is only set on sent frames.
         mtu_probe: Option<usize>,
     },
 
+    /// An ACK frame.
     ACK {
+        /// The encoded ACK delay.
         ack_delay: u64,
+        /// The acknowledged packet number ranges.
         ranges: ranges::RangeSet,
+        /// The ECN counts, if any.
         ecn_counts: Option<EcnCounts>,
     },
 
+    /// A RESET_STREAM frame.
     ResetStream {
+        /// The ID of the stream being reset.
+        stream_id: u64,
+        /// The application error code.
+        error_code: u64,
+        /// The final size of the stream.
+        final_size: u64,
+    },
+
+    /// A RESET_STREAM_AT frame.
+    ResetStreamAt {
+        /// The ID of the stream being reset.
         stream_id: u64,
+        /// The application error code.
         error_code: u64,
+        /// The final size of the stream.
         final_size: u64,
+        /// The amount of data that must still be delivered.
+        reliable_size: u64,
     },
 
+    /// A STOP_SENDING frame.
     StopSending {
+        /// The ID of the stream being stopped.
         stream_id: u64,
+        /// The application error code.
         error_code: u64,
     },
 
+    /// A CRYPTO frame.
     Crypto {
+        /// The cryptographic handshake data and its offset.
         data: stream::RangeBuf,
     },
 
-    CryptoHeader {
-        offset: u64,
-        length: usize,
-    },
-
+    /// A NEW_TOKEN frame.
     NewToken {
+        /// The address validation token.
         token: Vec<u8>,
     },
 
+    /// A STREAM frame.
     Stream {
+        /// The ID of the stream.
         stream_id: u64,
+        /// The stream data, its offset and whether it is final.
         data: stream::RangeBuf,
     },
 
-    StreamHeader {
-        stream_id: u64,
-        offset: u64,
-        length: usize,
-        fin: bool,
-    },
-
+    /// A MAX_DATA frame.
     MaxData {
+        /// The maximum amount of data that can be sent on the connection.
         max: u64,
     },
 
+    /// A MAX_STREAM_DATA frame.
     MaxStreamData {
+        /// The ID of the stream.
         stream_id: u64,
+        /// The maximum amount of data that can be sent on the stream.
         max: u64,
     },
 
+    /// A MAX_STREAMS frame for bidirectional streams.
     MaxStreamsBidi {
+        /// The cumulative number of streams that can be opened.
         max: u64,
     },
 
+    /// A MAX_STREAMS frame for unidirectional streams.
     MaxStreamsUni {
+        /// The cumulative number of streams that can be opened.
         max: u64,
     },
 
+    /// A DATA_BLOCKED frame.
     DataBlocked {
+        /// The connection-level limit at which blocking occurred.
         limit: u64,
     },
 
+    /// A STREAM_DATA_BLOCKED frame.
     StreamDataBlocked {
+        /// The ID of the blocked stream.
         stream_id: u64,
+        /// The stream-level limit at which blocking occurred.
         limit: u64,
     },
 
+    /// A STREAMS_BLOCKED frame for bidirectional streams.
     StreamsBlockedBidi {
+        /// The stream limit at which blocking occurred.
         limit: u64,
     },
 
+    /// A STREAMS_BLOCKED frame for unidirectional streams.
     StreamsBlockedUni {
+        /// The stream limit at which blocking occurred.
         limit: u64,
     },
 
+    /// A NEW_CONNECTION_ID frame.
     NewConnectionId {
+        /// The sequence number of the connection ID.
         seq_num: u64,
+        /// The connection IDs that should be retired.
         retire_prior_to: u64,
+        /// The new connection ID.
         conn_id: Vec<u8>,
+        /// The stateless reset token associated with the connection ID.
         reset_token: [u8; 16],
     },
 
+    /// A RETIRE_CONNECTION_ID frame.
     RetireConnectionId {
+        /// The sequence number of the connection ID being retired.
         seq_num: u64,
     },
 
+    /// A PATH_CHALLENGE frame.
     PathChallenge {
+        /// The challenge data.
         data: [u8; 8],
     },
 
+    /// A PATH_RESPONSE frame.
     PathResponse {
+        /// The data echoed from the matching PATH_CHALLENGE frame.
         data: [u8; 8],
     },
 
+    /// A CONNECTION_CLOSE frame signaling a transport error.
     ConnectionClose {
+        /// The transport error code.
         error_code: u64,
+        /// The type of the frame that triggered the error.
         frame_type: u64,
+        /// The reason phrase.
         reason: Vec<u8>,
     },
 
+    /// A CONNECTION_CLOSE frame signaling an application error.
     ApplicationClose {
+        /// The application error code.
         error_code: u64,
+        /// The reason phrase.
         reason: Vec<u8>,
     },
 
+    /// A HANDSHAKE_DONE frame.
     HandshakeDone,
 
-    Datagram {
-        data: Vec<u8>,
+    /// An ACK_FREQUENCY frame.
+    AckFrequency {
+        /// The sequence number of the frame.
+        seq_num: u64,
+        /// The number of ack-eliciting packets the peer can receive before
+        /// sending an ACK.
+        ack_eliciting_threshold: u64,
+        /// The requested maximum ACK delay, in microseconds.
+        request_max_ack_delay: u64,
+        /// The number of out-of-order packets that trigger an immediate ACK.
+        reordering_threshold: u64,
     },
 
-    DatagramHeader {
-        length: usize,
+    /// An IMMEDIATE_ACK frame.
+    ImmediateAck,
+
+    /// A DATAGRAM frame.
+    Datagram {
+        /// The datagram payload.
+        data: Vec<u8>,
     },
 }
 
 impl Frame {
-    pub fn from_bytes(
+    /// Parses a frame from the given buffer.
+    ///
+    /// The `pkt` parameter is the type of the packet carrying the frame, as
+    /// not all frames are allowed in all packet types, in which case
+    /// [`InvalidPacket`] is returned.
+    ///
+    /// On success the frame and the number of bytes it occupies in `buf` are
+    /// returned, so that multiple frames can be parsed from a packet payload.
+    ///
+    /// [`InvalidPacket`]: ../enum.Error.html#variant.InvalidPacket
+    ///
+    /// ## Examples:
+    ///
+    /// ```
+    /// let payload = [0x01, 0x00, 0x00];
+    ///
+    /// let (frame, len) =
+    ///     quiche::frame::Frame::from_slice(&payload, quiche::Type::Short)?;
+    /// assert_eq!(frame, quiche::frame::Frame::Ping { mtu_probe: None });
+    /// assert_eq!(len, 1);
+    ///
+    /// let (frame, len) =
+    ///     quiche::frame::Frame::from_slice(&payload[len..], quiche::Type::Short)?;
+    /// assert_eq!(frame, quiche::frame::Frame::Padding { len: 2 });
+    /// assert_eq!(len, 2);
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn from_slice(buf: &[u8], pkt: packet::Type) -> Result<(Frame, usize)> {
+        let mut b = octets::Octets::with_slice(buf);
+
+        let frame = Frame::from_bytes(&mut b, pkt)?;
+
+        Ok((frame, b.off()))
+    }
+
+    /// Writes the frame to the given buffer.
+    ///
+    /// On success the number of bytes written to `out` is returned, which is
+    /// always equal to [`wire_len()`]. If `out` is too small
+    /// [`BufferTooShort`] is returned.
+    ///
+    /// [`wire_len()`]: enum.Frame.html#method.wire_len
+    /// [`BufferTooShort`]: ../enum.Error.html#variant.BufferTooShort
+    ///
+    /// ## Examples:
+    ///
+    /// ```
+    /// let mut out = [0; 32];
+    ///
+    /// let frame = quiche::frame::Frame::MaxData { max: 128 };
+    ///
+    /// let len = frame.to_slice(&mut out)?;
+    /// assert_eq!(len, frame.wire_len());
+    /// assert_eq!(&out[..len], &[0x10, 0x40, 0x80]);
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn to_slice(&self, out: &mut [u8]) -> Result<usize> {
+        let mut b = octets::OctetsMut::with_slice(out);
+
+        self.to_bytes(&mut b)
+    }
+
+    pub(crate) fn from_bytes(
         b: &mut octets::Octets, pkt: packet::Type,
     ) -> Result<Frame> {
         let frame_type = b.get_varint()?;
@@ -329,8 +475,19 @@ impl Frame {
 
             0x1e => Frame::HandshakeDone,
 
+            0x1f => Frame::ImmediateAck,
+
+            0x24 => parse_reset_stream_at_frame(b)?,
+
             0x30 | 0x31 => parse_datagram_frame(frame_type, b)?,
 
+            0xaf => Frame::AckFrequency {
+                seq_num: b.get_varint()?,
+                ack_eliciting_threshold: b.get_varint()?,
+                request_max_ack_delay: b.get_varint()?,
+                reordering_threshold: b.get_varint()?,
+            },
+
             _ => return Err(Error::InvalidFrame),
         };
 
@@ -369,7 +526,7 @@ impl Frame {
         Ok(frame)
     }
 
-    pub fn to_bytes(&self, b: &mut octets::OctetsMut) -> Result<usize> {
+    pub(crate) fn to_bytes(&self, b: &mut octets::OctetsMut) -> Result<usize> {
         let before = b.cap();
 
         match self {
@@ -439,6 +596,20 @@ impl Frame {
                 b.put_varint(*final_size)?;
             },
 
+            Frame::ResetStreamAt {
+                stream_id,
+                error_code,
+                final_size,
+                reliable_size,
+            } => {
+                b.put_varint(0x24)?;
+
+                b.put_varint(*stream_id)?;
+                b.put_varint(*error_code)?;
+                b.put_varint(*final_size)?;
+                b.put_varint(*reliable_size)?;
+            },
+
             Frame::StopSending {
                 stream_id,
                 error_code,
@@ -455,8 +626,6 @@ impl Frame {
                 b.put_bytes(data)?;
             },
 
-            Frame::CryptoHeader { .. } => (),
-
             Frame::NewToken { token } => {
                 b.put_varint(0x07)?;
 
@@ -476,8 +645,6 @@ impl Frame {
                 b.put_bytes(data)?;
             },
 
-            Frame::StreamHeader { .. } => (),
-
             Frame::MaxData { max } => {
                 b.put_varint(0x10)?;
 
@@ -586,18 +753,35 @@ impl Frame {
                 b.put_varint(0x1e)?;
             },
 
+            Frame::AckFrequency {
+                seq_num,
+                ack_eliciting_threshold,
+                request_max_ack_delay,
+                reordering_threshold,
+            } => {
+                b.put_varint(0xaf)?;
+
+                b.put_varint(*seq_num)?;
+                b.put_varint(*ack_eliciting_threshold)?;
+                b.put_varint(*request_max_ack_delay)?;
+                b.put_varint(*reordering_threshold)?;
+            },
+
+            Frame::ImmediateAck => {
+                b.put_varint(0x1f)?;
+            },
+
             Frame::Datagram { data } => {
                 encode_dgram_header(data.len() as u64, b)?;
 
                 b.put_bytes(data.as_ref())?;
             },
-
-            Frame::DatagramHeader { .. } => (),
         }
 
         Ok(before - b.cap())
     }
 
+    /// Returns the number of bytes the frame occupies on the wire.
     pub fn wire_len(&self) -> usize {
         match self {
             Frame::Padding { len } => *len,
@@ -652,6 +836,19 @@ impl Frame {
                 octets::varint_len(*final_size) // final_size
             },
 
+            Frame::ResetStreamAt {
+                stream_id,
+                error_code,
+                final_size,
+                reliable_size,
+            } => {
+                1 + // frame type
+                octets::varint_len(*stream_id) + // stream_id
+                octets::varint_len(*error_code) + // error_code
+                octets::varint_len(*final_size) + // final_size
+                octets::varint_len(*reliable_size) // reliable_size
+            },
+
             Frame::StopSending {
                 stream_id,
                 error_code,
@@ -668,13 +865,6 @@ impl Frame {
                 data.len() // data
             },
 
-            Frame::CryptoHeader { offset, length, .. } => {
-                1 + // frame type
-                octets::varint_len(*offset) + // offset
-                2 + // length, always encode as 2-byte varint
-                length // data
-            },
-
             Frame::NewToken { token } => {
                 1 + // frame type
                 octets::varint_len(token.len() as u64) + // token length
@@ -689,19 +879,6 @@ impl Frame {
                 data.len() // data
             },
 
-            Frame::StreamHeader {
-                stream_id,
-                offset,
-                length,
-                ..
-            } => {
-                1 + // frame type
-                octets::varint_len(*stream_id) + // stream_id
-                octets::varint_len(*offset) + // offset
-                2 + // length, always encode as 2-byte varint
-                length // data
-            },
-
             Frame::MaxData { max } => {
                 1 + // frame type
                 octets::varint_len(*max) // max
@@ -797,20 +974,32 @@ impl Frame {
                 1 // frame type
             },
 
-            Frame::Datagram { data } => {
-                1 + // frame type
-                2 + // length, always encode as 2-byte varint
-                data.len() // data
+            Frame::AckFrequency {
+                seq_num,
+                ack_eliciting_threshold,
+                request_max_ack_delay,
+                reordering_threshold,
+            } => {
+                2 + // frame type
+                octets::varint_len(*seq_num) + // seq_num
+                octets::varint_len(*ack_eliciting_threshold) + // threshold
+                octets::varint_len(*request_max_ack_delay) + // max_ack_delay
+                octets::varint_len(*reordering_threshold) // reordering
+            },
+
+            Frame::ImmediateAck => {
+                1 // frame type
             },
 
-            Frame::DatagramHeader { length } => {
+            Frame::Datagram { data } => {
                 1 + // frame type
                 2 + // length, always encode as 2-byte varint
-                *length // data
+                data.len() // data
             },
         }
     }
 
+    /// Returns true if the frame elicits an acknowledgement from the peer.
     pub fn ack_eliciting(&self) -> bool {
         // Any other frame is ack-eliciting (note the `!`).
         !matches!(
@@ -822,6 +1011,8 @@ impl Frame {
         )
     }
 
+    /// Returns true if the frame is a probing frame, which doesn't cause the
+    /// connection to migrate to the path the packet was received on.
     pub fn probing(&self) -> bool {
         matches!(
             self,
@@ -833,7 +1024,7 @@ impl Frame {
     }
 
     #[cfg(feature = "qlog")]
-    pub fn to_qlog(&self) -> QuicFrame {
+    pub(crate) fn to_qlog(&self) -> QuicFrame {
         match self {
             Frame::Padding { len } => QuicFrame::Padding {
                 length: None,
@@ -887,6 +1078,12 @@ impl Frame {
                 payload_length: None,
             },
 
+            Frame::ResetStreamAt { .. } => QuicFrame::Unknown {
+                raw_frame_type: 0x24,
+                frame_type_value: None,
+                raw: None,
+            },
+
             Frame::StopSending {
                 stream_id,
                 error_code,
@@ -902,11 +1099,6 @@ impl Frame {
                 length: data.len() as u64,
             },
 
-            Frame::CryptoHeader { offset, length } => QuicFrame::Crypto {
-                offset: *offset,
-                length: *length as u64,
-            },
-
             Frame::NewToken { token } => QuicFrame::NewToken {
                 token: qlog::Token {
                     // TODO: pick the token type some how
@@ -928,19 +1120,6 @@ impl Frame {
                 raw: None,
             },
 
-            Frame::StreamHeader {
-                stream_id,
-                offset,
-                length,
-                fin,
-            } => QuicFrame::Stream {
-                stream_id: *stream_id,
-                offset: *offset,
-                length: *length as u64,
-                fin: fin.then(|| true),
-                raw: None,
-            },
-
             Frame::MaxData { max } => QuicFrame::MaxData { maximum: *max },
 
             Frame::MaxStreamData { stream_id, max } => QuicFrame::MaxStreamData {
@@ -1023,13 +1202,20 @@ impl Frame {
 
             Frame::HandshakeDone => QuicFrame::HandshakeDone,
 
-            Frame::Datagram { data } => QuicFrame::Datagram {
-                length: data.len() as u64,
+            Frame::AckFrequency { .. } => QuicFrame::Unknown {
+                raw_frame_type: 0xaf,
+                frame_type_value: None,
                 raw: None,
             },
 
-            Frame::DatagramHeader { length } => QuicFrame::Datagram {
-                length: *length as u64,
+            Frame::ImmediateAck => QuicFrame::Unknown {
+                raw_frame_type: 0x1f,
+                frame_type_value: None,
+                raw: None,
+            },
+
+            Frame::Datagram { data } => QuicFrame::Datagram {
+                length: data.len() as u64,
                 raw: None,
             },
         }
@@ -1069,6 +1255,18 @@ impl std::fmt::Debug for Frame {
                 )?;
             },
 
+            Frame::ResetStreamAt {
+                stream_id,
+                error_code,
+                final_size,
+                reliable_size,
+            } => {
+                write!(
+                    f,
+                    "RESET_STREAM_AT stream={stream_id} err={error_code:x} size={final_size} reliable_size={reliable_size}"
+                )?;
+            },
+
             Frame::StopSending {
                 stream_id,
                 error_code,
@@ -1080,10 +1278,6 @@ impl std::fmt::Debug for Frame {
                 write!(f, "CRYPTO off={} len={}", data.off(), data.len())?;
             },
 
-            Frame::CryptoHeader { offset, length } => {
-                write!(f, "CRYPTO off={offset} len={length}")?;
-            },
-
             Frame::NewToken { token } => {
                 write!(f, "NEW_TOKEN len={}", token.len())?;
             },
@@ -1099,18 +1293,6 @@ impl std::fmt::Debug for Frame {
                 )?;
             },
 
-            Frame::StreamHeader {
-                stream_id,
-                offset,
-                length,
-                fin,
-            } => {
-                write!(
-                    f,
-                    "STREAM id={stream_id} off={offset} len={length} fin={fin}"
-                )?;
-            },
-
             Frame::MaxData { max } => {
                 write!(f, "MAX_DATA max={max}")?;
             },
@@ -1192,16 +1374,163 @@ impl std::fmt::Debug for Frame {
                 write!(f, "HANDSHAKE_DONE")?;
             },
 
+            Frame::AckFrequency {
+                seq_num,
+                ack_eliciting_threshold,
+                request_max_ack_delay,
+                reordering_threshold,
+            } => {
+                write!(
+                    f,
+                    "ACK_FREQUENCY seq_num={seq_num} threshold={ack_eliciting_threshold} max_ack_delay={request_max_ack_delay} reordering={reordering_threshold}"
+                )?;
+            },
+
+            Frame::ImmediateAck => {
+                write!(f, "IMMEDIATE_ACK")?;
+            },
+
             Frame::Datagram { data } => {
                 write!(f, "DATAGRAM len={}", data.len())?;
             },
+        }
+
+        Ok(())
+    }
+}
+
+/// A frame carried by a sent packet, as tracked by loss recovery.
+///
+/// The data of CRYPTO, STREAM and DATAGRAM frames is written directly to the
+/// output buffer, so only their header is kept in order to retransmit them.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) enum SentFrame {
+    Frame(Frame),
+
+    CryptoHeader {
+        offset: u64,
+        length: usize,
+    },
+
+    StreamHeader {
+        stream_id: u64,
+        offset: u64,
+        length: usize,
+        fin: bool,
+    },
+
+    DatagramHeader {
+        length: usize,
+    },
+}
+
+impl SentFrame {
+    /// Writes the frame to the given buffer.
+    ///
+    /// For the `*Header` variants nothing is written, as the header has
+    /// already been written along with the data.
+    pub fn to_bytes(&self, b: &mut octets::OctetsMut) -> Result<usize> {
+        match self {
+            SentFrame::Frame(frame) => frame.to_bytes(b),
+
+            _ => Ok(0),
+        }
+    }
+
+    pub fn wire_len(&self) -> usize {
+        match self {
+            SentFrame::Frame(frame) => frame.wire_len(),
+
+            SentFrame::CryptoHeader { offset, length } => {
+                1 + // frame type
+                octets::varint_len(*offset) + // offset
+                2 + // length, always encode as 2-byte varint
+                length // data
+            },
+
+            SentFrame::StreamHeader {
+                stream_id,
+                offset,
+                length,
+                ..
+            } => {
+                1 + // frame type
+                octets::varint_len(*stream_id) + // stream_id
+                octets::varint_len(*offset) + // offset
+                2 + // length, always encode as 2-byte varint
+                length // data
+            },
 
-            Frame::DatagramHeader { length } => {
-                write!(f, "DATAGRAM len={length}")?;
+            SentFrame::DatagramHeader { length } => {
+                1 + // frame type
+                2 + // length, always encode as 2-byte varint
+                *length // data
             },
         }
+    }
 
-        Ok(())
+    #[cfg(feature = "qlog")]
+    pub fn to_qlog(&self) -> QuicFrame {
+        match self {
+            SentFrame::Frame(frame) => frame.to_qlog(),
+
+            SentFrame::CryptoHeader { offset, length } => QuicFrame::Crypto {
+                offset: *offset,
+                length: *length as u64,
+            },
+
+            SentFrame::StreamHeader {
+                stream_id,
+                offset,
+                length,
+                fin,
+            } => QuicFrame::Stream {
+                stream_id: *stream_id,
+                offset: *offset,
+                length: *length as u64,
+                fin: fin.then_some(true),
+                raw: None,
+            },
+
+            SentFrame::DatagramHeader { length } => QuicFrame::Datagram {
+                length: *length as u64,
+                raw: None,
+            },
+        }
+    }
+}
+
+impl From<Frame> for SentFrame {
+    fn from(frame: Frame) -> Self {
+        SentFrame::Frame(frame)
+    }
+}
+
+impl std::fmt::Debug for SentFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SentFrame::Frame(frame) => frame.fmt(f),
+
+            SentFrame::CryptoHeader { offset, length } => {
+                write!(f, "CRYPTO off={offset} len={length}")
+            },
+
+            SentFrame::StreamHeader {
+                stream_id,
+                offset,
+                length,
+                fin,
+            } => {
+                write!(
+                    f,
+                    "STREAM id={stream_id} off={offset} len={length} fin={fin}"
+                )
+            },
+
+            SentFrame::DatagramHeader { length } => {
+                write!(f, "DATAGRAM len={length}")
+            },
+        }
     }
 }
 
@@ -1261,7 +1590,7 @@ fn parse_ack_frame(ty: u64, b: &mut octets::Octets) -> Result<Frame> {
     })
 }
 
-pub fn encode_crypto_header(
+pub(crate) fn encode_crypto_header(
     offset: u64, length: u64, b: &mut octets::OctetsMut,
 ) -> Result<()> {
     b.put_varint(0x06)?;
@@ -1274,7 +1603,7 @@ pub fn encode_crypto_header(
     Ok(())
 }
 
-pub fn encode_stream_header(
+pub(crate) fn encode_stream_header(
     stream_id: u64, offset: u64, length: u64, fin: bool,
     b: &mut octets::OctetsMut,
 ) -> Result<()> {
@@ -1301,7 +1630,9 @@ pub fn encode_stream_header(
     Ok(())
 }
 
-pub fn encode_dgram_header(length: u64, b: &mut octets::OctetsMut) -> Result<()> {
+pub(crate) fn encode_dgram_header(
+    length: u64, b: &mut octets::OctetsMut,
+) -> Result<()> {
     let mut ty: u8 = 0x30;
 
     // Always encode length
@@ -1360,6 +1691,25 @@ fn parse_datagram_frame(ty: u64, b: &mut octets::Octets) -> Result<Frame> {
     })
 }
 
+fn parse_reset_stream_at_frame(b: &mut octets::Octets) -> Result<Frame> {
+    let stream_id = b.get_varint()?;
+    let error_code = b.get_varint()?;
+    let final_size = b.get_varint()?;
+    let reliable_size = b.get_varint()?;
+
+    // The reliable size can't exceed the final size of the stream.
+    if reliable_size > final_size {
+        return Err(Error::InvalidFrame);
+    }
+
+    Ok(Frame::ResetStreamAt {
+        stream_id,
+        error_code,
+        final_size,
+        reliable_size,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1390,6 +1740,56 @@ mod tests {
         assert!(Frame::from_bytes(&mut b, packet::Type::Handshake).is_ok());
     }
 
+    #[test]
+    fn slice_api() {
+        let mut d = [42; 128];
+
+        let frames = [
+            Frame::Stream {
+                stream_id: 4,
+                data: stream::RangeBuf::from(b"hello", 12, true),
+            },
+            Frame::MaxStreamData {
+                stream_id: 4,
+                max: 1024,
+            },
+            Frame::HandshakeDone,
+        ];
+
+        let mut off = 0;
+
+        for frame in &frames {
+            let len = frame.to_slice(&mut d[off..]).unwrap();
+            assert_eq!(len, frame.wire_len());
+
+            off += len;
+        }
+
+        let mut parsed = Vec::new();
+        let mut read = 0;
+
+        while read < off {
+            let (frame, len) =
+                Frame::from_slice(&d[read..off], packet::Type::Short).unwrap();
+
+            parsed.push(frame);
+            read += len;
+        }
+
+        assert_eq!(read, off);
+        assert_eq!(parsed, frames);
+
+        // Output buffer is too short.
+        assert_eq!(frames[0].to_slice(&mut d[..4]), Err(Error::BufferTooShort));
+
+        // HANDSHAKE_DONE is not allowed in Handshake packets.
+        let len = frames[2].to_slice(&mut d).unwrap();
+        assert_eq!(
+            Frame::from_slice(&d[..len], packet::Type::Handshake),
+            Err(Error::InvalidPacket)
+        );
+    }
+
     #[test]
     fn ping() {
         let mut d = [42; 128];
@@ -1525,6 +1925,56 @@ mod tests {
         assert!(Frame::from_bytes(&mut b, packet::Type::Handshake).is_err());
     }
 
+    #[test]
+    fn reset_stream_at() {
+        let mut d = [42; 128];
+
+        let frame = Frame::ResetStreamAt {
+            stream_id: 123_213,
+            error_code: 21_123_767,
+            final_size: 21_123_767,
+            reliable_size: 1_024,
+        };
+
+        let wire_len = {
+            let mut b = octets::OctetsMut::with_slice(&mut d);
+            frame.to_bytes(&mut b).unwrap()
+        };
+
+        assert_eq!(wire_len, 15);
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert_eq!(Frame::from_bytes(&mut b, packet::Type::Short), Ok(frame));
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert!(Frame::from_bytes(&mut b, packet::Type::ZeroRTT).is_ok());
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert!(Frame::from_bytes(&mut b, packet::Type::Initial).is_err());
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert!(Frame::from_bytes(&mut b, packet::Type::Handshake).is_err());
+
+        // Reliable size larger than the final size.
+        let frame = Frame::ResetStreamAt {
+            stream_id: 4,
+            error_code: 0,
+            final_size: 10,
+            reliable_size: 11,
+        };
+
+        let wire_len = {
+            let mut b = octets::OctetsMut::with_slice(&mut d);
+            frame.to_bytes(&mut b).unwrap()
+        };
+
+        let mut b = octets::Octets::with_slice(&d[..wire_len]);
+        assert_eq!(
+            Frame::from_bytes(&mut b, packet::Type::Short),
+            Err(Error::InvalidFrame)
+        );
+    }
+
     #[test]
     fn stop_sending() {
         let mut d = [42; 128];
@@ -2080,6 +2530,64 @@ mod tests {
         assert!(Frame::from_bytes(&mut b, packet::Type::Handshake).is_err());
     }
 
+    #[test]
+    fn ack_frequency() {
+        let mut d = [42; 128];
+
+        let frame = Frame::AckFrequency {
+            seq_num: 3,
+            ack_eliciting_threshold: 10,
+            request_max_ack_delay: 25_000,
+            reordering_threshold: 1,
+        };
+
+        let wire_len = {
+            let mut b = octets::OctetsMut::with_slice(&mut d);
+            frame.to_bytes(&mut b).unwrap()
+        };
+
+        assert_eq!(wire_len, 9);
+        assert_eq!(wire_len, frame.wire_len());
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert_eq!(Frame::from_bytes(&mut b, packet::Type::Short), Ok(frame));
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert!(Frame::from_bytes(&mut b, packet::Type::ZeroRTT).is_ok());
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert!(Frame::from_bytes(&mut b, packet::Type::Initial).is_err());
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert!(Frame::from_bytes(&mut b, packet::Type::Handshake).is_err());
+    }
+
+    #[test]
+    fn immediate_ack() {
+        let mut d = [42; 128];
+
+        let frame = Frame::ImmediateAck;
+
+        let wire_len = {
+            let mut b = octets::OctetsMut::with_slice(&mut d);
+            frame.to_bytes(&mut b).unwrap()
+        };
+
+        assert_eq!(wire_len, 1);
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert_eq!(Frame::from_bytes(&mut b, packet::Type::Short), Ok(frame));
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert!(Frame::from_bytes(&mut b, packet::Type::ZeroRTT).is_ok());
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert!(Frame::from_bytes(&mut b, packet::Type::Initial).is_err());
+
+        let mut b = octets::Octets::with_slice(&d);
+        assert!(Frame::from_bytes(&mut b, packet::Type::Handshake).is_err());
+    }
+
     #[test]
     fn datagram() {
         let mut d = [42; 128];
@@ -122,7 +122,11 @@
 //! loop {
 //!     let (read, from) = socket.recv_from(&mut buf).unwrap();
 //!
-//!     let recv_info = quiche::RecvInfo { from, to };
+//!     let recv_info = quiche::RecvInfo {
+//!         from,
+//!         to,
+//!         ecn: quiche::Ecn::NotEct,
+//!     };
 //!
 //!     let read = match conn.recv(&mut buf[..read], recv_info) {
 //!         Ok(v) => v,
@@ -252,6 +256,25 @@
 //! [pace]: https://datatracker.ietf.org/doc/html/rfc9002#section-7.7
 //! [`SO_TXTIME`]: https://man7.org/linux/man-pages/man8/tc-etf.8.html
 //!
+//! ### Explicit Congestion Notification
+//!
+//! quiche reports the [ECN] codepoints of received packets back to the peer,
+//! which applications provide through the [`ecn`] field of [`RecvInfo`] (for
+//! example by using the `IP_RECVTOS` and `IPV6_RECVTCLASS` socket options on
+//! Linux). When the ECN codepoint can't be read it should be set to
+//! [`Ecn::NotEct`].
+//!
+//! When ECN is enabled with [`enable_ecn()`], the [`ecn`][send-ecn] field of
+//! [`SendInfo`] indicates how outgoing packets should be marked. quiche stops
+//! marking packets if the path turns out not to support ECN, and treats
+//! congestion marks reported by the peer as a congestion signal.
+//!
+//! [ECN]: https://datatracker.ietf.org/doc/html/rfc9000#section-13.4
+//! [`ecn`]: struct.RecvInfo.html#structfield.ecn
+//! [`Ecn::NotEct`]: enum.Ecn.html#variant.NotEct
+//! [`enable_ecn()`]: struct.Config.html#method.enable_ecn
+//! [send-ecn]: struct.SendInfo.html#structfield.ecn
+//!
 //! ## Sending and receiving stream data
 //!
 //! After some back and forth, the connection will complete its handshake and
@@ -389,6 +412,8 @@ use qlog::events::connectivity::TransportOwner;
 #[cfg(feature = "qlog")]
 use qlog::events::quic::RecoveryEventType;
 #[cfg(feature = "qlog")]
+use qlog::events::quic::SecurityEventType;
+#[cfg(feature = "qlog")]
 use qlog::events::quic::TransportEventType;
 #[cfg(feature = "qlog")]
 use qlog::events::DataRecipient;
@@ -491,6 +516,18 @@ const MAX_PROBING_TIMEOUTS: usize = 3;
 // The default initial congestion window size in terms of packet count.
 const DEFAULT_INITIAL_CONGESTION_WINDOW_PACKETS: usize = 10;
 
+// The default minimum congestion window size in terms of packet count.
+const DEFAULT_MINIMUM_CONGESTION_WINDOW_PACKETS: usize = 2;
+
+// The default factor the PTO is multiplied by on each consecutive timeout.
+const DEFAULT_PTO_BACKOFF_MULTIPLIER: f64 = 2.0;
+
+// The default maximum number of times the PTO is backed off.
+const DEFAULT_MAX_PTO_BACKOFFS: u32 = 16;
+
+// The default fraction of the idle timeout after which a keep-alive is sent.
+const DEFAULT_KEEP_ALIVE_RATIO: f64 = 0.5;
+
 // The maximum data offset that can be stored in a crypto stream.
 const MAX_CRYPTO_STREAM_OFFSET: u64 = 1 << 16;
 
@@ -540,6 +577,15 @@ pub enum Error {
     /// The TLS handshake failed.
     TlsFail,
 
+    /// The TLS handshake failed and the given TLS alert was sent to the peer.
+    ///
+    /// The alert is also carried in the `CRYPTO_ERROR` sent to the peer, see
+    /// [`ConnectionError::tls_alert()`].
+    ///
+    /// [`ConnectionError::tls_alert()`]:
+    /// struct.ConnectionError.html#method.tls_alert
+    TlsAlert(u8),
+
     /// The peer violated the local flow control limits.
     FlowControl,
 
@@ -575,6 +621,14 @@ pub enum Error {
 
     /// The peer sent more data in CRYPTO frames than we can buffer.
     CryptoBufferExceeded,
+
+    /// The provided packet carries a connection ID that is malformed or
+    /// doesn't belong to the connection.
+    InvalidConnectionId,
+
+    /// The confidentiality or integrity limit of the negotiated AEAD was
+    /// reached.
+    AeadLimitReached,
 }
 
 /// QUIC error codes sent on the wire.
@@ -659,6 +713,8 @@ impl Error {
             Error::CryptoBufferExceeded =>
                 WireErrorCode::CryptoBufferExceeded as u64,
             Error::KeyUpdate => WireErrorCode::KeyUpdateError as u64,
+            Error::AeadLimitReached => WireErrorCode::AeadLimitReached as u64,
+            Error::TlsAlert(alert) => 0x100 + u64::from(alert),
             _ => WireErrorCode::ProtocolViolation as u64,
         }
     }
@@ -686,6 +742,9 @@ impl Error {
             Error::OutOfIdentifiers => -18,
             Error::KeyUpdate => -19,
             Error::CryptoBufferExceeded => -20,
+            Error::InvalidConnectionId => -21,
+            Error::AeadLimitReached => -22,
+            Error::TlsAlert(_) => -23,
         }
     }
 }
@@ -716,6 +775,13 @@ pub struct RecvInfo {
 
     /// The local address the packet was received on.
     pub to: SocketAddr,
+
+    /// The ECN codepoint of the IP packet carrying the QUIC packet.
+    ///
+    /// See [ECN] for more details.
+    ///
+    /// [ECN]: index.html#explicit-congestion-notification
+    pub ecn: Ecn,
 }
 
 /// Ancillary information about outgoing packets.
@@ -733,6 +799,13 @@ pub struct SendInfo {
     ///
     /// [Pacing]: index.html#pacing
     pub at: time::Instant,
+
+    /// The ECN codepoint the IP packet should be marked with.
+    ///
+    /// See [ECN] for more details.
+    ///
+    /// [ECN]: index.html#explicit-congestion-notification
+    pub ecn: Ecn,
 }
 
 /// Represents information carried by `CONNECTION_CLOSE` frames.
@@ -746,6 +819,46 @@ pub struct ConnectionError {
 
     /// The reason carried by the `CONNECTION_CLOSE` frame.
     pub reason: Vec<u8>,
+
+    /// The type of the frame that triggered the error, if any.
+    ///
+    /// This is only set for transport errors received from the peer, as
+    /// application errors don't carry a frame type, and quiche always reports
+    /// a frame type of 0 when closing the connection itself.
+    pub frame_type: Option<u64>,
+}
+
+impl ConnectionError {
+    /// Returns the TLS alert that caused the error, if any.
+    ///
+    /// When the TLS handshake fails, the alert is carried in the transport
+    /// error code as a `CRYPTO_ERROR` (0x0100-0x01ff).
+    pub fn tls_alert(&self) -> Option<u8> {
+        match self.error_code {
+            0x100..=0x1ff if !self.is_app => Some(self.error_code as u8),
+
+            _ => None,
+        }
+    }
+}
+
+/// Certificate compression algorithms, as defined in [RFC 8879].
+///
+/// This should be used when calling [`add_cert_compression()`].
+///
+/// [RFC 8879]: https://www.rfc-editor.org/rfc/rfc8879.html
+/// [`add_cert_compression()`]: struct.Config.html#method.add_cert_compression
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CertificateCompressionAlgorithm {
+    /// zlib compression.
+    Zlib   = 1,
+
+    /// Brotli compression.
+    Brotli = 2,
+
+    /// Zstandard compression.
+    Zstd   = 3,
 }
 
 /// The side of the stream to be shut down.
@@ -763,6 +876,52 @@ pub enum Shutdown {
     Write = 1,
 }
 
+/// The direction of a packet passed to the packet trace callback.
+///
+/// See [`set_packet_trace()`].
+///
+/// [`set_packet_trace()`]: struct.Config.html#method.set_packet_trace
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// The packet was sent to the peer.
+    Sent,
+
+    /// The packet was received from the peer.
+    Received,
+}
+
+/// An Explicit Congestion Notification codepoint, as carried in the ECN
+/// field of the IP header.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Ecn {
+    /// Not ECN-Capable Transport.
+    #[default]
+    NotEct = 0,
+
+    /// ECN Capable Transport, ECT(1).
+    Ect1   = 1,
+
+    /// ECN Capable Transport, ECT(0).
+    Ect0   = 2,
+
+    /// Congestion Experienced.
+    Ce     = 3,
+}
+
+impl Ecn {
+    /// Returns the codepoint matching the two least significant bits of the
+    /// given IP traffic class (or IPv4 TOS) byte.
+    pub fn from_tos(tos: u8) -> Ecn {
+        match tos & 0x03 {
+            0x01 => Ecn::Ect1,
+            0x02 => Ecn::Ect0,
+            0x03 => Ecn::Ce,
+            _ => Ecn::NotEct,
+        }
+    }
+}
+
 /// Qlog logging level.
 #[repr(C)]
 #[cfg(feature = "qlog")]
@@ -786,20 +945,44 @@ pub struct Config {
 
     tls_ctx: tls::Context,
 
+    verify_peer: bool,
+
+    require_client_cert: bool,
+
+    cert_selector: Option<tls::CertificateSelector>,
+
+    ocsp_response: Option<Arc<[u8]>>,
+
+    cert_compression: Vec<tls::CertCompression>,
+
+    cipher_suites: Vec<crypto::Algorithm>,
+
     application_protos: Vec<Vec<u8>>,
 
     grease: bool,
 
     cc_algorithm: CongestionControlAlgorithm,
     initial_congestion_window_packets: usize,
+    minimum_congestion_window_packets: usize,
+    custom_cc: Option<recovery::congestion::CongestionControlFactory>,
+    pto_backoff_multiplier: f64,
+    max_pto_backoffs: u32,
 
     pmtud: bool,
 
+    spin_bit: bool,
+
+    packet_trace: Option<fn(&Header, &[frame::Frame], Direction)>,
+
     hystart: bool,
 
+    stream_events: bool,
+
     pacing: bool,
     max_pacing_rate: Option<u64>,
 
+    ecn: bool,
+
     dgram_recv_max_queue_len: usize,
     dgram_send_max_queue_len: usize,
 
@@ -813,6 +996,12 @@ pub struct Config {
     max_amplification_factor: usize,
 
     disable_dcid_reuse: bool,
+
+    local_conn_id_len: Option<usize>,
+
+    keep_alive: bool,
+
+    keep_alive_ratio: f64,
 }
 
 // See https://quicwg.org/base-drafts/rfc9000.html#section-15
@@ -853,20 +1042,40 @@ impl Config {
             return Err(Error::UnknownVersion);
         }
 
+        let local_transport_params = TransportParams::default();
+
+        let (verify_peer, require_client_cert) = tls_ctx.verify();
+
         Ok(Config {
-            local_transport_params: TransportParams::default(),
+            local_transport_params,
             version,
             tls_ctx,
+            verify_peer,
+            require_client_cert,
+            cert_selector: None,
+            ocsp_response: None,
+            cert_compression: Vec::new(),
+            cipher_suites: Vec::new(),
             application_protos: Vec::new(),
             grease: true,
             cc_algorithm: CongestionControlAlgorithm::CUBIC,
             initial_congestion_window_packets:
                 DEFAULT_INITIAL_CONGESTION_WINDOW_PACKETS,
+            minimum_congestion_window_packets:
+                DEFAULT_MINIMUM_CONGESTION_WINDOW_PACKETS,
+            custom_cc: None,
+            pto_backoff_multiplier: DEFAULT_PTO_BACKOFF_MULTIPLIER,
+            max_pto_backoffs: DEFAULT_MAX_PTO_BACKOFFS,
             pmtud: false,
+            spin_bit: false,
+            packet_trace: None,
             hystart: true,
+            stream_events: false,
             pacing: true,
             max_pacing_rate: None,
 
+            ecn: false,
+
             dgram_recv_max_queue_len: DEFAULT_MAX_DGRAM_QUEUE_LEN,
             dgram_send_max_queue_len: DEFAULT_MAX_DGRAM_QUEUE_LEN,
 
@@ -881,6 +1090,12 @@ impl Config {
             max_amplification_factor: MAX_AMPLIFICATION_FACTOR,
 
             disable_dcid_reuse: false,
+
+            local_conn_id_len: None,
+
+            keep_alive: false,
+
+            keep_alive_ratio: DEFAULT_KEEP_ALIVE_RATIO,
         })
     }
 
@@ -915,6 +1130,105 @@ impl Config {
         self.tls_ctx.use_privkey_file(file)
     }
 
+    /// Configures a callback selecting the certificate to present for a given
+    /// server name.
+    ///
+    /// When a client requests a server name through the TLS Server Name
+    /// Indication extension, `selector` is called with the name as sent by
+    /// the client. If it returns a [`Certificate`], that certificate chain
+    /// and private key are presented instead of the ones configured with
+    /// [`load_cert_chain_from_pem_file()`] and
+    /// [`load_priv_key_from_pem_file()`]. Clients requesting no name at all,
+    /// or names for which `None` is returned, get the default certificate.
+    ///
+    /// The rest of the TLS configuration (e.g. cipher suites or certificate
+    /// compression) applies regardless of the selected certificate.
+    ///
+    /// The server name requested by the client can be retrieved with
+    /// [`server_name()`].
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # use std::sync::Arc;
+    /// # let mut config = quiche::Config::new(0xbabababa)?;
+    /// config.load_cert_chain_from_pem_file("/path/to/default.pem")?;
+    /// config.load_priv_key_from_pem_file("/path/to/default.key")?;
+    ///
+    /// let cert = Arc::new(quiche::Certificate::from_pem_files(
+    ///     "/path/to/example.com.pem",
+    ///     "/path/to/example.com.key",
+    /// )?);
+    ///
+    /// config.set_certificate_selector(move |server_name| {
+    ///     if server_name.eq_ignore_ascii_case("example.com") {
+    ///         return Some(cert.clone());
+    ///     }
+    ///
+    ///     None
+    /// });
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    ///
+    /// [`Certificate`]: struct.Certificate.html
+    /// [`load_cert_chain_from_pem_file()`]: struct.Config.html#method.load_cert_chain_from_pem_file
+    /// [`load_priv_key_from_pem_file()`]: struct.Config.html#method.load_priv_key_from_pem_file
+    /// [`server_name()`]: struct.Connection.html#method.server_name
+    pub fn set_certificate_selector<F>(&mut self, selector: F)
+    where
+        F: Fn(&str) -> Option<Arc<Certificate>> + Send + Sync + 'static,
+    {
+        self.tls_ctx.enable_certificate_selection();
+
+        self.cert_selector = Some(Arc::new(selector));
+    }
+
+    /// Enables compression of certificates with the given algorithm.
+    ///
+    /// quiche doesn't implement any compression algorithm itself, so the
+    /// application needs to provide both the `compress` and `decompress`
+    /// functions. The latter receives the expected uncompressed length, and
+    /// the handshake fails if the decompressed certificate doesn't match it.
+    /// Either function can return `None` to signal a failure.
+    ///
+    /// Servers compress their certificate with the first algorithm that is
+    /// also supported by the client, which reduces the size of the first
+    /// flight and helps staying within the anti-amplification limit.
+    ///
+    /// This is only supported with BoringSSL, otherwise [`TlsFail`] is
+    /// returned.
+    ///
+    /// [`TlsFail`]: enum.Error.html#variant.TlsFail
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut config = quiche::Config::new(0xbabababa)?;
+    /// # fn brotli_compress(cert: &[u8]) -> Option<Vec<u8>> { None }
+    /// # fn brotli_decompress(buf: &[u8], len: usize) -> Option<Vec<u8>> { None }
+    /// config.add_cert_compression(
+    ///     quiche::CertificateCompressionAlgorithm::Brotli,
+    ///     brotli_compress,
+    ///     brotli_decompress,
+    /// )?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn add_cert_compression(
+        &mut self, alg: CertificateCompressionAlgorithm,
+        compress: fn(&[u8]) -> Option<Vec<u8>>,
+        decompress: fn(&[u8], usize) -> Option<Vec<u8>>,
+    ) -> Result<()> {
+        self.tls_ctx.add_cert_compression_alg(alg as u16)?;
+
+        self.cert_compression.push(tls::CertCompression {
+            alg: alg as u16,
+            compress,
+            decompress,
+        });
+
+        Ok(())
+    }
+
     /// Specifies a file where trusted CA certificates are stored for the
     /// purposes of certificate verification.
     ///
@@ -962,7 +1276,36 @@ impl Config {
     ///
     /// [`peer_cert()`]: struct.Connection.html#method.peer_cert
     pub fn verify_peer(&mut self, verify: bool) {
-        self.tls_ctx.set_verify(verify);
+        self.verify_peer = verify;
+
+        self.tls_ctx
+            .set_verify(self.verify_peer, self.require_client_cert);
+    }
+
+    /// Configures whether the server requires clients to present a
+    /// certificate.
+    ///
+    /// When enabled, the peer's certificate is verified as with
+    /// [`verify_peer()`], regardless of the value set with it, and the
+    /// handshake fails if the client doesn't present a certificate at all.
+    /// When disabled, whether certificates are requested and verified is
+    /// decided by [`verify_peer()`] alone.
+    ///
+    /// The client's certificate can be loaded with the same methods used to
+    /// load the server's (e.g. [`load_cert_chain_from_pem_file()`]), and the
+    /// authenticated identity can be retrieved on the server after the
+    /// handshake with [`peer_cert()`].
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`verify_peer()`]: struct.Config.html#method.verify_peer
+    /// [`load_cert_chain_from_pem_file()`]: struct.Config.html#method.load_cert_chain_from_pem_file
+    /// [`peer_cert()`]: struct.Connection.html#method.peer_cert
+    pub fn require_client_cert(&mut self, require: bool) {
+        self.require_client_cert = require;
+
+        self.tls_ctx
+            .set_verify(self.verify_peer, self.require_client_cert);
     }
 
     /// Configures whether to do path MTU discovery.
@@ -979,6 +1322,47 @@ impl Config {
         self.grease = grease;
     }
 
+    /// Configures whether to grease the QUIC bit (RFC 9287).
+    ///
+    /// When enabled the `grease_quic_bit` transport parameter is advertised,
+    /// allowing the peer to clear the fixed bit of the packets it sends, and
+    /// the fixed bit is cleared at random on outgoing short header packets if
+    /// the peer advertised the same parameter.
+    ///
+    /// When disabled, receiving a packet with the fixed bit cleared closes the
+    /// connection with a `PROTOCOL_VIOLATION` error.
+    ///
+    /// The default value is `false`.
+    pub fn grease_quic_bit(&mut self, grease: bool) {
+        self.local_transport_params.grease_quic_bit = grease;
+    }
+
+    /// Configures whether to maintain the latency spin bit in short header
+    /// packets.
+    ///
+    /// The spin bit allows on-path observers to passively measure the RTT of
+    /// the connection. When disabled, the spin bit is always set to zero.
+    ///
+    /// The default value is `false`.
+    pub fn enable_spin_bit(&mut self, v: bool) {
+        self.spin_bit = v;
+    }
+
+    /// Sets a callback invoked for every packet sent or received by
+    /// connections created with this configuration.
+    ///
+    /// The callback is passed the packet's header and the frames it carries,
+    /// once they have been successfully decrypted and parsed, which is useful
+    /// to debug interoperability issues. Packets that are dropped before
+    /// being decrypted are not reported.
+    ///
+    /// The default value is `None`.
+    pub fn set_packet_trace(
+        &mut self, cb: Option<fn(&Header, &[frame::Frame], Direction)>,
+    ) {
+        self.packet_trace = cb;
+    }
+
     /// Enables logging of secrets.
     ///
     /// When logging is enabled, the [`set_keylog()`] method must be called on
@@ -1010,6 +1394,128 @@ impl Config {
         self.tls_ctx.set_early_data_enabled(true);
     }
 
+    /// Configures the OCSP response to staple in the server's handshake.
+    ///
+    /// The `response` is a DER-encoded OCSP response for the configured leaf
+    /// certificate. It is only sent to clients that request it, and it is
+    /// the application's responsibility to refresh it before it expires.
+    ///
+    /// The response only applies to the default certificate. Certificates
+    /// returned by the [`set_certificate_selector()`] callback carry their own
+    /// response, configured with [`Certificate::set_ocsp_response()`].
+    ///
+    /// This is only supported with BoringSSL, otherwise [`TlsFail`] is
+    /// returned.
+    ///
+    /// [`set_certificate_selector()`]: struct.Config.html#method.set_certificate_selector
+    /// [`Certificate::set_ocsp_response()`]: struct.Certificate.html#method.set_ocsp_response
+    /// [`TlsFail`]: enum.Error.html#variant.TlsFail
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut config = quiche::Config::new(0xbabababa)?;
+    /// let response = std::fs::read("/path/to/ocsp.der").unwrap();
+    /// config.set_ocsp_response(&response)?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn set_ocsp_response(&mut self, response: &[u8]) -> Result<()> {
+        self.tls_ctx.enable_ocsp_response()?;
+
+        self.ocsp_response = Some(Arc::from(response));
+
+        Ok(())
+    }
+
+    /// Configures the list of allowed TLS 1.3 cipher suites, in order of
+    /// preference.
+    ///
+    /// Cipher suite names follow the TLS 1.3 naming (e.g.
+    /// `TLS_AES_256_GCM_SHA384`). Unknown names are ignored, but if none of
+    /// the given cipher suites is known, [`TlsFail`] is returned.
+    ///
+    /// BoringSSL doesn't allow configuring the TLS 1.3 cipher suites it
+    /// offers or selects, so when quiche is built with it, the list is only
+    /// enforced: the handshake fails if the negotiated cipher suite isn't in
+    /// it. The preference order is only honored with the `openssl` feature.
+    ///
+    /// [`TlsFail`]: enum.Error.html#variant.TlsFail
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut config = quiche::Config::new(0xbabababa)?;
+    /// config.set_cipher_suites(&["TLS_AES_256_GCM_SHA384"])?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn set_cipher_suites(&mut self, v: &[&str]) -> Result<()> {
+        let cipher_suites: Vec<crypto::Algorithm> = v
+            .iter()
+            .filter_map(|name| tls::cipher_from_name(name))
+            .collect();
+
+        if cipher_suites.is_empty() {
+            return Err(Error::TlsFail);
+        }
+
+        self.tls_ctx.set_ciphersuites(&v.join(":"))?;
+
+        self.cipher_suites = cipher_suites;
+
+        Ok(())
+    }
+
+    /// Configures the list of supported key exchange groups, in order of
+    /// preference.
+    ///
+    /// Group names follow the TLS library's naming (e.g. `X25519` or
+    /// `P-256`). If any of the given groups is not known, [`TlsFail`] is
+    /// returned.
+    ///
+    /// [`TlsFail`]: enum.Error.html#variant.TlsFail
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut config = quiche::Config::new(0xbabababa)?;
+    /// config.set_groups(&["X25519"])?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn set_groups(&mut self, v: &[&str]) -> Result<()> {
+        self.tls_ctx.set_groups(&v.join(":"))
+    }
+
+    /// Configures the list of supported signature algorithms, in order of
+    /// preference.
+    ///
+    /// The list applies both to signing the local certificate and to
+    /// verifying the peer's. Algorithm names follow the TLS 1.3 naming (e.g.
+    /// `ecdsa_secp256r1_sha256` or `rsa_pss_rsae_sha256`). If any of the given
+    /// algorithms is not known, [`TlsFail`] is returned.
+    ///
+    /// [`TlsFail`]: enum.Error.html#variant.TlsFail
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut config = quiche::Config::new(0xbabababa)?;
+    /// config.set_signature_algorithms(&["ecdsa_secp256r1_sha256", "ed25519"])?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn set_signature_algorithms(&mut self, v: &[&str]) -> Result<()> {
+        self.tls_ctx.set_sigalgs(&v.join(":"))
+    }
+
+    /// Enables requesting a stapled OCSP response from the server.
+    ///
+    /// The response (if any) can be retrieved with [`peer_ocsp_response()`]
+    /// once the handshake is complete.
+    ///
+    /// [`peer_ocsp_response()`]: struct.Connection.html#method.peer_ocsp_response
+    pub fn enable_ocsp_stapling(&mut self) {
+        self.tls_ctx.enable_ocsp_stapling();
+    }
+
     /// Configures the list of supported application protocols.
     ///
     /// On the client this configures the list of protocols to send to the
@@ -1080,6 +1586,37 @@ impl Config {
         self.local_transport_params.max_idle_timeout = v;
     }
 
+    /// Configures whether to send keep-alive PING frames.
+    ///
+    /// When enabled, a PING frame is sent if a fraction of the idle timeout
+    /// (see [`set_keep_alive_ratio()`]) elapsed without any packet being
+    /// received, so that the connection (and any NAT binding along the path)
+    /// is not torn down while idle. This has no effect if neither endpoint set
+    /// an idle timeout.
+    ///
+    /// Keep-alives can also be toggled on individual connections with
+    /// [`set_keep_alive()`].
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`set_keep_alive_ratio()`]: struct.Config.html#method.set_keep_alive_ratio
+    /// [`set_keep_alive()`]: struct.Connection.html#method.set_keep_alive
+    pub fn enable_keep_alive(&mut self, v: bool) {
+        self.keep_alive = v;
+    }
+
+    /// Sets the fraction of the idle timeout after which a keep-alive PING
+    /// frame is sent, when keep-alives are enabled.
+    ///
+    /// Lower values make keep-alives more robust to the loss of PING frames,
+    /// at the cost of sending them more often. Values are clamped between
+    /// 0.1 and 0.9.
+    ///
+    /// The default value is 0.5.
+    pub fn set_keep_alive_ratio(&mut self, v: f64) {
+        self.keep_alive_ratio = v.clamp(0.1, 0.9);
+    }
+
     /// Sets the `max_udp_payload_size transport` parameter.
     ///
     /// The default value is `65527`.
@@ -1211,9 +1748,32 @@ impl Config {
 
     /// Sets the `max_ack_delay` transport parameter.
     ///
-    /// The default value is `25`.
+    /// The default value is `25`. A previously set `min_ack_delay` larger
+    /// than the new value is clamped to it.
     pub fn set_max_ack_delay(&mut self, v: u64) {
         self.local_transport_params.max_ack_delay = v;
+
+        if let Some(min_ack_delay) = self.local_transport_params.min_ack_delay {
+            self.set_min_ack_delay(min_ack_delay);
+        }
+    }
+
+    /// Sets the `min_ack_delay` transport parameter, in microseconds.
+    ///
+    /// Advertising this parameter enables the ACK frequency extension,
+    /// allowing the peer to ask for less frequent ACKs by sending
+    /// `ACK_FREQUENCY` frames. Values larger than the `max_ack_delay`
+    /// transport parameter are clamped to it.
+    ///
+    /// The default value is unset, meaning the extension is disabled.
+    pub fn set_min_ack_delay(&mut self, v: u64) {
+        let max_ack_delay = self
+            .local_transport_params
+            .max_ack_delay
+            .saturating_mul(1000);
+
+        self.local_transport_params.min_ack_delay =
+            Some(cmp::min(v, max_ack_delay));
     }
 
     /// Sets the `active_connection_id_limit` transport parameter.
@@ -1225,6 +1785,49 @@ impl Config {
         }
     }
 
+    /// Sets the length of the Source Connection IDs used by the local
+    /// endpoint.
+    ///
+    /// Once set, [`connect()`], [`accept()`] and [`new_scid()`] return
+    /// [`InvalidState`] when given a Source Connection ID of a different
+    /// length. The length can be zero, e.g. for clients that don't need to be
+    /// identified by their Connection ID, but it can't be greater than
+    /// [`MAX_CONN_ID_LEN`], in which case [`InvalidState`] is returned.
+    ///
+    /// The length is also used to parse the Destination Connection ID of
+    /// received short header packets, which don't carry it on the wire.
+    ///
+    /// The default value is unset, meaning any length up to
+    /// [`MAX_CONN_ID_LEN`] is accepted.
+    ///
+    /// [`connect()`]: fn.connect.html
+    /// [`accept()`]: fn.accept.html
+    /// [`new_scid()`]: struct.Connection.html#method.new_scid
+    /// [`InvalidState`]: enum.Error.html#variant.InvalidState
+    /// [`MAX_CONN_ID_LEN`]: constant.MAX_CONN_ID_LEN.html
+    pub fn set_local_connection_id_len(&mut self, len: usize) -> Result<()> {
+        if len > MAX_CONN_ID_LEN {
+            return Err(Error::InvalidState);
+        }
+
+        self.local_conn_id_len = Some(len);
+
+        Ok(())
+    }
+
+    /// Returns the length of the local Source Connection IDs, if set with
+    /// [`set_local_connection_id_len()`].
+    ///
+    /// Applications can pass this value to [`Header::from_slice()`] to parse
+    /// short header packets before routing them to a connection.
+    ///
+    /// [`set_local_connection_id_len()`]:
+    /// struct.Config.html#method.set_local_connection_id_len
+    /// [`Header::from_slice()`]: struct.Header.html#method.from_slice
+    pub fn local_connection_id_len(&self) -> Option<usize> {
+        self.local_conn_id_len
+    }
+
     /// Sets the `disable_active_migration` transport parameter.
     ///
     /// The default value is `false`.
@@ -1232,6 +1835,20 @@ impl Config {
         self.local_transport_params.disable_active_migration = v;
     }
 
+    /// Configures whether to accept reliable stream resets.
+    ///
+    /// When enabled the `reset_stream_at` transport parameter is advertised,
+    /// allowing the peer to reset streams using `RESET_STREAM_AT` frames.
+    /// Resetting streams this way with [`stream_reset_at()`] in turn requires
+    /// the peer to advertise the same parameter.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`stream_reset_at()`]: struct.Connection.html#method.stream_reset_at
+    pub fn enable_reset_stream_at(&mut self, v: bool) {
+        self.local_transport_params.reset_stream_at = v;
+    }
+
     /// Sets the congestion control algorithm used by string.
     ///
     /// The default value is `cubic`. On error `Error::CongestionControl`
@@ -1257,6 +1874,60 @@ impl Config {
         self.initial_congestion_window_packets = packets;
     }
 
+    /// Sets the minimum congestion window size in terms of packet count.
+    ///
+    /// The congestion window is never reduced below this size in response
+    /// to losses. Values smaller than 1 are treated as 1, as the connection
+    /// would otherwise be unable to send any packet.
+    ///
+    /// [RFC 9002] recommends a minimum window of 2 packets, which is needed
+    /// to avoid delayed acknowledgements stalling the connection. Note that
+    /// BBR and BBRv2 target a minimum window of 4 packets on their own, so
+    /// with those algorithms this value only matters while recovering from
+    /// losses.
+    ///
+    /// The default value is 2.
+    ///
+    /// [RFC 9002]: https://datatracker.ietf.org/doc/html/rfc9002#section-7.2
+    pub fn set_minimum_congestion_window_packets(&mut self, packets: usize) {
+        self.minimum_congestion_window_packets = cmp::max(packets, 1);
+    }
+
+    /// Sets a custom congestion control algorithm.
+    ///
+    /// The given function is called to create a new [`CongestionControl`]
+    /// instance for every path. When set, the algorithm selected with
+    /// [`set_cc_algorithm()`] is not used.
+    ///
+    /// [`CongestionControl`]: trait.CongestionControl.html
+    /// [`set_cc_algorithm()`]: struct.Config.html#method.set_cc_algorithm
+    pub fn set_custom_cc<F>(&mut self, factory: F)
+    where
+        F: Fn() -> Box<dyn CongestionControl> + Send + Sync + 'static,
+    {
+        self.custom_cc = Some(std::sync::Arc::new(factory));
+    }
+
+    /// Sets the factor the probe timeout (PTO) is multiplied by after each
+    /// consecutive timeout.
+    ///
+    /// Values lower than 1 are treated as 1, i.e. no backoff.
+    ///
+    /// The default value is 2.
+    pub fn set_pto_backoff_multiplier(&mut self, v: f64) {
+        self.pto_backoff_multiplier = v.max(1.0);
+    }
+
+    /// Sets the maximum number of consecutive timeouts the probe timeout
+    /// (PTO) is backed off for.
+    ///
+    /// Once reached, further timeouts keep using the last backed off value.
+    ///
+    /// The default value is 16.
+    pub fn set_max_pto_backoffs(&mut self, v: u32) {
+        self.max_pto_backoffs = v;
+    }
+
     /// Sets the congestion control algorithm used.
     ///
     /// The default value is `CongestionControlAlgorithm::CUBIC`.
@@ -1271,6 +1942,19 @@ impl Config {
         self.hystart = v;
     }
 
+    /// Configures whether to queue stream lifecycle events.
+    ///
+    /// When enabled, events can be collected using the [`stream_event_next()`]
+    /// method. Applications enabling events must collect them regularly, as
+    /// they are queued until then.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`stream_event_next()`]: struct.Connection.html#method.stream_event_next
+    pub fn enable_stream_events(&mut self, v: bool) {
+        self.stream_events = v;
+    }
+
     /// Configures whether to enable pacing.
     ///
     /// The default value is `true`.
@@ -1285,6 +1969,25 @@ impl Config {
         self.max_pacing_rate = Some(v);
     }
 
+    /// Configures whether to mark outgoing packets as ECN-capable.
+    ///
+    /// When enabled, packets are marked with ECT(0) through [`SendInfo.ecn`]
+    /// as long as the path is found to support ECN, and congestion marks
+    /// reported by the peer are treated as congestion signals. See [ECN] for
+    /// more details.
+    ///
+    /// Note that ECN marks received from the peer via [`RecvInfo.ecn`] are
+    /// always reported back to it, regardless of this setting.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`SendInfo.ecn`]: struct.SendInfo.html#structfield.ecn
+    /// [`RecvInfo.ecn`]: struct.RecvInfo.html#structfield.ecn
+    /// [ECN]: index.html#explicit-congestion-notification
+    pub fn enable_ecn(&mut self, v: bool) {
+        self.ecn = v;
+    }
+
     /// Configures whether to enable receiving DATAGRAM frames.
     ///
     /// When enabled, the `max_datagram_frame_size` transport parameter is set
@@ -1393,9 +2096,27 @@ pub struct Connection {
     /// Total number of received PATH_CHALLENGE frames.
     path_challenge_rx_count: u64,
 
+    /// Total number of packets dropped because of invalid connection IDs.
+    invalid_cid_count: u64,
+
+    /// Total number of received packets that failed authentication.
+    decrypt_fail_count: u64,
+
     /// List of supported application protocols.
     application_protos: Vec<Vec<u8>>,
 
+    /// Callback selecting the certificate to use for a given server name.
+    cert_selector: Option<tls::CertificateSelector>,
+
+    /// OCSP response to staple with the default certificate.
+    ocsp_response: Option<Arc<[u8]>>,
+
+    /// Certificate compression functions provided by the application.
+    cert_compression: Vec<tls::CertCompression>,
+
+    /// Allowed TLS 1.3 cipher suites, or empty if all are allowed.
+    cipher_suites: Vec<crypto::Algorithm>,
+
     /// Total number of received packets.
     recv_count: usize,
 
@@ -1476,9 +2197,24 @@ pub struct Connection {
     /// Idle timeout expiration time.
     idle_timer: Option<time::Instant>,
 
+    /// Whether to send keep-alive PING frames.
+    keep_alive: bool,
+
+    /// The fraction of the idle timeout after which a keep-alive is sent.
+    keep_alive_ratio: f64,
+
+    /// Keep-alive PING expiration time.
+    keep_alive_timer: Option<time::Instant>,
+
     /// Draining timeout expiration time.
     draining_timer: Option<time::Instant>,
 
+    /// Number of packets received after sending a CONNECTION_CLOSE frame.
+    closing_recv_count: u64,
+
+    /// Whether the CONNECTION_CLOSE frame needs to be sent again.
+    closing_resend: bool,
+
     /// List of raw packets that were received before they could be decrypted.
     undecryptable_pkts: VecDeque<(Vec<u8>, RecvInfo)>,
 
@@ -1519,9 +2255,21 @@ pub struct Connection {
     /// Whether the connection handshake has been confirmed.
     handshake_confirmed: bool,
 
+    /// State of the ACK frequency extension.
+    ack_freq: ackfreq::AckFrequency,
+
     /// Key phase bit used for outgoing protected packets.
     key_phase: bool,
 
+    /// Whether the latency spin bit is enabled.
+    spin_bit_enabled: bool,
+
+    /// Latency spin bit used for outgoing protected packets.
+    spin_bit: bool,
+
+    /// Callback invoked for every packet sent or received.
+    packet_trace: Option<fn(&Header, &[frame::Frame], Direction)>,
+
     /// Whether an ack-eliciting packet has been sent since last receiving a
     /// packet.
     ack_eliciting_sent: bool,
@@ -1535,9 +2283,16 @@ pub struct Connection {
     /// Whether to send GREASE.
     grease: bool,
 
+    /// Decides whether to clear the fixed bit of an outgoing short header
+    /// packet, when the QUIC bit is greased.
+    clear_fixed_bit: fn() -> bool,
+
     /// TLS keylog writer.
     keylog: Option<Box<dyn std::io::Write + Send + Sync>>,
 
+    /// Handler for loss recovery and congestion control events.
+    recovery_event_handler: Option<RecoveryEventHandler>,
+
     #[cfg(feature = "qlog")]
     qlog: QlogInfo,
 
@@ -1566,6 +2321,9 @@ pub struct Connection {
 
     /// The anti-amplification limit factor.
     max_amplification_factor: usize,
+
+    /// The required length of local Source Connection IDs, if any.
+    local_conn_id_len: Option<usize>,
 }
 
 /// Creates a new server-side connection.
@@ -1603,6 +2361,15 @@ pub fn accept(
 /// while the optional `server_name` parameter is used to verify the peer's
 /// certificate.
 ///
+/// The source connection ID can be zero-length, but it must not be longer
+/// than [`MAX_CONN_ID_LEN`], or have a different length than the one set with
+/// [`set_local_connection_id_len()`], otherwise [`InvalidState`] is returned.
+///
+/// [`MAX_CONN_ID_LEN`]: constant.MAX_CONN_ID_LEN.html
+/// [`set_local_connection_id_len()`]:
+/// struct.Config.html#method.set_local_connection_id_len
+/// [`InvalidState`]: enum.Error.html#variant.InvalidState
+///
 /// ## Examples:
 ///
 /// ```no_run
@@ -1643,8 +2410,7 @@ pub fn connect(
 /// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
 /// let (len, src) = socket.recv_from(&mut buf).unwrap();
 ///
-/// let hdr =
-///     quiche::Header::from_slice(&mut buf[..len], quiche::MAX_CONN_ID_LEN)?;
+/// let hdr = quiche::Header::from_slice(&buf[..len], quiche::MAX_CONN_ID_LEN)?;
 ///
 /// if hdr.version != quiche::PROTOCOL_VERSION {
 ///     let len = quiche::negotiate_version(&hdr.scid, &hdr.dcid, &mut out)?;
@@ -1656,7 +2422,46 @@ pub fn connect(
 pub fn negotiate_version(
     scid: &ConnectionId, dcid: &ConnectionId, out: &mut [u8],
 ) -> Result<usize> {
-    packet::negotiate_version(scid, dcid, out)
+    packet::negotiate_version(scid, dcid, &[PROTOCOL_VERSION_V1], out)
+}
+
+/// Writes a version negotiation packet advertising the given versions.
+///
+/// This is the same as [`negotiate_version()`], except that the list of
+/// versions advertised to the client is provided by the application through
+/// the `versions` parameter, rather than being the ones supported by quiche.
+///
+/// In both cases, a randomly chosen reserved version is also advertised, in
+/// order to make sure clients correctly ignore versions they don't know about.
+///
+/// [`negotiate_version()`]: fn.negotiate_version.html
+///
+/// ## Examples:
+///
+/// ```no_run
+/// # let mut buf = [0; 512];
+/// # let mut out = [0; 512];
+/// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let (len, src) = socket.recv_from(&mut buf).unwrap();
+///
+/// let hdr = quiche::Header::from_slice(&buf[..len], quiche::MAX_CONN_ID_LEN)?;
+///
+/// if hdr.version != quiche::PROTOCOL_VERSION {
+///     let len = quiche::negotiate_version_with_versions(
+///         &hdr.scid,
+///         &hdr.dcid,
+///         &[quiche::PROTOCOL_VERSION],
+///         &mut out,
+///     )?;
+///     socket.send_to(&out[..len], &src).unwrap();
+/// }
+/// # Ok::<(), quiche::Error>(())
+/// ```
+#[inline]
+pub fn negotiate_version_with_versions(
+    scid: &ConnectionId, dcid: &ConnectionId, versions: &[u32], out: &mut [u8],
+) -> Result<usize> {
+    packet::negotiate_version(scid, dcid, versions, out)
 }
 
 /// Writes a stateless retry packet.
@@ -1691,7 +2496,7 @@ pub fn negotiate_version(
 /// # }
 /// let (len, peer) = socket.recv_from(&mut buf).unwrap();
 ///
-/// let hdr = quiche::Header::from_slice(&mut buf[..len], quiche::MAX_CONN_ID_LEN)?;
+/// let hdr = quiche::Header::from_slice(&buf[..len], quiche::MAX_CONN_ID_LEN)?;
 ///
 /// let token = hdr.token.as_ref().unwrap();
 ///
@@ -1726,6 +2531,178 @@ pub fn retry(
     packet::retry(scid, dcid, new_scid, token, version, out)
 }
 
+/// Writes a stateless reset packet.
+///
+/// The `reset_token` parameter is the stateless reset token associated with
+/// the connection ID of the packet that couldn't be matched to any existing
+/// connection, and `pkt_len` is the length of that packet.
+///
+/// The generated packet is always smaller than the received one, to prevent
+/// reset loops between endpoints. If there is not enough room for a valid
+/// stateless reset, i.e. either `pkt_len` or `out` are too short, [`Done`] is
+/// returned and no packet should be sent.
+///
+/// [`Done`]: enum.Error.html#variant.Done
+///
+/// ## Examples:
+///
+/// ```no_run
+/// # let mut buf = [0; 512];
+/// # let mut out = [0; 512];
+/// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+/// # let key = [0; 32];
+/// let (len, src) = socket.recv_from(&mut buf).unwrap();
+///
+/// let hdr = quiche::Header::from_slice(&buf[..len], quiche::MAX_CONN_ID_LEN)?;
+///
+/// // No connection matches the packet's destination connection ID.
+/// let reset_token = quiche::stateless_reset_token(&key, &hdr.dcid);
+///
+/// if let Ok(v) = quiche::stateless_reset(reset_token, len, &mut out) {
+///     socket.send_to(&out[..v], &src).unwrap();
+/// }
+/// # Ok::<(), quiche::Error>(())
+/// ```
+#[inline]
+pub fn stateless_reset(
+    reset_token: u128, pkt_len: usize, out: &mut [u8],
+) -> Result<usize> {
+    packet::stateless_reset(reset_token, pkt_len, out)
+}
+
+/// Derives the stateless reset token for a connection ID from a static key.
+///
+/// The token is computed as the HMAC-SHA256 of `cid` using `key`, truncated
+/// to 16 bytes. Servers sharing the same key can thus generate and recognize
+/// each other's tokens without keeping any per-connection state.
+///
+/// The `key` must be kept secret and should be at least 32 bytes long, as
+/// anyone knowing it can forge stateless resets for any connection.
+///
+/// ## Examples:
+///
+/// ```no_run
+/// # let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+/// # let key = [0; 32];
+/// # let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+/// let token = quiche::stateless_reset_token(&key, &scid);
+///
+/// config.set_stateless_reset_token(Some(token));
+/// # Ok::<(), quiche::Error>(())
+/// ```
+#[inline]
+pub fn stateless_reset_token(key: &[u8], cid: &[u8]) -> u128 {
+    packet::stateless_reset_token(key, cid)
+}
+
+/// Derives the client and server Initial secrets for the given connection ID.
+///
+/// The `dcid` parameter is the destination connection ID of the first Initial
+/// packet sent by the client. The returned tuple contains the client's secret
+/// followed by the server's, which are used with [`AES128_GCM`] to protect
+/// Initial packets sent by each side, e.g. through
+/// [`PacketKey::from_secret()`].
+///
+/// [`AES128_GCM`]: enum.CipherAlgorithm.html#variant.AES128_GCM
+/// [`PacketKey::from_secret()`]: struct.PacketKey.html#method.from_secret
+///
+/// ## Examples:
+///
+/// ```no_run
+/// # let odcid = [0xba; 16];
+/// let (client_secret, server_secret) =
+///     quiche::derive_initial_secrets(&odcid, quiche::PROTOCOL_VERSION)?;
+///
+/// let client_key = quiche::PacketKey::from_secret(
+///     quiche::CipherAlgorithm::AES128_GCM,
+///     &client_secret,
+/// )?;
+/// # Ok::<(), quiche::Error>(())
+/// ```
+#[inline]
+pub fn derive_initial_secrets(
+    dcid: &[u8], version: u32,
+) -> Result<([u8; 32], [u8; 32])> {
+    crypto::derive_initial_secrets(dcid, version)
+}
+
+/// Removes packet protection from a single QUIC packet.
+///
+/// This is meant for offline tooling, such as dissectors or replay analysis,
+/// that needs to decrypt packets using keys derived outside of a
+/// [`Connection`], i.e. Initial keys or traffic secrets exported in the keylog
+/// format.
+///
+/// The `dcid_len` parameter is the length of the destination connection ID,
+/// which is needed to parse short header packets, and `largest_pn` is the
+/// largest packet number successfully decrypted so far in the same packet
+/// number space (or `0`), which is needed to recover the full packet number.
+///
+/// The packet is decrypted in place. On success the packet's header, with the
+/// full packet number, the decrypted payload and the length of the packet in
+/// `buf` are returned. When `buf` contains coalesced packets, the latter can be
+/// used to decrypt the next packet.
+///
+/// Note that key updates are not handled, as that requires a key for each key
+/// phase.
+///
+/// [`Connection`]: struct.Connection.html
+///
+/// ## Examples:
+///
+/// ```no_run
+/// # let mut buf = [0; 1350];
+/// # let odcid = [0xba; 16];
+/// let key =
+///     quiche::PacketKey::initial(&odcid, quiche::PROTOCOL_VERSION, false)?;
+///
+/// let (hdr, payload, len) = quiche::decrypt_packet(&mut buf, 0, 0, &key)?;
+///
+/// let (frame, _) = quiche::frame::Frame::from_slice(payload, hdr.ty)?;
+/// # Ok::<(), quiche::Error>(())
+/// ```
+#[inline]
+pub fn decrypt_packet<'a>(
+    buf: &'a mut [u8], dcid_len: usize, largest_pn: u64, key: &PacketKey,
+) -> Result<(Header<'static>, &'a [u8], usize)> {
+    packet::decrypt_packet(buf, dcid_len, largest_pn, key)
+}
+
+/// Splits a UDP datagram into the QUIC packets coalesced in it.
+///
+/// The returned iterator yields a slice for each packet, which can be parsed
+/// with [`Header::from_slice()`] for example. If a packet is malformed an
+/// error is returned and the iteration stops, as the boundaries of the
+/// following packets can't be known.
+///
+/// Note that short header packets always extend to the end of the datagram.
+///
+/// When using GRO, the buffer returned by the kernel needs to be split into
+/// the individual datagrams first, using the segment size reported by the
+/// kernel.
+///
+/// [`Header::from_slice()`]: struct.Header.html#method.from_slice
+///
+/// ## Examples:
+///
+/// ```no_run
+/// # let buf = [0; 65535];
+/// # let len = 0;
+/// # let segment_size = 1350;
+/// for dgram in buf[..len].chunks(segment_size) {
+///     for pkt in quiche::split_datagram(dgram) {
+///         let pkt = pkt?;
+///
+///         let hdr = quiche::Header::from_slice(pkt, quiche::MAX_CONN_ID_LEN)?;
+///     }
+/// }
+/// # Ok::<(), quiche::Error>(())
+/// ```
+#[inline]
+pub fn split_datagram(buf: &[u8]) -> DatagramIter<'_> {
+    packet::split_datagram(buf)
+}
+
 /// Returns true if the given protocol version is supported.
 #[inline]
 pub fn version_is_supported(version: u32) -> bool {
@@ -1744,7 +2721,7 @@ macro_rules! push_frame_to_pkt {
 
             $frame.to_bytes(&mut $out)?;
 
-            $frames.push($frame);
+            $frames.push($frame.into());
 
             true
         } else {
@@ -1793,6 +2770,10 @@ const QLOG_METRICS: EventType =
 const QLOG_CONNECTION_CLOSED: EventType =
     EventType::ConnectivityEventType(ConnectivityEventType::ConnectionClosed);
 
+#[cfg(feature = "qlog")]
+const QLOG_KEY_UPDATED: EventType =
+    EventType::SecurityEventType(SecurityEventType::KeyUpdated);
+
 #[cfg(feature = "qlog")]
 struct QlogInfo {
     streamer: Option<qlog::streamer::QlogStreamer>,
@@ -1824,6 +2805,14 @@ impl Connection {
         scid: &ConnectionId, odcid: Option<&ConnectionId>, local: SocketAddr,
         peer: SocketAddr, config: &Config, tls: tls::Handshake, is_server: bool,
     ) -> Result<Connection> {
+        if scid.len() > MAX_CONN_ID_LEN ||
+            config
+                .local_conn_id_len
+                .map_or(false, |len| scid.len() != len)
+        {
+            return Err(Error::InvalidState);
+        }
+
         let max_rx_data = config.local_transport_params.initial_max_data;
 
         let scid_as_hex: Vec<String> =
@@ -1897,8 +2886,20 @@ impl Connection {
                 .path_challenge_recv_max_queue_len,
             path_challenge_rx_count: 0,
 
+            invalid_cid_count: 0,
+
+            decrypt_fail_count: 0,
+
             application_protos: config.application_protos.clone(),
 
+            cert_selector: config.cert_selector.clone(),
+
+            ocsp_response: config.ocsp_response.clone(),
+
+            cert_compression: config.cert_compression.clone(),
+
+            cipher_suites: config.cipher_suites.clone(),
+
             recv_count: 0,
             sent_count: 0,
             lost_count: 0,
@@ -1946,8 +2947,18 @@ impl Connection {
 
             idle_timer: None,
 
+            keep_alive: config.keep_alive,
+
+            keep_alive_ratio: config.keep_alive_ratio,
+
+            keep_alive_timer: None,
+
             draining_timer: None,
 
+            closing_recv_count: 0,
+
+            closing_resend: false,
+
             undecryptable_pkts: VecDeque::new(),
 
             alpn: Vec::new(),
@@ -1974,8 +2985,16 @@ impl Connection {
 
             handshake_confirmed: false,
 
+            ack_freq: ackfreq::AckFrequency::default(),
+
             key_phase: false,
 
+            spin_bit_enabled: config.spin_bit,
+
+            spin_bit: false,
+
+            packet_trace: config.packet_trace,
+
             ack_eliciting_sent: false,
 
             closed: false,
@@ -1984,8 +3003,12 @@ impl Connection {
 
             grease: config.grease,
 
+            clear_fixed_bit: || rand::rand_u8() & 1 == 0,
+
             keylog: None,
 
+            recovery_event_handler: None,
+
             #[cfg(feature = "qlog")]
             qlog: Default::default(),
 
@@ -2007,6 +3030,8 @@ impl Connection {
             stopped_stream_remote_count: 0,
 
             max_amplification_factor: config.max_amplification_factor,
+
+            local_conn_id_len: config.local_conn_id_len,
         };
 
         if let Some(odcid) = odcid {
@@ -2022,6 +3047,8 @@ impl Connection {
         conn.local_transport_params.initial_source_connection_id =
             Some(conn.ids.get_scid(0)?.cid.to_vec().into());
 
+        conn.streams.enable_events(config.stream_events);
+
         conn.handshake.init(is_server)?;
 
         conn.handshake
@@ -2070,6 +3097,36 @@ impl Connection {
         self.keylog = Some(writer);
     }
 
+    /// Sets the handler for loss recovery and congestion control events.
+    ///
+    /// The handler is called with a [`RecoveryEvent`] when an RTT sample is
+    /// taken, when packets are declared lost, and when the congestion window
+    /// changes, on any path. It is called synchronously while processing
+    /// incoming packets and timeouts, so it should return quickly.
+    ///
+    /// [`RecoveryEvent`]: enum.RecoveryEvent.html
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+    /// # let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    /// # let peer = "127.0.0.1:1234".parse().unwrap();
+    /// # let local = "127.0.0.1:4321".parse().unwrap();
+    /// # let mut conn = quiche::accept(&scid, None, local, peer, &mut config)?;
+    /// conn.set_recovery_event_handler(Box::new(|ev| {
+    ///     if let quiche::RecoveryEvent::CongestionWindowUpdated { cwnd, .. } =
+    ///         ev
+    ///     {
+    ///         println!("cwnd is now {} bytes", cwnd);
+    ///     }
+    /// }));
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn set_recovery_event_handler(&mut self, handler: RecoveryEventHandler) {
+        self.recovery_event_handler = Some(handler);
+    }
+
     /// Sets qlog output to the designated [`Writer`].
     ///
     /// Only events included in `QlogLevel::Base` are written. The serialization
@@ -2222,6 +3279,7 @@ impl Connection {
     ///     let recv_info = quiche::RecvInfo {
     ///         from,
     ///         to: local,
+    ///         ecn: quiche::Ecn::NotEct,
     ///     };
     ///
     ///     let read = match conn.recv(&mut buf[..read], recv_info) {
@@ -2273,6 +3331,24 @@ impl Connection {
             return Ok(len);
         }
 
+        // Servers must discard Initial packets carried in UDP datagrams
+        // smaller than the minimum size required from clients. As coalesced
+        // packets are sent in order, an Initial packet is always the first one
+        // in a datagram.
+        #[cfg(not(feature = "fuzzing"))]
+        if self.is_server &&
+            len < MIN_CLIENT_INITIAL_LEN &&
+            packet::Header::is_initial(buf[0])
+        {
+            trace!(
+                "{} dropped undersized Initial datagram len={}",
+                self.trace_id,
+                len,
+            );
+
+            return Ok(len);
+        }
+
         let mut done = 0;
         let mut left = len;
 
@@ -2340,24 +3416,27 @@ impl Connection {
     fn is_stateless_reset(&self, buf: &[u8]) -> bool {
         // If the packet is too small, then we just throw it away.
         let buf_len = buf.len();
-        if buf_len < 21 {
+        if buf_len < packet::MIN_STATELESS_RESET_LEN {
             return false;
         }
 
-        // TODO: we should iterate over all active destination connection IDs
-        // and check against their reset token.
-        match self.peer_transport_params.stateless_reset_token {
-            Some(token) => {
-                let token_len = 16;
-                ring::constant_time::verify_slices_are_equal(
-                    &token.to_be_bytes(),
-                    &buf[buf_len - token_len..buf_len],
-                )
-                .is_ok()
-            },
-
-            None => false,
+        // A stateless reset looks like a short header packet.
+        if packet::Header::is_long(buf[0]) {
+            return false;
         }
+
+        let token_len = 16;
+        let tail = &buf[buf_len - token_len..buf_len];
+
+        // Check against the reset tokens of all the destination connection IDs
+        // in use.
+        self.ids.active_reset_tokens().any(|token| {
+            ring::constant_time::verify_slices_are_equal(
+                &token.to_be_bytes(),
+                tail,
+            )
+            .is_ok()
+        })
     }
 
     /// Processes a single QUIC packet received from the peer.
@@ -2383,7 +3462,13 @@ impl Connection {
             return Err(Error::Done);
         }
 
-        if self.is_closed() || self.is_draining() {
+        if self.is_closed() {
+            return Err(Error::Done);
+        }
+
+        if self.is_draining() {
+            self.on_closing_recv();
+
             return Err(Error::Done);
         }
 
@@ -2395,17 +3480,19 @@ impl Connection {
 
         let buf_len = buf.len();
 
+        let has_fixed_bit = buf[0] & packet::FIXED_BIT != 0;
+
         let mut b = octets::OctetsMut::with_slice(buf);
 
-        let mut hdr = Header::from_bytes(&mut b, self.source_id().len())
-            .map_err(|e| {
-                drop_pkt_on_err(
-                    e,
-                    self.recv_count,
-                    self.is_server,
-                    &self.trace_id,
-                )
-            })?;
+        // Short header packets don't carry the length of the Destination
+        // Connection ID, so use the configured one if any.
+        let dcid_len = self
+            .local_conn_id_len
+            .unwrap_or_else(|| self.source_id().len());
+
+        let mut hdr = Header::from_bytes(&mut b, dcid_len).map_err(|e| {
+            drop_pkt_on_err(e, self.recv_count, self.is_server, &self.trace_id)
+        })?;
 
         if hdr.ty == packet::Type::VersionNegotiation {
             // Version negotiation packets can only be sent by the server.
@@ -2499,6 +3586,24 @@ impl Connection {
             return Err(Error::Done);
         }
 
+        if !self.is_valid_recv_cids(&hdr) {
+            self.invalid_cid_count += 1;
+
+            trace!(
+                "{} dropped packet with invalid cids dcid={:?} scid={:?}",
+                self.trace_id,
+                hdr.dcid,
+                hdr.scid
+            );
+
+            return Err(drop_pkt_on_err(
+                Error::InvalidConnectionId,
+                self.recv_count,
+                self.is_server,
+                &self.trace_id,
+            ));
+        }
+
         if hdr.ty == packet::Type::Retry {
             // Retry packets can only be sent by the server.
             if self.is_server {
@@ -2679,6 +3784,8 @@ impl Connection {
 
         let pn_len = hdr.pkt_num_len;
 
+        let has_reserved_bits = packet::has_reserved_bits(b.buf()[0]);
+
         trace!(
             "{} rx pkt {:?} len={} pn={} {}",
             self.trace_id,
@@ -2729,16 +3836,29 @@ impl Connection {
             }
         }
 
-        let mut payload = packet::decrypt_pkt(
-            &mut b,
-            pn,
-            pn_len,
-            payload_len,
-            aead,
-        )
-        .map_err(|e| {
-            drop_pkt_on_err(e, self.recv_count, self.is_server, &self.trace_id)
-        })?;
+        let integrity_limit = aead.alg().integrity_limit();
+
+        let mut payload =
+            match packet::decrypt_pkt(&mut b, pn, pn_len, payload_len, aead) {
+                Ok(v) => v,
+
+                Err(e) => {
+                    self.decrypt_fail_count += 1;
+
+                    // Too many forged packets were received over the lifetime
+                    // of the connection, so stop using it.
+                    if self.decrypt_fail_count >= integrity_limit {
+                        return Err(Error::AeadLimitReached);
+                    }
+
+                    return Err(drop_pkt_on_err(
+                        e,
+                        self.recv_count,
+                        self.is_server,
+                        &self.trace_id,
+                    ));
+                },
+            };
 
         if self.pkt_num_spaces[epoch].recv_pkt_num.contains(pn) {
             trace!("{} ignored duplicate packet {}", self.trace_id, pn);
@@ -2750,6 +3870,19 @@ impl Connection {
             return Err(Error::InvalidPacket);
         }
 
+        // Reserved bits are only checked once the packet has been
+        // authenticated, as otherwise they could be tampered with.
+        if has_reserved_bits {
+            return Err(Error::InvalidPacket);
+        }
+
+        // Packets with the fixed bit cleared are only valid if we advertised
+        // support for them (RFC 9287). The bit is covered by the AEAD, so it
+        // was cleared by the peer.
+        if !has_fixed_bit && !self.local_transport_params.grease_quic_bit {
+            return Err(Error::InvalidPacket);
+        }
+
         // Now that we decrypted the packet, let's see if we can map it to an
         // existing path.
         let recv_pid = if hdr.ty == packet::Type::Short && self.got_peer_conn_id {
@@ -2775,6 +3908,7 @@ impl Connection {
             trace!("{} key update verified", self.trace_id);
 
             let _ = self.pkt_num_spaces[epoch].crypto_seal.replace(seal_next);
+            self.pkt_num_spaces[epoch].crypto_seal_count = 0;
 
             let open_prev = self.pkt_num_spaces[epoch]
                 .crypto_open
@@ -2821,6 +3955,17 @@ impl Connection {
 
                 q.add_event_data_with_instant(ev_data_server, now).ok();
             });
+        } else if hdr.ty == Type::Short && hdr.key_phase == self.key_phase {
+            // After a locally-initiated key update, the first packet received
+            // with the new keys marks the point after which the previous keys
+            // are not needed anymore.
+            if let Some(key_update) = self.pkt_num_spaces[epoch]
+                .key_update
+                .as_mut()
+                .filter(|key_update| key_update.pn_on_update == u64::MAX)
+            {
+                key_update.pn_on_update = pn;
+            }
         }
 
         if !self.is_server && !self.got_peer_conn_id {
@@ -2866,6 +4011,9 @@ impl Connection {
         // whether this is a non-probing packet.
         let mut probing = true;
 
+        // Frames are only collected when a packet trace callback is set.
+        let mut trace_frames = Vec::new();
+
         // Process packet payload.
         while payload.cap() > 0 {
             let frame = frame::Frame::from_bytes(&mut payload, hdr.ty)?;
@@ -2874,6 +4022,10 @@ impl Connection {
                 qlog_frames.push(frame.to_qlog());
             });
 
+            if self.packet_trace.is_some() {
+                trace_frames.push(frame.clone());
+            }
+
             if frame.ack_eliciting() {
                 ack_elicited = true;
             }
@@ -2929,8 +4081,14 @@ impl Connection {
             }
         });
 
-        if let Some(e) = frame_processing_err {
-            // Any frame error is terminal, so now just return.
+        if let Some(cb) = self.packet_trace {
+            hdr.pkt_num = pn;
+
+            cb(&hdr, &trace_frames, Direction::Received);
+        }
+
+        if let Some(e) = frame_processing_err {
+            // Any frame error is terminal, so now just return.
             return Err(e);
         }
 
@@ -2954,14 +4112,18 @@ impl Connection {
         // Following flag used to upgrade datagram size, if probe is successful.
         let mut pmtud_probe = false;
 
+        // The max ACK delay requested by the peer, if the latest ACK_FREQUENCY
+        // frame sent was acked.
+        let mut acked_max_ack_delay = None;
+
         // Process acked frames. Note that several packets from several paths
         // might have been acked by the received packet.
         for (_, p) in self.paths.iter_mut() {
             for acked in p.recovery.get_acked_frames(epoch) {
                 match acked {
-                    frame::Frame::Ping {
+                    frame::SentFrame::Frame(frame::Frame::Ping {
                         mtu_probe: Some(mtu_probe),
-                    } => {
+                    }) => {
                         let pmtud_next = p.pmtud.get_current();
                         p.pmtud.set_current(cmp::max(pmtud_next, mtu_probe));
 
@@ -2976,7 +4138,9 @@ impl Connection {
                         );
                     },
 
-                    frame::Frame::ACK { ranges, .. } => {
+                    frame::SentFrame::Frame(frame::Frame::ACK {
+                        ranges, ..
+                    }) => {
                         // Stop acknowledging packets less than or equal to the
                         // largest acknowledged in the sent ACK frame that, in
                         // turn, got acked.
@@ -2987,14 +4151,14 @@ impl Connection {
                         }
                     },
 
-                    frame::Frame::CryptoHeader { offset, length } => {
+                    frame::SentFrame::CryptoHeader { offset, length } => {
                         self.pkt_num_spaces[epoch]
                             .crypto_stream
                             .send
                             .ack_and_drop(offset, length);
                     },
 
-                    frame::Frame::StreamHeader {
+                    frame::SentFrame::StreamHeader {
                         stream_id,
                         offset,
                         length,
@@ -3031,11 +4195,11 @@ impl Connection {
                         // stream_recv() is used.
                         if stream.is_complete() && !stream.is_readable() {
                             let local = stream.local;
-                            self.streams.collect(stream_id, local);
+                            self.streams.collect(stream_id, local, now);
                         }
                     },
 
-                    frame::Frame::HandshakeDone => {
+                    frame::SentFrame::Frame(frame::Frame::HandshakeDone) => {
                         // Explicitly set this to true, so that if the frame was
                         // already scheduled for retransmission, it is aborted.
                         self.handshake_done_sent = true;
@@ -3043,7 +4207,20 @@ impl Connection {
                         self.handshake_done_acked = true;
                     },
 
-                    frame::Frame::ResetStream { stream_id, .. } => {
+                    frame::SentFrame::Frame(frame::Frame::AckFrequency {
+                        seq_num,
+                        request_max_ack_delay,
+                        ..
+                    }) if self.ack_freq.is_latest_sent(seq_num) => {
+                        acked_max_ack_delay = Some(time::Duration::from_micros(
+                            request_max_ack_delay,
+                        ));
+                    },
+
+                    frame::SentFrame::Frame(
+                        frame::Frame::ResetStream { stream_id, .. } |
+                        frame::Frame::ResetStreamAt { stream_id, .. },
+                    ) => {
                         let stream = match self.streams.get_mut(stream_id) {
                             Some(v) => v,
 
@@ -3055,7 +4232,7 @@ impl Connection {
                         // stream_recv() is used.
                         if stream.is_complete() && !stream.is_readable() {
                             let local = stream.local;
-                            self.streams.collect(stream_id, local);
+                            self.streams.collect(stream_id, local, now);
                         }
                     },
 
@@ -3090,8 +4267,24 @@ impl Connection {
                     }
                 );
 
+                let prev_cwnd = p.recovery.cwnd();
+
                 p.recovery
                     .pmtud_update_max_datagram_size(p.pmtud.get_current());
+
+                if let Some(handler) = &mut self.recovery_event_handler {
+                    notify_cwnd_updated(handler, p, prev_cwnd);
+                }
+            }
+        }
+
+        // Once the peer acknowledged the latest requested max ACK delay, use
+        // it for computing the PTO.
+        if let Some(max_ack_delay) = acked_max_ack_delay {
+            self.recovery_config.max_ack_delay = max_ack_delay;
+
+            for (_, p) in self.paths.iter_mut() {
+                p.recovery.update_max_ack_delay(max_ack_delay);
             }
         }
 
@@ -3128,9 +4321,37 @@ impl Connection {
 
         self.pkt_num_spaces[epoch].recv_pkt_need_ack.push_item(pn);
 
+        self.pkt_num_spaces[epoch].on_ecn_received(info.ecn);
+
+        // When the peer asked for less frequent ACKs, the ACK might need to be
+        // delayed.
+        let ack_elicited = ack_elicited &&
+            (epoch != packet::Epoch::Application ||
+                self.ack_freq.on_packet_received(
+                    pn,
+                    self.pkt_num_spaces[epoch].largest_rx_pkt_num,
+                    now,
+                ));
+
         self.pkt_num_spaces[epoch].ack_elicited =
             cmp::max(self.pkt_num_spaces[epoch].ack_elicited, ack_elicited);
 
+        // The spin bit is updated based on the packet with the largest packet
+        // number received. Duplicate packets have already been discarded, so
+        // the first packet in the space is also accounted for.
+        if self.spin_bit_enabled &&
+            hdr.ty == Type::Short &&
+            pn >= self.pkt_num_spaces[epoch].largest_rx_pkt_num
+        {
+            // The server reflects the client's spin bit, while the client
+            // inverts the server's one.
+            self.spin_bit = if self.is_server {
+                hdr.spin_bit
+            } else {
+                !hdr.spin_bit
+            };
+        }
+
         self.pkt_num_spaces[epoch].largest_rx_pkt_num =
             cmp::max(self.pkt_num_spaces[epoch].largest_rx_pkt_num, pn);
 
@@ -3153,6 +4374,8 @@ impl Connection {
 
         if let Some(idle_timeout) = self.idle_timeout() {
             self.idle_timer = Some(now + idle_timeout);
+
+            self.arm_keep_alive_timer(idle_timeout, now);
         }
 
         // Update send capacity.
@@ -3336,7 +4559,7 @@ impl Connection {
             return Err(Error::BufferTooShort);
         }
 
-        if self.is_closed() || self.is_draining() {
+        if self.is_closed() || (self.is_draining() && !self.closing_resend) {
             return Err(Error::Done);
         }
 
@@ -3379,6 +4602,9 @@ impl Connection {
 
         let send_path = self.paths.get_mut(send_pid)?;
 
+        // All packets coalesced in the datagram share the same ECN codepoint.
+        let ecn = send_path.recovery.ecn_codepoint();
+
         // Update max datagram size to allow path MTU discovery probe to be sent.
         if send_path.pmtud.get_probe_status() {
             let size = if self.handshake_confirmed || self.handshake_done_sent {
@@ -3404,6 +4630,7 @@ impl Connection {
                 &mut out[done..done + left],
                 send_pid,
                 has_initial,
+                ecn,
                 now,
             ) {
                 Ok(v) => v,
@@ -3466,20 +4693,22 @@ impl Connection {
             to: send_path.peer_addr(),
 
             at: send_path.recovery.get_packet_send_time(),
+
+            ecn,
         };
 
         Ok((done, info))
     }
 
     fn send_single(
-        &mut self, out: &mut [u8], send_pid: usize, has_initial: bool,
+        &mut self, out: &mut [u8], send_pid: usize, has_initial: bool, ecn: Ecn,
         now: time::Instant,
     ) -> Result<(packet::Type, usize)> {
         if out.is_empty() {
             return Err(Error::BufferTooShort);
         }
 
-        if self.is_draining() {
+        if self.is_draining() && !self.closing_resend {
             return Err(Error::Done);
         }
 
@@ -3498,13 +4727,18 @@ impl Connection {
         };
 
         let epoch = pkt_type.to_epoch()?;
+
+        if epoch == packet::Epoch::Application {
+            self.check_confidentiality_limit()?;
+        }
+
         let pkt_space = &mut self.pkt_num_spaces[epoch];
 
         // Process lost frames. There might be several paths having lost frames.
         for (_, p) in self.paths.iter_mut() {
             for lost in p.recovery.get_lost_frames(epoch) {
                 match lost {
-                    frame::Frame::CryptoHeader { offset, length } => {
+                    frame::SentFrame::CryptoHeader { offset, length } => {
                         pkt_space.crypto_stream.send.retransmit(offset, length);
 
                         self.stream_retrans_bytes += length as u64;
@@ -3514,7 +4748,7 @@ impl Connection {
                         p.retrans_count += 1;
                     },
 
-                    frame::Frame::StreamHeader {
+                    frame::SentFrame::StreamHeader {
                         stream_id,
                         offset,
                         length,
@@ -3552,45 +4786,78 @@ impl Connection {
                         p.retrans_count += 1;
                     },
 
-                    frame::Frame::ACK { .. } => {
+                    frame::SentFrame::Frame(frame::Frame::ACK { .. }) => {
                         pkt_space.ack_elicited = true;
                     },
 
-                    frame::Frame::ResetStream {
+                    frame::SentFrame::Frame(frame::Frame::ResetStream {
                         stream_id,
                         error_code,
                         final_size,
-                    } =>
+                    }) =>
                         if self.streams.get(stream_id).is_some() {
-                            self.streams
-                                .insert_reset(stream_id, error_code, final_size);
+                            self.streams.insert_reset(
+                                stream_id, error_code, final_size, 0,
+                            );
                         },
 
+                    frame::SentFrame::Frame(frame::Frame::ResetStreamAt {
+                        stream_id,
+                        error_code,
+                        final_size,
+                        reliable_size,
+                    }) if self.streams.get(stream_id).is_some() => {
+                        self.streams.insert_reset(
+                            stream_id,
+                            error_code,
+                            final_size,
+                            reliable_size,
+                        );
+                    },
+
                     // Retransmit HANDSHAKE_DONE only if it hasn't been acked at
                     // least once already.
-                    frame::Frame::HandshakeDone if !self.handshake_done_acked => {
+                    frame::SentFrame::Frame(frame::Frame::HandshakeDone)
+                        if !self.handshake_done_acked =>
+                    {
                         self.handshake_done_sent = false;
                     },
 
-                    frame::Frame::MaxStreamData { stream_id, .. } => {
+                    frame::SentFrame::Frame(frame::Frame::AckFrequency {
+                        seq_num,
+                        ..
+                    }) => {
+                        self.ack_freq.on_frame_lost(seq_num);
+                    },
+
+                    frame::SentFrame::Frame(frame::Frame::MaxStreamData {
+                        stream_id,
+                        ..
+                    }) =>
                         if self.streams.get(stream_id).is_some() {
                             self.streams.insert_almost_full(stream_id);
-                        }
-                    },
+                        },
 
-                    frame::Frame::MaxData { .. } => {
+                    frame::SentFrame::Frame(frame::Frame::MaxData { .. }) => {
                         self.almost_full = true;
                     },
 
-                    frame::Frame::NewConnectionId { seq_num, .. } => {
+                    frame::SentFrame::Frame(frame::Frame::NewConnectionId {
+                        seq_num,
+                        ..
+                    }) => {
                         self.ids.mark_advertise_new_scid_seq(seq_num, true);
                     },
 
-                    frame::Frame::RetireConnectionId { seq_num } => {
+                    frame::SentFrame::Frame(
+                        frame::Frame::RetireConnectionId { seq_num },
+                    ) => {
                         self.ids.mark_retire_dcid_seq(seq_num, true)?;
                     },
 
-                    frame::Frame::Ping { mtu_probe } if mtu_probe.is_some() => {
+                    frame::SentFrame::Frame(frame::Frame::Ping { mtu_probe })
+                        if mtu_probe.is_some() =>
+                    {
                         p.pmtud.pmtu_probe_lost();
                     },
 
@@ -3641,7 +4908,7 @@ impl Connection {
             dcid,
             scid,
 
-            pkt_num: 0,
+            pkt_num: pn,
             pkt_num_len: pn_len,
 
             // Only clone token for Initial packets, as other packets don't have
@@ -3655,16 +4922,31 @@ impl Connection {
 
             versions: None,
             key_phase: self.key_phase,
+            spin_bit: self.spin_bit,
         };
 
         hdr.to_bytes(&mut b)?;
 
+        // Randomly clear the fixed bit of short header packets when both
+        // endpoints advertised that they accept them (RFC 9287).
+        if pkt_type == packet::Type::Short &&
+            self.local_transport_params.grease_quic_bit &&
+            self.peer_transport_params.grease_quic_bit &&
+            (self.clear_fixed_bit)()
+        {
+            let (mut first, _) = b.split_at(1)?;
+            first.as_mut()[0] &= !packet::FIXED_BIT;
+        }
+
         let hdr_trace = if log::max_level() == log::LevelFilter::Trace {
             Some(format!("{hdr:?}"))
         } else {
             None
         };
 
+        let packet_trace =
+            self.packet_trace.map(|cb| (cb, hdr.clone().into_owned()));
+
         let hdr_ty = hdr.ty;
 
         #[cfg(feature = "qlog")]
@@ -3710,7 +4992,7 @@ impl Connection {
             return Err(Error::Done);
         }
 
-        let mut frames: SmallVec<[frame::Frame; 1]> = SmallVec::new();
+        let mut frames: SmallVec<[frame::SentFrame; 1]> = SmallVec::new();
 
         let mut ack_eliciting = false;
         let mut in_flight = false;
@@ -3747,7 +5029,7 @@ impl Connection {
         // generate an ACK (if there's anything to ACK) since we're going to
         // send a packet with PING anyways, even if we haven't received anything
         // ACK eliciting.
-        if pkt_space.recv_pkt_need_ack.len() > 0 &&
+        if !pkt_space.recv_pkt_need_ack.is_empty() &&
             (pkt_space.ack_elicited || ack_elicit_required) &&
             (!is_closing ||
                 (pkt_type == Type::Handshake &&
@@ -3765,7 +5047,7 @@ impl Connection {
             let frame = frame::Frame::ACK {
                 ack_delay,
                 ranges: pkt_space.recv_pkt_need_ack.clone(),
-                ecn_counts: None, // sending ECN is not supported at this time
+                ecn_counts: pkt_space.ecn_counts(),
             };
 
             // When a PING frame needs to be sent, avoid sending the ACK if
@@ -3778,6 +5060,10 @@ impl Connection {
                 // available cwnd.
                 if push_frame_to_pkt!(b, frames, frame, left) {
                     pkt_space.ack_elicited = false;
+
+                    if epoch == packet::Epoch::Application {
+                        self.ack_freq.on_ack_sent();
+                    }
                 }
             }
         }
@@ -3935,6 +5221,28 @@ impl Connection {
                 }
             }
 
+            // Create ACK_FREQUENCY frame.
+            if let Some(frame) = self.ack_freq.frame() {
+                if push_frame_to_pkt!(b, frames, frame, left) {
+                    self.ack_freq.on_frame_sent();
+
+                    ack_eliciting = true;
+                    in_flight = true;
+                }
+            }
+
+            // Create IMMEDIATE_ACK frame.
+            if self.ack_freq.should_send_immediate_ack() {
+                let frame = frame::Frame::ImmediateAck;
+
+                if push_frame_to_pkt!(b, frames, frame, left) {
+                    self.ack_freq.on_immediate_ack_sent();
+
+                    ack_eliciting = true;
+                    in_flight = true;
+                }
+            }
+
             // Create MAX_STREAMS_BIDI frame.
             if self.streams.should_update_max_streams_bidi() {
                 let frame = frame::Frame::MaxStreamsBidi {
@@ -4060,17 +5368,26 @@ impl Connection {
                 }
             }
 
-            // Create RESET_STREAM frames as needed.
-            for (stream_id, (error_code, final_size)) in self
+            // Create RESET_STREAM and RESET_STREAM_AT frames as needed.
+            for (stream_id, (error_code, final_size, reliable_size)) in self
                 .streams
                 .reset()
                 .map(|(&k, &v)| (k, v))
-                .collect::<Vec<(u64, (u64, u64))>>()
+                .collect::<Vec<(u64, (u64, u64, u64))>>()
             {
-                let frame = frame::Frame::ResetStream {
-                    stream_id,
-                    error_code,
-                    final_size,
+                let frame = if reliable_size > 0 {
+                    frame::Frame::ResetStreamAt {
+                        stream_id,
+                        error_code,
+                        final_size,
+                        reliable_size,
+                    }
+                } else {
+                    frame::Frame::ResetStream {
+                        stream_id,
+                        error_code,
+                        final_size,
+                    }
                 };
 
                 if push_frame_to_pkt!(b, frames, frame, left) {
@@ -4136,7 +5453,8 @@ impl Connection {
 
                         if push_frame_to_pkt!(b, frames, frame, left) {
                             let pto = path.recovery.pto();
-                            self.draining_timer = Some(now + (pto * 3));
+                            self.draining_timer.get_or_insert(now + (pto * 3));
+                            self.closing_resend = false;
 
                             ack_eliciting = true;
                             in_flight = true;
@@ -4146,13 +5464,14 @@ impl Connection {
                     // Create ConnectionClose frame.
                     let frame = frame::Frame::ConnectionClose {
                         error_code: conn_err.error_code,
-                        frame_type: 0,
+                        frame_type: conn_err.frame_type.unwrap_or(0),
                         reason: conn_err.reason.clone(),
                     };
 
                     if push_frame_to_pkt!(b, frames, frame, left) {
                         let pto = path.recovery.pto();
-                        self.draining_timer = Some(now + (pto * 3));
+                        self.draining_timer.get_or_insert(now + (pto * 3));
+                        self.closing_resend = false;
 
                         ack_eliciting = true;
                         in_flight = true;
@@ -4214,7 +5533,7 @@ impl Connection {
                 // Advance the packet buffer's offset.
                 b.skip(hdr_len + len)?;
 
-                let frame = frame::Frame::CryptoHeader {
+                let frame = frame::SentFrame::CryptoHeader {
                     offset: crypto_off,
                     length: len,
                 };
@@ -4299,8 +5618,9 @@ impl Connection {
                                 // Advance the packet buffer's offset.
                                 b.skip(hdr_len + len)?;
 
-                                let frame =
-                                    frame::Frame::DatagramHeader { length: len };
+                                let frame = frame::SentFrame::DatagramHeader {
+                                    length: len,
+                                };
 
                                 if push_frame_to_pkt!(b, frames, frame, left) {
                                     ack_eliciting = true;
@@ -4399,7 +5719,7 @@ impl Connection {
                 // Advance the packet buffer's offset.
                 b.skip(hdr_len + len)?;
 
-                let frame = frame::Frame::StreamHeader {
+                let frame = frame::SentFrame::StreamHeader {
                     stream_id,
                     offset: stream_off,
                     length: len,
@@ -4532,6 +5852,22 @@ impl Connection {
             });
         }
 
+        if let Some((cb, hdr)) = packet_trace {
+            // Parse the frames back from the payload, as the data of CRYPTO,
+            // STREAM and DATAGRAM frames isn't otherwise kept around.
+            let mut payload =
+                octets::Octets::with_slice(&b.buf()[payload_offset..b.off()]);
+
+            let mut trace_frames = Vec::with_capacity(frames.len());
+
+            while payload.cap() > 0 {
+                trace_frames
+                    .push(frame::Frame::from_bytes(&mut payload, pkt_type)?);
+            }
+
+            cb(&hdr, &trace_frames, Direction::Sent);
+        }
+
         qlog_with_type!(QLOG_PACKET_TX, self.qlog, q, {
             if let Some(header) = qlog_pkt_hdr {
                 // Qlog packet raw info described at
@@ -4581,6 +5917,8 @@ impl Connection {
             aead,
         )?;
 
+        pkt_space.crypto_seal_count += 1;
+
         let sent_pkt = recovery::Sent {
             pkt_num: pn,
             frames,
@@ -4613,6 +5951,8 @@ impl Connection {
             completed: self.handshake_completed,
         };
 
+        path.recovery.on_ecn_packet_sent(pn, epoch, ecn);
+
         path.recovery.on_packet_sent(
             sent_pkt,
             epoch,
@@ -4653,6 +5993,8 @@ impl Connection {
         if ack_eliciting && !self.ack_eliciting_sent {
             if let Some(idle_timeout) = self.idle_timeout() {
                 self.idle_timer = Some(now + idle_timeout);
+
+                self.arm_keep_alive_timer(idle_timeout, now);
             }
         }
 
@@ -4740,6 +6082,60 @@ impl Connection {
     pub fn stream_recv(
         &mut self, stream_id: u64, out: &mut [u8],
     ) -> Result<(usize, bool)> {
+        self.stream_do_recv(stream_id, |stream| {
+            let (read, fin) = stream.recv.emit(out)?;
+
+            Ok((read, read, fin))
+        })
+    }
+
+    /// Reads the next contiguous chunk of stream data, without copying it.
+    ///
+    /// This is the same as [`stream_recv()`], except that the data is returned
+    /// in the same buffer it was stored in when received, which avoids a copy
+    /// for applications that would otherwise just forward it. The returned
+    /// chunk can be of any size, and it is not guaranteed that all readable
+    /// data is returned at once.
+    ///
+    /// On success the data and a flag indicating the fin state is returned as
+    /// a tuple, or [`Done`] if there is no data to read.
+    ///
+    /// [`stream_recv()`]: struct.Connection.html#method.stream_recv
+    /// [`Done`]: enum.Error.html#variant.Done
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// # let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+    /// # let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    /// # let peer = "127.0.0.1:1234".parse().unwrap();
+    /// # let local = socket.local_addr().unwrap();
+    /// # let mut conn = quiche::accept(&scid, None, local, peer, &mut config)?;
+    /// # let stream_id = 0;
+    /// while let Ok((data, fin)) = conn.stream_recv_owned(stream_id) {
+    ///     println!("Got {} bytes on stream {}", data.len(), stream_id);
+    /// }
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn stream_recv_owned(
+        &mut self, stream_id: u64,
+    ) -> Result<(Vec<u8>, bool)> {
+        self.stream_do_recv(stream_id, |stream| {
+            let (buf, fin) = stream.recv.emit_owned()?;
+            let read = buf.len();
+
+            Ok((buf, read, fin))
+        })
+    }
+
+    /// Reads data from a stream using the given `emit` function.
+    ///
+    /// The function returns the data read, its length and the fin state.
+    fn stream_do_recv<T>(
+        &mut self, stream_id: u64,
+        emit: impl FnOnce(&mut stream::Stream) -> Result<(T, usize, bool)>,
+    ) -> Result<(T, bool)> {
         // We can't read on our own unidirectional streams.
         if !stream::is_bidi(stream_id) &&
             stream::is_local(stream_id, self.is_server)
@@ -4762,7 +6158,7 @@ impl Connection {
         #[cfg(feature = "qlog")]
         let offset = stream.recv.off_front();
 
-        let (read, fin) = match stream.recv.emit(out) {
+        let (data, read, fin) = match emit(stream) {
             Ok(v) => v,
 
             Err(e) => {
@@ -4771,7 +6167,9 @@ impl Connection {
                 // the application, so we don't need to keep the stream's state
                 // anymore.
                 if stream.is_complete() {
-                    self.streams.collect(stream_id, local);
+                    let now = time::Instant::now();
+
+                    self.streams.collect(stream_id, local, now);
                 }
 
                 self.streams.remove_readable(&priority_key);
@@ -4794,7 +6192,7 @@ impl Connection {
         }
 
         if complete {
-            self.streams.collect(stream_id, local);
+            self.streams.collect(stream_id, local, time::Instant::now());
         }
 
         qlog_with_type!(QLOG_DATA_MV, self.qlog, q, {
@@ -4821,7 +6219,7 @@ impl Connection {
             self.streams.insert_readable(&priority_key);
         }
 
-        Ok((read, fin))
+        Ok((data, fin))
     }
 
     /// Writes data to a stream.
@@ -4874,6 +6272,53 @@ impl Connection {
     pub fn stream_send(
         &mut self, stream_id: u64, buf: &[u8], fin: bool,
     ) -> Result<usize> {
+        self.stream_do_send(stream_id, &[buf], fin)
+    }
+
+    /// Writes data from multiple buffers to a stream.
+    ///
+    /// This is the same as [`stream_send()`], except that the data is taken
+    /// from each of the buffers in `bufs` in order, as if they were a single
+    /// contiguous buffer. This avoids having to concatenate data coming from
+    /// separate buffers (e.g. headers and body) before writing it.
+    ///
+    /// On success the total number of bytes written is returned, which can be
+    /// lower than the combined length of the buffers when the stream doesn't
+    /// have enough capacity. In that case the fin flag is ignored.
+    ///
+    /// [`stream_send()`]: struct.Connection.html#method.stream_send
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// # let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+    /// # let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    /// # let peer = "127.0.0.1:1234".parse().unwrap();
+    /// # let local = "127.0.0.1:4321".parse().unwrap();
+    /// # let mut conn = quiche::accept(&scid, None, local, peer, &mut config)?;
+    /// # let stream_id = 0;
+    /// # let headers = b"headers";
+    /// # let body = b"body";
+    /// conn.stream_send_vectored(stream_id, &[headers, body], true)?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn stream_send_vectored(
+        &mut self, stream_id: u64, bufs: &[&[u8]], fin: bool,
+    ) -> Result<usize> {
+        if bufs.is_empty() {
+            return self.stream_send(stream_id, b"", fin);
+        }
+
+        self.stream_do_send(stream_id, bufs, fin)
+    }
+
+    /// Writes data from the given buffers to a stream, in order.
+    fn stream_do_send(
+        &mut self, stream_id: u64, bufs: &[&[u8]], fin: bool,
+    ) -> Result<usize> {
+        let len = bufs.iter().map(|b| b.len()).sum::<usize>();
+
         // We can't write on the peer's unidirectional streams.
         if !stream::is_bidi(stream_id) &&
             !stream::is_local(stream_id, self.is_server)
@@ -4886,14 +6331,15 @@ impl Connection {
         //
         // Note that this is separate from "send capacity" as that also takes
         // congestion control into consideration.
-        if self.max_tx_data - self.tx_data < buf.len() as u64 {
+        if self.max_tx_data - self.tx_data < len as u64 {
             self.blocked_limit = Some(self.max_tx_data);
         }
 
         let cap = self.tx_cap;
 
         // Get existing stream or create a new one.
-        let stream = self.get_or_create_stream(stream_id, true)?;
+        let stream =
+            self.get_or_create_stream(stream_id, true, time::Instant::now())?;
 
         #[cfg(feature = "qlog")]
         let offset = stream.send.off_back();
@@ -4909,7 +6355,7 @@ impl Connection {
         //
         // When the cap is zero, the method returns Ok(0) *only* when the passed
         // buffer is empty. We return Error::Done otherwise.
-        if cap == 0 && !buf.is_empty() {
+        if cap == 0 && len > 0 {
             if was_writable {
                 // When `stream_writable_next()` returns a stream, the writable
                 // mark is removed, but because the stream is blocked by the
@@ -4923,20 +6369,37 @@ impl Connection {
             return Err(Error::Done);
         }
 
-        let (buf, fin, blocked_by_cap) = if cap < buf.len() {
-            (&buf[..cap], false, true)
+        let (len, fin, blocked_by_cap) = if cap < len {
+            (cap, false, true)
         } else {
-            (buf, fin, false)
+            (len, fin, false)
         };
 
-        let sent = match stream.send.write(buf, fin) {
-            Ok(v) => v,
+        let mut sent = 0;
 
-            Err(e) => {
-                self.streams.remove_writable(&priority_key);
-                return Err(e);
-            },
-        };
+        // Append the buffers to the stream in order, up to `len` bytes, only
+        // setting the fin flag with the last one.
+        for buf in bufs {
+            let buf = &buf[..cmp::min(buf.len(), len - sent)];
+
+            let last = sent + buf.len() == len;
+
+            let written = match stream.send.write(buf, fin && last) {
+                Ok(v) => v,
+
+                Err(e) => {
+                    self.streams.remove_writable(&priority_key);
+                    return Err(e);
+                },
+            };
+
+            sent += written;
+
+            // Stop when the stream's flow control limit is reached.
+            if last || written < buf.len() {
+                break;
+            }
+        }
 
         let incremental = stream.incremental;
         let priority_key = Arc::clone(&stream.priority_key);
@@ -4945,9 +6408,9 @@ impl Connection {
 
         let writable = stream.is_writable();
 
-        let empty_fin = buf.is_empty() && fin;
+        let empty_fin = len == 0 && fin;
 
-        if sent < buf.len() {
+        if sent < len {
             let max_off = stream.send.max_off();
 
             if stream.send.blocked_at() != Some(max_off) {
@@ -5000,7 +6463,7 @@ impl Connection {
             q.add_event_data_with_instant(ev_data, now).ok();
         });
 
-        if sent == 0 && !buf.is_empty() {
+        if sent == 0 && len > 0 {
             return Err(Error::Done);
         }
 
@@ -5013,6 +6476,52 @@ impl Connection {
         Ok(sent)
     }
 
+    /// Opens a new locally-initiated bidirectional stream.
+    ///
+    /// The new stream is assigned the lowest stream ID that is valid for the
+    /// local endpoint and was not used yet, which is then returned. The stream
+    /// is only announced to the peer once data is sent on it.
+    ///
+    /// If the peer's bidirectional stream count limit has been reached, the
+    /// [`StreamLimit`] error is returned.
+    ///
+    /// [`StreamLimit`]: enum.Error.html#variant.StreamLimit
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+    /// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// # let peer = "127.0.0.1:1234".parse().unwrap();
+    /// # let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    /// # let local = socket.local_addr().unwrap();
+    /// # let mut conn = quiche::connect(None, &scid, local, peer, &mut config)?;
+    /// let stream_id = conn.stream_open_bidi()?;
+    /// conn.stream_send(stream_id, b"hello", true)?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn stream_open_bidi(&mut self) -> Result<u64> {
+        let stream_id = self.streams.next_local_stream_id(true, self.is_server);
+
+        self.get_or_create_stream(stream_id, true, time::Instant::now())?;
+
+        Ok(stream_id)
+    }
+
+    /// Opens a new locally-initiated unidirectional stream.
+    ///
+    /// This is the same as [`stream_open_bidi()`], but for unidirectional
+    /// streams.
+    ///
+    /// [`stream_open_bidi()`]: struct.Connection.html#method.stream_open_bidi
+    pub fn stream_open_uni(&mut self) -> Result<u64> {
+        let stream_id = self.streams.next_local_stream_id(false, self.is_server);
+
+        self.get_or_create_stream(stream_id, true, time::Instant::now())?;
+
+        Ok(stream_id)
+    }
+
     /// Sets the priority for a stream.
     ///
     /// A stream's priority determines the order in which stream data is sent
@@ -5024,9 +6533,11 @@ impl Connection {
     pub fn stream_priority(
         &mut self, stream_id: u64, urgency: u8, incremental: bool,
     ) -> Result<()> {
+        let now = time::Instant::now();
+
         // Get existing stream or create a new one, but if the stream
         // has already been closed and collected, ignore the prioritization.
-        let stream = match self.get_or_create_stream(stream_id, true) {
+        let stream = match self.get_or_create_stream(stream_id, true, now) {
             Ok(v) => v,
 
             Err(Error::Done) => return Ok(()),
@@ -5110,6 +6621,8 @@ impl Connection {
             Shutdown::Read => {
                 stream.recv.shutdown()?;
 
+                stream.local_stop_sending_error = Some(err);
+
                 if !stream.recv.is_fin() {
                     self.streams.insert_stopped(stream_id, err);
                 }
@@ -5124,6 +6637,8 @@ impl Connection {
             Shutdown::Write => {
                 let (final_size, unsent) = stream.send.shutdown()?;
 
+                stream.local_reset_error = Some(err);
+
                 // Claw back some flow control allowance from data that was
                 // buffered but not actually sent before the stream was reset.
                 self.tx_data = self.tx_data.saturating_sub(unsent);
@@ -5134,7 +6649,7 @@ impl Connection {
                 // Update send capacity.
                 self.update_tx_cap();
 
-                self.streams.insert_reset(stream_id, err, final_size);
+                self.streams.insert_reset(stream_id, err, final_size, 0);
 
                 // Once shutdown, the stream is guaranteed to be non-writable.
                 self.streams.remove_writable(&priority_key);
@@ -5147,44 +6662,132 @@ impl Connection {
         Ok(())
     }
 
-    /// Returns the stream's send capacity in bytes.
-    ///
-    /// If the specified stream doesn't exist (including when it has already
-    /// been completed and closed), the [`InvalidStreamState`] error will be
-    /// returned.
-    ///
-    /// In addition, if the peer has signalled that it doesn't want to receive
-    /// any more data from this stream by sending the `STOP_SENDING` frame, the
-    /// [`StreamStopped`] error will be returned.
+    /// Resets the write side of a stream, while still delivering the data
+    /// written up to the given offset.
     ///
-    /// [`InvalidStreamState`]: enum.Error.html#variant.InvalidStreamState
-    /// [`StreamStopped`]: enum.Error.html#variant.StreamStopped
-    #[inline]
-    pub fn stream_capacity(&self, stream_id: u64) -> Result<usize> {
-        if let Some(stream) = self.streams.get(stream_id) {
-            let cap = cmp::min(self.tx_cap, stream.send.cap()?);
-            return Ok(cap);
-        };
-
-        Err(Error::InvalidStreamState(stream_id))
-    }
-
-    /// Returns the next stream that has data to read.
+    /// This is the same as calling [`stream_shutdown()`] with
+    /// [`Shutdown::Write`], except that the peer is guaranteed to receive the
+    /// first `reliable_size` bytes of the stream before the reset is reported
+    /// to its application. Data past that offset is discarded.
     ///
-    /// Note that once returned by this method, a stream ID will not be returned
-    /// again until it is "re-armed".
+    /// This requires the peer to have advertised support for `RESET_STREAM_AT`
+    /// frames, otherwise the [`InvalidState`] error is returned. If the given
+    /// offset is beyond the data written to the stream so far, the
+    /// [`InvalidStreamState`] error is returned.
     ///
-    /// The application will need to read all of the pending data on the stream,
-    /// and new data has to be received before the stream is reported again.
+    /// ## Examples:
     ///
-    /// This is unlike the [`readable()`] method, that returns the same list of
-    /// readable streams when called multiple times in succession.
+    /// ```no_run
+    /// # let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+    /// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// # let peer = "127.0.0.1:1234".parse().unwrap();
+    /// # let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    /// # let local = socket.local_addr().unwrap();
+    /// # let mut conn = quiche::accept(&scid, None, local, peer, &mut config)?;
+    /// # let stream_id = 0;
+    /// // Make sure the stream header is delivered before aborting.
+    /// conn.stream_reset_at(stream_id, 0, 4)?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
     ///
-    /// [`readable()`]: struct.Connection.html#method.readable
-    pub fn stream_readable_next(&mut self) -> Option<u64> {
-        let priority_key = self.streams.readable.front().clone_pointer()?;
+    /// [`stream_shutdown()`]: struct.Connection.html#method.stream_shutdown
+    /// [`Shutdown::Write`]: enum.Shutdown.html#variant.Write
+    /// [`InvalidState`]: enum.Error.html#variant.InvalidState
+    /// [`InvalidStreamState`]: enum.Error.html#variant.InvalidStreamState
+    pub fn stream_reset_at(
+        &mut self, stream_id: u64, err: u64, reliable_size: u64,
+    ) -> Result<()> {
+        if !self.peer_transport_params.reset_stream_at {
+            return Err(Error::InvalidState);
+        }
 
-        self.streams.remove_readable(&priority_key);
+        // Dont' try to reset a remote unidirectional stream.
+        if !stream::is_local(stream_id, self.is_server) &&
+            !stream::is_bidi(stream_id)
+        {
+            return Err(Error::InvalidStreamState(stream_id));
+        }
+
+        // Get existing stream.
+        let stream = self.streams.get_mut(stream_id).ok_or(Error::Done)?;
+
+        if reliable_size > stream.send.off_back() {
+            return Err(Error::InvalidStreamState(stream_id));
+        }
+
+        let priority_key = Arc::clone(&stream.priority_key);
+
+        let (final_size, unsent) = stream.send.shutdown_at(reliable_size)?;
+
+        stream.local_reset_error = Some(err);
+
+        let flushable = stream.is_flushable();
+
+        // Claw back some flow control allowance from data that was buffered
+        // but not actually sent before the stream was reset.
+        self.tx_data = self.tx_data.saturating_sub(unsent);
+
+        self.tx_buffered = self.tx_buffered.saturating_sub(unsent as usize);
+
+        // Update send capacity.
+        self.update_tx_cap();
+
+        self.streams
+            .insert_reset(stream_id, err, final_size, reliable_size);
+
+        // Once shutdown, the stream is guaranteed to be non-writable.
+        self.streams.remove_writable(&priority_key);
+
+        // Data past the reliable size was dropped, so there might be nothing
+        // left to send.
+        if !flushable {
+            self.streams.remove_flushable(&priority_key);
+        }
+
+        self.reset_stream_local_count =
+            self.reset_stream_local_count.saturating_add(1);
+
+        Ok(())
+    }
+
+    /// Returns the stream's send capacity in bytes.
+    ///
+    /// If the specified stream doesn't exist (including when it has already
+    /// been completed and closed), the [`InvalidStreamState`] error will be
+    /// returned.
+    ///
+    /// In addition, if the peer has signalled that it doesn't want to receive
+    /// any more data from this stream by sending the `STOP_SENDING` frame, the
+    /// [`StreamStopped`] error will be returned.
+    ///
+    /// [`InvalidStreamState`]: enum.Error.html#variant.InvalidStreamState
+    /// [`StreamStopped`]: enum.Error.html#variant.StreamStopped
+    #[inline]
+    pub fn stream_capacity(&self, stream_id: u64) -> Result<usize> {
+        if let Some(stream) = self.streams.get(stream_id) {
+            let cap = cmp::min(self.tx_cap, stream.send.cap()?);
+            return Ok(cap);
+        };
+
+        Err(Error::InvalidStreamState(stream_id))
+    }
+
+    /// Returns the next stream that has data to read.
+    ///
+    /// Note that once returned by this method, a stream ID will not be returned
+    /// again until it is "re-armed".
+    ///
+    /// The application will need to read all of the pending data on the stream,
+    /// and new data has to be received before the stream is reported again.
+    ///
+    /// This is unlike the [`readable()`] method, that returns the same list of
+    /// readable streams when called multiple times in succession.
+    ///
+    /// [`readable()`]: struct.Connection.html#method.readable
+    pub fn stream_readable_next(&mut self) -> Option<u64> {
+        let priority_key = self.streams.readable.front().clone_pointer()?;
+
+        self.streams.remove_readable(&priority_key);
 
         Some(priority_key.id)
     }
@@ -5335,6 +6938,25 @@ impl Connection {
         stream.recv.is_fin()
     }
 
+    /// Collects and returns statistics about the specified stream.
+    ///
+    /// Once both sides of the stream are complete and the stream is collected,
+    /// its final statistics are still returned, as long as it's one of the
+    /// last 1024 collected streams. Otherwise, or if the stream doesn't exist,
+    /// [`InvalidStreamState`] is returned.
+    ///
+    /// [`InvalidStreamState`]: enum.Error.html#variant.InvalidStreamState
+    pub fn stream_stats(&self, stream_id: u64) -> Result<StreamStats> {
+        if let Some(stream) = self.streams.get(stream_id) {
+            return Ok(stream.stats(time::Instant::now()));
+        }
+
+        self.streams
+            .collected_stats(stream_id)
+            .cloned()
+            .ok_or(Error::InvalidStreamState(stream_id))
+    }
+
     /// Returns the number of bidirectional streams that can be created
     /// before the peer's stream count limit is reached.
     ///
@@ -5487,6 +7109,22 @@ impl Connection {
         Ok(())
     }
 
+    /// Configures whether to send keep-alive PING frames on this connection.
+    ///
+    /// This overrides the value set with [`enable_keep_alive()`], so that
+    /// applications can only keep connections alive while they consider them
+    /// active.
+    ///
+    /// [`enable_keep_alive()`]: struct.Config.html#method.enable_keep_alive
+    pub fn set_keep_alive(&mut self, v: bool) {
+        self.keep_alive = v;
+        self.keep_alive_timer = None;
+
+        if let Some(idle_timeout) = self.idle_timeout() {
+            self.arm_keep_alive_timer(idle_timeout, time::Instant::now());
+        }
+    }
+
     /// Schedule an ack-eliciting packet on the specified path.
     ///
     /// See [`send_ack_eliciting()`] for more detail. [`InvalidState`] is
@@ -5508,6 +7146,168 @@ impl Connection {
         Ok(())
     }
 
+    /// Asks the peer to change how often it sends ACKs.
+    ///
+    /// This sends an `ACK_FREQUENCY` frame requesting the peer to only send
+    /// an ACK after receiving more than `ack_eliciting_threshold`
+    /// ack-eliciting packets, or after `max_ack_delay` has elapsed since an
+    /// unacknowledged ack-eliciting packet was received, whichever comes
+    /// first. When `reordering_threshold` is not zero, the peer also sends
+    /// an ACK immediately when that many packets are received after a missing
+    /// one.
+    ///
+    /// Once the peer acknowledges the request, `max_ack_delay` replaces the
+    /// peer's `max_ack_delay` transport parameter for computing the PTO.
+    ///
+    /// [`InvalidState`] is returned if the peer didn't advertise support for
+    /// the ACK frequency extension, or if `max_ack_delay` is lower than the
+    /// peer's `min_ack_delay` transport parameter.
+    ///
+    /// [`InvalidState`]: enum.Error.html#variant.InvalidState
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut buf = [0; 512];
+    /// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// # let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+    /// # let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    /// # let peer = "127.0.0.1:1234".parse().unwrap();
+    /// # let local = socket.local_addr().unwrap();
+    /// # let mut conn = quiche::accept(&scid, None, local, peer, &mut config)?;
+    /// let max_ack_delay = std::time::Duration::from_millis(50);
+    ///
+    /// conn.send_ack_frequency(10, max_ack_delay, 1)?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn send_ack_frequency(
+        &mut self, ack_eliciting_threshold: u64, max_ack_delay: time::Duration,
+        reordering_threshold: u64,
+    ) -> Result<()> {
+        let min_ack_delay = self
+            .peer_transport_params
+            .min_ack_delay
+            .ok_or(Error::InvalidState)?;
+
+        let max_ack_delay = max_ack_delay.as_micros() as u64;
+
+        if max_ack_delay < min_ack_delay {
+            return Err(Error::InvalidState);
+        }
+
+        self.ack_freq.request(
+            ack_eliciting_threshold,
+            max_ack_delay,
+            reordering_threshold,
+        );
+
+        Ok(())
+    }
+
+    /// Asks the peer to send an ACK immediately.
+    ///
+    /// This sends an `IMMEDIATE_ACK` frame. [`InvalidState`] is returned if
+    /// the peer didn't advertise support for the ACK frequency extension.
+    ///
+    /// [`InvalidState`]: enum.Error.html#variant.InvalidState
+    pub fn send_immediate_ack(&mut self) -> Result<()> {
+        if self.peer_transport_params.min_ack_delay.is_none() {
+            return Err(Error::InvalidState);
+        }
+
+        self.ack_freq.request_immediate_ack();
+
+        Ok(())
+    }
+
+    /// Initiates a 1-RTT key update.
+    ///
+    /// Packets sent after this call are protected with the next generation of
+    /// 1-RTT keys, and have the key phase bit flipped. The previous keys are
+    /// kept around for a while, in order to decrypt delayed packets from the
+    /// peer.
+    ///
+    /// [`InvalidState`] is returned if the handshake is not confirmed yet, or
+    /// if the keys from a previous key update have not been discarded yet.
+    ///
+    /// [`InvalidState`]: enum.Error.html#variant.InvalidState
+    pub fn update_key(&mut self) -> Result<()> {
+        if !self.handshake_confirmed {
+            return Err(Error::InvalidState);
+        }
+
+        let now = time::Instant::now();
+
+        let pto = self.paths.get_active()?.recovery.pto();
+
+        let space = &mut self.pkt_num_spaces[packet::Epoch::Application];
+
+        if space.key_update.is_some() {
+            return Err(Error::InvalidState);
+        }
+
+        let open_next = space
+            .crypto_open
+            .as_ref()
+            .ok_or(Error::InvalidState)?
+            .derive_next_packet_key()?;
+
+        let seal_next = space
+            .crypto_seal
+            .as_ref()
+            .ok_or(Error::InvalidState)?
+            .derive_next_packet_key()?;
+
+        let _ = space.crypto_seal.replace(seal_next);
+        space.crypto_seal_count = 0;
+
+        let open_prev = space.crypto_open.replace(open_next).unwrap();
+
+        trace!("{} local key update", self.trace_id);
+
+        // The packet number that triggered the update is not known until the
+        // peer responds with the new keys, so until then all packets using the
+        // previous key phase are decrypted with the previous keys.
+        space.key_update = Some(packet::KeyUpdate {
+            crypto_open: open_prev,
+            pn_on_update: u64::MAX,
+            update_acked: false,
+            timer: now + (pto * 3),
+        });
+
+        self.key_phase = !self.key_phase;
+
+        qlog_with_type!(QLOG_KEY_UPDATED, self.qlog, q, {
+            let trigger = Some(
+                qlog::events::security::KeyUpdateOrRetiredTrigger::LocalUpdate,
+            );
+
+            let ev_data_client =
+                EventData::KeyUpdated(qlog::events::security::KeyUpdated {
+                    key_type: qlog::events::security::KeyType::Client1RttSecret,
+                    old: None,
+                    new: String::new(),
+                    generation: None,
+                    trigger: trigger.clone(),
+                });
+
+            q.add_event_data_with_instant(ev_data_client, now).ok();
+
+            let ev_data_server =
+                EventData::KeyUpdated(qlog::events::security::KeyUpdated {
+                    key_type: qlog::events::security::KeyType::Server1RttSecret,
+                    old: None,
+                    new: String::new(),
+                    generation: None,
+                    trigger,
+                });
+
+            q.add_event_data_with_instant(ev_data_server, now).ok();
+        });
+
+        Ok(())
+    }
+
     /// Reads the first received DATAGRAM.
     ///
     /// On success the DATAGRAM's data is returned along with its size.
@@ -5823,7 +7623,13 @@ impl Connection {
                 .as_ref()
                 .map(|key_update| key_update.timer);
 
-            let timers = [self.idle_timer, path_timer, key_update_timer];
+            let timers = [
+                self.idle_timer,
+                self.keep_alive_timer,
+                path_timer,
+                key_update_timer,
+                self.ack_freq.ack_timer(),
+            ];
 
             timers.iter().filter_map(|&x| x).min()
         }
@@ -5876,6 +7682,18 @@ impl Connection {
             }
         }
 
+        if let Some(timer) = self.keep_alive_timer {
+            if timer <= now {
+                trace!("{} keep-alive timeout expired", self.trace_id);
+
+                self.keep_alive_timer = None;
+
+                if self.is_established() {
+                    self.send_ack_eliciting().ok();
+                }
+            }
+        }
+
         if let Some(timer) = self.pkt_num_spaces[packet::Epoch::Application]
             .key_update
             .as_ref()
@@ -5889,6 +7707,17 @@ impl Connection {
             }
         }
 
+        if let Some(timer) = self.ack_freq.ack_timer() {
+            if timer <= now {
+                trace!("{} ack timeout expired", self.trace_id);
+
+                self.pkt_num_spaces[packet::Epoch::Application].ack_elicited =
+                    true;
+
+                self.ack_freq.on_ack_sent();
+            }
+        }
+
         let handshake_status = self.handshake_status();
 
         for (_, p) in self.paths.iter_mut() {
@@ -5896,6 +7725,9 @@ impl Connection {
                 if timer <= now {
                     trace!("{} loss detection timeout expired", self.trace_id);
 
+                    let prev_cwnd = p.recovery.cwnd();
+                    let prev_rtt_samples = p.recovery.rtt_sample_count();
+
                     let (lost_packets, lost_bytes) = p.on_loss_detection_timeout(
                         handshake_status,
                         now,
@@ -5906,6 +7738,15 @@ impl Connection {
                     self.lost_count += lost_packets;
                     self.lost_bytes += lost_bytes as u64;
 
+                    notify_recovery_events(
+                        &mut self.recovery_event_handler,
+                        p,
+                        prev_cwnd,
+                        prev_rtt_samples,
+                        lost_packets,
+                        lost_bytes,
+                    );
+
                     qlog_with_type!(QLOG_METRICS, self.qlog, q, {
                         if let Some(ev_data) = p.recovery.maybe_qlog() {
                             q.add_event_data_with_instant(ev_data, now).ok();
@@ -6095,16 +7936,29 @@ impl Connection {
     ///
     /// Note that if the host uses zero-length Source Connection IDs, it cannot
     /// advertise Source Connection IDs and calling this method returns an
-    /// [`InvalidState`].
+    /// [`InvalidState`]. The same error is returned if the provided Connection
+    /// ID is empty, longer than [`MAX_CONN_ID_LEN`], or doesn't have the length
+    /// set with [`set_local_connection_id_len()`].
     ///
     /// Returns the sequence number associated to the provided Connection ID.
     ///
     /// [`scids_left()`]: struct.Connection.html#method.scids_left
     /// [`IdLimit`]: enum.Error.html#IdLimit
     /// [`InvalidState`]: enum.Error.html#InvalidState
+    /// [`MAX_CONN_ID_LEN`]: constant.MAX_CONN_ID_LEN.html
+    /// [`set_local_connection_id_len()`]:
+    /// struct.Config.html#method.set_local_connection_id_len
     pub fn new_scid(
         &mut self, scid: &ConnectionId, reset_token: u128, retire_if_needed: bool,
     ) -> Result<u64> {
+        if scid.is_empty() ||
+            scid.len() > MAX_CONN_ID_LEN ||
+            self.local_conn_id_len
+                .map_or(false, |len| scid.len() != len)
+        {
+            return Err(Error::InvalidState);
+        }
+
         self.ids.new_scid(
             scid.to_vec().into(),
             Some(reset_token),
@@ -6214,6 +8068,43 @@ impl Connection {
         self.paths.pop_event()
     }
 
+    /// Processes stream lifecycle events.
+    ///
+    /// On success it returns a [`StreamEvent`], or `None` when there are no
+    /// events to report. Events are only queued when enabled with
+    /// [`enable_stream_events()`]. Please refer to [`StreamEvent`] for the
+    /// exhaustive event list.
+    ///
+    /// Note that all events are edge-triggered, meaning that once reported they
+    /// will not be reported again by calling this method again, until the event
+    /// is re-armed.
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut buf = [0; 512];
+    /// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// # let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+    /// # let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    /// # let peer = "127.0.0.1:1234".parse().unwrap();
+    /// # let local = socket.local_addr().unwrap();
+    /// # let mut conn = quiche::accept(&scid, None, local, peer, &mut config)?;
+    /// while let Some(ev) = conn.stream_event_next() {
+    ///     if let quiche::StreamEvent::DataAvailable(id) = ev {
+    ///         while let Ok((read, _)) = conn.stream_recv(id, &mut buf) {
+    ///             println!("Got {} bytes on stream {}", read, id);
+    ///         }
+    ///     }
+    /// }
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    ///
+    /// [`StreamEvent`]: enum.StreamEvent.html
+    /// [`enable_stream_events()`]: struct.Config.html#method.enable_stream_events
+    pub fn stream_event_next(&mut self) -> Option<StreamEvent> {
+        self.streams.pop_event()
+    }
+
     /// Returns the number of source Connection IDs that are retired.
     pub fn retired_scids(&self) -> usize {
         self.ids.retired_source_cids()
@@ -6345,12 +8236,14 @@ impl Connection {
                 is_app: false,
                 error_code: 0x0c,
                 reason: vec![],
+                frame_type: None,
             });
         } else {
             self.local_error = Some(ConnectionError {
                 is_app: app,
                 error_code: err,
                 reason: reason.to_vec(),
+                frame_type: None,
             });
         }
 
@@ -6391,6 +8284,18 @@ impl Connection {
         self.handshake.peer_cert()
     }
 
+    /// Returns the OCSP response stapled by the server (if any) as a
+    /// DER-encoded buffer.
+    ///
+    /// The response is only available to clients that enabled it with
+    /// [`enable_ocsp_stapling()`], and must be validated by the application.
+    ///
+    /// [`enable_ocsp_stapling()`]: struct.Config.html#method.enable_ocsp_stapling
+    #[inline]
+    pub fn peer_ocsp_response(&self) -> Option<&[u8]> {
+        self.handshake.ocsp_response()
+    }
+
     /// Returns the peer's certificate chain (if any) as a vector of DER-encoded
     /// buffers.
     ///
@@ -6402,6 +8307,45 @@ impl Connection {
         self.handshake.peer_cert_chain()
     }
 
+    /// Derives keying material from the connection's TLS handshake.
+    ///
+    /// This uses the TLS exporter defined in [RFC 8446] to derive `len` bytes
+    /// of secret keying material bound to the connection, using the given
+    /// `label` and `context`. Both endpoints of a connection derive the same
+    /// output for the same inputs, which allows applications to authenticate
+    /// each other or to create channel-bound secrets.
+    ///
+    /// [`InvalidState`] is returned if the handshake is not completed yet.
+    ///
+    /// [RFC 8446]: https://www.rfc-editor.org/rfc/rfc8446.html#section-7.5
+    /// [`InvalidState`]: enum.Error.html#variant.InvalidState
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+    /// # let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    /// # let peer = "127.0.0.1:1234".parse().unwrap();
+    /// # let local = "127.0.0.1:4321".parse().unwrap();
+    /// # let conn = quiche::accept(&scid, None, local, peer, &mut config)?;
+    /// let secret = conn.export_keying_material(b"EXPORTER-my-protocol", b"", 32)?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn export_keying_material(
+        &self, label: &[u8], context: &[u8], len: usize,
+    ) -> Result<Vec<u8>> {
+        if !self.handshake_completed {
+            return Err(Error::InvalidState);
+        }
+
+        let mut out = vec![0; len];
+
+        self.handshake
+            .export_keying_material(&mut out, label, context)?;
+
+        Ok(out)
+    }
+
     /// Returns the serialized cryptographic session for the connection.
     ///
     /// This can be used by a client to cache a connection's session, and resume
@@ -6512,7 +8456,10 @@ impl Connection {
     /// methods as normal, until the [`is_closed()`] method returns `true`.
     ///
     /// In contrast, once `is_draining()` returns `true`, calling [`send()`]
-    /// is not required because no new outgoing packets will be generated.
+    /// is only required after [`recv()`], as the `CONNECTION_CLOSE` frame
+    /// might be sent again in response to packets received from the peer
+    /// after the connection was closed locally. No other outgoing packets will
+    /// be generated.
     ///
     /// [`recv()`]: struct.Connection.html#method.recv
     /// [`send()`]: struct.Connection.html#method.send
@@ -6583,6 +8530,7 @@ impl Connection {
             reset_stream_count_remote: self.reset_stream_remote_count,
             stopped_stream_count_remote: self.stopped_stream_remote_count,
             path_challenge_rx_count: self.path_challenge_rx_count,
+            invalid_cid_count: self.invalid_cid_count,
         }
     }
 
@@ -6602,33 +8550,108 @@ impl Connection {
         self.paths.iter().map(|(_, p)| p.stats())
     }
 
-    /// Returns whether or not this is a server-side connection.
-    pub fn is_server(&self) -> bool {
-        self.is_server
-    }
-
-    fn encode_transport_params(&mut self) -> Result<()> {
-        let mut raw_params = [0; 128];
-
-        let raw_params = TransportParams::encode(
-            &self.local_transport_params,
-            self.is_server,
-            &mut raw_params,
-        )?;
-
-        self.handshake.set_quic_transport_params(raw_params)?;
+    /// Returns the congestion control parameters of the active path.
+    ///
+    /// They can be saved when the connection is closed, and used to seed a
+    /// later connection to the same peer with [`set_careful_resume_params()`].
+    ///
+    /// `None` is returned until an RTT sample is taken on the path.
+    ///
+    /// [`set_careful_resume_params()`]: struct.Connection.html#method.set_careful_resume_params
+    pub fn careful_resume_params(&self) -> Option<CarefulResumeParams> {
+        let path = self.paths.get_active().ok()?;
 
-        Ok(())
+        Some(CarefulResumeParams {
+            cwnd: path.recovery.cwnd(),
+            rtt: path.recovery.min_rtt()?,
+            delivery_rate: path.recovery.delivery_rate(),
+        })
     }
 
-    fn parse_peer_transport_params(
-        &mut self, peer_params: TransportParams,
-    ) -> Result<()> {
-        // Validate initial_source_connection_id.
-        match &peer_params.initial_source_connection_id {
-            Some(v) if v != &self.destination_id() =>
-                return Err(Error::InvalidTransportParam),
-
+    /// Seeds the active path with congestion control parameters saved from a
+    /// previous connection to the same peer.
+    ///
+    /// The parameters are used following the careful resume algorithm. The
+    /// congestion window is raised to half of the saved one, but only if the
+    /// first RTT sample is comparable to the saved RTT. If congestion is
+    /// detected before the packets sent with the raised window are acked, the
+    /// window is reduced to half of the data acked so far.
+    ///
+    /// Since the congestion window is changed directly, this is only effective
+    /// with the Reno and CUBIC congestion control algorithms. Applications
+    /// should also discard saved parameters after a while, as the path's
+    /// capacity can change over time.
+    ///
+    /// This needs to be called before an RTT sample is taken, typically right
+    /// after the connection is created, otherwise [`InvalidState`] is
+    /// returned. [`InvalidState`] is also returned if the parameters are
+    /// unusable.
+    ///
+    /// [`InvalidState`]: enum.Error.html#variant.InvalidState
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+    /// # let server_name = "quic.tech";
+    /// # let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    /// # let peer = "127.0.0.1:1234".parse().unwrap();
+    /// # let local = "127.0.0.1:4321".parse().unwrap();
+    /// # let saved = quiche::CarefulResumeParams {
+    /// #     cwnd: 100_000,
+    /// #     rtt: std::time::Duration::from_millis(50),
+    /// #     delivery_rate: 0,
+    /// # };
+    /// let mut conn =
+    ///     quiche::connect(Some(&server_name), &scid, local, peer, &mut config)?;
+    /// conn.set_careful_resume_params(&saved)?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn set_careful_resume_params(
+        &mut self, params: &CarefulResumeParams,
+    ) -> Result<()> {
+        if params.cwnd == 0 || params.rtt.is_zero() {
+            return Err(Error::InvalidState);
+        }
+
+        let path = self.paths.get_active_mut()?;
+
+        if path.recovery.min_rtt().is_some() {
+            return Err(Error::InvalidState);
+        }
+
+        path.recovery.set_careful_resume_params(params);
+
+        Ok(())
+    }
+
+    /// Returns whether or not this is a server-side connection.
+    pub fn is_server(&self) -> bool {
+        self.is_server
+    }
+
+    fn encode_transport_params(&mut self) -> Result<()> {
+        let mut raw_params = [0; 128];
+
+        let raw_params = TransportParams::encode(
+            &self.local_transport_params,
+            self.is_server,
+            &mut raw_params,
+        )?;
+
+        self.handshake.set_quic_transport_params(raw_params)?;
+
+        Ok(())
+    }
+
+    fn parse_peer_transport_params(
+        &mut self, peer_params: TransportParams,
+    ) -> Result<()> {
+        // Validate initial_source_connection_id.
+        match &peer_params.initial_source_connection_id {
+            Some(v) if v != &self.destination_id() =>
+                return Err(Error::InvalidTransportParam),
+
             Some(_) => (),
 
             // initial_source_connection_id must be sent by
@@ -6667,6 +8690,12 @@ impl Connection {
             }
         }
 
+        // The server's reset token is associated with the connection ID it
+        // picked during the handshake.
+        if let Some(reset_token) = peer_params.stateless_reset_token {
+            self.ids.set_initial_dcid_reset_token(reset_token);
+        }
+
         self.process_peer_transport_params(peer_params)?;
 
         self.parsed_peer_transport_params = true;
@@ -6696,6 +8725,8 @@ impl Connection {
 
         active_path.recovery.update_max_ack_delay(max_ack_delay);
 
+        let prev_cwnd = active_path.recovery.cwnd();
+
         if active_path.pmtud.get_probe_status() {
             active_path.recovery.pmtud_update_max_datagram_size(
                 active_path
@@ -6709,6 +8740,10 @@ impl Connection {
             );
         }
 
+        if let Some(handler) = &mut self.recovery_event_handler {
+            notify_cwnd_updated(handler, active_path, prev_cwnd);
+        }
+
         // Record the max_active_conn_id parameter advertised by the peer.
         self.ids
             .set_source_conn_id_limit(peer_params.active_conn_id_limit);
@@ -6733,6 +8768,14 @@ impl Connection {
 
             keylog: self.keylog.as_mut(),
 
+            cert_selector: self.cert_selector.as_ref(),
+
+            ocsp_response: self.ocsp_response.as_deref(),
+
+            cert_compression: &self.cert_compression,
+
+            cipher_suites: &self.cipher_suites,
+
             trace_id: &self.trace_id,
 
             is_server: self.is_server,
@@ -6890,6 +8933,7 @@ impl Connection {
                 self.streams.has_stopped() ||
                 self.ids.has_new_scids() ||
                 self.ids.has_retire_dcids() ||
+                self.ack_freq.has_pending() ||
                 send_path.pmtud.get_probe_status() ||
                 send_path.needs_ack_eliciting ||
                 send_path.probing_required())
@@ -6908,7 +8952,7 @@ impl Connection {
     /// Returns the mutable stream with the given ID if it exists, or creates
     /// a new one otherwise.
     fn get_or_create_stream(
-        &mut self, id: u64, local: bool,
+        &mut self, id: u64, local: bool, now: time::Instant,
     ) -> Result<&mut stream::Stream> {
         self.streams.get_or_create(
             id,
@@ -6916,6 +8960,7 @@ impl Connection {
             &self.peer_transport_params,
             local,
             self.is_server,
+            now,
         )
     }
 
@@ -6932,7 +8977,9 @@ impl Connection {
             frame::Frame::Ping { .. } => (),
 
             frame::Frame::ACK {
-                ranges, ack_delay, ..
+                ranges,
+                ack_delay,
+                ecn_counts,
             } => {
                 let ack_delay = ack_delay
                     .checked_mul(2_u64.pow(
@@ -6956,6 +9003,17 @@ impl Connection {
                         p.recovery.delivery_rate_update_app_limited(true);
                     }
 
+                    let prev_cwnd = p.recovery.cwnd();
+                    let prev_rtt_samples = p.recovery.rtt_sample_count();
+
+                    p.recovery.on_ecn_counts_received(
+                        &ranges,
+                        ecn_counts.as_ref(),
+                        epoch,
+                        now,
+                        &self.trace_id,
+                    );
+
                     let (lost_packets, lost_bytes, acked_bytes) =
                         p.recovery.on_ack_received(
                             &ranges,
@@ -6969,6 +9027,15 @@ impl Connection {
                     self.lost_count += lost_packets;
                     self.lost_bytes += lost_bytes as u64;
                     self.acked_bytes += acked_bytes as u64;
+
+                    notify_recovery_events(
+                        &mut self.recovery_event_handler,
+                        p,
+                        prev_cwnd,
+                        prev_rtt_samples,
+                        lost_packets,
+                        lost_bytes,
+                    );
                 }
             },
 
@@ -6977,51 +9044,29 @@ impl Connection {
                 error_code,
                 final_size,
             } => {
-                // Peer can't send on our unidirectional streams.
-                if !stream::is_bidi(stream_id) &&
-                    stream::is_local(stream_id, self.is_server)
-                {
-                    return Err(Error::InvalidStreamState(stream_id));
-                }
-
-                let max_rx_data_left = self.max_rx_data() - self.rx_data;
-
-                // Get existing stream or create a new one, but if the stream
-                // has already been closed and collected, ignore the frame.
-                //
-                // This can happen if e.g. an ACK frame is lost, and the peer
-                // retransmits another frame before it realizes that the stream
-                // is gone.
-                //
-                // Note that it makes it impossible to check if the frame is
-                // illegal, since we have no state, but since we ignore the
-                // frame, it should be fine.
-                let stream = match self.get_or_create_stream(stream_id, false) {
-                    Ok(v) => v,
-
-                    Err(Error::Done) => return Ok(()),
-
-                    Err(e) => return Err(e),
-                };
-
-                let was_readable = stream.is_readable();
-                let priority_key = Arc::clone(&stream.priority_key);
-
-                let max_off_delta =
-                    stream.recv.reset(error_code, final_size)? as u64;
-
-                if max_off_delta > max_rx_data_left {
-                    return Err(Error::FlowControl);
-                }
+                self.process_reset_stream(
+                    stream_id, error_code, final_size, 0, now,
+                )?;
+            },
 
-                if !was_readable && stream.is_readable() {
-                    self.streams.insert_readable(&priority_key);
+            frame::Frame::ResetStreamAt {
+                stream_id,
+                error_code,
+                final_size,
+                reliable_size,
+            } => {
+                // The frame can only be sent if we advertised support for it.
+                if !self.local_transport_params.reset_stream_at {
+                    return Err(Error::InvalidFrame);
                 }
 
-                self.rx_data += max_off_delta;
-
-                self.reset_stream_remote_count =
-                    self.reset_stream_remote_count.saturating_add(1);
+                self.process_reset_stream(
+                    stream_id,
+                    error_code,
+                    final_size,
+                    reliable_size,
+                    now,
+                )?;
             },
 
             frame::Frame::StopSending {
@@ -7045,13 +9090,14 @@ impl Connection {
                 // Note that it makes it impossible to check if the frame is
                 // illegal, since we have no state, but since we ignore the
                 // frame, it should be fine.
-                let stream = match self.get_or_create_stream(stream_id, false) {
-                    Ok(v) => v,
+                let stream =
+                    match self.get_or_create_stream(stream_id, false, now) {
+                        Ok(v) => v,
 
-                    Err(Error::Done) => return Ok(()),
+                        Err(Error::Done) => return Ok(()),
 
-                    Err(e) => return Err(e),
-                };
+                        Err(e) => return Err(e),
+                    };
 
                 let was_writable = stream.is_writable();
 
@@ -7059,6 +9105,8 @@ impl Connection {
 
                 // Try stopping the stream.
                 if let Ok((final_size, unsent)) = stream.send.stop(error_code) {
+                    stream.local_reset_error = Some(error_code);
+
                     // Claw back some flow control allowance from data that was
                     // buffered but not actually sent before the stream was
                     // reset.
@@ -7070,7 +9118,12 @@ impl Connection {
                     self.tx_buffered =
                         self.tx_buffered.saturating_sub(unsent as usize);
 
-                    self.streams.insert_reset(stream_id, error_code, final_size);
+                    self.streams
+                        .insert_reset(stream_id, error_code, final_size, 0);
+
+                    self.streams.notify_event(stream::StreamEvent::StopSending(
+                        stream_id, error_code,
+                    ));
 
                     if !was_writable {
                         self.streams.insert_writable(&priority_key);
@@ -7107,8 +9160,6 @@ impl Connection {
                 self.do_handshake(now)?;
             },
 
-            frame::Frame::CryptoHeader { .. } => unreachable!(),
-
             // TODO: implement stateless retry
             frame::Frame::NewToken { .. } =>
                 if self.is_server {
@@ -7135,13 +9186,14 @@ impl Connection {
                 // Note that it makes it impossible to check if the frame is
                 // illegal, since we have no state, but since we ignore the
                 // frame, it should be fine.
-                let stream = match self.get_or_create_stream(stream_id, false) {
-                    Ok(v) => v,
+                let stream =
+                    match self.get_or_create_stream(stream_id, false, now) {
+                        Ok(v) => v,
 
-                    Err(Error::Done) => return Ok(()),
+                        Err(Error::Done) => return Ok(()),
 
-                    Err(e) => return Err(e),
-                };
+                        Err(e) => return Err(e),
+                    };
 
                 // Check for the connection-level flow control limit.
                 let max_off_delta =
@@ -7156,10 +9208,24 @@ impl Connection {
 
                 let was_draining = stream.recv.is_draining();
 
+                let had_fin = stream.recv.fin_off().is_some();
+
                 stream.recv.write(data)?;
 
-                if !was_readable && stream.is_readable() {
+                let is_readable = stream.is_readable();
+                let has_fin = stream.recv.fin_off().is_some();
+
+                if !was_readable && is_readable {
                     self.streams.insert_readable(&priority_key);
+
+                    self.streams.notify_event(
+                        stream::StreamEvent::DataAvailable(stream_id),
+                    );
+                }
+
+                if !had_fin && has_fin {
+                    self.streams
+                        .notify_event(stream::StreamEvent::Finished(stream_id));
                 }
 
                 self.rx_data += max_off_delta;
@@ -7177,10 +9243,20 @@ impl Connection {
                 }
             },
 
-            frame::Frame::StreamHeader { .. } => unreachable!(),
-
             frame::Frame::MaxData { max } => {
+                let was_blocked = self.tx_data >= self.max_tx_data;
+
                 self.max_tx_data = cmp::max(self.max_tx_data, max);
+
+                // Streams that were only blocked by the connection's flow
+                // control limit can be written to again.
+                if was_blocked && self.tx_data < self.max_tx_data {
+                    for stream_id in self.streams.writable() {
+                        self.streams.notify_event(stream::StreamEvent::Writable(
+                            stream_id,
+                        ));
+                    }
+                }
             },
 
             frame::Frame::MaxStreamData { stream_id, max } => {
@@ -7201,15 +9277,17 @@ impl Connection {
                 // Note that it makes it impossible to check if the frame is
                 // illegal, since we have no state, but since we ignore the
                 // frame, it should be fine.
-                let stream = match self.get_or_create_stream(stream_id, false) {
-                    Ok(v) => v,
+                let stream =
+                    match self.get_or_create_stream(stream_id, false, now) {
+                        Ok(v) => v,
 
-                    Err(Error::Done) => return Ok(()),
+                        Err(Error::Done) => return Ok(()),
 
-                    Err(e) => return Err(e),
-                };
+                        Err(e) => return Err(e),
+                    };
 
                 let was_flushable = stream.is_flushable();
+                let was_writable = stream.is_writable();
 
                 stream.send.update_max_data(max);
 
@@ -7227,6 +9305,11 @@ impl Connection {
                 if writable {
                     self.streams.insert_writable(&priority_key);
                 }
+
+                if writable && !was_writable {
+                    self.streams
+                        .notify_event(stream::StreamEvent::Writable(stream_id));
+                }
             },
 
             frame::Frame::MaxStreamsBidi { max } => {
@@ -7348,12 +9431,15 @@ impl Connection {
             },
 
             frame::Frame::ConnectionClose {
-                error_code, reason, ..
+                error_code,
+                frame_type,
+                reason,
             } => {
                 self.peer_error = Some(ConnectionError {
                     is_app: false,
                     error_code,
                     reason,
+                    frame_type: Some(frame_type),
                 });
 
                 let path = self.paths.get_active()?;
@@ -7365,6 +9451,7 @@ impl Connection {
                     is_app: true,
                     error_code,
                     reason,
+                    frame_type: None,
                 });
 
                 let path = self.paths.get_active()?;
@@ -7384,6 +9471,41 @@ impl Connection {
                 self.drop_epoch_state(packet::Epoch::Handshake, now);
             },
 
+            frame::Frame::AckFrequency {
+                seq_num,
+                ack_eliciting_threshold,
+                request_max_ack_delay,
+                reordering_threshold,
+            } => {
+                let min_ack_delay =
+                    match self.local_transport_params.min_ack_delay {
+                        Some(v) => v,
+
+                        None => return Err(Error::InvalidState),
+                    };
+
+                // Requesting a delay lower than the advertised minimum is a
+                // protocol violation.
+                if request_max_ack_delay < min_ack_delay {
+                    return Err(Error::InvalidState);
+                }
+
+                self.ack_freq.on_frame_received(
+                    seq_num,
+                    ack_eliciting_threshold,
+                    time::Duration::from_micros(request_max_ack_delay),
+                    reordering_threshold,
+                );
+            },
+
+            frame::Frame::ImmediateAck => {
+                if self.local_transport_params.min_ack_delay.is_none() {
+                    return Err(Error::InvalidState);
+                }
+
+                self.ack_freq.on_immediate_ack_received();
+            },
+
             frame::Frame::Datagram { data } => {
                 // Close the connection if DATAGRAMs are not enabled.
                 // quiche always advertises support for 64K sized DATAGRAM
@@ -7400,10 +9522,73 @@ impl Connection {
 
                 self.dgram_recv_queue.push(data)?;
             },
+        }
+
+        Ok(())
+    }
+
+    /// Processes a RESET_STREAM or RESET_STREAM_AT frame.
+    ///
+    /// A `reliable_size` of zero is equivalent to a RESET_STREAM frame.
+    fn process_reset_stream(
+        &mut self, stream_id: u64, error_code: u64, final_size: u64,
+        reliable_size: u64, now: time::Instant,
+    ) -> Result<()> {
+        // Peer can't send on our unidirectional streams.
+        if !stream::is_bidi(stream_id) &&
+            stream::is_local(stream_id, self.is_server)
+        {
+            return Err(Error::InvalidStreamState(stream_id));
+        }
+
+        let max_rx_data_left = self.max_rx_data() - self.rx_data;
+
+        // Get existing stream or create a new one, but if the stream
+        // has already been closed and collected, ignore the frame.
+        //
+        // This can happen if e.g. an ACK frame is lost, and the peer
+        // retransmits another frame before it realizes that the stream
+        // is gone.
+        //
+        // Note that it makes it impossible to check if the frame is
+        // illegal, since we have no state, but since we ignore the
+        // frame, it should be fine.
+        let stream = match self.get_or_create_stream(stream_id, false, now) {
+            Ok(v) => v,
+
+            Err(Error::Done) => return Ok(()),
+
+            Err(e) => return Err(e),
+        };
+
+        let was_readable = stream.is_readable();
+        let was_reset = stream.recv.error().is_some();
+        let priority_key = Arc::clone(&stream.priority_key);
 
-            frame::Frame::DatagramHeader { .. } => unreachable!(),
+        let max_off_delta =
+            stream
+                .recv
+                .reset_at(error_code, final_size, reliable_size)?
+                as u64;
+
+        if max_off_delta > max_rx_data_left {
+            return Err(Error::FlowControl);
+        }
+
+        if !was_readable && stream.is_readable() {
+            self.streams.insert_readable(&priority_key);
         }
 
+        if !was_reset {
+            self.streams
+                .notify_event(stream::StreamEvent::Reset(stream_id, error_code));
+        }
+
+        self.rx_data += max_off_delta;
+
+        self.reset_stream_remote_count =
+            self.reset_stream_remote_count.saturating_add(1);
+
         Ok(())
     }
 
@@ -7426,6 +9611,36 @@ impl Connection {
         trace!("{} dropped epoch {} state", self.trace_id, epoch);
     }
 
+    /// Arms the keep-alive timer at the configured fraction of the idle
+    /// timeout, if enabled.
+    fn arm_keep_alive_timer(
+        &mut self, idle_timeout: time::Duration, now: time::Instant,
+    ) {
+        if self.keep_alive {
+            self.keep_alive_timer =
+                Some(now + idle_timeout.mul_f64(self.keep_alive_ratio));
+        }
+    }
+
+    /// Handles a packet received while the connection is closing or draining.
+    ///
+    /// If the CONNECTION_CLOSE frame was sent by us (i.e. the connection is in
+    /// the closing state), schedule sending it again so that the peer stops
+    /// retransmitting. To limit the amount of packets sent in the closing
+    /// state, this is only done when the number of received packets is a
+    /// power of two.
+    fn on_closing_recv(&mut self) {
+        if self.local_error.is_none() || self.peer_error.is_some() {
+            return;
+        }
+
+        self.closing_recv_count += 1;
+
+        if self.closing_recv_count.is_power_of_two() {
+            self.closing_resend = true;
+        }
+    }
+
     /// Returns true if the connection-level flow control needs to be updated.
     ///
     /// This happens when the new max data limit is at least double the amount
@@ -7543,6 +9758,75 @@ impl Connection {
         Ok(())
     }
 
+    /// Checks the connection IDs of a received packet against the ones in use
+    /// by the connection.
+    ///
+    /// The Destination Connection ID must be one of the Source Connection IDs
+    /// we issued, except for the client's first flight which may still carry
+    /// the original Destination Connection ID it picked. Once the peer's
+    /// Source Connection ID is known, long header packets must keep using it.
+    fn is_valid_recv_cids(&self, hdr: &Header) -> bool {
+        if hdr.ty == packet::Type::Short {
+            return self.ids.find_scid_seq(&hdr.dcid).is_some();
+        }
+
+        if !self.got_peer_conn_id {
+            // The server doesn't know anything about the client's IDs yet.
+            return self.is_server || self.ids.find_scid_seq(&hdr.dcid).is_some();
+        }
+
+        let dcid_ok = self.ids.find_scid_seq(&hdr.dcid).is_some() ||
+            (self.is_server &&
+                self.local_transport_params
+                    .original_destination_connection_id
+                    .as_ref() ==
+                    Some(&hdr.dcid));
+
+        let scid_ok = self.ids.get_dcid(0).map_or(true, |e| e.cid == hdr.scid);
+
+        dcid_ok && scid_ok
+    }
+
+    /// Initiates a key update when the number of packets protected with the
+    /// current 1-RTT keys gets close to the AEAD's confidentiality limit.
+    ///
+    /// If the limit is reached and the keys couldn't be updated in time, the
+    /// connection can't be used anymore, so it's closed without sending any
+    /// more packets.
+    fn check_confidentiality_limit(&mut self) -> Result<()> {
+        let space = &self.pkt_num_spaces[packet::Epoch::Application];
+
+        let limit = match space.crypto_seal {
+            Some(ref v) => v.alg().confidentiality_limit(),
+
+            None => return Ok(()),
+        };
+
+        if space.crypto_seal_count >= limit {
+            trace!("{} AEAD confidentiality limit reached", self.trace_id);
+
+            self.local_error = Some(ConnectionError {
+                is_app: false,
+                error_code: WireErrorCode::AeadLimitReached as u64,
+                reason: vec![],
+                frame_type: None,
+            });
+
+            self.mark_closed();
+
+            return Err(Error::Done);
+        }
+
+        if space.crypto_seal_count >= limit - limit / 4 &&
+            space.key_update.is_none() &&
+            self.handshake_confirmed
+        {
+            self.update_key()?;
+        }
+
+        Ok(())
+    }
+
     /// Selects the path that the incoming packet belongs to, or creates a new
     /// one if no existing path matches.
     fn get_or_create_recv_path_id(
@@ -7898,22 +10182,77 @@ fn drop_pkt_on_err(
     Error::Done
 }
 
-struct AddrTupleFmt(SocketAddr, SocketAddr);
+/// Handler for loss recovery and congestion control events.
+pub type RecoveryEventHandler = Box<dyn FnMut(&RecoveryEvent) + Send + Sync>;
 
-impl std::fmt::Display for AddrTupleFmt {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let AddrTupleFmt(src, dst) = &self;
+/// Reports changes to the recovery state of a path to the recovery event
+/// handler, if any.
+fn notify_recovery_events(
+    handler: &mut Option<RecoveryEventHandler>, p: &path::Path, prev_cwnd: usize,
+    prev_rtt_samples: u64, lost_packets: usize, lost_bytes: usize,
+) {
+    let handler = match handler {
+        Some(v) => v,
 
-        if src.ip().is_unspecified() || dst.ip().is_unspecified() {
-            return Ok(());
-        }
+        None => return,
+    };
 
-        f.write_fmt(format_args!("src:{src} dst:{dst}"))
-    }
-}
+    let local_addr = p.local_addr();
+    let peer_addr = p.peer_addr();
 
-/// Statistics about the connection.
-///
+    if p.recovery.rtt_sample_count() != prev_rtt_samples {
+        handler(&RecoveryEvent::RttUpdated {
+            local_addr,
+            peer_addr,
+            latest_rtt: p.recovery.latest_rtt(),
+            rtt: p.recovery.rtt(),
+        });
+    }
+
+    if lost_packets > 0 {
+        handler(&RecoveryEvent::PacketsLost {
+            local_addr,
+            peer_addr,
+            packets: lost_packets,
+            bytes: lost_bytes,
+        });
+    }
+
+    notify_cwnd_updated(handler, p, prev_cwnd);
+}
+
+/// Reports a change to the congestion window of a path to the recovery event
+/// handler.
+fn notify_cwnd_updated(
+    handler: &mut RecoveryEventHandler, p: &path::Path, prev_cwnd: usize,
+) {
+    let cwnd = p.recovery.cwnd();
+
+    if cwnd != prev_cwnd {
+        handler(&RecoveryEvent::CongestionWindowUpdated {
+            local_addr: p.local_addr(),
+            peer_addr: p.peer_addr(),
+            cwnd,
+        });
+    }
+}
+
+struct AddrTupleFmt(SocketAddr, SocketAddr);
+
+impl std::fmt::Display for AddrTupleFmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let AddrTupleFmt(src, dst) = &self;
+
+        if src.ip().is_unspecified() || dst.ip().is_unspecified() {
+            return Ok(());
+        }
+
+        f.write_fmt(format_args!("src:{src} dst:{dst}"))
+    }
+}
+
+/// Statistics about the connection.
+///
 /// A connection's statistics can be collected using the [`stats()`] method.
 ///
 /// [`stats()`]: struct.Connection.html#method.stats
@@ -7963,6 +10302,10 @@ pub struct Stats {
 
     /// The total number of PATH_CHALLENGE frames that were received.
     pub path_challenge_rx_count: u64,
+
+    /// The number of packets dropped because their connection IDs didn't
+    /// match the ones in use by the connection.
+    pub invalid_cid_count: u64,
 }
 
 impl std::fmt::Debug for Stats {
@@ -8023,6 +10366,12 @@ pub struct TransportParams {
     pub retry_source_connection_id: Option<ConnectionId<'static>>,
     /// DATAGRAM frame extension parameter, if any.
     pub max_datagram_frame_size: Option<u64>,
+    /// Whether the endpoint accepts packets with the QUIC bit cleared.
+    pub grease_quic_bit: bool,
+    /// Whether the endpoint accepts RESET_STREAM_AT frames.
+    pub reset_stream_at: bool,
+    /// ACK frequency extension parameter, if any.
+    pub min_ack_delay: Option<u64>,
     // pub preferred_address: ...,
 }
 
@@ -8046,6 +10395,9 @@ impl Default for TransportParams {
             initial_source_connection_id: None,
             retry_source_connection_id: None,
             max_datagram_frame_size: None,
+            grease_quic_bit: false,
+            reset_stream_at: false,
+            min_ack_delay: None,
         }
     }
 }
@@ -8196,11 +10548,40 @@ impl TransportParams {
                     tp.max_datagram_frame_size = Some(val.get_varint()?);
                 },
 
+                0x2ab2 => {
+                    if val.cap() != 0 {
+                        return Err(Error::InvalidTransportParam);
+                    }
+
+                    tp.grease_quic_bit = true;
+                },
+
+                0x17f7586d2cb571 => {
+                    if val.cap() != 0 {
+                        return Err(Error::InvalidTransportParam);
+                    }
+
+                    tp.reset_stream_at = true;
+                },
+
+                0xff04de1b => {
+                    tp.min_ack_delay = Some(val.get_varint()?);
+                },
+
                 // Ignore unknown parameters.
                 _ => (),
             }
         }
 
+        // The minimum ACK delay (in microseconds) can't be larger than the
+        // maximum ACK delay (in milliseconds).
+        if tp
+            .min_ack_delay
+            .map_or(false, |v| v > tp.max_ack_delay * 1000)
+        {
+            return Err(Error::InvalidTransportParam);
+        }
+
         Ok(tp)
     }
 
@@ -8358,6 +10739,23 @@ impl TransportParams {
             b.put_varint(max_datagram_frame_size)?;
         }
 
+        if tp.grease_quic_bit {
+            TransportParams::encode_param(&mut b, 0x2ab2, 0)?;
+        }
+
+        if tp.reset_stream_at {
+            TransportParams::encode_param(&mut b, 0x17f7586d2cb571, 0)?;
+        }
+
+        if let Some(min_ack_delay) = tp.min_ack_delay {
+            TransportParams::encode_param(
+                &mut b,
+                0xff04de1b,
+                octets::varint_len(min_ack_delay),
+            )?;
+            b.put_varint(min_ack_delay)?;
+        }
+
         let out_len = b.off();
 
         Ok(&mut out[..out_len])
@@ -8662,6 +11060,7 @@ pub mod testing {
             let info = RecvInfo {
                 to: server_path.peer_addr(),
                 from: server_path.local_addr(),
+                ecn: Ecn::NotEct,
             };
 
             self.client.recv(buf, info)
@@ -8672,6 +11071,7 @@ pub mod testing {
             let info = RecvInfo {
                 to: client_path.peer_addr(),
                 from: client_path.local_addr(),
+                ecn: Ecn::NotEct,
             };
 
             self.server.recv(buf, info)
@@ -8704,6 +11104,7 @@ pub mod testing {
 
             let open_prev = space.crypto_open.replace(open_next);
             space.crypto_seal.replace(seal_next);
+            space.crypto_seal_count = 0;
 
             space.key_update = Some(packet::KeyUpdate {
                 crypto_open: open_prev.unwrap(),
@@ -8725,6 +11126,7 @@ pub mod testing {
         let info = RecvInfo {
             to: active_path.local_addr(),
             from: active_path.peer_addr(),
+            ecn: Ecn::NotEct,
         };
 
         conn.recv(&mut buf[..len], info)?;
@@ -8749,6 +11151,7 @@ pub mod testing {
             let info = RecvInfo {
                 to: si.to,
                 from: si.from,
+                ecn: si.ecn,
             };
 
             conn.recv(&mut pkt, info)?;
@@ -8836,6 +11239,7 @@ pub mod testing {
             token: conn.token.clone(),
             versions: None,
             key_phase: conn.key_phase,
+            spin_bit: false,
         };
 
         hdr.to_bytes(&mut b)?;
@@ -8952,12 +11356,15 @@ mod tests {
             initial_source_connection_id: Some(b"woot woot".to_vec().into()),
             retry_source_connection_id: Some(b"retry".to_vec().into()),
             max_datagram_frame_size: Some(32),
+            grease_quic_bit: true,
+            reset_stream_at: true,
+            min_ack_delay: None,
         };
 
         let mut raw_params = [42; 256];
         let raw_params =
             TransportParams::encode(&tp, true, &mut raw_params).unwrap();
-        assert_eq!(raw_params.len(), 94);
+        assert_eq!(raw_params.len(), 106);
 
         let new_tp = TransportParams::decode(raw_params, false).unwrap();
 
@@ -8982,12 +11389,15 @@ mod tests {
             initial_source_connection_id: Some(b"woot woot".to_vec().into()),
             retry_source_connection_id: None,
             max_datagram_frame_size: Some(32),
+            grease_quic_bit: true,
+            reset_stream_at: false,
+            min_ack_delay: Some(1_000),
         };
 
         let mut raw_params = [42; 256];
         let raw_params =
             TransportParams::encode(&tp, false, &mut raw_params).unwrap();
-        assert_eq!(raw_params.len(), 69);
+        assert_eq!(raw_params.len(), 83);
 
         let new_tp = TransportParams::decode(raw_params, true).unwrap();
 
@@ -9069,7 +11479,7 @@ mod tests {
 
         let (mut len, _) = pipe.client.send(&mut buf).unwrap();
 
-        let hdr = packet::Header::from_slice(&mut buf[..len], 0).unwrap();
+        let hdr = packet::Header::from_slice(&buf[..len], 0).unwrap();
         len = crate::negotiate_version(&hdr.scid, &hdr.dcid, &mut buf).unwrap();
 
         assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
@@ -9080,6 +11490,37 @@ mod tests {
         assert_eq!(pipe.server.version, PROTOCOL_VERSION);
     }
 
+    #[test]
+    fn version_negotiation_with_versions() {
+        let mut buf = [0; 65535];
+
+        let mut config = Config::new(0xbabababa).unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.verify_peer(false);
+
+        let mut pipe = testing::Pipe::with_client_config(&mut config).unwrap();
+
+        let (mut len, _) = pipe.client.send(&mut buf).unwrap();
+
+        let hdr = packet::Header::from_slice(&buf[..len], 0).unwrap();
+        len = crate::negotiate_version_with_versions(
+            &hdr.scid,
+            &hdr.dcid,
+            &[0x1a2a3a4a, PROTOCOL_VERSION],
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
+
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert_eq!(pipe.client.version, PROTOCOL_VERSION);
+        assert_eq!(pipe.server.version, PROTOCOL_VERSION);
+    }
+
     #[test]
     fn verify_custom_root() {
         let mut config = Config::new(PROTOCOL_VERSION).unwrap();
@@ -9146,7 +11587,9 @@ mod tests {
             &mut server_config,
         )
         .unwrap();
-        assert_eq!(pipe.handshake(), Err(Error::TlsFail));
+
+        // The server sent an unknown_ca alert.
+        assert_eq!(pipe.handshake(), Err(Error::TlsAlert(48)));
 
         // Client did send a certificate.
         assert!(pipe.server.peer_cert().is_some());
@@ -9179,6 +11622,122 @@ mod tests {
         assert!(pipe.server.peer_cert().is_none());
     }
 
+    #[test]
+    fn verify_client_required() {
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
+        config.set_initial_max_streams_bidi(3);
+
+        // Anonymous clients are rejected.
+        config.require_client_cert(true);
+
+        let mut pipe = testing::Pipe::with_server_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Err(Error::TlsAlert(116)));
+
+        // Client didn't send a certificate.
+        assert!(pipe.server.peer_cert().is_none());
+
+        // The server sent a certificate_required alert.
+        let err = pipe.server.local_error().unwrap();
+        assert_eq!(err.error_code, 0x100 + 116);
+        assert_eq!(err.tls_alert(), Some(116));
+        assert!(err.reason.is_empty());
+    }
+
+    // Disable this for openssl, as peer_cert() doesn't return the client's
+    // certificate there.
+    #[cfg(not(feature = "openssl"))]
+    #[test]
+    fn verify_client_required_valid() {
+        let mut server_config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        server_config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        server_config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        server_config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        server_config
+            .load_verify_locations_from_file("examples/rootca.crt")
+            .unwrap();
+        server_config.require_client_cert(true);
+
+        let mut client_config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        client_config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        client_config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        client_config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        client_config.verify_peer(false);
+
+        let mut pipe = testing::Pipe::with_client_and_server_config(
+            &mut client_config,
+            &mut server_config,
+        )
+        .unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // The client's certificate was verified and accepted.
+        assert!(pipe.server.peer_cert().is_some());
+    }
+
+    #[test]
+    fn verify_client_not_required() {
+        let mut server_config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        server_config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        server_config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        server_config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        server_config.verify_peer(false);
+
+        // Turning the requirement off doesn't enable verification.
+        server_config.require_client_cert(true);
+        server_config.require_client_cert(false);
+
+        let mut client_config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        client_config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        client_config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        client_config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        client_config.verify_peer(false);
+
+        // The server doesn't have the CA needed to verify the client's
+        // certificate, so the handshake only succeeds if it's not verified.
+        let mut pipe = testing::Pipe::with_client_and_server_config(
+            &mut client_config,
+            &mut server_config,
+        )
+        .unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+    }
+
     #[test]
     fn missing_initial_source_connection_id() {
         let mut buf = [0; 65535];
@@ -9308,6 +11867,31 @@ mod tests {
         assert!(pipe.server.handshake_confirmed);
     }
 
+    #[test]
+    fn handshake_drop_epoch_state() {
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // Client confirms handshake after receiving HANDSHAKE_DONE.
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert!(pipe.client.handshake_confirmed);
+        assert!(pipe.server.handshake_confirmed);
+
+        // Keys and packet state for the Initial and Handshake spaces are
+        // gone on both sides.
+        for conn in [&pipe.client, &pipe.server] {
+            for epoch in [packet::Epoch::Initial, packet::Epoch::Handshake] {
+                let pkt_space = &conn.pkt_num_spaces[epoch];
+
+                assert!(!pkt_space.has_keys());
+                assert!(pkt_space.recv_pkt_need_ack.is_empty());
+                assert!(!pkt_space.ready());
+                assert!(pkt_space.ecn_counts().is_none());
+            }
+        }
+    }
+
     #[test]
     fn handshake_resumption() {
         #[cfg(not(feature = "openssl"))]
@@ -9389,7 +11973,9 @@ mod tests {
         config.verify_peer(false);
 
         let mut pipe = testing::Pipe::with_client_config(&mut config).unwrap();
-        assert_eq!(pipe.handshake(), Err(Error::TlsFail));
+
+        // The server sent a no_application_protocol alert.
+        assert_eq!(pipe.handshake(), Err(Error::TlsAlert(120)));
 
         assert_eq!(pipe.client.application_proto(), b"");
         assert_eq!(pipe.server.application_proto(), b"");
@@ -9636,6 +12222,7 @@ mod tests {
         let info = RecvInfo {
             to: active_path.local_addr(),
             from: active_path.peer_addr(),
+            ecn: Ecn::NotEct,
         };
 
         assert_eq!(
@@ -9959,54 +12546,610 @@ mod tests {
     }
 
     #[test]
-    /// Tests that receiving a MAX_STREAM_DATA frame for a receive-only
-    /// unidirectional stream is forbidden.
-    fn max_stream_data_receive_uni() {
-        let mut buf = [0; 65535];
+    fn spin_bit() {
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
+        config.set_initial_max_streams_bidi(3);
+        config.verify_peer(false);
+        config.enable_spin_bit(true);
 
-        let mut pipe = testing::Pipe::new().unwrap();
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        // Client opens unidirectional stream.
-        assert_eq!(pipe.client.stream_send(2, b"hello", false), Ok(5));
-        assert_eq!(pipe.advance(), Ok(()));
+        let spin = pipe.client.spin_bit;
 
-        // Client sends MAX_STREAM_DATA on local unidirectional stream.
-        let frames = [frame::Frame::MaxStreamData {
-            stream_id: 2,
-            max: 1024,
-        }];
+        // Server reflects the client's spin bit.
+        assert_eq!(pipe.client.stream_send(0, b"hello", false), Ok(5));
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        testing::process_flight(&mut pipe.server, flight).unwrap();
+        assert_eq!(pipe.server.spin_bit, spin);
 
-        let pkt_type = packet::Type::Short;
-        assert_eq!(
-            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
-            Err(Error::InvalidStreamState(2)),
-        );
+        // Client inverts the server's spin bit.
+        assert_eq!(pipe.server.stream_send(0, b"world", false), Ok(5));
+        let flight = testing::emit_flight(&mut pipe.server).unwrap();
+        testing::process_flight(&mut pipe.client, flight).unwrap();
+        assert_eq!(pipe.client.spin_bit, !spin);
+
+        // And so on for the next round trip.
+        assert_eq!(pipe.client.stream_send(0, b"hello", false), Ok(5));
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        testing::process_flight(&mut pipe.server, flight).unwrap();
+        assert_eq!(pipe.server.spin_bit, !spin);
     }
 
     #[test]
-    fn empty_payload() {
-        let mut buf = [0; 65535];
-
+    fn spin_bit_disabled() {
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        // Send a packet with no frames.
-        let pkt_type = packet::Type::Short;
-        assert_eq!(
-            pipe.send_pkt_to_server(pkt_type, &[], &mut buf),
-            Err(Error::InvalidPacket)
-        );
+        assert_eq!(pipe.client.stream_send(0, b"hello", false), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+        assert_eq!(pipe.server.stream_send(0, b"world", false), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert!(!pipe.client.spin_bit);
+        assert!(!pipe.server.spin_bit);
     }
 
     #[test]
-    fn min_payload() {
+    fn stateless_reset() {
         let mut buf = [0; 65535];
 
-        let mut pipe = testing::Pipe::new().unwrap();
-
-        // Send a non-ack-eliciting packet.
-        let frames = [frame::Frame::Padding { len: 4 }];
+        let reset_token = 0xba5eba11_u128;
+
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
+        config.set_initial_max_streams_bidi(3);
+        config.set_stateless_reset_token(Some(reset_token));
+
+        let mut pipe = testing::Pipe::with_server_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // A reset with the wrong token is ignored.
+        let len = super::stateless_reset(0xbad, 100, &mut buf).unwrap();
+        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
+        assert!(!pipe.client.is_closed());
+
+        let len = super::stateless_reset(reset_token, 100, &mut buf).unwrap();
+        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
+        assert!(pipe.client.is_closed());
+    }
+
+    #[test]
+    fn stateless_reset_new_connection_id() {
+        let mut buf = [0; 65535];
+
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.verify_peer(false);
+        config.set_active_connection_id_limit(2);
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
+        config.set_initial_max_streams_bidi(3);
+
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let (c_cid, c_reset_token) = testing::create_cid_and_reset_token(16);
+        assert_eq!(pipe.client.new_scid(&c_cid, c_reset_token, false), Ok(1));
+
+        let (s_cid, s_reset_token) = testing::create_cid_and_reset_token(16);
+        assert_eq!(pipe.server.new_scid(&s_cid, s_reset_token, false), Ok(1));
+
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // The token of a connection ID that isn't in use yet is ignored.
+        let len = super::stateless_reset(s_reset_token, 100, &mut buf).unwrap();
+        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
+        assert!(!pipe.client.is_closed());
+
+        // Start using the new connection ID on a new path.
+        let server_addr = testing::Pipe::server_addr();
+        let client_addr_2 = "127.0.0.1:5678".parse().unwrap();
+        assert_eq!(pipe.client.probe_path(client_addr_2, server_addr), Ok(1));
+
+        let len = super::stateless_reset(s_reset_token, 100, &mut buf).unwrap();
+        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
+        assert!(pipe.client.is_closed());
+    }
+
+    #[test]
+    fn grease_quic_bit() {
+        let mut buf = [0; 65535];
+
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
+        config.set_initial_max_streams_bidi(3);
+        config.verify_peer(false);
+        config.grease_quic_bit(true);
+
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert!(pipe.client.peer_transport_params.grease_quic_bit);
+        assert!(pipe.server.peer_transport_params.grease_quic_bit);
+
+        // Packets with the fixed bit cleared are accepted by the server.
+        pipe.client.clear_fixed_bit = || true;
+
+        assert_eq!(pipe.client.send_ack_eliciting(), Ok(()));
+        let (len, _) = pipe.client.send(&mut buf).unwrap();
+        assert_eq!(buf[0] & packet::FIXED_BIT, 0);
+
+        let recv_count = pipe.server.recv_count;
+        assert_eq!(pipe.server_recv(&mut buf[..len]), Ok(len));
+        assert_eq!(pipe.server.recv_count, recv_count + 1);
+
+        // Packets with the fixed bit set are still accepted.
+        pipe.client.clear_fixed_bit = || false;
+
+        assert_eq!(pipe.client.send_ack_eliciting(), Ok(()));
+        let (len, _) = pipe.client.send(&mut buf).unwrap();
+        assert_eq!(buf[0] & packet::FIXED_BIT, packet::FIXED_BIT);
+
+        assert_eq!(pipe.server_recv(&mut buf[..len]), Ok(len));
+        assert_eq!(pipe.server.recv_count, recv_count + 2);
+    }
+
+    #[test]
+    fn invalid_cids() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+
+        // Client sends initial flight.
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        testing::process_flight(&mut pipe.server, flight).unwrap();
+
+        // Server sends initial flight.
+        let flight = testing::emit_flight(&mut pipe.server).unwrap();
+        testing::process_flight(&mut pipe.client, flight).unwrap();
+
+        // Server uses a different source connection ID in a long header
+        // packet.
+        let written = testing::encode_pkt(
+            &mut pipe.server,
+            packet::Type::Handshake,
+            &[frame::Frame::Ping { mtu_probe: None }],
+            &mut buf,
+        )
+        .unwrap();
+
+        let scid_off = 1 + 4 + 1 + pipe.server.destination_id().len() + 1;
+        buf[scid_off] ^= 0xff;
+
+        assert_eq!(pipe.client_recv(&mut buf[..written]), Ok(written));
+        assert_eq!(pipe.client.stats().invalid_cid_count, 1);
+
+        assert_eq!(pipe.advance(), Ok(()));
+        assert!(pipe.client.is_established());
+        assert!(pipe.server.is_established());
+
+        // Client uses an unknown destination connection ID in a short header
+        // packet.
+        let written = testing::encode_pkt(
+            &mut pipe.client,
+            packet::Type::Short,
+            &[frame::Frame::Ping { mtu_probe: None }],
+            &mut buf,
+        )
+        .unwrap();
+
+        buf[1] ^= 0xff;
+
+        assert_eq!(pipe.server_recv(&mut buf[..written]), Ok(written));
+        assert_eq!(pipe.server.stats().invalid_cid_count, 1);
+        assert!(!pipe.server.is_closed());
+    }
+
+    #[test]
+    fn grease_quic_bit_not_advertised() {
+        let mut buf = [0; 65535];
+
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.verify_peer(false);
+        config.grease_quic_bit(true);
+
+        let mut pipe = testing::Pipe::with_client_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert!(!pipe.client.peer_transport_params.grease_quic_bit);
+        assert!(pipe.server.peer_transport_params.grease_quic_bit);
+
+        // The client doesn't clear the fixed bit, as the server didn't
+        // advertise support for it.
+        pipe.client.clear_fixed_bit = || true;
+
+        assert_eq!(pipe.client.send_ack_eliciting(), Ok(()));
+        let (len, _) = pipe.client.send(&mut buf).unwrap();
+        assert_eq!(buf[0] & packet::FIXED_BIT, packet::FIXED_BIT);
+        assert_eq!(pipe.server_recv(&mut buf[..len]), Ok(len));
+
+        // Force the client to clear the fixed bit anyway.
+        pipe.client.peer_transport_params.grease_quic_bit = true;
+
+        assert_eq!(pipe.client.send_ack_eliciting(), Ok(()));
+        let (len, _) = pipe.client.send(&mut buf).unwrap();
+        assert_eq!(buf[0] & packet::FIXED_BIT, 0);
+
+        assert_eq!(pipe.server_recv(&mut buf[..len]), Err(Error::InvalidPacket));
+
+        assert_eq!(
+            pipe.server.local_error.as_ref().map(|e| e.error_code),
+            Some(WireErrorCode::ProtocolViolation as u64)
+        );
+    }
+
+    #[test]
+    fn packet_trace() {
+        thread_local! {
+            static TRACED: std::cell::RefCell<Vec<(Direction, frame::Frame)>> =
+                const { std::cell::RefCell::new(Vec::new()) };
+        }
+
+        fn trace(hdr: &Header, frames: &[frame::Frame], dir: Direction) {
+            if hdr.ty != packet::Type::Short {
+                return;
+            }
+
+            TRACED.with(|t| {
+                t.borrow_mut()
+                    .extend(frames.iter().map(|f| (dir, f.clone())))
+            });
+        }
+
+        let mut buf = [0; 65535];
+
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
+        config.set_initial_max_streams_bidi(3);
+        config.verify_peer(false);
+        config.set_packet_trace(Some(trace));
+
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        TRACED.with(|t| t.borrow_mut().clear());
+
+        assert_eq!(pipe.client.stream_send(0, b"hello", true), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(pipe.server.stream_recv(0, &mut buf), Ok((5, true)));
+
+        let stream_frames: Vec<Direction> = TRACED.with(|t| {
+            t.borrow()
+                .iter()
+                .filter(|(_, f)| match f {
+                    frame::Frame::Stream { stream_id: 0, data } =>
+                        data.as_ref() == b"hello",
+
+                    _ => false,
+                })
+                .map(|(dir, _)| *dir)
+                .collect()
+        });
+
+        assert_eq!(stream_frames, vec![Direction::Sent, Direction::Received]);
+    }
+
+    #[test]
+    fn aead_confidentiality_limit() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        let epoch = packet::Epoch::Application;
+
+        let limit = pipe.client.pkt_num_spaces[epoch]
+            .crypto_seal
+            .as_ref()
+            .unwrap()
+            .alg()
+            .confidentiality_limit();
+
+        // Getting close to the limit triggers a key update.
+        pipe.client.pkt_num_spaces[epoch].crypto_seal_count = limit - limit / 4;
+
+        assert_eq!(pipe.client.stream_send(4, b"hello", false), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert!(pipe.client.key_phase);
+        assert!(pipe.server.key_phase);
+        assert!(pipe.client.pkt_num_spaces[epoch].crypto_seal_count < 10);
+
+        // Reaching the limit closes the connection without sending anything.
+        pipe.client.pkt_num_spaces[epoch].crypto_seal_count = limit;
+
+        assert_eq!(pipe.client.stream_send(4, b"world", false), Ok(5));
+        assert_eq!(pipe.client.send(&mut buf), Err(Error::Done));
+
+        assert!(pipe.client.is_closed());
+        assert_eq!(
+            pipe.client.local_error.as_ref().map(|e| e.error_code),
+            Some(WireErrorCode::AeadLimitReached as u64)
+        );
+    }
+
+    #[test]
+    fn aead_integrity_limit() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let epoch = packet::Epoch::Application;
+
+        let limit = pipe.server.pkt_num_spaces[epoch]
+            .crypto_open
+            .as_ref()
+            .unwrap()
+            .alg()
+            .integrity_limit();
+
+        let frames = [frame::Frame::Ping { mtu_probe: None }];
+
+        // Forged packets are dropped until the limit is reached.
+        let written = testing::encode_pkt(
+            &mut pipe.client,
+            packet::Type::Short,
+            &frames,
+            &mut buf,
+        )
+        .unwrap();
+        buf[written - 1] ^= 0xff;
+
+        assert_eq!(pipe.server_recv(&mut buf[..written]), Ok(written));
+        assert_eq!(pipe.server.decrypt_fail_count, 1);
+
+        pipe.server.decrypt_fail_count = limit - 1;
+
+        let written = testing::encode_pkt(
+            &mut pipe.client,
+            packet::Type::Short,
+            &frames,
+            &mut buf,
+        )
+        .unwrap();
+        buf[written - 1] ^= 0xff;
+
+        assert_eq!(
+            pipe.server_recv(&mut buf[..written]),
+            Err(Error::AeadLimitReached)
+        );
+        assert_eq!(
+            pipe.server.local_error.as_ref().map(|e| e.error_code),
+            Some(WireErrorCode::AeadLimitReached as u64)
+        );
+    }
+
+    #[test]
+    fn update_key_local() {
+        let mut b = [0; 15];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+
+        // Key update is not allowed before the handshake is confirmed.
+        assert_eq!(pipe.client.update_key(), Err(Error::InvalidState));
+
+        assert_eq!(pipe.handshake(), Ok(()));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(pipe.client.update_key(), Ok(()));
+        assert!(pipe.client.key_phase);
+
+        // A second update can't be initiated while the previous keys are
+        // still around.
+        assert_eq!(pipe.client.update_key(), Err(Error::InvalidState));
+
+        // Client sends message with the new keys, server follows the update.
+        assert_eq!(pipe.client.stream_send(4, b"hello", false), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert!(pipe.server.key_phase);
+        assert_eq!(pipe.server.stream_recv(4, &mut b), Ok((5, false)));
+        assert_eq!(&b[..5], b"hello");
+
+        // Server replies with the new keys.
+        assert_eq!(pipe.server.stream_send(4, b"world", true), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(pipe.client.stream_recv(4, &mut b), Ok((5, true)));
+        assert_eq!(&b[..5], b"world");
+
+        assert_ne!(
+            pipe.client.pkt_num_spaces[packet::Epoch::Application]
+                .key_update
+                .as_ref()
+                .unwrap()
+                .pn_on_update,
+            u64::MAX
+        );
+    }
+
+    #[test]
+    /// Tests that receiving a MAX_STREAM_DATA frame for a receive-only
+    /// unidirectional stream is forbidden.
+    fn max_stream_data_receive_uni() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // Client opens unidirectional stream.
+        assert_eq!(pipe.client.stream_send(2, b"hello", false), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Client sends MAX_STREAM_DATA on local unidirectional stream.
+        let frames = [frame::Frame::MaxStreamData {
+            stream_id: 2,
+            max: 1024,
+        }];
+
+        let pkt_type = packet::Type::Short;
+        assert_eq!(
+            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
+            Err(Error::InvalidStreamState(2)),
+        );
+    }
+
+    #[test]
+    fn reserved_bits() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let epoch = packet::Epoch::Application;
+        let pn = pipe.client.pkt_num_spaces[epoch].next_pkt_num;
+        let pn_len = 4;
+
+        let dcid = pipe.client.destination_id().to_vec();
+
+        let mut b = octets::OctetsMut::with_slice(&mut buf);
+
+        let hdr = Header {
+            ty: packet::Type::Short,
+            version: pipe.client.version,
+            dcid: ConnectionId::from_ref(&dcid),
+            scid: ConnectionId::default(),
+            pkt_num: 0,
+            pkt_num_len: pn_len,
+            token: None,
+            versions: None,
+            key_phase: pipe.client.key_phase,
+            spin_bit: false,
+        };
+
+        hdr.to_bytes(&mut b).unwrap();
+
+        // Set one of the reserved bits before protecting the packet.
+        {
+            let (mut first, _) = b.split_at(1).unwrap();
+            first.as_mut()[0] |= 0x08;
+        }
+
+        packet::encode_pkt_num(pn, pn_len, &mut b).unwrap();
+
+        let payload_offset = b.off();
+
+        let frames = [frame::Frame::Ping { mtu_probe: None }];
+
+        for frame in &frames {
+            frame.to_bytes(&mut b).unwrap();
+        }
+
+        let payload_len = frames.iter().fold(0, |acc, x| acc + x.wire_len());
+
+        let aead = pipe.client.pkt_num_spaces[epoch]
+            .crypto_seal
+            .as_ref()
+            .unwrap();
+
+        let written = packet::encrypt_pkt(
+            &mut b,
+            pn,
+            pn_len,
+            payload_len,
+            payload_offset,
+            None,
+            aead,
+        )
+        .unwrap();
+
+        assert_eq!(
+            pipe.server_recv(&mut buf[..written]),
+            Err(Error::InvalidPacket)
+        );
+
+        assert_eq!(
+            pipe.server.local_error.as_ref().map(|e| e.error_code),
+            Some(WireErrorCode::ProtocolViolation as u64)
+        );
+    }
+
+    #[test]
+    fn empty_payload() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // Send a packet with no frames.
+        let pkt_type = packet::Type::Short;
+        assert_eq!(
+            pipe.send_pkt_to_server(pkt_type, &[], &mut buf),
+            Err(Error::InvalidPacket)
+        );
+    }
+
+    #[test]
+    fn min_payload() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+
+        // Send a non-ack-eliciting packet.
+        let frames = [frame::Frame::Padding {
+            len: MIN_CLIENT_INITIAL_LEN,
+        }];
 
         let pkt_type = packet::Type::Initial;
         let written =
@@ -10020,7 +13163,7 @@ mod tests {
             .get_active()
             .expect("initial path not found");
 
-        assert_eq!(initial_path.max_send_bytes, 195);
+        assert_eq!(initial_path.max_send_bytes, 3786);
 
         // Force server to send a single PING frame.
         pipe.server
@@ -10209,116 +13352,407 @@ mod tests {
     fn stream_flow_control_limit_uni() {
         let mut buf = [0; 65535];
 
-        let mut pipe = testing::Pipe::new().unwrap();
-        assert_eq!(pipe.handshake(), Ok(()));
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let frames = [frame::Frame::Stream {
+            stream_id: 2,
+            data: stream::RangeBuf::from(b"aaaaaaaaaaa", 0, true),
+        }];
+
+        let pkt_type = packet::Type::Short;
+        assert_eq!(
+            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
+            Err(Error::FlowControl),
+        );
+    }
+
+    #[test]
+    fn stream_flow_control_update() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let frames = [frame::Frame::Stream {
+            stream_id: 4,
+            data: stream::RangeBuf::from(b"aaaaaaaaa", 0, false),
+        }];
+
+        let pkt_type = packet::Type::Short;
+
+        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+
+        pipe.server.stream_recv(4, &mut buf).unwrap();
+
+        let frames = [frame::Frame::Stream {
+            stream_id: 4,
+            data: stream::RangeBuf::from(b"a", 9, false),
+        }];
+
+        let len = pipe
+            .send_pkt_to_server(pkt_type, &frames, &mut buf)
+            .unwrap();
+
+        assert!(len > 0);
+
+        let frames =
+            testing::decode_pkt(&mut pipe.client, &mut buf[..len]).unwrap();
+        let mut iter = frames.iter();
+
+        // Ignore ACK.
+        iter.next().unwrap();
+
+        assert_eq!(
+            iter.next(),
+            Some(&frame::Frame::MaxStreamData {
+                stream_id: 4,
+                max: 24,
+            })
+        );
+    }
+
+    #[test]
+    fn stream_left_bidi() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert_eq!(3, pipe.client.peer_streams_left_bidi());
+        assert_eq!(3, pipe.server.peer_streams_left_bidi());
+
+        pipe.server.stream_send(1, b"a", false).ok();
+        assert_eq!(2, pipe.server.peer_streams_left_bidi());
+        pipe.server.stream_send(5, b"a", false).ok();
+        assert_eq!(1, pipe.server.peer_streams_left_bidi());
+
+        pipe.server.stream_send(9, b"a", false).ok();
+        assert_eq!(0, pipe.server.peer_streams_left_bidi());
+
+        let frames = [frame::Frame::MaxStreamsBidi { max: MAX_STREAM_ID }];
+
+        let pkt_type = packet::Type::Short;
+        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+
+        assert_eq!(MAX_STREAM_ID - 3, pipe.server.peer_streams_left_bidi());
+    }
+
+    #[test]
+    fn stream_left_uni() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert_eq!(3, pipe.client.peer_streams_left_uni());
+        assert_eq!(3, pipe.server.peer_streams_left_uni());
+
+        pipe.server.stream_send(3, b"a", false).ok();
+        assert_eq!(2, pipe.server.peer_streams_left_uni());
+        pipe.server.stream_send(7, b"a", false).ok();
+        assert_eq!(1, pipe.server.peer_streams_left_uni());
+
+        pipe.server.stream_send(11, b"a", false).ok();
+        assert_eq!(0, pipe.server.peer_streams_left_uni());
+
+        let frames = [frame::Frame::MaxStreamsUni { max: MAX_STREAM_ID }];
+
+        let pkt_type = packet::Type::Short;
+        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+
+        assert_eq!(MAX_STREAM_ID - 3, pipe.server.peer_streams_left_uni());
+    }
+
+    #[test]
+    fn stream_send_vectored() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert_eq!(
+            pipe.client.stream_send_vectored(
+                0,
+                &[b"hello", b"", b", world"],
+                true
+            ),
+            Ok(12)
+        );
+
+        // Only part of the data fits in the stream's flow control window.
+        assert_eq!(
+            pipe.client.stream_send_vectored(
+                4,
+                &[b"aaaaaaaaaa", b"bbbbbbbbbb"],
+                true
+            ),
+            Ok(15)
+        );
+        assert_eq!(
+            pipe.client.stream_send_vectored(4, &[b"bbbbb"], true),
+            Err(Error::Done)
+        );
+
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(pipe.server.stream_recv(0, &mut buf), Ok((12, true)));
+        assert_eq!(&buf[..12], b"hello, world");
+
+        assert_eq!(pipe.server.stream_recv(4, &mut buf), Ok((15, false)));
+        assert_eq!(&buf[..15], b"aaaaaaaaaabbbbb");
+    }
+
+    #[test]
+    fn stream_events() {
+        let mut buf = [0; 65535];
+
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
+        config.set_initial_max_streams_bidi(3);
+        config.verify_peer(false);
+        config.enable_stream_events(true);
+
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert_eq!(pipe.client.stream_event_next(), None);
+        assert_eq!(pipe.server.stream_event_next(), None);
+
+        assert_eq!(pipe.client.stream_send(0, b"hello", false), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(
+            pipe.server.stream_event_next(),
+            Some(StreamEvent::Opened(0))
+        );
+        assert_eq!(
+            pipe.server.stream_event_next(),
+            Some(StreamEvent::DataAvailable(0))
+        );
+        assert_eq!(pipe.server.stream_event_next(), None);
+
+        assert_eq!(pipe.server.stream_recv(0, &mut buf), Ok((5, false)));
+
+        assert_eq!(pipe.client.stream_send(0, b", world", true), Ok(7));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(
+            pipe.server.stream_event_next(),
+            Some(StreamEvent::DataAvailable(0))
+        );
+        assert_eq!(
+            pipe.server.stream_event_next(),
+            Some(StreamEvent::Finished(0))
+        );
+        assert_eq!(pipe.server.stream_event_next(), None);
+
+        // Exhaust the stream's flow control credit.
+        assert_eq!(pipe.client.stream_send(4, &buf[..15], false), Ok(15));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(
+            pipe.server.stream_event_next(),
+            Some(StreamEvent::Opened(4))
+        );
+        assert_eq!(
+            pipe.server.stream_event_next(),
+            Some(StreamEvent::DataAvailable(4))
+        );
+        assert_eq!(pipe.server.stream_event_next(), None);
+
+        // Reading the data makes the server issue new credit.
+        assert_eq!(pipe.server.stream_recv(4, &mut buf), Ok((15, false)));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(
+            pipe.client.stream_event_next(),
+            Some(StreamEvent::Writable(4))
+        );
+        assert_eq!(pipe.client.stream_event_next(), None);
+
+        assert_eq!(pipe.client.stream_shutdown(4, Shutdown::Write, 42), Ok(()));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(
+            pipe.server.stream_event_next(),
+            Some(StreamEvent::Reset(4, 42))
+        );
+        assert_eq!(pipe.server.stream_event_next(), None);
+
+        assert_eq!(pipe.server.stream_send(4, b"a", false), Ok(1));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(
+            pipe.client.stream_event_next(),
+            Some(StreamEvent::DataAvailable(4))
+        );
+        assert_eq!(pipe.client.stream_event_next(), None);
+
+        assert_eq!(pipe.client.stream_shutdown(4, Shutdown::Read, 7), Ok(()));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(
+            pipe.server.stream_event_next(),
+            Some(StreamEvent::StopSending(4, 7))
+        );
+        assert_eq!(pipe.server.stream_event_next(), None);
+    }
+
+    #[test]
+    fn stream_events_connection_flow_control() {
+        let mut buf = [0; 65535];
+
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(15);
+        config.set_initial_max_stream_data_bidi_local(30);
+        config.set_initial_max_stream_data_bidi_remote(30);
+        config.set_initial_max_streams_bidi(3);
+        config.verify_peer(false);
+        config.enable_stream_events(true);
+
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // Exhaust the connection's flow control credit, but not the stream's.
+        assert_eq!(pipe.client.stream_send(0, &buf[..15], false), Ok(15));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(pipe.client.stream_send(0, b"a", false), Err(Error::Done));
+        assert_eq!(pipe.client.stream_event_next(), None);
 
-        let frames = [frame::Frame::Stream {
-            stream_id: 2,
-            data: stream::RangeBuf::from(b"aaaaaaaaaaa", 0, true),
-        }];
+        // Reading the data makes the server send MAX_DATA.
+        assert_eq!(pipe.server.stream_recv(0, &mut buf), Ok((15, false)));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        let pkt_type = packet::Type::Short;
         assert_eq!(
-            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
-            Err(Error::FlowControl),
+            pipe.client.stream_event_next(),
+            Some(StreamEvent::Writable(0))
         );
+        assert_eq!(pipe.client.stream_event_next(), None);
     }
 
     #[test]
-    fn stream_flow_control_update() {
+    fn stream_stats() {
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        let frames = [frame::Frame::Stream {
-            stream_id: 4,
-            data: stream::RangeBuf::from(b"aaaaaaaaa", 0, false),
-        }];
-
-        let pkt_type = packet::Type::Short;
+        assert_eq!(
+            pipe.client.stream_stats(0).err(),
+            Some(Error::InvalidStreamState(0))
+        );
 
-        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+        assert_eq!(pipe.client.stream_send(0, b"hello, world", true), Ok(12));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        pipe.server.stream_recv(4, &mut buf).unwrap();
+        assert_eq!(pipe.server.stream_recv(0, &mut buf[..5]), Ok((5, false)));
+        assert_eq!(pipe.server.stream_send(0, b"a", false), Ok(1));
 
-        let frames = [frame::Frame::Stream {
-            stream_id: 4,
-            data: stream::RangeBuf::from(b"a", 9, false),
-        }];
+        let stats = pipe.client.stream_stats(0).unwrap();
+        assert_eq!(stats.written, 12);
+        assert_eq!(stats.sent, 12);
+        assert_eq!(stats.acked, 12);
+        assert_eq!(stats.retransmitted, 0);
+        assert_eq!(stats.send_final_size, Some(12));
+        assert_eq!(stats.recv_final_size, None);
+
+        let stats = pipe.server.stream_stats(0).unwrap();
+        assert_eq!(stats.written, 1);
+        assert_eq!(stats.sent, 0);
+        assert_eq!(stats.received, 12);
+        assert_eq!(stats.consumed, 5);
+        assert_eq!(stats.recv_final_size, Some(12));
+        assert_eq!(stats.peer_reset_error, None);
+        assert_eq!(stats.peer_stop_sending_error, None);
+        assert_eq!(stats.local_reset_error, None);
+        assert_eq!(stats.local_stop_sending_error, None);
 
-        let len = pipe
-            .send_pkt_to_server(pkt_type, &frames, &mut buf)
-            .unwrap();
+        assert_eq!(pipe.advance(), Ok(()));
 
-        assert!(len > 0);
+        // The server resets its side of the stream, and reads the rest of the
+        // client's data, so the stream is collected.
+        assert_eq!(pipe.server.stream_shutdown(0, Shutdown::Write, 42), Ok(()));
+        assert_eq!(pipe.server.stream_recv(0, &mut buf), Ok((7, true)));
+        assert!(pipe.server.streams.is_collected(0));
 
-        let frames =
-            testing::decode_pkt(&mut pipe.client, &mut buf[..len]).unwrap();
-        let mut iter = frames.iter();
+        let stats = pipe.server.stream_stats(0).unwrap();
+        assert_eq!(stats.consumed, 12);
+        assert_eq!(stats.local_reset_error, Some(42));
 
-        // Ignore ACK.
-        iter.next().unwrap();
+        assert_eq!(pipe.advance(), Ok(()));
 
+        // Final statistics are kept once the stream is collected.
         assert_eq!(
-            iter.next(),
-            Some(&frame::Frame::MaxStreamData {
-                stream_id: 4,
-                max: 24,
-            })
+            pipe.client.stream_recv(0, &mut buf),
+            Err(Error::StreamReset(42))
         );
-    }
-
-    #[test]
-    fn stream_left_bidi() {
-        let mut buf = [0; 65535];
-
-        let mut pipe = testing::Pipe::new().unwrap();
-        assert_eq!(pipe.handshake(), Ok(()));
-
-        assert_eq!(3, pipe.client.peer_streams_left_bidi());
-        assert_eq!(3, pipe.server.peer_streams_left_bidi());
-
-        pipe.server.stream_send(1, b"a", false).ok();
-        assert_eq!(2, pipe.server.peer_streams_left_bidi());
-        pipe.server.stream_send(5, b"a", false).ok();
-        assert_eq!(1, pipe.server.peer_streams_left_bidi());
+        assert!(pipe.client.streams.is_collected(0));
 
-        pipe.server.stream_send(9, b"a", false).ok();
-        assert_eq!(0, pipe.server.peer_streams_left_bidi());
+        let stats = pipe.client.stream_stats(0).unwrap();
+        assert_eq!(stats.acked, 12);
+        assert_eq!(stats.peer_reset_error, Some(42));
+        assert_eq!(stats.local_reset_error, None);
 
-        let frames = [frame::Frame::MaxStreamsBidi { max: MAX_STREAM_ID }];
+        // The client stops a stream, and the server resets it in response.
+        assert_eq!(pipe.client.stream_send(4, b"a", false), Ok(1));
+        assert_eq!(pipe.client.stream_shutdown(4, Shutdown::Read, 7), Ok(()));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        let pkt_type = packet::Type::Short;
-        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+        let stats = pipe.client.stream_stats(4).unwrap();
+        assert_eq!(stats.local_stop_sending_error, Some(7));
 
-        assert_eq!(MAX_STREAM_ID - 3, pipe.server.peer_streams_left_bidi());
+        let stats = pipe.server.stream_stats(4).unwrap();
+        assert_eq!(stats.peer_stop_sending_error, Some(7));
+        assert_eq!(stats.local_reset_error, Some(7));
     }
 
     #[test]
-    fn stream_left_uni() {
+    fn stream_open() {
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        assert_eq!(3, pipe.client.peer_streams_left_uni());
-        assert_eq!(3, pipe.server.peer_streams_left_uni());
-
-        pipe.server.stream_send(3, b"a", false).ok();
-        assert_eq!(2, pipe.server.peer_streams_left_uni());
-        pipe.server.stream_send(7, b"a", false).ok();
-        assert_eq!(1, pipe.server.peer_streams_left_uni());
+        assert_eq!(pipe.client.stream_open_bidi(), Ok(0));
+        assert_eq!(pipe.client.stream_open_bidi(), Ok(4));
+        assert_eq!(pipe.client.stream_open_uni(), Ok(2));
 
-        pipe.server.stream_send(11, b"a", false).ok();
-        assert_eq!(0, pipe.server.peer_streams_left_uni());
+        assert_eq!(pipe.server.stream_open_bidi(), Ok(1));
+        assert_eq!(pipe.server.stream_open_uni(), Ok(3));
 
-        let frames = [frame::Frame::MaxStreamsUni { max: MAX_STREAM_ID }];
+        // Streams opened explicitly skip the IDs below them.
+        assert_eq!(pipe.client.stream_send(8, b"a", false), Ok(1));
+        assert_eq!(pipe.client.stream_send(10, b"a", false), Ok(1));
+        assert_eq!(pipe.client.stream_open_uni(), Err(Error::StreamLimit));
+        assert_eq!(pipe.client.stream_open_bidi(), Err(Error::StreamLimit));
 
-        let pkt_type = packet::Type::Short;
-        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+        assert_eq!(pipe.client.stream_send(0, b"a", true), Ok(1));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        assert_eq!(MAX_STREAM_ID - 3, pipe.server.peer_streams_left_uni());
+        assert_eq!(pipe.server.stream_recv(0, &mut buf), Ok((1, true)));
+        assert_eq!(pipe.server.stream_recv(8, &mut buf), Ok((1, false)));
     }
 
     #[test]
@@ -10680,46 +14114,278 @@ mod tests {
         }];
 
         let pkt_type = packet::Type::Short;
-        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+
+        let frames = [frame::Frame::StreamsBlockedBidi {
+            limit: MAX_STREAM_ID + 1,
+        }];
+
+        let pkt_type = packet::Type::Short;
+        assert_eq!(
+            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
+            Err(Error::InvalidFrame),
+        );
+    }
+
+    #[test]
+    fn streams_blocked_max_uni() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let frames = [frame::Frame::StreamsBlockedUni {
+            limit: MAX_STREAM_ID,
+        }];
+
+        let pkt_type = packet::Type::Short;
+        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+
+        let frames = [frame::Frame::StreamsBlockedUni {
+            limit: MAX_STREAM_ID + 1,
+        }];
+
+        let pkt_type = packet::Type::Short;
+        assert_eq!(
+            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
+            Err(Error::InvalidFrame),
+        );
+    }
+
+    #[test]
+    fn stream_data_overlap() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let frames = [
+            frame::Frame::Stream {
+                stream_id: 0,
+                data: stream::RangeBuf::from(b"aaaaa", 0, false),
+            },
+            frame::Frame::Stream {
+                stream_id: 0,
+                data: stream::RangeBuf::from(b"bbbbb", 3, false),
+            },
+            frame::Frame::Stream {
+                stream_id: 0,
+                data: stream::RangeBuf::from(b"ccccc", 6, false),
+            },
+        ];
+
+        let pkt_type = packet::Type::Short;
+        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+
+        let mut b = [0; 15];
+        assert_eq!(pipe.server.stream_recv(0, &mut b), Ok((11, false)));
+        assert_eq!(&b[..11], b"aaaaabbbccc");
+    }
+
+    #[test]
+    fn stream_recv_owned() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let frames = [
+            frame::Frame::Stream {
+                stream_id: 0,
+                data: stream::RangeBuf::from(b"aaaaa", 0, false),
+            },
+            frame::Frame::Stream {
+                stream_id: 0,
+                data: stream::RangeBuf::from(b"bbbbb", 5, true),
+            },
+        ];
+
+        let pkt_type = packet::Type::Short;
+        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+
+        assert_eq!(
+            pipe.server.stream_recv_owned(0),
+            Ok((b"aaaaa".to_vec(), false))
+        );
+        assert!(pipe.server.stream_readable(0));
+
+        assert_eq!(
+            pipe.server.stream_recv_owned(0),
+            Ok((b"bbbbb".to_vec(), true))
+        );
+        assert!(!pipe.server.stream_readable(0));
+        assert!(pipe.server.stream_finished(0));
+
+        // Own unidirectional streams can't be read from.
+        assert_eq!(
+            pipe.server.stream_recv_owned(3),
+            Err(Error::InvalidStreamState(3))
+        );
+    }
+
+    #[test]
+    fn stream_data_overlap_with_reordering() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let frames = [
+            frame::Frame::Stream {
+                stream_id: 0,
+                data: stream::RangeBuf::from(b"aaaaa", 0, false),
+            },
+            frame::Frame::Stream {
+                stream_id: 0,
+                data: stream::RangeBuf::from(b"ccccc", 6, false),
+            },
+            frame::Frame::Stream {
+                stream_id: 0,
+                data: stream::RangeBuf::from(b"bbbbb", 3, false),
+            },
+        ];
+
+        let pkt_type = packet::Type::Short;
+        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+
+        let mut b = [0; 15];
+        assert_eq!(pipe.server.stream_recv(0, &mut b), Ok((11, false)));
+        assert_eq!(&b[..11], b"aaaaabccccc");
+    }
+
+    #[test]
+    /// Tests that receiving a valid RESET_STREAM frame when all data has
+    /// already been read, notifies the application.
+    fn reset_stream_data_recvd() {
+        let mut b = [0; 15];
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // Client sends some data.
+        assert_eq!(pipe.client.stream_send(0, b"hello", false), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Server gets data and sends data back, closing stream.
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(0));
+        assert_eq!(r.next(), None);
+
+        assert_eq!(pipe.server.stream_recv(0, &mut b), Ok((5, false)));
+        assert!(!pipe.server.stream_finished(0));
+
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), None);
+
+        assert_eq!(pipe.server.stream_send(0, b"", true), Ok(0));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        let mut r = pipe.client.readable();
+        assert_eq!(r.next(), Some(0));
+        assert_eq!(r.next(), None);
+
+        assert_eq!(pipe.client.stream_recv(0, &mut b), Ok((0, true)));
+        assert!(pipe.client.stream_finished(0));
+
+        // Client sends RESET_STREAM, closing stream.
+        let frames = [frame::Frame::ResetStream {
+            stream_id: 0,
+            error_code: 42,
+            final_size: 5,
+        }];
+
+        let pkt_type = packet::Type::Short;
+        assert_eq!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf), Ok(39));
 
-        let frames = [frame::Frame::StreamsBlockedBidi {
-            limit: MAX_STREAM_ID + 1,
-        }];
+        // Server is notified of stream readability, due to reset.
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(0));
+        assert_eq!(r.next(), None);
 
-        let pkt_type = packet::Type::Short;
         assert_eq!(
-            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
-            Err(Error::InvalidFrame),
+            pipe.server.stream_recv(0, &mut b),
+            Err(Error::StreamReset(42))
         );
+
+        assert!(pipe.server.stream_finished(0));
+
+        // Sending RESET_STREAM again shouldn't make stream readable again.
+        pipe.send_pkt_to_server(pkt_type, &frames, &mut buf)
+            .unwrap();
+
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), None);
     }
 
     #[test]
-    fn streams_blocked_max_uni() {
+    /// Tests that receiving a valid RESET_STREAM frame when all data has _not_
+    /// been read, discards all buffered data and notifies the application.
+    fn reset_stream_data_not_recvd() {
+        let mut b = [0; 15];
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        let frames = [frame::Frame::StreamsBlockedUni {
-            limit: MAX_STREAM_ID,
-        }];
+        // Client sends some data.
+        assert_eq!(pipe.client.stream_send(0, b"h", false), Ok(1));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        let pkt_type = packet::Type::Short;
-        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+        // Server gets data and sends data back, closing stream.
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(0));
+        assert_eq!(r.next(), None);
 
-        let frames = [frame::Frame::StreamsBlockedUni {
-            limit: MAX_STREAM_ID + 1,
+        assert_eq!(pipe.server.stream_recv(0, &mut b), Ok((1, false)));
+        assert!(!pipe.server.stream_finished(0));
+
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), None);
+
+        assert_eq!(pipe.server.stream_send(0, b"", true), Ok(0));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        let mut r = pipe.client.readable();
+        assert_eq!(r.next(), Some(0));
+        assert_eq!(r.next(), None);
+
+        assert_eq!(pipe.client.stream_recv(0, &mut b), Ok((0, true)));
+        assert!(pipe.client.stream_finished(0));
+
+        // Client sends RESET_STREAM, closing stream.
+        let frames = [frame::Frame::ResetStream {
+            stream_id: 0,
+            error_code: 42,
+            final_size: 5,
         }];
 
         let pkt_type = packet::Type::Short;
+        assert_eq!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf), Ok(39));
+
+        // Server is notified of stream readability, due to reset.
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(0));
+        assert_eq!(r.next(), None);
+
         assert_eq!(
-            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
-            Err(Error::InvalidFrame),
+            pipe.server.stream_recv(0, &mut b),
+            Err(Error::StreamReset(42))
         );
+
+        assert!(pipe.server.stream_finished(0));
+
+        // Sending RESET_STREAM again shouldn't make stream readable again.
+        assert_eq!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf), Ok(39));
+
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), None);
     }
 
     #[test]
-    fn stream_data_overlap() {
+    /// Tests that RESET_STREAM frames exceeding the connection-level flow
+    /// control limit cause an error.
+    fn reset_stream_flow_control() {
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
@@ -10728,28 +14394,34 @@ mod tests {
         let frames = [
             frame::Frame::Stream {
                 stream_id: 0,
-                data: stream::RangeBuf::from(b"aaaaa", 0, false),
+                data: stream::RangeBuf::from(b"aaaaaaaaaaaaaaa", 0, false),
             },
             frame::Frame::Stream {
-                stream_id: 0,
-                data: stream::RangeBuf::from(b"bbbbb", 3, false),
+                stream_id: 4,
+                data: stream::RangeBuf::from(b"a", 0, false),
+            },
+            frame::Frame::ResetStream {
+                stream_id: 4,
+                error_code: 0,
+                final_size: 15,
             },
             frame::Frame::Stream {
-                stream_id: 0,
-                data: stream::RangeBuf::from(b"ccccc", 6, false),
+                stream_id: 8,
+                data: stream::RangeBuf::from(b"a", 0, false),
             },
         ];
 
         let pkt_type = packet::Type::Short;
-        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
-
-        let mut b = [0; 15];
-        assert_eq!(pipe.server.stream_recv(0, &mut b), Ok((11, false)));
-        assert_eq!(&b[..11], b"aaaaabbbccc");
+        assert_eq!(
+            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
+            Err(Error::FlowControl),
+        );
     }
 
     #[test]
-    fn stream_data_overlap_with_reordering() {
+    /// Tests that RESET_STREAM frames exceeding the stream-level flow control
+    /// limit cause an error.
+    fn reset_stream_flow_control_stream() {
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
@@ -10757,517 +14429,691 @@ mod tests {
 
         let frames = [
             frame::Frame::Stream {
-                stream_id: 0,
-                data: stream::RangeBuf::from(b"aaaaa", 0, false),
-            },
-            frame::Frame::Stream {
-                stream_id: 0,
-                data: stream::RangeBuf::from(b"ccccc", 6, false),
+                stream_id: 4,
+                data: stream::RangeBuf::from(b"a", 0, false),
             },
-            frame::Frame::Stream {
-                stream_id: 0,
-                data: stream::RangeBuf::from(b"bbbbb", 3, false),
+            frame::Frame::ResetStream {
+                stream_id: 4,
+                error_code: 0,
+                final_size: 16, // Past stream's flow control limit.
             },
         ];
 
         let pkt_type = packet::Type::Short;
-        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+        assert_eq!(
+            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
+            Err(Error::FlowControl),
+        );
+    }
 
-        let mut b = [0; 15];
-        assert_eq!(pipe.server.stream_recv(0, &mut b), Ok((11, false)));
-        assert_eq!(&b[..11], b"aaaaabccccc");
+    #[test]
+    fn path_challenge() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let frames = [frame::Frame::PathChallenge { data: [0xba; 8] }];
+
+        let pkt_type = packet::Type::Short;
+
+        let len = pipe
+            .send_pkt_to_server(pkt_type, &frames, &mut buf)
+            .unwrap();
+
+        assert!(len > 0);
+
+        let frames =
+            testing::decode_pkt(&mut pipe.client, &mut buf[..len]).unwrap();
+        let mut iter = frames.iter();
+
+        // Ignore ACK.
+        iter.next().unwrap();
+
+        assert_eq!(
+            iter.next(),
+            Some(&frame::Frame::PathResponse { data: [0xba; 8] })
+        );
     }
 
+    #[cfg(not(feature = "openssl"))] // 0-RTT not supported when using openssl/quictls
     #[test]
-    /// Tests that receiving a valid RESET_STREAM frame when all data has
-    /// already been read, notifies the application.
-    fn reset_stream_data_recvd() {
+    /// Simulates reception of an early 1-RTT packet on the server, by
+    /// delaying the client's Handshake packet that completes the handshake.
+    fn early_1rtt_packet() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+
+        // Client sends initial flight
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        testing::process_flight(&mut pipe.server, flight).unwrap();
+
+        // Server sends initial flight.
+        let flight = testing::emit_flight(&mut pipe.server).unwrap();
+        testing::process_flight(&mut pipe.client, flight).unwrap();
+
+        // Client sends Handshake packet.
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+
+        // Emulate handshake packet delay by not making server process client
+        // packet.
+        let delayed = flight;
+
+        testing::emit_flight(&mut pipe.server).ok();
+
+        assert!(pipe.client.is_established());
+
+        // Send 1-RTT packet #0.
+        let frames = [frame::Frame::Stream {
+            stream_id: 0,
+            data: stream::RangeBuf::from(b"hello, world", 0, true),
+        }];
+
+        let pkt_type = packet::Type::Short;
+        let written =
+            testing::encode_pkt(&mut pipe.client, pkt_type, &frames, &mut buf)
+                .unwrap();
+
+        assert_eq!(pipe.server_recv(&mut buf[..written]), Ok(written));
+
+        // Send 1-RTT packet #1.
+        let frames = [frame::Frame::Stream {
+            stream_id: 4,
+            data: stream::RangeBuf::from(b"hello, world", 0, true),
+        }];
+
+        let written =
+            testing::encode_pkt(&mut pipe.client, pkt_type, &frames, &mut buf)
+                .unwrap();
+
+        assert_eq!(pipe.server_recv(&mut buf[..written]), Ok(written));
+
+        assert!(!pipe.server.is_established());
+
+        // Client sent 1-RTT packets 0 and 1, but server hasn't received them.
+        //
+        // Note that `largest_rx_pkt_num` is initialized to 0, so we need to
+        // send another 1-RTT packet to make this check meaningful.
+        assert_eq!(
+            pipe.server.pkt_num_spaces[packet::Epoch::Application]
+                .largest_rx_pkt_num,
+            0
+        );
+
+        // Process delayed packet.
+        testing::process_flight(&mut pipe.server, delayed).unwrap();
+
+        assert!(pipe.server.is_established());
+
+        assert_eq!(
+            pipe.server.pkt_num_spaces[packet::Epoch::Application]
+                .largest_rx_pkt_num,
+            0
+        );
+    }
+
+    #[test]
+    fn stop_sending() {
         let mut b = [0; 15];
+
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        // Client sends some data.
-        assert_eq!(pipe.client.stream_send(0, b"hello", false), Ok(5));
+        // Client sends some data, and closes stream.
+        assert_eq!(pipe.client.stream_send(0, b"hello", true), Ok(5));
         assert_eq!(pipe.advance(), Ok(()));
 
-        // Server gets data and sends data back, closing stream.
+        // Server gets data.
         let mut r = pipe.server.readable();
         assert_eq!(r.next(), Some(0));
         assert_eq!(r.next(), None);
 
-        assert_eq!(pipe.server.stream_recv(0, &mut b), Ok((5, false)));
-        assert!(!pipe.server.stream_finished(0));
+        assert_eq!(pipe.server.stream_recv(0, &mut b), Ok((5, true)));
+        assert!(pipe.server.stream_finished(0));
 
         let mut r = pipe.server.readable();
         assert_eq!(r.next(), None);
 
-        assert_eq!(pipe.server.stream_send(0, b"", true), Ok(0));
-        assert_eq!(pipe.advance(), Ok(()));
-
-        let mut r = pipe.client.readable();
+        // Server sends data, until blocked.
+        let mut r = pipe.server.writable();
         assert_eq!(r.next(), Some(0));
         assert_eq!(r.next(), None);
 
-        assert_eq!(pipe.client.stream_recv(0, &mut b), Ok((0, true)));
-        assert!(pipe.client.stream_finished(0));
+        while pipe.server.stream_send(0, b"world", false) != Err(Error::Done) {
+            assert_eq!(pipe.advance(), Ok(()));
+        }
 
-        // Client sends RESET_STREAM, closing stream.
-        let frames = [frame::Frame::ResetStream {
+        let mut r = pipe.server.writable();
+        assert_eq!(r.next(), None);
+
+        // Client sends STOP_SENDING.
+        let frames = [frame::Frame::StopSending {
             stream_id: 0,
             error_code: 42,
-            final_size: 5,
         }];
 
         let pkt_type = packet::Type::Short;
-        assert_eq!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf), Ok(39));
+        let len = pipe
+            .send_pkt_to_server(pkt_type, &frames, &mut buf)
+            .unwrap();
 
-        // Server is notified of stream readability, due to reset.
-        let mut r = pipe.server.readable();
+        // Server sent a RESET_STREAM frame in response.
+        let frames =
+            testing::decode_pkt(&mut pipe.client, &mut buf[..len]).unwrap();
+
+        let mut iter = frames.iter();
+
+        // Skip ACK frame.
+        iter.next();
+
+        assert_eq!(
+            iter.next(),
+            Some(&frame::Frame::ResetStream {
+                stream_id: 0,
+                error_code: 42,
+                final_size: 15,
+            })
+        );
+
+        // Stream is writable, but writing returns an error.
+        let mut r = pipe.server.writable();
         assert_eq!(r.next(), Some(0));
         assert_eq!(r.next(), None);
 
         assert_eq!(
-            pipe.server.stream_recv(0, &mut b),
-            Err(Error::StreamReset(42))
+            pipe.server.stream_send(0, b"world", true),
+            Err(Error::StreamStopped(42)),
         );
 
-        assert!(pipe.server.stream_finished(0));
+        assert_eq!(pipe.server.streams.len(), 1);
 
-        // Sending RESET_STREAM again shouldn't make stream readable again.
-        pipe.send_pkt_to_server(pkt_type, &frames, &mut buf)
+        // Client acks RESET_STREAM frame.
+        let mut ranges = ranges::RangeSet::default();
+        ranges.insert(0..6);
+
+        let frames = [frame::Frame::ACK {
+            ack_delay: 15,
+            ranges,
+            ecn_counts: None,
+        }];
+
+        assert_eq!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf), Ok(0));
+
+        // Stream is collected on the server after RESET_STREAM is acked.
+        assert_eq!(pipe.server.streams.len(), 0);
+
+        // Sending STOP_SENDING again shouldn't trigger RESET_STREAM again.
+        let frames = [frame::Frame::StopSending {
+            stream_id: 0,
+            error_code: 42,
+        }];
+
+        let len = pipe
+            .send_pkt_to_server(pkt_type, &frames, &mut buf)
             .unwrap();
 
-        let mut r = pipe.server.readable();
+        let frames =
+            testing::decode_pkt(&mut pipe.client, &mut buf[..len]).unwrap();
+
+        assert_eq!(frames.len(), 1);
+
+        match frames.first() {
+            Some(frame::Frame::ACK { .. }) => (),
+
+            f => panic!("expected ACK frame, got {:?}", f),
+        };
+
+        let mut r = pipe.server.writable();
         assert_eq!(r.next(), None);
     }
 
     #[test]
-    /// Tests that receiving a valid RESET_STREAM frame when all data has _not_
-    /// been read, discards all buffered data and notifies the application.
-    fn reset_stream_data_not_recvd() {
+    fn stop_sending_fin() {
         let mut b = [0; 15];
+
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        // Client sends some data.
-        assert_eq!(pipe.client.stream_send(0, b"h", false), Ok(1));
+        // Client sends some data, and closes stream.
+        assert_eq!(pipe.client.stream_send(4, b"hello", true), Ok(5));
         assert_eq!(pipe.advance(), Ok(()));
 
-        // Server gets data and sends data back, closing stream.
+        // Server gets data.
         let mut r = pipe.server.readable();
-        assert_eq!(r.next(), Some(0));
+        assert_eq!(r.next(), Some(4));
         assert_eq!(r.next(), None);
 
-        assert_eq!(pipe.server.stream_recv(0, &mut b), Ok((1, false)));
-        assert!(!pipe.server.stream_finished(0));
+        assert_eq!(pipe.server.stream_recv(4, &mut b), Ok((5, true)));
+        assert!(pipe.server.stream_finished(4));
 
         let mut r = pipe.server.readable();
         assert_eq!(r.next(), None);
 
-        assert_eq!(pipe.server.stream_send(0, b"", true), Ok(0));
-        assert_eq!(pipe.advance(), Ok(()));
-
-        let mut r = pipe.client.readable();
-        assert_eq!(r.next(), Some(0));
+        // Server sends data...
+        let mut r = pipe.server.writable();
+        assert_eq!(r.next(), Some(4));
         assert_eq!(r.next(), None);
 
-        assert_eq!(pipe.client.stream_recv(0, &mut b), Ok((0, true)));
-        assert!(pipe.client.stream_finished(0));
+        assert_eq!(pipe.server.stream_send(4, b"world", false), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        // Client sends RESET_STREAM, closing stream.
-        let frames = [frame::Frame::ResetStream {
-            stream_id: 0,
+        // ...and buffers more, and closes stream.
+        assert_eq!(pipe.server.stream_send(4, b"world", true), Ok(5));
+
+        // Client sends STOP_SENDING before server flushes stream.
+        let frames = [frame::Frame::StopSending {
+            stream_id: 4,
             error_code: 42,
-            final_size: 5,
         }];
 
         let pkt_type = packet::Type::Short;
-        assert_eq!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf), Ok(39));
-
-        // Server is notified of stream readability, due to reset.
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), Some(0));
-        assert_eq!(r.next(), None);
-
-        assert_eq!(
-            pipe.server.stream_recv(0, &mut b),
-            Err(Error::StreamReset(42))
-        );
-
-        assert!(pipe.server.stream_finished(0));
-
-        // Sending RESET_STREAM again shouldn't make stream readable again.
-        assert_eq!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf), Ok(39));
-
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), None);
-    }
+        let len = pipe
+            .send_pkt_to_server(pkt_type, &frames, &mut buf)
+            .unwrap();
 
-    #[test]
-    /// Tests that RESET_STREAM frames exceeding the connection-level flow
-    /// control limit cause an error.
-    fn reset_stream_flow_control() {
-        let mut buf = [0; 65535];
+        // Server sent a RESET_STREAM frame in response.
+        let frames =
+            testing::decode_pkt(&mut pipe.client, &mut buf[..len]).unwrap();
 
-        let mut pipe = testing::Pipe::new().unwrap();
-        assert_eq!(pipe.handshake(), Ok(()));
+        let mut iter = frames.iter();
 
-        let frames = [
-            frame::Frame::Stream {
-                stream_id: 0,
-                data: stream::RangeBuf::from(b"aaaaaaaaaaaaaaa", 0, false),
-            },
-            frame::Frame::Stream {
-                stream_id: 4,
-                data: stream::RangeBuf::from(b"a", 0, false),
-            },
-            frame::Frame::ResetStream {
-                stream_id: 4,
-                error_code: 0,
-                final_size: 15,
-            },
-            frame::Frame::Stream {
-                stream_id: 8,
-                data: stream::RangeBuf::from(b"a", 0, false),
-            },
-        ];
+        // Skip ACK frame.
+        iter.next();
 
-        let pkt_type = packet::Type::Short;
         assert_eq!(
-            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
-            Err(Error::FlowControl),
+            iter.next(),
+            Some(&frame::Frame::ResetStream {
+                stream_id: 4,
+                error_code: 42,
+                final_size: 5,
+            })
         );
+
+        // No more frames are sent by the server.
+        assert_eq!(iter.next(), None);
     }
 
     #[test]
-    /// Tests that RESET_STREAM frames exceeding the stream-level flow control
-    /// limit cause an error.
-    fn reset_stream_flow_control_stream() {
+    /// Tests that resetting a stream restores flow control for unsent data.
+    fn stop_sending_unsent_tx_cap() {
         let mut buf = [0; 65535];
 
-        let mut pipe = testing::Pipe::new().unwrap();
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(15);
+        config.set_initial_max_stream_data_bidi_local(30);
+        config.set_initial_max_stream_data_bidi_remote(30);
+        config.set_initial_max_stream_data_uni(30);
+        config.set_initial_max_streams_bidi(3);
+        config.set_initial_max_streams_uni(0);
+        config.verify_peer(false);
+
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        let frames = [
-            frame::Frame::Stream {
-                stream_id: 4,
-                data: stream::RangeBuf::from(b"a", 0, false),
-            },
-            frame::Frame::ResetStream {
-                stream_id: 4,
-                error_code: 0,
-                final_size: 16, // Past stream's flow control limit.
-            },
-        ];
+        // Client sends some data.
+        assert_eq!(pipe.client.stream_send(4, b"hello", true), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        let pkt_type = packet::Type::Short;
-        assert_eq!(
-            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
-            Err(Error::FlowControl),
-        );
-    }
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(4));
+        assert_eq!(r.next(), None);
 
-    #[test]
-    fn path_challenge() {
-        let mut buf = [0; 65535];
+        let mut b = [0; 15];
+        assert_eq!(pipe.server.stream_recv(4, &mut b), Ok((5, true)));
+
+        // Server sends some data.
+        assert_eq!(pipe.server.stream_send(4, b"hello", false), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        let mut pipe = testing::Pipe::new().unwrap();
-        assert_eq!(pipe.handshake(), Ok(()));
+        // Server buffers some data, until send capacity limit reached.
+        assert_eq!(pipe.server.stream_send(4, b"hello", false), Ok(5));
+        assert_eq!(pipe.server.stream_send(4, b"hello", false), Ok(5));
+        assert_eq!(
+            pipe.server.stream_send(4, b"hello", false),
+            Err(Error::Done)
+        );
 
-        let frames = [frame::Frame::PathChallenge { data: [0xba; 8] }];
+        // Client sends STOP_SENDING.
+        let frames = [frame::Frame::StopSending {
+            stream_id: 4,
+            error_code: 42,
+        }];
 
         let pkt_type = packet::Type::Short;
-
-        let len = pipe
-            .send_pkt_to_server(pkt_type, &frames, &mut buf)
+        pipe.send_pkt_to_server(pkt_type, &frames, &mut buf)
             .unwrap();
 
-        assert!(len > 0);
-
-        let frames =
-            testing::decode_pkt(&mut pipe.client, &mut buf[..len]).unwrap();
-        let mut iter = frames.iter();
-
-        // Ignore ACK.
-        iter.next().unwrap();
+        // Server can now send more data (on a different stream).
+        assert_eq!(pipe.client.stream_send(8, b"hello", true), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
 
+        assert_eq!(pipe.server.stream_send(8, b"hello", false), Ok(5));
+        assert_eq!(pipe.server.stream_send(8, b"hello", false), Ok(5));
         assert_eq!(
-            iter.next(),
-            Some(&frame::Frame::PathResponse { data: [0xba; 8] })
+            pipe.server.stream_send(8, b"hello", false),
+            Err(Error::Done)
         );
+        assert_eq!(pipe.advance(), Ok(()));
     }
 
-    #[cfg(not(feature = "openssl"))] // 0-RTT not supported when using openssl/quictls
     #[test]
-    /// Simulates reception of an early 1-RTT packet on the server, by
-    /// delaying the client's Handshake packet that completes the handshake.
-    fn early_1rtt_packet() {
+    fn stream_shutdown_read() {
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
 
-        // Client sends initial flight
-        let flight = testing::emit_flight(&mut pipe.client).unwrap();
-        testing::process_flight(&mut pipe.server, flight).unwrap();
-
-        // Server sends initial flight.
-        let flight = testing::emit_flight(&mut pipe.server).unwrap();
-        testing::process_flight(&mut pipe.client, flight).unwrap();
+        // Client sends some data.
+        assert_eq!(pipe.client.stream_send(4, b"hello, world", false), Ok(12));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        // Client sends Handshake packet.
-        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(4));
+        assert_eq!(r.next(), None);
 
-        // Emulate handshake packet delay by not making server process client
-        // packet.
-        let delayed = flight;
+        assert_eq!(pipe.client.streams.len(), 1);
+        assert_eq!(pipe.server.streams.len(), 1);
 
-        testing::emit_flight(&mut pipe.server).ok();
+        // Server shuts down stream.
+        assert_eq!(pipe.server.stream_shutdown(4, Shutdown::Read, 42), Ok(()));
 
-        assert!(pipe.client.is_established());
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), None);
 
-        // Send 1-RTT packet #0.
-        let frames = [frame::Frame::Stream {
-            stream_id: 0,
-            data: stream::RangeBuf::from(b"hello, world", 0, true),
-        }];
+        let (len, _) = pipe.server.send(&mut buf).unwrap();
 
-        let pkt_type = packet::Type::Short;
-        let written =
-            testing::encode_pkt(&mut pipe.client, pkt_type, &frames, &mut buf)
-                .unwrap();
+        let mut dummy = buf[..len].to_vec();
 
-        assert_eq!(pipe.server_recv(&mut buf[..written]), Ok(written));
+        let frames =
+            testing::decode_pkt(&mut pipe.client, &mut dummy[..len]).unwrap();
+        let mut iter = frames.iter();
 
-        // Send 1-RTT packet #1.
-        let frames = [frame::Frame::Stream {
-            stream_id: 4,
-            data: stream::RangeBuf::from(b"hello, world", 0, true),
-        }];
+        assert_eq!(
+            iter.next(),
+            Some(&frame::Frame::StopSending {
+                stream_id: 4,
+                error_code: 42,
+            })
+        );
 
-        let written =
-            testing::encode_pkt(&mut pipe.client, pkt_type, &frames, &mut buf)
-                .unwrap();
+        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
 
-        assert_eq!(pipe.server_recv(&mut buf[..written]), Ok(written));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        assert!(!pipe.server.is_established());
+        // Sending more data is forbidden.
+        let mut r = pipe.client.writable();
+        assert_eq!(r.next(), Some(4));
+        assert_eq!(r.next(), None);
 
-        // Client sent 1-RTT packets 0 and 1, but server hasn't received them.
-        //
-        // Note that `largest_rx_pkt_num` is initialized to 0, so we need to
-        // send another 1-RTT packet to make this check meaningful.
         assert_eq!(
-            pipe.server.pkt_num_spaces[packet::Epoch::Application]
-                .largest_rx_pkt_num,
-            0
+            pipe.client.stream_send(4, b"bye", false),
+            Err(Error::StreamStopped(42))
         );
 
-        // Process delayed packet.
-        testing::process_flight(&mut pipe.server, delayed).unwrap();
+        // Server sends some data, without reading the incoming data, and closes
+        // the stream.
+        assert_eq!(pipe.server.stream_send(4, b"hello, world", true), Ok(12));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        assert!(pipe.server.is_established());
+        // Client reads the data.
+        let mut r = pipe.client.readable();
+        assert_eq!(r.next(), Some(4));
+        assert_eq!(r.next(), None);
+
+        assert_eq!(pipe.client.stream_recv(4, &mut buf), Ok((12, true)));
+
+        // Stream is collected on both sides.
+        assert_eq!(pipe.client.streams.len(), 0);
+        assert_eq!(pipe.server.streams.len(), 0);
 
         assert_eq!(
-            pipe.server.pkt_num_spaces[packet::Epoch::Application]
-                .largest_rx_pkt_num,
-            0
+            pipe.server.stream_shutdown(4, Shutdown::Read, 0),
+            Err(Error::Done)
         );
     }
 
     #[test]
-    fn stop_sending() {
-        let mut b = [0; 15];
-
+    fn stream_shutdown_read_after_fin() {
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        // Client sends some data, and closes stream.
-        assert_eq!(pipe.client.stream_send(0, b"hello", true), Ok(5));
+        // Client sends some data.
+        assert_eq!(pipe.client.stream_send(4, b"hello, world", true), Ok(12));
         assert_eq!(pipe.advance(), Ok(()));
 
-        // Server gets data.
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), Some(0));
-        assert_eq!(r.next(), None);
-
-        assert_eq!(pipe.server.stream_recv(0, &mut b), Ok((5, true)));
-        assert!(pipe.server.stream_finished(0));
-
         let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(4));
         assert_eq!(r.next(), None);
 
-        // Server sends data, until blocked.
-        let mut r = pipe.server.writable();
-        assert_eq!(r.next(), Some(0));
-        assert_eq!(r.next(), None);
+        assert_eq!(pipe.client.streams.len(), 1);
+        assert_eq!(pipe.server.streams.len(), 1);
 
-        while pipe.server.stream_send(0, b"world", false) != Err(Error::Done) {
-            assert_eq!(pipe.advance(), Ok(()));
-        }
+        // Server shuts down stream.
+        assert_eq!(pipe.server.stream_shutdown(4, Shutdown::Read, 42), Ok(()));
 
-        let mut r = pipe.server.writable();
+        let mut r = pipe.server.readable();
         assert_eq!(r.next(), None);
 
-        // Client sends STOP_SENDING.
-        let frames = [frame::Frame::StopSending {
-            stream_id: 0,
-            error_code: 42,
-        }];
-
-        let pkt_type = packet::Type::Short;
-        let len = pipe
-            .send_pkt_to_server(pkt_type, &frames, &mut buf)
-            .unwrap();
+        // Server has nothing to send.
+        assert_eq!(pipe.server.send(&mut buf), Err(Error::Done));
 
-        // Server sent a RESET_STREAM frame in response.
-        let frames =
-            testing::decode_pkt(&mut pipe.client, &mut buf[..len]).unwrap();
+        assert_eq!(pipe.advance(), Ok(()));
 
-        let mut iter = frames.iter();
+        // Server sends some data, without reading the incoming data, and closes
+        // the stream.
+        assert_eq!(pipe.server.stream_send(4, b"hello, world", true), Ok(12));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        // Skip ACK frame.
-        iter.next();
+        // Client reads the data.
+        let mut r = pipe.client.readable();
+        assert_eq!(r.next(), Some(4));
+        assert_eq!(r.next(), None);
 
-        assert_eq!(
-            iter.next(),
-            Some(&frame::Frame::ResetStream {
-                stream_id: 0,
-                error_code: 42,
-                final_size: 15,
-            })
-        );
+        assert_eq!(pipe.client.stream_recv(4, &mut buf), Ok((12, true)));
 
-        // Stream is writable, but writing returns an error.
-        let mut r = pipe.server.writable();
-        assert_eq!(r.next(), Some(0));
-        assert_eq!(r.next(), None);
+        // Stream is collected on both sides.
+        assert_eq!(pipe.client.streams.len(), 0);
+        assert_eq!(pipe.server.streams.len(), 0);
 
         assert_eq!(
-            pipe.server.stream_send(0, b"world", true),
-            Err(Error::StreamStopped(42)),
+            pipe.server.stream_shutdown(4, Shutdown::Read, 0),
+            Err(Error::Done)
         );
+    }
 
-        assert_eq!(pipe.server.streams.len(), 1);
-
-        // Client acks RESET_STREAM frame.
-        let mut ranges = ranges::RangeSet::default();
-        ranges.insert(0..6);
-
-        let frames = [frame::Frame::ACK {
-            ack_delay: 15,
-            ranges,
-            ecn_counts: None,
-        }];
-
-        assert_eq!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf), Ok(0));
+    #[test]
+    fn stream_shutdown_read_update_max_data() {
+        let mut buf = [0; 65535];
 
-        // Stream is collected on the server after RESET_STREAM is acked.
-        assert_eq!(pipe.server.streams.len(), 0);
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(10000);
+        config.set_initial_max_stream_data_bidi_remote(10000);
+        config.set_initial_max_streams_bidi(10);
+        config.verify_peer(false);
 
-        // Sending STOP_SENDING again shouldn't trigger RESET_STREAM again.
-        let frames = [frame::Frame::StopSending {
-            stream_id: 0,
-            error_code: 42,
-        }];
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
 
-        let len = pipe
-            .send_pkt_to_server(pkt_type, &frames, &mut buf)
-            .unwrap();
+        assert_eq!(pipe.client.stream_send(0, b"a", false), Ok(1));
+        assert_eq!(pipe.advance(), Ok(()));
 
-        let frames =
-            testing::decode_pkt(&mut pipe.client, &mut buf[..len]).unwrap();
+        assert_eq!(pipe.server.stream_recv(0, &mut buf), Ok((1, false)));
+        assert_eq!(pipe.server.stream_shutdown(0, Shutdown::Read, 123), Ok(()));
 
-        assert_eq!(frames.len(), 1);
+        assert_eq!(pipe.server.rx_data, 1);
+        assert_eq!(pipe.client.tx_data, 1);
+        assert_eq!(pipe.client.max_tx_data, 30);
 
-        match frames.first() {
-            Some(frame::Frame::ACK { .. }) => (),
+        assert_eq!(
+            pipe.client
+                .stream_send(0, &buf[..pipe.client.tx_cap], false),
+            Ok(29)
+        );
+        assert_eq!(pipe.advance(), Ok(()));
 
-            f => panic!("expected ACK frame, got {:?}", f),
-        };
+        assert!(!pipe.server.stream_readable(0)); // nothing can be consumed
 
-        let mut r = pipe.server.writable();
-        assert_eq!(r.next(), None);
+        // The client has increased its tx_data, and server has received it, so
+        // it increases flow control accordingly.
+        assert_eq!(pipe.client.tx_data, 30);
+        assert_eq!(pipe.server.rx_data, 30);
+        assert_eq!(pipe.client.tx_cap, 45);
     }
 
     #[test]
-    fn stop_sending_fin() {
-        let mut b = [0; 15];
+    fn stream_shutdown_uni() {
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // Exchange some data on uni streams.
+        assert_eq!(pipe.client.stream_send(2, b"hello, world", false), Ok(10));
+        assert_eq!(pipe.server.stream_send(3, b"hello, world", false), Ok(10));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Test local and remote shutdown.
+        assert_eq!(pipe.client.stream_shutdown(2, Shutdown::Write, 42), Ok(()));
+        assert_eq!(
+            pipe.client.stream_shutdown(2, Shutdown::Read, 42),
+            Err(Error::InvalidStreamState(2))
+        );
 
+        assert_eq!(
+            pipe.client.stream_shutdown(3, Shutdown::Write, 42),
+            Err(Error::InvalidStreamState(3))
+        );
+        assert_eq!(pipe.client.stream_shutdown(3, Shutdown::Read, 42), Ok(()));
+    }
+
+    #[test]
+    fn stream_shutdown_write() {
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        // Client sends some data, and closes stream.
-        assert_eq!(pipe.client.stream_send(4, b"hello", true), Ok(5));
+        // Client sends some data.
+        assert_eq!(pipe.client.stream_send(4, b"hello, world", false), Ok(12));
         assert_eq!(pipe.advance(), Ok(()));
 
-        // Server gets data.
         let mut r = pipe.server.readable();
         assert_eq!(r.next(), Some(4));
         assert_eq!(r.next(), None);
 
-        assert_eq!(pipe.server.stream_recv(4, &mut b), Ok((5, true)));
-        assert!(pipe.server.stream_finished(4));
-
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), None);
-
-        // Server sends data...
         let mut r = pipe.server.writable();
         assert_eq!(r.next(), Some(4));
         assert_eq!(r.next(), None);
 
-        assert_eq!(pipe.server.stream_send(4, b"world", false), Ok(5));
+        assert_eq!(pipe.client.streams.len(), 1);
+        assert_eq!(pipe.server.streams.len(), 1);
+
+        // Server sends some data.
+        assert_eq!(pipe.server.stream_send(4, b"goodbye, world", false), Ok(14));
         assert_eq!(pipe.advance(), Ok(()));
 
-        // ...and buffers more, and closes stream.
-        assert_eq!(pipe.server.stream_send(4, b"world", true), Ok(5));
+        // Server shuts down stream.
+        assert_eq!(pipe.server.stream_shutdown(4, Shutdown::Write, 42), Ok(()));
 
-        // Client sends STOP_SENDING before server flushes stream.
-        let frames = [frame::Frame::StopSending {
-            stream_id: 4,
-            error_code: 42,
-        }];
+        let mut r = pipe.server.writable();
+        assert_eq!(r.next(), None);
 
-        let pkt_type = packet::Type::Short;
-        let len = pipe
-            .send_pkt_to_server(pkt_type, &frames, &mut buf)
-            .unwrap();
+        let (len, _) = pipe.server.send(&mut buf).unwrap();
 
-        // Server sent a RESET_STREAM frame in response.
-        let frames =
-            testing::decode_pkt(&mut pipe.client, &mut buf[..len]).unwrap();
+        let mut dummy = buf[..len].to_vec();
 
+        let frames =
+            testing::decode_pkt(&mut pipe.client, &mut dummy[..len]).unwrap();
         let mut iter = frames.iter();
 
-        // Skip ACK frame.
-        iter.next();
-
         assert_eq!(
             iter.next(),
             Some(&frame::Frame::ResetStream {
                 stream_id: 4,
                 error_code: 42,
-                final_size: 5,
+                final_size: 14,
             })
         );
 
-        // No more frames are sent by the server.
-        assert_eq!(iter.next(), None);
+        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
+
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Sending more data is forbidden.
+        assert_eq!(
+            pipe.server.stream_send(4, b"bye", false),
+            Err(Error::FinalSize)
+        );
+
+        // Client sends some data and closes the stream.
+        assert_eq!(pipe.client.stream_send(4, b"bye", true), Ok(3));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Server reads the data.
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(4));
+        assert_eq!(r.next(), None);
+
+        assert_eq!(pipe.server.stream_recv(4, &mut buf), Ok((15, true)));
+
+        // Client processes readable streams.
+        let mut r = pipe.client.readable();
+        assert_eq!(r.next(), Some(4));
+        assert_eq!(r.next(), None);
+
+        assert_eq!(
+            pipe.client.stream_recv(4, &mut buf),
+            Err(Error::StreamReset(42))
+        );
+
+        // Stream is collected on both sides.
+        assert_eq!(pipe.client.streams.len(), 0);
+        assert_eq!(pipe.server.streams.len(), 0);
+
+        assert_eq!(
+            pipe.server.stream_shutdown(4, Shutdown::Write, 0),
+            Err(Error::Done)
+        );
     }
 
     #[test]
-    /// Tests that resetting a stream restores flow control for unsent data.
-    fn stop_sending_unsent_tx_cap() {
+    fn stream_reset_at() {
         let mut buf = [0; 65535];
 
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // The peer didn't advertise support for RESET_STREAM_AT.
+        assert_eq!(pipe.client.stream_send(0, b"hello", false), Ok(5));
+        assert_eq!(
+            pipe.client.stream_reset_at(0, 42, 5),
+            Err(Error::InvalidState)
+        );
+
         let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
         config
             .load_cert_chain_from_pem_file("examples/cert.crt")
@@ -11278,194 +15124,203 @@ mod tests {
         config
             .set_application_protos(&[b"proto1", b"proto2"])
             .unwrap();
-        config.set_initial_max_data(15);
-        config.set_initial_max_stream_data_bidi_local(30);
-        config.set_initial_max_stream_data_bidi_remote(30);
-        config.set_initial_max_stream_data_uni(30);
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
         config.set_initial_max_streams_bidi(3);
-        config.set_initial_max_streams_uni(0);
         config.verify_peer(false);
+        config.enable_reset_stream_at(true);
 
         let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        // Client sends some data.
-        assert_eq!(pipe.client.stream_send(4, b"hello", true), Ok(5));
+        assert_eq!(pipe.client.stream_send(0, b"hello, world", false), Ok(12));
         assert_eq!(pipe.advance(), Ok(()));
 
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), Some(4));
-        assert_eq!(r.next(), None);
+        // Only part of the unsent data needs to be delivered.
+        assert_eq!(pipe.client.stream_send(0, b"abc", false), Ok(3));
+        assert_eq!(
+            pipe.client.stream_reset_at(0, 42, 16),
+            Err(Error::InvalidStreamState(0))
+        );
+        assert_eq!(pipe.client.stream_reset_at(0, 42, 14), Ok(()));
+        assert_eq!(pipe.client.stream_reset_at(0, 42, 14), Err(Error::Done));
+        assert_eq!(pipe.client.tx_data, 14);
 
-        let mut b = [0; 15];
-        assert_eq!(pipe.server.stream_recv(4, &mut b), Ok((5, true)));
+        assert_eq!(
+            pipe.client.stream_send(0, b"d", false),
+            Err(Error::FinalSize)
+        );
 
-        // Server sends some data.
-        assert_eq!(pipe.server.stream_send(4, b"hello", false), Ok(5));
         assert_eq!(pipe.advance(), Ok(()));
 
-        // Server buffers some data, until send capacity limit reached.
-        assert_eq!(pipe.server.stream_send(4, b"hello", false), Ok(5));
-        assert_eq!(pipe.server.stream_send(4, b"hello", false), Ok(5));
+        // The reliable data is read before the reset is reported.
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(0));
+        assert_eq!(r.next(), None);
+
+        assert_eq!(pipe.server.stream_recv(0, &mut buf), Ok((14, false)));
+        assert_eq!(&buf[..14], b"hello, worldab");
+
         assert_eq!(
-            pipe.server.stream_send(4, b"hello", false),
-            Err(Error::Done)
+            pipe.server.stream_recv(0, &mut buf),
+            Err(Error::StreamReset(42))
         );
+        assert_eq!(pipe.server.stream_recv(0, &mut buf), Err(Error::Done));
+    }
 
-        // Client sends STOP_SENDING.
-        let frames = [frame::Frame::StopSending {
-            stream_id: 4,
+    #[test]
+    fn stream_reset_at_not_advertised() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let frames = [frame::Frame::ResetStreamAt {
+            stream_id: 0,
             error_code: 42,
+            final_size: 5,
+            reliable_size: 2,
         }];
 
         let pkt_type = packet::Type::Short;
-        pipe.send_pkt_to_server(pkt_type, &frames, &mut buf)
-            .unwrap();
-
-        // Server can now send more data (on a different stream).
-        assert_eq!(pipe.client.stream_send(8, b"hello", true), Ok(5));
-        assert_eq!(pipe.advance(), Ok(()));
-
-        assert_eq!(pipe.server.stream_send(8, b"hello", false), Ok(5));
-        assert_eq!(pipe.server.stream_send(8, b"hello", false), Ok(5));
         assert_eq!(
-            pipe.server.stream_send(8, b"hello", false),
-            Err(Error::Done)
+            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
+            Err(Error::InvalidFrame)
         );
-        assert_eq!(pipe.advance(), Ok(()));
     }
 
     #[test]
-    fn stream_shutdown_read() {
+    fn ack_frequency() {
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        // Client sends some data.
-        assert_eq!(pipe.client.stream_send(4, b"hello, world", false), Ok(12));
-        assert_eq!(pipe.advance(), Ok(()));
-
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), Some(4));
-        assert_eq!(r.next(), None);
-
-        assert_eq!(pipe.client.streams.len(), 1);
-        assert_eq!(pipe.server.streams.len(), 1);
+        // The peer didn't advertise support for the ACK frequency extension.
+        assert_eq!(
+            pipe.client.send_ack_frequency(
+                2,
+                time::Duration::from_millis(100),
+                0
+            ),
+            Err(Error::InvalidState)
+        );
+        assert_eq!(pipe.client.send_immediate_ack(), Err(Error::InvalidState));
 
-        // Server shuts down stream.
-        assert_eq!(pipe.server.stream_shutdown(4, Shutdown::Read, 42), Ok(()));
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
+        config.set_initial_max_streams_bidi(3);
+        config.verify_peer(false);
 
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), None);
+        // The minimum ACK delay is clamped to the maximum ACK delay.
+        config.set_min_ack_delay(u64::MAX);
+        assert_eq!(config.local_transport_params.min_ack_delay, Some(25_000));
 
-        let (len, _) = pipe.server.send(&mut buf).unwrap();
+        config.set_min_ack_delay(1_000);
 
-        let mut dummy = buf[..len].to_vec();
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
 
-        let frames =
-            testing::decode_pkt(&mut pipe.client, &mut dummy[..len]).unwrap();
-        let mut iter = frames.iter();
+        // The requested delay is lower than the peer's minimum.
+        assert_eq!(
+            pipe.client.send_ack_frequency(
+                2,
+                time::Duration::from_micros(500),
+                0
+            ),
+            Err(Error::InvalidState)
+        );
 
         assert_eq!(
-            iter.next(),
-            Some(&frame::Frame::StopSending {
-                stream_id: 4,
-                error_code: 42,
-            })
+            pipe.client.send_ack_frequency(
+                2,
+                time::Duration::from_millis(100),
+                0
+            ),
+            Ok(())
         );
 
-        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
+        // The ACK for the ACK_FREQUENCY frame is delayed.
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        testing::process_flight(&mut pipe.server, flight).unwrap();
 
-        assert_eq!(pipe.advance(), Ok(()));
+        assert_eq!(pipe.server.send(&mut buf), Err(Error::Done));
+        assert!(pipe.server.ack_freq.ack_timer().is_some());
 
-        // Sending more data is forbidden.
-        let mut r = pipe.client.writable();
-        assert_eq!(r.next(), Some(4));
-        assert_eq!(r.next(), None);
+        assert_eq!(pipe.client.stream_send(0, b"a", false), Ok(1));
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        testing::process_flight(&mut pipe.server, flight).unwrap();
 
-        assert_eq!(
-            pipe.client.stream_send(4, b"bye", false),
-            Err(Error::StreamStopped(42))
-        );
+        assert_eq!(pipe.server.send(&mut buf), Err(Error::Done));
 
-        // Server sends some data, without reading the incoming data, and closes
-        // the stream.
-        assert_eq!(pipe.server.stream_send(4, b"hello, world", true), Ok(12));
-        assert_eq!(pipe.advance(), Ok(()));
+        // The ack-eliciting threshold is exceeded.
+        assert_eq!(pipe.client.stream_send(0, b"b", false), Ok(1));
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        testing::process_flight(&mut pipe.server, flight).unwrap();
 
-        // Client reads the data.
-        let mut r = pipe.client.readable();
-        assert_eq!(r.next(), Some(4));
-        assert_eq!(r.next(), None);
+        assert!(pipe.server.send(&mut buf).is_ok());
+        assert!(pipe.server.ack_freq.ack_timer().is_none());
 
-        assert_eq!(pipe.client.stream_recv(4, &mut buf), Ok((12, true)));
+        // IMMEDIATE_ACK is acked right away.
+        assert_eq!(pipe.client.send_immediate_ack(), Ok(()));
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        testing::process_flight(&mut pipe.server, flight).unwrap();
 
-        // Stream is collected on both sides.
-        assert_eq!(pipe.client.streams.len(), 0);
-        assert_eq!(pipe.server.streams.len(), 0);
+        let flight = testing::emit_flight(&mut pipe.server).unwrap();
+        testing::process_flight(&mut pipe.client, flight).unwrap();
 
+        // The PTO uses the requested max ACK delay once acked.
         assert_eq!(
-            pipe.server.stream_shutdown(4, Shutdown::Read, 0),
-            Err(Error::Done)
+            pipe.client.recovery_config.max_ack_delay,
+            time::Duration::from_millis(100)
         );
     }
 
     #[test]
-    fn stream_shutdown_read_after_fin() {
+    fn ack_frequency_not_advertised() {
         let mut buf = [0; 65535];
 
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        // Client sends some data.
-        assert_eq!(pipe.client.stream_send(4, b"hello, world", true), Ok(12));
-        assert_eq!(pipe.advance(), Ok(()));
-
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), Some(4));
-        assert_eq!(r.next(), None);
-
-        assert_eq!(pipe.client.streams.len(), 1);
-        assert_eq!(pipe.server.streams.len(), 1);
-
-        // Server shuts down stream.
-        assert_eq!(pipe.server.stream_shutdown(4, Shutdown::Read, 42), Ok(()));
-
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), None);
-
-        // Server has nothing to send.
-        assert_eq!(pipe.server.send(&mut buf), Err(Error::Done));
-
-        assert_eq!(pipe.advance(), Ok(()));
-
-        // Server sends some data, without reading the incoming data, and closes
-        // the stream.
-        assert_eq!(pipe.server.stream_send(4, b"hello, world", true), Ok(12));
-        assert_eq!(pipe.advance(), Ok(()));
+        let frames = [frame::Frame::AckFrequency {
+            seq_num: 0,
+            ack_eliciting_threshold: 10,
+            request_max_ack_delay: 25_000,
+            reordering_threshold: 1,
+        }];
 
-        // Client reads the data.
-        let mut r = pipe.client.readable();
-        assert_eq!(r.next(), Some(4));
-        assert_eq!(r.next(), None);
+        let pkt_type = packet::Type::Short;
+        assert_eq!(
+            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
+            Err(Error::InvalidState)
+        );
 
-        assert_eq!(pipe.client.stream_recv(4, &mut buf), Ok((12, true)));
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
 
-        // Stream is collected on both sides.
-        assert_eq!(pipe.client.streams.len(), 0);
-        assert_eq!(pipe.server.streams.len(), 0);
+        let frames = [frame::Frame::ImmediateAck];
 
         assert_eq!(
-            pipe.server.stream_shutdown(4, Shutdown::Read, 0),
-            Err(Error::Done)
+            pipe.send_pkt_to_server(pkt_type, &frames, &mut buf),
+            Err(Error::InvalidState)
         );
     }
 
     #[test]
-    fn stream_shutdown_read_update_max_data() {
-        let mut buf = [0; 65535];
-
+    fn ecn() {
         let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
         config
             .load_cert_chain_from_pem_file("examples/cert.crt")
@@ -11477,152 +15332,203 @@ mod tests {
             .set_application_protos(&[b"proto1", b"proto2"])
             .unwrap();
         config.set_initial_max_data(30);
-        config.set_initial_max_stream_data_bidi_local(10000);
-        config.set_initial_max_stream_data_bidi_remote(10000);
-        config.set_initial_max_streams_bidi(10);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
+        config.set_initial_max_streams_bidi(3);
         config.verify_peer(false);
+        config.enable_ecn(true);
 
         let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
+        let cwnd = pipe.client.paths.get_active().unwrap().recovery.cwnd();
+
         assert_eq!(pipe.client.stream_send(0, b"a", false), Ok(1));
-        assert_eq!(pipe.advance(), Ok(()));
+        let mut flight = testing::emit_flight(&mut pipe.client).unwrap();
 
-        assert_eq!(pipe.server.stream_recv(0, &mut buf), Ok((1, false)));
-        assert_eq!(pipe.server.stream_shutdown(0, Shutdown::Read, 123), Ok(()));
+        // Packets are marked as ECT(0), and the network marks them as
+        // congestion experienced.
+        for (_, si) in flight.iter_mut() {
+            assert_eq!(si.ecn, Ecn::Ect0);
+            si.ecn = Ecn::Ce;
+        }
 
-        assert_eq!(pipe.server.rx_data, 1);
-        assert_eq!(pipe.client.tx_data, 1);
-        assert_eq!(pipe.client.max_tx_data, 30);
+        testing::process_flight(&mut pipe.server, flight).unwrap();
 
-        assert_eq!(
-            pipe.client
-                .stream_send(0, &buf[..pipe.client.tx_cap], false),
-            Ok(29)
-        );
-        assert_eq!(pipe.advance(), Ok(()));
+        // The server reports the marks it received from the client.
+        let counts = pipe.server.pkt_num_spaces[packet::Epoch::Application]
+            .ecn_counts()
+            .unwrap();
+        assert!(counts.ecn_ce_count > 0);
 
-        assert!(!pipe.server.stream_readable(0)); // nothing can be consumed
+        let flight = testing::emit_flight(&mut pipe.server).unwrap();
+        testing::process_flight(&mut pipe.client, flight).unwrap();
 
-        // The client has increased its tx_data, and server has received it, so
-        // it increases flow control accordingly.
-        assert_eq!(pipe.client.tx_data, 30);
-        assert_eq!(pipe.server.rx_data, 30);
-        assert_eq!(pipe.client.tx_cap, 45);
+        // The CE mark triggers a congestion response.
+        assert!(pipe.client.paths.get_active().unwrap().recovery.cwnd() < cwnd);
     }
 
     #[test]
-    fn stream_shutdown_uni() {
+    fn ecn_disabled() {
         let mut pipe = testing::Pipe::new().unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        // Exchange some data on uni streams.
-        assert_eq!(pipe.client.stream_send(2, b"hello, world", false), Ok(10));
-        assert_eq!(pipe.server.stream_send(3, b"hello, world", false), Ok(10));
-        assert_eq!(pipe.advance(), Ok(()));
-
-        // Test local and remote shutdown.
-        assert_eq!(pipe.client.stream_shutdown(2, Shutdown::Write, 42), Ok(()));
-        assert_eq!(
-            pipe.client.stream_shutdown(2, Shutdown::Read, 42),
-            Err(Error::InvalidStreamState(2))
-        );
+        assert_eq!(pipe.client.stream_send(0, b"a", false), Ok(1));
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
 
-        assert_eq!(
-            pipe.client.stream_shutdown(3, Shutdown::Write, 42),
-            Err(Error::InvalidStreamState(3))
-        );
-        assert_eq!(pipe.client.stream_shutdown(3, Shutdown::Read, 42), Ok(()));
+        for (_, si) in flight.iter() {
+            assert_eq!(si.ecn, Ecn::NotEct);
+        }
     }
 
     #[test]
-    fn stream_shutdown_write() {
-        let mut buf = [0; 65535];
+    fn ecn_coalesced() {
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.verify_peer(false);
+        config.enable_ecn(true);
 
-        let mut pipe = testing::Pipe::new().unwrap();
-        assert_eq!(pipe.handshake(), Ok(()));
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
 
-        // Client sends some data.
-        assert_eq!(pipe.client.stream_send(4, b"hello, world", false), Ok(12));
-        assert_eq!(pipe.advance(), Ok(()));
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        testing::process_flight(&mut pipe.server, flight).unwrap();
 
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), Some(4));
-        assert_eq!(r.next(), None);
+        // Make the server's Initial packet the last testing packet, so that
+        // the Handshake packet coalesced with it is sent after the
+        // validation state changed.
+        let recovery = &mut pipe.server.paths.get_active_mut().unwrap().recovery;
 
-        let mut r = pipe.server.writable();
-        assert_eq!(r.next(), Some(4));
-        assert_eq!(r.next(), None);
+        for pn in 0..9 {
+            recovery.on_ecn_packet_sent(
+                1_000 + pn,
+                packet::Epoch::Application,
+                Ecn::Ect0,
+            );
+        }
 
-        assert_eq!(pipe.client.streams.len(), 1);
-        assert_eq!(pipe.server.streams.len(), 1);
+        let flight = testing::emit_flight(&mut pipe.server).unwrap();
 
-        // Server sends some data.
-        assert_eq!(pipe.server.stream_send(4, b"goodbye, world", false), Ok(14));
-        assert_eq!(pipe.advance(), Ok(()));
+        assert_eq!(flight[0].1.ecn, Ecn::Ect0);
 
-        // Server shuts down stream.
-        assert_eq!(pipe.server.stream_shutdown(4, Shutdown::Write, 42), Ok(()));
+        for (_, si) in flight.iter().skip(1) {
+            assert_eq!(si.ecn, Ecn::NotEct);
+        }
 
-        let mut r = pipe.server.writable();
-        assert_eq!(r.next(), None);
+        testing::process_flight(&mut pipe.client, flight).unwrap();
 
-        let (len, _) = pipe.server.send(&mut buf).unwrap();
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        testing::process_flight(&mut pipe.server, flight).unwrap();
 
-        let mut dummy = buf[..len].to_vec();
+        // The client reported the marked Handshake packet, which doesn't fail
+        // validation.
+        assert_eq!(
+            pipe.server
+                .paths
+                .get_active()
+                .unwrap()
+                .recovery
+                .ecn_codepoint(),
+            Ecn::Ect0
+        );
+    }
 
-        let frames =
-            testing::decode_pkt(&mut pipe.client, &mut dummy[..len]).unwrap();
-        let mut iter = frames.iter();
+    #[test]
+    fn careful_resume_params() {
+        let mut pipe = testing::Pipe::new().unwrap();
+
+        assert_eq!(pipe.client.careful_resume_params(), None);
+
+        let saved = CarefulResumeParams {
+            cwnd: 100_000,
+            rtt: time::Duration::from_millis(50),
+            delivery_rate: 0,
+        };
 
         assert_eq!(
-            iter.next(),
-            Some(&frame::Frame::ResetStream {
-                stream_id: 4,
-                error_code: 42,
-                final_size: 14,
-            })
+            pipe.client.set_careful_resume_params(&CarefulResumeParams {
+                cwnd: 0,
+                ..saved
+            }),
+            Err(Error::InvalidState)
         );
+        assert_eq!(pipe.client.set_careful_resume_params(&saved), Ok(()));
 
-        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
-
-        assert_eq!(pipe.advance(), Ok(()));
+        assert_eq!(pipe.handshake(), Ok(()));
 
-        // Sending more data is forbidden.
+        // An RTT sample was already taken.
         assert_eq!(
-            pipe.server.stream_send(4, b"bye", false),
-            Err(Error::FinalSize)
+            pipe.client.set_careful_resume_params(&saved),
+            Err(Error::InvalidState)
         );
 
-        // Client sends some data and closes the stream.
-        assert_eq!(pipe.client.stream_send(4, b"bye", true), Ok(3));
-        assert_eq!(pipe.advance(), Ok(()));
+        let params = pipe.client.careful_resume_params().unwrap();
+        let path = pipe.client.paths.get_active().unwrap();
 
-        // Server reads the data.
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), Some(4));
-        assert_eq!(r.next(), None);
+        assert_eq!(params.cwnd, path.recovery.cwnd());
+        assert_eq!(Some(params.rtt), path.recovery.min_rtt());
+    }
 
-        assert_eq!(pipe.server.stream_recv(4, &mut buf), Ok((15, true)));
+    #[test]
+    fn recovery_events() {
+        let mut pipe = testing::Pipe::new().unwrap();
 
-        // Client processes readable streams.
-        let mut r = pipe.client.readable();
-        assert_eq!(r.next(), Some(4));
-        assert_eq!(r.next(), None);
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let handler_events = events.clone();
 
-        assert_eq!(
-            pipe.client.stream_recv(4, &mut buf),
-            Err(Error::StreamReset(42))
-        );
+        pipe.client.set_recovery_event_handler(Box::new(move |ev| {
+            handler_events.lock().unwrap().push(ev.clone());
+        }));
+
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // The client took RTT samples during the handshake.
+        assert!(events
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|ev| matches!(ev, RecoveryEvent::RttUpdated { .. })));
+
+        events.lock().unwrap().clear();
+
+        // The first packet is lost.
+        assert_eq!(pipe.client.stream_send(0, b"a", false), Ok(1));
+        testing::emit_flight(&mut pipe.client).unwrap();
+
+        // The following packets are received.
+        for _ in 0..3 {
+            assert_eq!(pipe.client.stream_send(0, b"b", false), Ok(1));
+            let flight = testing::emit_flight(&mut pipe.client).unwrap();
+            testing::process_flight(&mut pipe.server, flight).unwrap();
+        }
+
+        let flight = testing::emit_flight(&mut pipe.server).unwrap();
+        testing::process_flight(&mut pipe.client, flight).unwrap();
 
-        // Stream is collected on both sides.
-        assert_eq!(pipe.client.streams.len(), 0);
-        assert_eq!(pipe.server.streams.len(), 0);
+        let path = pipe.client.paths.get_active().unwrap();
+        let local_addr = path.local_addr();
+        let peer_addr = path.peer_addr();
 
-        assert_eq!(
-            pipe.server.stream_shutdown(4, Shutdown::Write, 0),
-            Err(Error::Done)
-        );
+        let events = events.lock().unwrap();
+
+        assert!(matches!(events[0], RecoveryEvent::RttUpdated { .. }));
+        assert!(matches!(events[1], RecoveryEvent::PacketsLost {
+            packets: 1,
+            ..
+        }));
+        assert_eq!(events[2], RecoveryEvent::CongestionWindowUpdated {
+            local_addr,
+            peer_addr,
+            cwnd: path.recovery.cwnd(),
+        });
+        assert_eq!(events.len(), 3);
     }
 
     #[test]
@@ -11981,7 +15887,9 @@ mod tests {
         let mut buf = [0; 65535];
         let mut pipe = testing::Pipe::new().unwrap();
 
-        let frames = [frame::Frame::Padding { len: 10 }];
+        let frames = [frame::Frame::Padding {
+            len: MIN_CLIENT_INITIAL_LEN,
+        }];
 
         let written = testing::encode_pkt(
             &mut pipe.client,
@@ -12006,6 +15914,36 @@ mod tests {
         assert!(pipe.server.is_closed());
     }
 
+    #[test]
+    /// Tests that Initial packets carried in datagrams smaller than the
+    /// minimum client Initial size are discarded by the server.
+    fn undersized_initial_server() {
+        let mut buf = [0; 65535];
+        let mut pipe = testing::Pipe::new().unwrap();
+
+        let frames = [frame::Frame::Ping { mtu_probe: None }];
+
+        let written = testing::encode_pkt(
+            &mut pipe.client,
+            packet::Type::Initial,
+            &frames,
+            &mut buf,
+        )
+        .unwrap();
+        assert!(written < MIN_CLIENT_INITIAL_LEN);
+
+        assert_eq!(pipe.server_recv(&mut buf[..written]), Ok(written));
+        assert_eq!(pipe.server.recv_count, 0);
+        assert!(!pipe.server.is_closed());
+
+        // A properly padded Initial is accepted.
+        let (len, _) = pipe.client.send(&mut buf).unwrap();
+        assert_eq!(len, MIN_CLIENT_INITIAL_LEN);
+
+        assert_eq!(pipe.server_recv(&mut buf[..len]), Ok(len));
+        assert_eq!(pipe.server.recv_count, 1);
+    }
+
     #[test]
     /// Tests that invalid Initial packets received to cause
     /// the client to close the connection immediately.
@@ -12071,6 +16009,7 @@ mod tests {
             token: pipe.client.token.clone(),
             versions: None,
             key_phase: false,
+            spin_bit: false,
         };
 
         hdr.to_bytes(&mut b).unwrap();
@@ -12085,7 +16024,9 @@ mod tests {
 
         let payload_offset = b.off();
 
-        let frames = [frame::Frame::Padding { len: 10 }];
+        let frames = [frame::Frame::Padding {
+            len: MIN_CLIENT_INITIAL_LEN,
+        }];
 
         for frame in &frames {
             frame.to_bytes(&mut b).unwrap();
@@ -12504,160 +16445,520 @@ mod tests {
         assert_eq!(pipe.handshake(), Ok(()));
 
         assert_eq!(
-            pipe.client.stream_send(0, b"aaaaaaaaaaaaaaa", false),
-            Ok(15)
+            pipe.client.stream_send(0, b"aaaaaaaaaaaaaaa", false),
+            Ok(15)
+        );
+        assert_eq!(pipe.advance(), Ok(()));
+
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(0));
+        assert!(r.next().is_none());
+
+        let mut b = [0; 15];
+        pipe.server.stream_recv(0, &mut b).unwrap();
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Client sends zero-length frame.
+        assert_eq!(pipe.client.stream_send(0, b"", true), Ok(0));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Server sends zero-length frame.
+        assert_eq!(pipe.server.stream_send(0, b"", true), Ok(0));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Stream should be readable on the server after receiving empty fin.
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), Some(0));
+        assert!(r.next().is_none());
+
+        let mut b = [0; 15];
+        pipe.server.stream_recv(0, &mut b).unwrap();
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Client sends zero-length frame (again).
+        assert_eq!(pipe.client.stream_send(0, b"", true), Ok(0));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Stream should _not_ be readable on the server after receiving empty
+        // fin, because it was already finished.
+        let mut r = pipe.server.readable();
+        assert_eq!(r.next(), None);
+
+        // Stream _is_readable on the client side.
+        let mut r = pipe.client.readable();
+        assert_eq!(r.next(), Some(0));
+
+        pipe.client.stream_recv(0, &mut b).unwrap();
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Stream is completed and _is not_ readable.
+        let mut r = pipe.client.readable();
+        assert_eq!(r.next(), None);
+    }
+
+    #[test]
+    /// Tests that the stream gets created with stream_send() even if there's
+    /// no data in the buffer and the fin flag is not set.
+    fn stream_zero_length_non_fin() {
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert_eq!(pipe.client.stream_send(0, b"", false), Ok(0));
+
+        // The stream now should have been created.
+        assert_eq!(pipe.client.streams.len(), 1);
+        assert_eq!(pipe.advance(), Ok(()));
+
+        // Sending an empty non-fin should not change any stream state on the
+        // other side.
+        let mut r = pipe.server.readable();
+        assert!(r.next().is_none());
+    }
+
+    #[test]
+    /// Tests that completed streams are garbage collected.
+    fn collect_streams() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert_eq!(pipe.client.streams.len(), 0);
+        assert_eq!(pipe.server.streams.len(), 0);
+
+        assert_eq!(pipe.client.stream_send(0, b"aaaaa", true), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert!(!pipe.client.stream_finished(0));
+        assert!(!pipe.server.stream_finished(0));
+
+        assert_eq!(pipe.client.streams.len(), 1);
+        assert_eq!(pipe.server.streams.len(), 1);
+
+        let mut b = [0; 5];
+        pipe.server.stream_recv(0, &mut b).unwrap();
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(pipe.server.stream_send(0, b"aaaaa", true), Ok(5));
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert!(!pipe.client.stream_finished(0));
+        assert!(pipe.server.stream_finished(0));
+
+        assert_eq!(pipe.client.streams.len(), 1);
+        assert_eq!(pipe.server.streams.len(), 0);
+
+        let mut b = [0; 5];
+        pipe.client.stream_recv(0, &mut b).unwrap();
+        assert_eq!(pipe.advance(), Ok(()));
+
+        assert_eq!(pipe.client.streams.len(), 0);
+        assert_eq!(pipe.server.streams.len(), 0);
+
+        assert!(pipe.client.stream_finished(0));
+        assert!(pipe.server.stream_finished(0));
+
+        assert_eq!(pipe.client.stream_send(0, b"", true), Err(Error::Done));
+
+        let frames = [frame::Frame::Stream {
+            stream_id: 0,
+            data: stream::RangeBuf::from(b"aa", 0, false),
+        }];
+
+        let pkt_type = packet::Type::Short;
+        assert_eq!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf), Ok(39));
+    }
+
+    #[test]
+    fn config_set_cc_algorithm_name() {
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+
+        assert_eq!(config.set_cc_algorithm_name("reno"), Ok(()));
+
+        // Unknown name.
+        assert_eq!(
+            config.set_cc_algorithm_name("???"),
+            Err(Error::CongestionControl)
+        );
+    }
+
+    #[test]
+    fn peer_cert() {
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        match pipe.client.peer_cert() {
+            Some(c) => assert_eq!(c.len(), 753),
+
+            None => panic!("missing server certificate"),
+        }
+    }
+
+    #[test]
+    fn peer_cert_chain() {
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert-big.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+
+        let mut pipe = testing::Pipe::with_server_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        match pipe.client.peer_cert_chain() {
+            Some(c) => assert_eq!(c.len(), 5),
+
+            None => panic!("missing server certificate chain"),
+        }
+    }
+
+    #[test]
+    fn tls_crypto_policy() {
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.verify_peer(false);
+
+        assert_eq!(config.set_groups(&["bogus"]), Err(Error::TlsFail));
+        assert_eq!(
+            config.set_signature_algorithms(&["bogus"]),
+            Err(Error::TlsFail)
+        );
+
+        // Neither of these is the default.
+        config.set_groups(&["P-384"]).unwrap();
+        config
+            .set_signature_algorithms(&["rsa_pss_rsae_sha384"])
+            .unwrap();
+
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert_eq!(pipe.client.handshake.curve(), Some("P-384".to_string()));
+        assert_eq!(
+            pipe.client.handshake.sigalg(),
+            Some("rsa_pss_rsae_sha384".to_string())
+        );
+    }
+
+    #[test]
+    fn tls_cipher_suites() {
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.verify_peer(false);
+
+        assert_eq!(config.set_cipher_suites(&["bogus"]), Err(Error::TlsFail));
+
+        config
+            .set_cipher_suites(&["bogus", "TLS_AES_256_GCM_SHA384"])
+            .unwrap();
+
+        let mut pipe = testing::Pipe::with_client_config(&mut config).unwrap();
+
+        // BoringSSL always offers all cipher suites, and the server picks
+        // AES-128-GCM, which the client doesn't allow.
+        #[cfg(not(feature = "openssl"))]
+        assert!(pipe.handshake().is_err());
+
+        #[cfg(feature = "openssl")]
+        {
+            assert_eq!(pipe.handshake(), Ok(()));
+            assert_eq!(
+                pipe.client.handshake.cipher(),
+                Some(crypto::Algorithm::AES256_GCM)
+            );
+        }
+
+        config
+            .set_cipher_suites(&["TLS_AES_128_GCM_SHA256"])
+            .unwrap();
+
+        let mut pipe = testing::Pipe::with_client_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+        assert_eq!(
+            pipe.client.handshake.cipher(),
+            Some(crypto::Algorithm::AES128_GCM)
         );
-        assert_eq!(pipe.advance(), Ok(()));
-
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), Some(0));
-        assert!(r.next().is_none());
+    }
 
-        let mut b = [0; 15];
-        pipe.server.stream_recv(0, &mut b).unwrap();
-        assert_eq!(pipe.advance(), Ok(()));
+    /// Performs a handshake where the server presents `examples/cert-big.crt`
+    /// for "quic.tech" instead of its default certificate.
+    ///
+    /// `configure` is called with the shared config and the certificate
+    /// before the handshake starts.
+    fn server_name_cert_handshake(
+        configure: impl FnOnce(&mut Config, &mut Certificate),
+    ) -> testing::Pipe {
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.verify_peer(false);
 
-        // Client sends zero-length frame.
-        assert_eq!(pipe.client.stream_send(0, b"", true), Ok(0));
-        assert_eq!(pipe.advance(), Ok(()));
+        let mut cert = Certificate::from_pem_files(
+            "examples/cert-big.crt",
+            "examples/cert.key",
+        )
+        .unwrap();
 
-        // Server sends zero-length frame.
-        assert_eq!(pipe.server.stream_send(0, b"", true), Ok(0));
-        assert_eq!(pipe.advance(), Ok(()));
+        configure(&mut config, &mut cert);
 
-        // Stream should be readable on the server after receiving empty fin.
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), Some(0));
-        assert!(r.next().is_none());
+        let cert = Arc::new(cert);
 
-        let mut b = [0; 15];
-        pipe.server.stream_recv(0, &mut b).unwrap();
-        assert_eq!(pipe.advance(), Ok(()));
+        config.set_certificate_selector(move |server_name| {
+            if server_name == "quic.tech" {
+                return Some(cert.clone());
+            }
 
-        // Client sends zero-length frame (again).
-        assert_eq!(pipe.client.stream_send(0, b"", true), Ok(0));
-        assert_eq!(pipe.advance(), Ok(()));
+            None
+        });
 
-        // Stream should _not_ be readable on the server after receiving empty
-        // fin, because it was already finished.
-        let mut r = pipe.server.readable();
-        assert_eq!(r.next(), None);
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
 
-        // Stream _is_readable on the client side.
-        let mut r = pipe.client.readable();
-        assert_eq!(r.next(), Some(0));
+        // The certificate for the requested name was used.
+        match pipe.client.peer_cert_chain() {
+            Some(c) => assert_eq!(c.len(), 5),
 
-        pipe.client.stream_recv(0, &mut b).unwrap();
-        assert_eq!(pipe.advance(), Ok(()));
+            None => panic!("missing server certificate chain"),
+        }
 
-        // Stream is completed and _is not_ readable.
-        let mut r = pipe.client.readable();
-        assert_eq!(r.next(), None);
+        pipe
     }
 
     #[test]
-    /// Tests that the stream gets created with stream_send() even if there's
-    /// no data in the buffer and the fin flag is not set.
-    fn stream_zero_length_non_fin() {
-        let mut pipe = testing::Pipe::new().unwrap();
-        assert_eq!(pipe.handshake(), Ok(()));
-
-        assert_eq!(pipe.client.stream_send(0, b"", false), Ok(0));
-
-        // The stream now should have been created.
-        assert_eq!(pipe.client.streams.len(), 1);
-        assert_eq!(pipe.advance(), Ok(()));
+    fn tls_crypto_policy_server_name_cert() {
+        let pipe = server_name_cert_handshake(|config, _| {
+            config.set_groups(&["P-256"]).unwrap();
+            config
+                .set_signature_algorithms(&["rsa_pss_rsae_sha384"])
+                .unwrap();
+        });
 
-        // Sending an empty non-fin should not change any stream state on the
-        // other side.
-        let mut r = pipe.server.readable();
-        assert!(r.next().is_none());
+        assert_eq!(pipe.client.handshake.curve(), Some("P-256".to_string()));
+        assert_eq!(
+            pipe.client.handshake.sigalg(),
+            Some("rsa_pss_rsae_sha384".to_string())
+        );
     }
 
     #[test]
-    /// Tests that completed streams are garbage collected.
-    fn collect_streams() {
-        let mut buf = [0; 65535];
+    fn cert_compression() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::atomic::Ordering;
+
+        static COMPRESSED: AtomicUsize = AtomicUsize::new(0);
+        static DECOMPRESSED: AtomicUsize = AtomicUsize::new(0);
+
+        // Not really compression, but enough to check that the functions
+        // are used on both sides.
+        fn compress(cert: &[u8]) -> Option<Vec<u8>> {
+            COMPRESSED.fetch_add(1, Ordering::SeqCst);
+            Some(cert.iter().rev().copied().collect())
+        }
 
-        let mut pipe = testing::Pipe::new().unwrap();
+        fn decompress(buf: &[u8], len: usize) -> Option<Vec<u8>> {
+            DECOMPRESSED.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(buf.len(), len);
+            Some(buf.iter().rev().copied().collect())
+        }
+
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.verify_peer(false);
+        config
+            .add_cert_compression(
+                CertificateCompressionAlgorithm::Brotli,
+                compress,
+                decompress,
+            )
+            .unwrap();
+
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
         assert_eq!(pipe.handshake(), Ok(()));
 
-        assert_eq!(pipe.client.streams.len(), 0);
-        assert_eq!(pipe.server.streams.len(), 0);
+        assert_eq!(COMPRESSED.load(Ordering::SeqCst), 1);
+        assert_eq!(DECOMPRESSED.load(Ordering::SeqCst), 1);
 
-        assert_eq!(pipe.client.stream_send(0, b"aaaaa", true), Ok(5));
-        assert_eq!(pipe.advance(), Ok(()));
+        assert!(pipe.client.peer_cert().is_some());
+    }
 
-        assert!(!pipe.client.stream_finished(0));
-        assert!(!pipe.server.stream_finished(0));
+    #[test]
+    fn cert_compression_server_name_cert() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::atomic::Ordering;
 
-        assert_eq!(pipe.client.streams.len(), 1);
-        assert_eq!(pipe.server.streams.len(), 1);
+        static COMPRESSED: AtomicUsize = AtomicUsize::new(0);
 
-        let mut b = [0; 5];
-        pipe.server.stream_recv(0, &mut b).unwrap();
-        assert_eq!(pipe.advance(), Ok(()));
+        fn compress(cert: &[u8]) -> Option<Vec<u8>> {
+            COMPRESSED.fetch_add(1, Ordering::SeqCst);
+            Some(cert.iter().rev().copied().collect())
+        }
 
-        assert_eq!(pipe.server.stream_send(0, b"aaaaa", true), Ok(5));
-        assert_eq!(pipe.advance(), Ok(()));
+        fn decompress(buf: &[u8], _len: usize) -> Option<Vec<u8>> {
+            Some(buf.iter().rev().copied().collect())
+        }
 
-        assert!(!pipe.client.stream_finished(0));
-        assert!(pipe.server.stream_finished(0));
+        server_name_cert_handshake(|config, _| {
+            config
+                .add_cert_compression(
+                    CertificateCompressionAlgorithm::Brotli,
+                    compress,
+                    decompress,
+                )
+                .unwrap();
+        });
 
-        assert_eq!(pipe.client.streams.len(), 1);
-        assert_eq!(pipe.server.streams.len(), 0);
+        assert_eq!(COMPRESSED.load(Ordering::SeqCst), 1);
+    }
 
-        let mut b = [0; 5];
-        pipe.client.stream_recv(0, &mut b).unwrap();
-        assert_eq!(pipe.advance(), Ok(()));
+    #[test]
+    fn ocsp_stapling() {
+        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.verify_peer(false);
+        config.set_ocsp_response(b"ocsp response").unwrap();
 
-        assert_eq!(pipe.client.streams.len(), 0);
-        assert_eq!(pipe.server.streams.len(), 0);
+        // The client didn't request a stapled response.
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
 
-        assert!(pipe.client.stream_finished(0));
-        assert!(pipe.server.stream_finished(0));
+        assert_eq!(pipe.client.peer_ocsp_response(), None);
 
-        assert_eq!(pipe.client.stream_send(0, b"", true), Err(Error::Done));
+        config.enable_ocsp_stapling();
 
-        let frames = [frame::Frame::Stream {
-            stream_id: 0,
-            data: stream::RangeBuf::from(b"aa", 0, false),
-        }];
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
 
-        let pkt_type = packet::Type::Short;
-        assert_eq!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf), Ok(39));
+        assert_eq!(
+            pipe.client.peer_ocsp_response(),
+            Some(&b"ocsp response"[..])
+        );
+        assert_eq!(pipe.server.peer_ocsp_response(), None);
     }
 
     #[test]
-    fn config_set_cc_algorithm_name() {
-        let mut config = Config::new(PROTOCOL_VERSION).unwrap();
+    fn ocsp_stapling_server_name_cert() {
+        // The default certificate's response isn't stapled with the selected
+        // one.
+        let pipe = server_name_cert_handshake(|config, _| {
+            config.set_ocsp_response(b"default response").unwrap();
+            config.enable_ocsp_stapling();
+        });
 
-        assert_eq!(config.set_cc_algorithm_name("reno"), Ok(()));
+        assert_eq!(pipe.client.peer_ocsp_response(), None);
+
+        let pipe = server_name_cert_handshake(|config, cert| {
+            config.set_ocsp_response(b"default response").unwrap();
+            config.enable_ocsp_stapling();
+
+            cert.set_ocsp_response(b"quic.tech response").unwrap();
+        });
 
-        // Unknown name.
         assert_eq!(
-            config.set_cc_algorithm_name("???"),
-            Err(Error::CongestionControl)
+            pipe.client.peer_ocsp_response(),
+            Some(&b"quic.tech response"[..])
         );
     }
 
     #[test]
-    fn peer_cert() {
+    fn export_keying_material() {
         let mut pipe = testing::Pipe::new().unwrap();
+
+        assert_eq!(
+            pipe.client
+                .export_keying_material(b"EXPORTER-test", b"", 32),
+            Err(Error::InvalidState)
+        );
+
         assert_eq!(pipe.handshake(), Ok(()));
 
-        match pipe.client.peer_cert() {
-            Some(c) => assert_eq!(c.len(), 753),
+        let client_secret = pipe
+            .client
+            .export_keying_material(b"EXPORTER-test", b"ctx", 32)
+            .unwrap();
+        let server_secret = pipe
+            .server
+            .export_keying_material(b"EXPORTER-test", b"ctx", 32)
+            .unwrap();
 
-            None => panic!("missing server certificate"),
-        }
+        assert_eq!(client_secret.len(), 32);
+        assert_eq!(client_secret, server_secret);
+
+        // Different labels or contexts produce different secrets.
+        assert_ne!(
+            pipe.client
+                .export_keying_material(b"EXPORTER-other", b"ctx", 32)
+                .unwrap(),
+            client_secret
+        );
+        assert_ne!(
+            pipe.client
+                .export_keying_material(b"EXPORTER-test", b"", 32)
+                .unwrap(),
+            client_secret
+        );
     }
 
     #[test]
-    fn peer_cert_chain() {
+    fn server_name_cert() {
+        assert!(matches!(
+            Certificate::from_pem_files(
+                "examples/missing.crt",
+                "examples/cert.key"
+            ),
+            Err(Error::TlsFail)
+        ));
+
+        let pipe = server_name_cert_handshake(|_, _| ());
+
+        assert_eq!(pipe.server.server_name(), Some("quic.tech"));
+        assert_eq!(pipe.client.application_proto(), b"proto1");
+    }
+
+    #[test]
+    fn retry() {
+        let mut buf = [0; 65535];
+
         let mut config = Config::new(PROTOCOL_VERSION).unwrap();
         config
-            .load_cert_chain_from_pem_file("examples/cert-big.crt")
+            .load_cert_chain_from_pem_file("examples/cert.crt")
             .unwrap();
         config
             .load_priv_key_from_pem_file("examples/cert.key")
@@ -12667,17 +16968,59 @@ mod tests {
             .unwrap();
 
         let mut pipe = testing::Pipe::with_server_config(&mut config).unwrap();
-        assert_eq!(pipe.handshake(), Ok(()));
 
-        match pipe.client.peer_cert_chain() {
-            Some(c) => assert_eq!(c.len(), 5),
+        // Client sends initial flight.
+        let (mut len, _) = pipe.client.send(&mut buf).unwrap();
+
+        // Server sends Retry packet.
+        let hdr = Header::from_slice(&buf[..len], MAX_CONN_ID_LEN).unwrap();
+
+        let odcid = hdr.dcid.clone();
+
+        let mut scid = [0; MAX_CONN_ID_LEN];
+        rand::rand_bytes(&mut scid[..]);
+        let scid = ConnectionId::from_ref(&scid);
+
+        let token = b"quiche test retry token";
+
+        len = packet::retry(
+            &hdr.scid,
+            &hdr.dcid,
+            &scid,
+            token,
+            hdr.version,
+            &mut buf,
+        )
+        .unwrap();
+
+        // Client receives Retry and sends new Initial.
+        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
+
+        let (len, _) = pipe.client.send(&mut buf).unwrap();
+
+        let hdr = Header::from_slice(&buf[..len], MAX_CONN_ID_LEN).unwrap();
+        assert_eq!(&hdr.token.unwrap(), token);
+
+        // Server accepts connection.
+        let from = "127.0.0.1:1234".parse().unwrap();
+        pipe.server = accept(
+            &scid,
+            Some(&odcid),
+            testing::Pipe::server_addr(),
+            from,
+            &mut config,
+        )
+        .unwrap();
+        assert_eq!(pipe.server_recv(&mut buf[..len]), Ok(len));
+
+        assert_eq!(pipe.advance(), Ok(()));
 
-            None => panic!("missing server certificate chain"),
-        }
+        assert!(pipe.client.is_established());
+        assert!(pipe.server.is_established());
     }
 
     #[test]
-    fn retry() {
+    fn retry_invalid_integrity_tag() {
         let mut buf = [0; 65535];
 
         let mut config = Config::new(PROTOCOL_VERSION).unwrap();
@@ -12697,9 +17040,7 @@ mod tests {
         let (mut len, _) = pipe.client.send(&mut buf).unwrap();
 
         // Server sends Retry packet.
-        let hdr = Header::from_slice(&mut buf[..len], MAX_CONN_ID_LEN).unwrap();
-
-        let odcid = hdr.dcid.clone();
+        let hdr = Header::from_slice(&buf[..len], MAX_CONN_ID_LEN).unwrap();
 
         let mut scid = [0; MAX_CONN_ID_LEN];
         rand::rand_bytes(&mut scid[..]);
@@ -12717,30 +17058,15 @@ mod tests {
         )
         .unwrap();
 
-        // Client receives Retry and sends new Initial.
-        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
-
-        let (len, _) = pipe.client.send(&mut buf).unwrap();
-
-        let hdr = Header::from_slice(&mut buf[..len], MAX_CONN_ID_LEN).unwrap();
-        assert_eq!(&hdr.token.unwrap(), token);
-
-        // Server accepts connection.
-        let from = "127.0.0.1:1234".parse().unwrap();
-        pipe.server = accept(
-            &scid,
-            Some(&odcid),
-            testing::Pipe::server_addr(),
-            from,
-            &mut config,
-        )
-        .unwrap();
-        assert_eq!(pipe.server_recv(&mut buf[..len]), Ok(len));
+        // Corrupt the integrity tag.
+        buf[len - 1] ^= 0xff;
 
-        assert_eq!(pipe.advance(), Ok(()));
+        // Client silently drops the forged Retry.
+        assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
 
-        assert!(pipe.client.is_established());
-        assert!(pipe.server.is_established());
+        assert!(!pipe.client.did_retry);
+        assert_eq!(pipe.client.token, None);
+        assert_eq!(pipe.client.odcid, None);
     }
 
     #[test]
@@ -12764,7 +17090,7 @@ mod tests {
         let (mut len, _) = pipe.client.send(&mut buf).unwrap();
 
         // Server sends Retry packet.
-        let hdr = Header::from_slice(&mut buf[..len], MAX_CONN_ID_LEN).unwrap();
+        let hdr = Header::from_slice(&buf[..len], MAX_CONN_ID_LEN).unwrap();
 
         let mut scid = [0; MAX_CONN_ID_LEN];
         rand::rand_bytes(&mut scid[..]);
@@ -12824,7 +17150,7 @@ mod tests {
         let (mut len, _) = pipe.client.send(&mut buf).unwrap();
 
         // Server sends Retry packet.
-        let hdr = Header::from_slice(&mut buf[..len], MAX_CONN_ID_LEN).unwrap();
+        let hdr = Header::from_slice(&buf[..len], MAX_CONN_ID_LEN).unwrap();
 
         let mut scid = [0; MAX_CONN_ID_LEN];
         rand::rand_bytes(&mut scid[..]);
@@ -14184,6 +18510,34 @@ mod tests {
         assert_eq!(pipe.server.sent_count, pipe.client.recv_count);
     }
 
+    #[test]
+    /// Tests that coalesced packets are all processed even when followed by
+    /// trailing garbage in the same datagram.
+    fn coalesce_trailing_garbage() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+
+        // Client sends first flight.
+        let (len, _) = pipe.client.send(&mut buf).unwrap();
+        assert_eq!(pipe.server_recv(&mut buf[..len]), Ok(len));
+
+        // Server sends first flight, with Initial and Handshake packets
+        // coalesced in a single datagram.
+        let (len, _) = pipe.server.send(&mut buf).unwrap();
+        assert!(pipe.server.sent_count > 1);
+
+        // Append junk that can't be parsed as a QUIC packet.
+        buf[len..len + 32].copy_from_slice(&[0xba; 32]);
+
+        assert_eq!(pipe.client_recv(&mut buf[..len + 32]), Ok(len + 32));
+
+        // All valid packets were processed, and the junk was ignored.
+        assert_eq!(pipe.server.sent_count, pipe.client.recv_count);
+        assert!(pipe.client.handshake_status().has_handshake_keys);
+        assert!(!pipe.client.is_closed());
+    }
+
     #[test]
     /// Tests that client avoids handshake deadlock by arming PTO.
     fn handshake_anti_deadlock() {
@@ -14258,7 +18612,13 @@ mod tests {
             pipe.client.paths.get_active_path_id().expect("no active");
         let (ty, len) = pipe
             .client
-            .send_single(&mut buf, active_pid, false, time::Instant::now())
+            .send_single(
+                &mut buf,
+                active_pid,
+                false,
+                Ecn::NotEct,
+                time::Instant::now(),
+            )
             .unwrap();
         assert_eq!(ty, Type::Initial);
 
@@ -14267,14 +18627,20 @@ mod tests {
         // Client sends Handshake packet.
         let (ty, len) = pipe
             .client
-            .send_single(&mut buf, active_pid, false, time::Instant::now())
+            .send_single(
+                &mut buf,
+                active_pid,
+                false,
+                Ecn::NotEct,
+                time::Instant::now(),
+            )
             .unwrap();
         assert_eq!(ty, Type::Handshake);
 
         // Packet type is corrupted to Initial.
         buf[0] &= !(0x20);
 
-        let hdr = Header::from_slice(&mut buf[..len], 0).unwrap();
+        let hdr = Header::from_slice(&buf[..len], 0).unwrap();
         assert_eq!(hdr.ty, Type::Initial);
 
         // Server receives corrupted packet without returning an error.
@@ -14758,6 +19124,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn close_resent_in_closing_state() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        assert_eq!(pipe.client.close(false, 0x1234, b"hello!"), Ok(()));
+
+        // The CONNECTION_CLOSE frame is lost.
+        let (len, _) = pipe.client.send(&mut buf).unwrap();
+        assert!(len > 0);
+        assert!(pipe.client.is_draining());
+        assert_eq!(pipe.client.send(&mut buf), Err(Error::Done));
+
+        let draining_timer = pipe.client.draining_timer;
+
+        for i in 1..=4 {
+            // Server keeps sending packets.
+            assert_eq!(pipe.server.send_ack_eliciting(), Ok(()));
+            let (len, _) = pipe.server.send(&mut buf).unwrap();
+
+            assert_eq!(pipe.client_recv(&mut buf[..len]), Ok(len));
+
+            // Client only responds to the 1st, 2nd and 4th packets.
+            if i == 3 {
+                assert_eq!(pipe.client.send(&mut buf), Err(Error::Done));
+                continue;
+            }
+
+            let (len, _) = pipe.client.send(&mut buf).unwrap();
+
+            let frames =
+                testing::decode_pkt(&mut pipe.server, &mut buf[..len]).unwrap();
+
+            assert_eq!(
+                frames.first(),
+                Some(&frame::Frame::ConnectionClose {
+                    error_code: 0x1234,
+                    frame_type: 0,
+                    reason: b"hello!".to_vec(),
+                })
+            );
+
+            assert_eq!(pipe.client.send(&mut buf), Err(Error::Done));
+        }
+
+        // The closing period is not extended.
+        assert_eq!(pipe.client.draining_timer, draining_timer);
+    }
+
     // OpenSSL does not provide a straightforward interface to deal with custom
     // off-load key signing.
     #[cfg(not(feature = "openssl"))]
@@ -14806,6 +19223,7 @@ mod tests {
                 is_app: false,
                 error_code: 0x01,
                 reason: vec![],
+                frame_type: None,
             })
         );
         assert_eq!(
@@ -14814,6 +19232,7 @@ mod tests {
                 is_app: false,
                 error_code: 0x01,
                 reason: vec![],
+                frame_type: Some(0),
             })
         );
     }
@@ -14856,6 +19275,7 @@ mod tests {
                 is_app: false,
                 error_code: 0x0c,
                 reason: vec![],
+                frame_type: None,
             })
         );
         assert_eq!(
@@ -14864,6 +19284,7 @@ mod tests {
                 is_app: false,
                 error_code: 0x0c,
                 reason: vec![],
+                frame_type: Some(0),
             })
         );
     }
@@ -14909,7 +19330,8 @@ mod tests {
             Some(&ConnectionError {
                 is_app: true,
                 error_code: 123,
-                reason: b"Invalid authentication".to_vec()
+                reason: b"Invalid authentication".to_vec(),
+                frame_type: None,
             })
         );
         assert_eq!(
@@ -14917,7 +19339,8 @@ mod tests {
             Some(&ConnectionError {
                 is_app: true,
                 error_code: 123,
-                reason: b"Invalid authentication".to_vec()
+                reason: b"Invalid authentication".to_vec(),
+                frame_type: None,
             })
         );
     }
@@ -14951,7 +19374,8 @@ mod tests {
             Some(&ConnectionError {
                 is_app: false,
                 error_code: 123,
-                reason: b"connection close".to_vec()
+                reason: b"connection close".to_vec(),
+                frame_type: Some(0),
             })
         );
         assert_eq!(
@@ -14959,7 +19383,8 @@ mod tests {
             Some(&ConnectionError {
                 is_app: false,
                 error_code: 123,
-                reason: b"connection close".to_vec()
+                reason: b"connection close".to_vec(),
+                frame_type: None,
             })
         );
     }
@@ -14977,7 +19402,35 @@ mod tests {
             Some(&ConnectionError {
                 is_app: false,
                 error_code: 0x1234u64,
-                reason: b"hello?".to_vec()
+                reason: b"hello?".to_vec(),
+                frame_type: Some(0),
+            })
+        );
+    }
+
+    #[test]
+    fn peer_error_frame_type() {
+        let mut buf = [0; 65535];
+
+        let mut pipe = testing::Pipe::new().unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        let frames = [frame::Frame::ConnectionClose {
+            error_code: 0x07,
+            frame_type: 0x08,
+            reason: b"bad frame".to_vec(),
+        }];
+
+        let pkt_type = packet::Type::Short;
+        assert!(pipe.send_pkt_to_server(pkt_type, &frames, &mut buf).is_ok());
+
+        assert_eq!(
+            pipe.server.peer_error(),
+            Some(&ConnectionError {
+                is_app: false,
+                error_code: 0x07,
+                reason: b"bad frame".to_vec(),
+                frame_type: Some(0x08),
             })
         );
     }
@@ -14995,7 +19448,8 @@ mod tests {
             Some(&ConnectionError {
                 is_app: true,
                 error_code: 0x1234u64,
-                reason: b"hello!".to_vec()
+                reason: b"hello!".to_vec(),
+                frame_type: None,
             })
         );
     }
@@ -15014,7 +19468,8 @@ mod tests {
             Some(&ConnectionError {
                 is_app: true,
                 error_code: 0x1234u64,
-                reason: b"hello!".to_vec()
+                reason: b"hello!".to_vec(),
+                frame_type: None,
             })
         );
     }
@@ -15406,6 +19861,7 @@ mod tests {
         let info = RecvInfo {
             to: active_path.local_addr(),
             from: active_path.peer_addr(),
+            ecn: Ecn::NotEct,
         };
 
         assert_eq!(
@@ -15477,6 +19933,7 @@ mod tests {
         let info = RecvInfo {
             to: active_path.local_addr(),
             from: active_path.peer_addr(),
+            ecn: Ecn::NotEct,
         };
 
         assert_eq!(
@@ -15754,6 +20211,7 @@ mod tests {
         let info = RecvInfo {
             to: active_path.local_addr(),
             from: active_path.peer_addr(),
+            ecn: Ecn::NotEct,
         };
 
         assert_eq!(
@@ -16267,6 +20725,7 @@ mod tests {
         let ri = RecvInfo {
             to: si.to,
             from: si.from,
+            ecn: si.ecn,
         };
         assert_eq!(pipe.server.recv(&mut buf[..sent], ri), Ok(sent));
 
@@ -16311,6 +20770,7 @@ mod tests {
         let ri = RecvInfo {
             to: si.to,
             from: si.from,
+            ecn: si.ecn,
         };
         assert_eq!(pipe.server.recv(&mut buf[..sent], ri), Ok(sent));
 
@@ -16328,6 +20788,7 @@ mod tests {
         let ri = RecvInfo {
             to: si.to,
             from: si.from,
+            ecn: si.ecn,
         };
         assert_eq!(pipe.server.recv(&mut buf[..sent], ri), Ok(sent));
 
@@ -16346,6 +20807,7 @@ mod tests {
         let ri = RecvInfo {
             to: si.to,
             from: si.from,
+            ecn: si.ecn,
         };
         assert_eq!(pipe.server.recv(&mut buf[..sent], ri), Ok(sent));
 
@@ -16363,6 +20825,7 @@ mod tests {
         let ri = RecvInfo {
             to: si.to,
             from: si.from,
+            ecn: si.ecn,
         };
         assert_eq!(pipe.server.recv(&mut buf[..sent], ri), Ok(sent));
 
@@ -16696,6 +21159,139 @@ mod tests {
         );
     }
 
+    #[test]
+    fn connection_id_length_limits() {
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.verify_peer(false);
+
+        let client_addr = testing::Pipe::client_addr();
+        let server_addr = testing::Pipe::server_addr();
+
+        // Source connection IDs longer than the maximum are rejected.
+        let scid = ConnectionId::from_vec(vec![0xba; MAX_CONN_ID_LEN + 1]);
+        assert!(matches!(
+            connect(None, &scid, client_addr, server_addr, &mut config),
+            Err(Error::InvalidState)
+        ));
+
+        // Zero-length and maximum length source connection IDs are fine.
+        let scid = ConnectionId::default();
+        assert!(
+            connect(None, &scid, client_addr, server_addr, &mut config).is_ok()
+        );
+
+        let scid = ConnectionId::from_vec(vec![0xba; MAX_CONN_ID_LEN]);
+        assert!(
+            connect(None, &scid, client_addr, server_addr, &mut config).is_ok()
+        );
+
+        config.set_active_connection_id_limit(2);
+
+        let mut pipe = pipe_with_exchanged_cids(&mut config, 16, 16, 0);
+
+        let (cid, reset_token) =
+            testing::create_cid_and_reset_token(MAX_CONN_ID_LEN + 1);
+        assert_eq!(
+            pipe.client.new_scid(&cid, reset_token, false),
+            Err(Error::InvalidState)
+        );
+
+        let (cid, reset_token) = testing::create_cid_and_reset_token(0);
+        assert_eq!(
+            pipe.client.new_scid(&cid, reset_token, false),
+            Err(Error::InvalidState)
+        );
+
+        let (cid, reset_token) =
+            testing::create_cid_and_reset_token(MAX_CONN_ID_LEN);
+        assert_eq!(pipe.client.new_scid(&cid, reset_token, false), Ok(1));
+    }
+
+    #[test]
+    fn local_connection_id_len() {
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_initial_max_data(30);
+        config.set_initial_max_stream_data_bidi_local(15);
+        config.set_initial_max_stream_data_bidi_remote(15);
+        config.set_initial_max_streams_bidi(3);
+        config.verify_peer(false);
+        config.set_active_connection_id_limit(2);
+
+        assert_eq!(
+            config.set_local_connection_id_len(MAX_CONN_ID_LEN + 1),
+            Err(Error::InvalidState)
+        );
+
+        let client_addr = testing::Pipe::client_addr();
+        let server_addr = testing::Pipe::server_addr();
+
+        // Zero-length source connection IDs.
+        assert_eq!(config.set_local_connection_id_len(0), Ok(()));
+
+        let scid = ConnectionId::default();
+        assert!(
+            connect(None, &scid, client_addr, server_addr, &mut config).is_ok()
+        );
+
+        let scid = ConnectionId::from_vec(vec![0xba; 8]);
+        assert!(matches!(
+            connect(None, &scid, client_addr, server_addr, &mut config),
+            Err(Error::InvalidState)
+        ));
+
+        assert_eq!(config.set_local_connection_id_len(16), Ok(()));
+
+        let scid = ConnectionId::from_vec(vec![0xba; 8]);
+        assert!(matches!(
+            accept(&scid, None, server_addr, client_addr, &mut config),
+            Err(Error::InvalidState)
+        ));
+
+        let mut pipe = pipe_with_exchanged_cids(&mut config, 16, 16, 0);
+
+        let (cid, reset_token) = testing::create_cid_and_reset_token(8);
+        assert_eq!(
+            pipe.client.new_scid(&cid, reset_token, false),
+            Err(Error::InvalidState)
+        );
+
+        let (cid, reset_token) = testing::create_cid_and_reset_token(16);
+        assert_eq!(pipe.client.new_scid(&cid, reset_token, false), Ok(1));
+
+        // Short header packets are parsed with the configured length.
+        assert_eq!(pipe.client.stream_send(0, b"a", true), Ok(1));
+
+        let flight = testing::emit_flight(&mut pipe.client).unwrap();
+        let dcid_len = config.local_connection_id_len().unwrap();
+
+        for (pkt, _) in &flight {
+            let hdr = Header::from_slice(pkt, dcid_len).unwrap();
+            assert_eq!(hdr.ty, packet::Type::Short);
+            assert_eq!(hdr.dcid.len(), 16);
+        }
+
+        testing::process_flight(&mut pipe.server, flight).unwrap();
+        assert!(pipe.server.stream_readable(0));
+    }
+
     #[test]
     fn connection_migration_reordered_non_probing() {
         let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
@@ -16984,6 +21580,89 @@ mod tests {
         assert!(iter.next().is_none());
     }
 
+    #[test]
+    fn keep_alive() {
+        let mut buf = [0; 65535];
+
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_max_idle_timeout(180_000);
+        config.enable_keep_alive(true);
+        config.verify_peer(false);
+
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // The keep-alive timer fires before the idle timer.
+        let keep_alive_timer = pipe.client.keep_alive_timer.unwrap();
+        assert!(keep_alive_timer < pipe.client.idle_timer.unwrap());
+        assert_eq!(pipe.client.timeout_instant(), Some(keep_alive_timer));
+
+        // Force the keep-alive timer to expire.
+        pipe.client.keep_alive_timer = Some(time::Instant::now());
+        pipe.client.on_timeout();
+
+        assert!(!pipe.client.is_closed());
+        assert_eq!(pipe.client.keep_alive_timer, None);
+
+        // Client sends a PING.
+        let (len, _) = pipe.client.send(&mut buf).unwrap();
+
+        let frames =
+            testing::decode_pkt(&mut pipe.server, &mut buf[..len]).unwrap();
+        assert!(frames
+            .iter()
+            .any(|f| matches!(f, frame::Frame::Ping { .. })));
+
+        // Sending the PING re-arms the timer.
+        assert!(pipe.client.keep_alive_timer.is_some());
+
+        // Keep-alives can be disabled on the connection.
+        pipe.client.set_keep_alive(false);
+        assert_eq!(pipe.client.keep_alive_timer, None);
+
+        pipe.client.set_keep_alive(true);
+        assert!(pipe.client.keep_alive_timer.is_some());
+    }
+
+    #[test]
+    fn keep_alive_ratio() {
+        let mut config = Config::new(crate::PROTOCOL_VERSION).unwrap();
+        config
+            .load_cert_chain_from_pem_file("examples/cert.crt")
+            .unwrap();
+        config
+            .load_priv_key_from_pem_file("examples/cert.key")
+            .unwrap();
+        config
+            .set_application_protos(&[b"proto1", b"proto2"])
+            .unwrap();
+        config.set_max_idle_timeout(180_000);
+        config.enable_keep_alive(true);
+        config.set_keep_alive_ratio(0.25);
+        config.verify_peer(false);
+
+        let mut pipe = testing::Pipe::with_config(&mut config).unwrap();
+        assert_eq!(pipe.handshake(), Ok(()));
+
+        // The keep-alive timer fires after a quarter of the idle timeout,
+        // i.e. 135 seconds before the idle timer.
+        let keep_alive_timer = pipe.client.keep_alive_timer.unwrap();
+        let idle_timer = pipe.client.idle_timer.unwrap();
+        assert_eq!(
+            idle_timer - keep_alive_timer,
+            time::Duration::from_secs(135)
+        );
+    }
+
     /// Tests that streams do not keep being "writable" after being collected
     /// on reset.
     #[test]
@@ -17233,6 +21912,7 @@ mod tests {
             token: pipe.client.token.clone(),
             versions: None,
             key_phase: pipe.client.key_phase,
+            spin_bit: false,
         };
         hdr.to_bytes(&mut b).expect("encode header");
         let payload_len = frames.iter().fold(0, |acc, x| acc + x.wire_len());
@@ -17262,6 +21942,7 @@ mod tests {
             .recv(&mut pkt_buf[..written], RecvInfo {
                 to: server_addr,
                 from: client_addr_2,
+                ecn: Ecn::NotEct,
             })
             .expect("server receive path challenge");
 
@@ -17372,25 +22053,42 @@ mod tests {
     }
 }
 
+pub use crate::crypto::Algorithm as CipherAlgorithm;
+
 pub use crate::packet::ConnectionId;
+pub use crate::packet::DatagramIter;
 pub use crate::packet::Header;
+pub use crate::packet::InvariantHeader;
+pub use crate::packet::PacketKey;
 pub use crate::packet::Type;
 
 pub use crate::path::PathEvent;
 pub use crate::path::PathStats;
 pub use crate::path::SocketAddrIter;
 
+pub use crate::recovery::congestion::AckedPacket;
+pub use crate::recovery::congestion::BuiltinCongestionControl;
+pub use crate::recovery::congestion::CongestionControl;
 pub use crate::recovery::congestion::CongestionControlAlgorithm;
+pub use crate::recovery::congestion::PacketNumberSpace;
+pub use crate::recovery::congestion::RttEstimate;
+pub use crate::recovery::CarefulResumeParams;
+pub use crate::recovery::RecoveryEvent;
 
+pub use crate::stream::StreamEvent;
 pub use crate::stream::StreamIter;
+pub use crate::stream::StreamStats;
+
+pub use crate::tls::Certificate;
 
+mod ackfreq;
 mod cid;
 mod crypto;
 mod dgram;
 #[cfg(feature = "ffi")]
 mod ffi;
 mod flowcontrol;
-mod frame;
+pub mod frame;
 pub mod h3;
 mod minmax;
 mod packet;
@@ -24,6 +24,7 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use std::cmp;
 use std::fmt::Display;
 use std::ops::Index;
 use std::ops::IndexMut;
@@ -31,28 +32,41 @@ use std::ops::RangeInclusive;
 use std::time;
 
 use ring::aead;
+use ring::hmac;
 
+use crate::Ecn;
 use crate::Error;
 use crate::Result;
 
 use crate::crypto;
+use crate::frame;
 use crate::rand;
 use crate::ranges;
 use crate::stream;
 
 const FORM_BIT: u8 = 0x80;
-const FIXED_BIT: u8 = 0x40;
+pub(crate) const FIXED_BIT: u8 = 0x40;
 const KEY_PHASE_BIT: u8 = 0x04;
+const SPIN_BIT: u8 = 0x20;
 
 const TYPE_MASK: u8 = 0x30;
 const PKT_NUM_MASK: u8 = 0x03;
 
+const LONG_RESERVED_BITS: u8 = 0x0c;
+const SHORT_RESERVED_BITS: u8 = 0x18;
+
 pub const MAX_CID_LEN: u8 = 20;
 
 pub const MAX_PKT_NUM_LEN: usize = 4;
 
 const SAMPLE_LEN: usize = 16;
 
+const RESET_TOKEN_LEN: usize = 16;
+
+/// The minimum length of a stateless reset packet, enough to look like a short
+/// header packet with a 1-byte packet number and minimal payload.
+pub const MIN_STATELESS_RESET_LEN: usize = 21;
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum Epoch {
     Initial     = 0,
@@ -315,6 +329,10 @@ pub struct Header<'a> {
     /// The key phase bit of the packet. It's only meaningful after the header
     /// protection is removed.
     pub(crate) key_phase: bool,
+
+    /// The latency spin bit of the packet. Only present in short header
+    /// packets, and not covered by header protection.
+    pub(crate) spin_bit: bool,
 }
 
 impl<'a> Header<'a> {
@@ -323,6 +341,12 @@ impl<'a> Header<'a> {
     /// The `dcid_len` parameter is the length of the destination connection ID,
     /// required to parse short header packets.
     ///
+    /// The buffer is not modified, so this can be used to inspect incoming
+    /// packets (e.g. to route them based on the destination connection ID)
+    /// before passing them to [`Connection::recv()`].
+    ///
+    /// [`Connection::recv()`]: struct.Connection.html#method.recv
+    ///
     /// ## Examples:
     ///
     /// ```no_run
@@ -332,20 +356,82 @@ impl<'a> Header<'a> {
     /// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
     /// let (len, src) = socket.recv_from(&mut buf).unwrap();
     ///
-    /// let hdr = quiche::Header::from_slice(&mut buf[..len], LOCAL_CONN_ID_LEN)?;
+    /// let hdr = quiche::Header::from_slice(&buf[..len], LOCAL_CONN_ID_LEN)?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    #[inline]
+    pub fn from_slice<'b>(buf: &'b [u8], dcid_len: usize) -> Result<Header<'a>> {
+        Ok(Header::from_slice_borrowed(buf, dcid_len)?.into_owned())
+    }
+
+    /// Parses a QUIC packet header from the given buffer, without copying the
+    /// connection IDs.
+    ///
+    /// This is the same as [`from_slice()`], except that the connection IDs of
+    /// the returned header borrow from `buf`, which avoids allocating memory
+    /// for every incoming packet, e.g. when only the destination connection ID
+    /// is needed to route it. [`into_owned()`] can be used to detach the header
+    /// from the buffer.
+    ///
+    /// Note that only the connection IDs are borrowed: the `token` of `Initial`
+    /// and `Retry` packets and the `versions` of `VersionNegotiation` packets
+    /// are still copied. [`Connection::recv()`] also keeps parsing an owning
+    /// header, since the packet is decrypted in place after the header.
+    ///
+    /// [`from_slice()`]: struct.Header.html#method.from_slice
+    /// [`Connection::recv()`]: struct.Connection.html#method.recv
+    /// [`into_owned()`]: struct.Header.html#method.into_owned
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # const LOCAL_CONN_ID_LEN: usize = 16;
+    /// # let mut buf = [0; 512];
+    /// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let (len, src) = socket.recv_from(&mut buf).unwrap();
+    ///
+    /// let hdr =
+    ///     quiche::Header::from_slice_borrowed(&buf[..len], LOCAL_CONN_ID_LEN)?;
     /// # Ok::<(), quiche::Error>(())
     /// ```
     #[inline]
-    pub fn from_slice<'b>(
-        buf: &'b mut [u8], dcid_len: usize,
+    pub fn from_slice_borrowed(
+        buf: &'a [u8], dcid_len: usize,
     ) -> Result<Header<'a>> {
-        let mut b = octets::OctetsMut::with_slice(buf);
-        Header::from_bytes(&mut b, dcid_len)
+        let mut b = octets::Octets::with_slice(buf);
+        Header::from_octets(&mut b, dcid_len)
+    }
+
+    /// Returns a new owning header from the given existing one.
+    #[inline]
+    pub fn into_owned(self) -> Header<'static> {
+        Header {
+            ty: self.ty,
+            version: self.version,
+            dcid: self.dcid.into_owned(),
+            scid: self.scid.into_owned(),
+            pkt_num: self.pkt_num,
+            pkt_num_len: self.pkt_num_len,
+            token: self.token,
+            versions: self.versions,
+            key_phase: self.key_phase,
+            spin_bit: self.spin_bit,
+        }
     }
 
     pub(crate) fn from_bytes<'b>(
         b: &'b mut octets::OctetsMut, dcid_len: usize,
     ) -> Result<Header<'a>> {
+        let mut ob = octets::Octets::with_slice(b.as_ref());
+
+        let hdr = Header::from_octets(&mut ob, dcid_len)?.into_owned();
+
+        b.skip(ob.off())?;
+
+        Ok(hdr)
+    }
+
+    fn from_octets(b: &mut octets::Octets<'a>, dcid_len: usize) -> Result<Self> {
         let first = b.get_u8()?;
 
         if !Header::is_long(first) {
@@ -355,13 +441,14 @@ impl<'a> Header<'a> {
             return Ok(Header {
                 ty: Type::Short,
                 version: 0,
-                dcid: dcid.to_vec().into(),
+                dcid: ConnectionId::from_ref(dcid.buf()),
                 scid: ConnectionId::default(),
                 pkt_num: 0,
                 pkt_num_len: 0,
                 token: None,
                 versions: None,
                 key_phase: false,
+                spin_bit: first & SPIN_BIT != 0,
             });
         }
 
@@ -382,15 +469,15 @@ impl<'a> Header<'a> {
 
         let dcid_len = b.get_u8()?;
         if crate::version_is_supported(version) && dcid_len > MAX_CID_LEN {
-            return Err(Error::InvalidPacket);
+            return Err(Error::InvalidConnectionId);
         }
-        let dcid = b.get_bytes(dcid_len as usize)?.to_vec();
+        let dcid = b.get_bytes(dcid_len as usize)?.buf();
 
         let scid_len = b.get_u8()?;
         if crate::version_is_supported(version) && scid_len > MAX_CID_LEN {
-            return Err(Error::InvalidPacket);
+            return Err(Error::InvalidConnectionId);
         }
-        let scid = b.get_bytes(scid_len as usize)?.to_vec();
+        let scid = b.get_bytes(scid_len as usize)?.buf();
 
         // End of invariants.
 
@@ -429,13 +516,14 @@ impl<'a> Header<'a> {
         Ok(Header {
             ty,
             version,
-            dcid: dcid.into(),
-            scid: scid.into(),
+            dcid: ConnectionId::from_ref(dcid),
+            scid: ConnectionId::from_ref(scid),
             pkt_num: 0,
             pkt_num_len: 0,
             token,
             versions,
             key_phase: false,
+            spin_bit: false,
         })
     }
 
@@ -460,6 +548,11 @@ impl<'a> Header<'a> {
                 first &= !KEY_PHASE_BIT;
             }
 
+            // Set spin bit.
+            if self.spin_bit {
+                first |= SPIN_BIT;
+            }
+
             out.put_u8(first)?;
             out.put_bytes(&self.dcid)?;
 
@@ -517,9 +610,16 @@ impl<'a> Header<'a> {
     /// Returns true if the packet has a long header.
     ///
     /// The `b` parameter represents the first byte of the QUIC header.
-    fn is_long(b: u8) -> bool {
+    pub(crate) fn is_long(b: u8) -> bool {
         b & FORM_BIT != 0
     }
+
+    /// Returns true if the packet is an Initial packet.
+    ///
+    /// The `b` parameter represents the first byte of the QUIC header.
+    pub(crate) fn is_initial(b: u8) -> bool {
+        Header::is_long(b) && (b & TYPE_MASK) >> 4 == 0x00
+    }
 }
 
 impl std::fmt::Debug for Header<'_> {
@@ -549,18 +649,124 @@ impl std::fmt::Debug for Header<'_> {
 
         if self.ty == Type::Short {
             write!(f, " key_phase={}", self.key_phase)?;
+            write!(f, " spin_bit={}", self.spin_bit)?;
         }
 
         Ok(())
     }
 }
 
+/// The version-independent properties of a QUIC packet's header.
+///
+/// Unlike [`Header`], this only relies on the invariants defined in
+/// [RFC 8999], so it can be parsed for packets of any version, including ones
+/// that are not supported, e.g. to route them or to decide whether to send a
+/// Version Negotiation packet.
+///
+/// [`Header`]: struct.Header.html
+/// [RFC 8999]: https://www.rfc-editor.org/rfc/rfc8999.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvariantHeader<'a> {
+    /// The version of the packet, or `None` for short header packets.
+    pub version: Option<u32>,
+
+    /// The destination connection ID of the packet.
+    pub dcid: ConnectionId<'a>,
+
+    /// The source connection ID of the packet. Always empty for short header
+    /// packets.
+    pub scid: ConnectionId<'a>,
+}
+
+impl<'a> InvariantHeader<'a> {
+    /// Parses the version-independent part of a QUIC packet header from the
+    /// given buffer.
+    ///
+    /// The `dcid_len` parameter is the length of the destination connection ID,
+    /// required to parse short header packets. Long header packets can carry
+    /// connection IDs of up to 255 bytes, regardless of [`MAX_CONN_ID_LEN`].
+    ///
+    /// [`MAX_CONN_ID_LEN`]: constant.MAX_CONN_ID_LEN.html
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// # const LOCAL_CONN_ID_LEN: usize = 16;
+    /// # let mut buf = [0; 512];
+    /// # let mut out = [0; 512];
+    /// # let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let (len, src) = socket.recv_from(&mut buf).unwrap();
+    ///
+    /// let hdr =
+    ///     quiche::InvariantHeader::from_slice(&buf[..len], LOCAL_CONN_ID_LEN)?;
+    ///
+    /// if let Some(version) = hdr.version {
+    ///     if version != 0 && !quiche::version_is_supported(version) {
+    ///         let len = quiche::negotiate_version(&hdr.scid, &hdr.dcid, &mut out)?;
+    ///
+    ///         socket.send_to(&out[..len], &src).unwrap();
+    ///     }
+    /// }
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn from_slice(buf: &'a [u8], dcid_len: usize) -> Result<Self> {
+        let mut b = octets::Octets::with_slice(buf);
+
+        let first = b.get_u8()?;
+
+        if !Header::is_long(first) {
+            let dcid = b.get_bytes(dcid_len)?;
+
+            return Ok(InvariantHeader {
+                version: None,
+                dcid: ConnectionId::from_ref(dcid.buf()),
+                scid: ConnectionId::default(),
+            });
+        }
+
+        let version = b.get_u32()?;
+        let dcid = b.get_bytes_with_u8_length()?;
+        let scid = b.get_bytes_with_u8_length()?;
+
+        Ok(InvariantHeader {
+            version: Some(version),
+            dcid: ConnectionId::from_ref(dcid.buf()),
+            scid: ConnectionId::from_ref(scid.buf()),
+        })
+    }
+
+    /// Returns a new owning header from the given existing one.
+    #[inline]
+    pub fn into_owned(self) -> InvariantHeader<'static> {
+        InvariantHeader {
+            version: self.version,
+            dcid: self.dcid.into_owned(),
+            scid: self.scid.into_owned(),
+        }
+    }
+}
+
+/// Returns whether any of the reserved bits of the given first byte are set.
+///
+/// This is only meaningful once header protection has been removed.
+pub(crate) fn has_reserved_bits(first: u8) -> bool {
+    let reserved = if Header::is_long(first) {
+        LONG_RESERVED_BITS
+    } else {
+        SHORT_RESERVED_BITS
+    };
+
+    first & reserved != 0
+}
+
 pub fn pkt_num_len(pn: u64, largest_acked: u64) -> usize {
-    let num_unacked: u64 = pn.saturating_sub(largest_acked) + 1;
-    // computes ceil of num_unacked.log2()
-    let min_bits = u64::BITS - num_unacked.leading_zeros();
+    let num_unacked: u64 = cmp::max(pn.saturating_sub(largest_acked), 1);
+    // The encoding needs to cover twice the range of unacked packets, so
+    // computes ceil of num_unacked.log2() + 1 (RFC 9000, Appendix A.2)
+    let range = num_unacked.saturating_mul(2) - 1;
+    let min_bits = u64::BITS - range.leading_zeros();
     // get the num len in bytes
-    ((min_bits + 7) / 8) as usize
+    cmp::min(((min_bits + 7) / 8) as usize, MAX_PKT_NUM_LEN)
 }
 
 pub fn decrypt_hdr(
@@ -660,6 +866,86 @@ pub fn decrypt_pkt<'a>(
     Ok(b.get_bytes(payload_len)?)
 }
 
+/// A packet protection key that can be used to decrypt packets with
+/// [`decrypt_packet()`].
+///
+/// [`decrypt_packet()`]: fn.decrypt_packet.html
+pub struct PacketKey {
+    open: crypto::Open,
+}
+
+impl PacketKey {
+    /// Derives the key protecting Initial packets sent by the client, or by
+    /// the server if `from_server` is `true`.
+    ///
+    /// The `odcid` parameter is the destination connection ID of the first
+    /// Initial packet sent by the client.
+    pub fn initial(
+        odcid: &[u8], version: u32, from_server: bool,
+    ) -> Result<PacketKey> {
+        // The keys derived for one side are used to open the packets sent by
+        // the other.
+        let (open, _) =
+            crypto::derive_initial_key_material(odcid, version, !from_server)?;
+
+        Ok(PacketKey { open })
+    }
+
+    /// Creates a key from a traffic secret, such as the ones logged in the
+    /// keylog format.
+    pub fn from_secret(
+        alg: crypto::Algorithm, secret: &[u8],
+    ) -> Result<PacketKey> {
+        let open = crypto::Open::from_secret(alg, secret)?;
+
+        Ok(PacketKey { open })
+    }
+}
+
+pub fn decrypt_packet<'a>(
+    buf: &'a mut [u8], dcid_len: usize, largest_pn: u64, key: &PacketKey,
+) -> Result<(Header<'static>, &'a [u8], usize)> {
+    let (hdr, payload_off, payload_len, pkt_len) = {
+        let mut b = octets::OctetsMut::with_slice(buf);
+
+        let mut hdr = Header::from_bytes(&mut b, dcid_len)?;
+
+        if hdr.ty == Type::Retry || hdr.ty == Type::VersionNegotiation {
+            return Err(Error::InvalidPacket);
+        }
+
+        let payload_len = if hdr.ty == Type::Short {
+            b.cap()
+        } else {
+            b.get_varint()? as usize
+        };
+
+        if payload_len > b.cap() {
+            return Err(Error::InvalidPacket);
+        }
+
+        let pkt_len = b.off() + payload_len;
+
+        decrypt_hdr(&mut b, &mut hdr, &key.open)?;
+
+        hdr.pkt_num = decode_pkt_num(largest_pn, hdr.pkt_num, hdr.pkt_num_len);
+
+        let payload_off = b.off();
+
+        let payload = decrypt_pkt(
+            &mut b,
+            hdr.pkt_num,
+            hdr.pkt_num_len,
+            payload_len,
+            &key.open,
+        )?;
+
+        (hdr, payload_off, payload.len(), pkt_len)
+    };
+
+    Ok((hdr, &buf[payload_off..payload_off + payload_len], pkt_len))
+}
+
 pub fn encrypt_hdr(
     b: &mut octets::OctetsMut, pn_len: usize, payload: &[u8], aead: &crypto::Seal,
 ) -> Result<()> {
@@ -724,7 +1010,7 @@ pub fn encode_pkt_num(
 }
 
 pub fn negotiate_version(
-    scid: &[u8], dcid: &[u8], out: &mut [u8],
+    scid: &[u8], dcid: &[u8], versions: &[u32], out: &mut [u8],
 ) -> Result<usize> {
     let mut b = octets::OctetsMut::with_slice(out);
 
@@ -737,11 +1023,89 @@ pub fn negotiate_version(
     b.put_bytes(scid)?;
     b.put_u8(dcid.len() as u8)?;
     b.put_bytes(dcid)?;
-    b.put_u32(crate::PROTOCOL_VERSION_V1)?;
+
+    // Advertise a reserved version first, so that clients are exercised to
+    // ignore versions they don't support.
+    b.put_u32(grease_version())?;
+
+    for version in versions {
+        b.put_u32(*version)?;
+    }
 
     Ok(b.off())
 }
 
+/// Returns a random version following the 0x?a?a?a?a pattern reserved to
+/// exercise version negotiation (RFC 9000 Section 15).
+fn grease_version() -> u32 {
+    (rand::rand_u32() & 0xf0f0f0f0) | 0x0a0a0a0a
+}
+
+/// An iterator over the QUIC packets coalesced in a UDP datagram.
+///
+/// This is created by the [`split_datagram()`] function.
+///
+/// [`split_datagram()`]: fn.split_datagram.html
+pub struct DatagramIter<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for DatagramIter<'a> {
+    type Item = Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        let len = match coalesced_pkt_len(self.buf) {
+            Ok(v) => v,
+
+            Err(e) => {
+                // Nothing else can be parsed from the datagram.
+                self.buf = &[];
+
+                return Some(Err(e));
+            },
+        };
+
+        let (pkt, rest) = self.buf.split_at(len);
+        self.buf = rest;
+
+        Some(Ok(pkt))
+    }
+}
+
+pub fn split_datagram(buf: &[u8]) -> DatagramIter<'_> {
+    DatagramIter { buf }
+}
+
+/// Returns the length of the first packet in a datagram.
+fn coalesced_pkt_len(buf: &[u8]) -> Result<usize> {
+    // Short header packets don't carry a length, so they extend to the end
+    // of the datagram.
+    if !Header::is_long(buf[0]) {
+        return Ok(buf.len());
+    }
+
+    let mut b = octets::Octets::with_slice(buf);
+
+    let hdr = Header::from_octets(&mut b, 0)?;
+
+    // Retry and Version Negotiation packets don't carry a length either.
+    if hdr.ty == Type::Retry || hdr.ty == Type::VersionNegotiation {
+        return Ok(buf.len());
+    }
+
+    let len = b.off() + b.get_varint()? as usize;
+
+    if len > buf.len() {
+        return Err(Error::InvalidPacket);
+    }
+
+    Ok(len)
+}
+
 pub fn retry(
     scid: &[u8], dcid: &[u8], new_scid: &[u8], token: &[u8], version: u32,
     out: &mut [u8],
@@ -762,6 +1126,7 @@ pub fn retry(
         token: Some(token.to_vec()),
         versions: None,
         key_phase: false,
+        spin_bit: false,
     };
 
     hdr.to_bytes(&mut b)?;
@@ -773,6 +1138,40 @@ pub fn retry(
     Ok(b.off())
 }
 
+pub fn stateless_reset(
+    reset_token: u128, pkt_len: usize, out: &mut [u8],
+) -> Result<usize> {
+    // The stateless reset must be smaller than the packet that triggered it,
+    // to prevent infinite loops between endpoints.
+    let len = cmp::min(pkt_len.saturating_sub(1), out.len());
+
+    if len < MIN_STATELESS_RESET_LEN {
+        return Err(Error::Done);
+    }
+
+    let out = &mut out[..len];
+
+    // Fill the packet with unpredictable bits, so that it is
+    // indistinguishable from a regular short header packet.
+    rand::rand_bytes(out);
+
+    out[0] = (out[0] & !FORM_BIT) | FIXED_BIT;
+
+    out[len - RESET_TOKEN_LEN..].copy_from_slice(&reset_token.to_be_bytes());
+
+    Ok(len)
+}
+
+pub fn stateless_reset_token(key: &[u8], cid: &[u8]) -> u128 {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    let tag = hmac::sign(&key, cid);
+
+    let mut token = [0; RESET_TOKEN_LEN];
+    token.copy_from_slice(&tag.as_ref()[..RESET_TOKEN_LEN]);
+
+    u128::from_be_bytes(token)
+}
+
 pub fn verify_retry_integrity(
     b: &octets::OctetsMut, odcid: &[u8], version: u32,
 ) -> Result<()> {
@@ -860,11 +1259,15 @@ pub struct PktNumSpace {
 
     pub ack_elicited: bool,
 
+    pub ecn_counts: frame::EcnCounts,
+
     pub key_update: Option<KeyUpdate>,
 
     pub crypto_open: Option<crypto::Open>,
     pub crypto_seal: Option<crypto::Seal>,
 
+    pub crypto_seal_count: u64,
+
     pub crypto_0rtt_open: Option<crypto::Open>,
 
     pub crypto_stream: stream::Stream,
@@ -887,11 +1290,15 @@ impl PktNumSpace {
 
             ack_elicited: false,
 
+            ecn_counts: frame::EcnCounts::default(),
+
             key_update: None,
 
             crypto_open: None,
             crypto_seal: None,
 
+            crypto_seal_count: 0,
+
             crypto_0rtt_open: None,
 
             crypto_stream: stream::Stream::new(
@@ -901,6 +1308,7 @@ impl PktNumSpace {
                 true,
                 true,
                 stream::MAX_STREAM_WINDOW,
+                time::Instant::now(),
             ),
         }
     }
@@ -913,9 +1321,37 @@ impl PktNumSpace {
             true,
             true,
             stream::MAX_STREAM_WINDOW,
+            time::Instant::now(),
         );
 
+        self.recv_pkt_need_ack = ranges::RangeSet::new(crate::MAX_ACK_RANGES);
+
         self.ack_elicited = false;
+
+        self.ecn_counts = frame::EcnCounts::default();
+    }
+
+    /// Counts the ECN codepoint of a received packet.
+    pub fn on_ecn_received(&mut self, ecn: Ecn) {
+        match ecn {
+            Ecn::NotEct => (),
+
+            Ecn::Ect0 => self.ecn_counts.ect0_count += 1,
+
+            Ecn::Ect1 => self.ecn_counts.ect1_count += 1,
+
+            Ecn::Ce => self.ecn_counts.ecn_ce_count += 1,
+        }
+    }
+
+    /// Returns the ECN counts to be sent in ACK frames, if any ECN-marked
+    /// packet was received.
+    pub fn ecn_counts(&self) -> Option<frame::EcnCounts> {
+        if self.ecn_counts == frame::EcnCounts::default() {
+            return None;
+        }
+
+        Some(self.ecn_counts.clone())
     }
 
     pub fn crypto_overhead(&self) -> Option<usize> {
@@ -995,6 +1431,7 @@ mod tests {
             token: Some(vec![0xba; 24]),
             versions: None,
             key_phase: false,
+            spin_bit: false,
         };
 
         let mut d = [0; 63];
@@ -1009,6 +1446,75 @@ mod tests {
         assert_eq!(Header::from_bytes(&mut b, 9).unwrap(), hdr);
     }
 
+    #[test]
+    fn retry_integrity_v1() {
+        // Test vector from RFC 9001, Appendix A.4.
+        let odcid = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+
+        let mut pkt = [
+            0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x08, 0xf0, 0x67, 0xa5, 0x50,
+            0x2a, 0x42, 0x62, 0xb5, 0x74, 0x6f, 0x6b, 0x65, 0x6e, 0x04, 0xa2,
+            0x65, 0xba, 0x2e, 0xff, 0x4d, 0x82, 0x90, 0x58, 0xfb, 0x3f, 0x0f,
+            0x24, 0x96, 0xba,
+        ];
+
+        let mut b = octets::OctetsMut::with_slice(&mut pkt);
+
+        let hdr = Header::from_bytes(&mut b, 0).unwrap();
+        assert_eq!(hdr.ty, Type::Retry);
+        assert_eq!(hdr.token, Some(b"token".to_vec()));
+
+        assert_eq!(
+            verify_retry_integrity(&b, &odcid, crate::PROTOCOL_VERSION_V1),
+            Ok(())
+        );
+
+        // A different original destination connection ID must not verify.
+        assert_eq!(
+            verify_retry_integrity(&b, &odcid[1..], crate::PROTOCOL_VERSION_V1),
+            Err(Error::CryptoFail)
+        );
+    }
+
+    #[test]
+    fn retry_integrity_tampered() {
+        let scid = [0xba; 8];
+        let dcid = [0xbb; 8];
+        let new_scid = [0xbc; 8];
+
+        let mut d = [0; 128];
+
+        let len = super::retry(
+            &scid,
+            &dcid,
+            &new_scid,
+            b"retry token",
+            crate::PROTOCOL_VERSION_V1,
+            &mut d,
+        )
+        .unwrap();
+
+        let mut pkt = d[..len].to_vec();
+
+        let mut b = octets::OctetsMut::with_slice(&mut pkt);
+        Header::from_bytes(&mut b, 0).unwrap();
+        assert_eq!(
+            verify_retry_integrity(&b, &dcid, crate::PROTOCOL_VERSION_V1),
+            Ok(())
+        );
+
+        // Flip a bit in the token, which is covered by the integrity tag.
+        let mut pkt = d[..len].to_vec();
+        pkt[len - aead::AES_128_GCM.tag_len() - 1] ^= 0x01;
+
+        let mut b = octets::OctetsMut::with_slice(&mut pkt);
+        Header::from_bytes(&mut b, 0).unwrap();
+        assert_eq!(
+            verify_retry_integrity(&b, &dcid, crate::PROTOCOL_VERSION_V1),
+            Err(Error::CryptoFail)
+        );
+    }
+
     #[test]
     fn initial() {
         let hdr = Header {
@@ -1022,6 +1528,7 @@ mod tests {
             token: Some(vec![0x05, 0x06, 0x07, 0x08]),
             versions: None,
             key_phase: false,
+            spin_bit: false,
         };
 
         let mut d = [0; 50];
@@ -1049,6 +1556,7 @@ mod tests {
             token: Some(vec![0x05, 0x06, 0x07, 0x08]),
             versions: None,
             key_phase: false,
+            spin_bit: false,
         };
 
         let mut d = [0; 50];
@@ -1057,7 +1565,10 @@ mod tests {
         assert!(hdr.to_bytes(&mut b).is_ok());
 
         let mut b = octets::OctetsMut::with_slice(&mut d);
-        assert_eq!(Header::from_bytes(&mut b, 21), Err(Error::InvalidPacket));
+        assert_eq!(
+            Header::from_bytes(&mut b, 21),
+            Err(Error::InvalidConnectionId)
+        );
     }
 
     #[test]
@@ -1077,6 +1588,7 @@ mod tests {
             token: Some(vec![0x05, 0x06, 0x07, 0x08]),
             versions: None,
             key_phase: false,
+            spin_bit: false,
         };
 
         let mut d = [0; 50];
@@ -1085,7 +1597,10 @@ mod tests {
         assert!(hdr.to_bytes(&mut b).is_ok());
 
         let mut b = octets::OctetsMut::with_slice(&mut d);
-        assert_eq!(Header::from_bytes(&mut b, 9), Err(Error::InvalidPacket));
+        assert_eq!(
+            Header::from_bytes(&mut b, 9),
+            Err(Error::InvalidConnectionId)
+        );
     }
 
     #[test]
@@ -1105,6 +1620,7 @@ mod tests {
             token: Some(vec![0x05, 0x06, 0x07, 0x08]),
             versions: None,
             key_phase: false,
+            spin_bit: false,
         };
 
         let mut d = [0; 50];
@@ -1129,6 +1645,7 @@ mod tests {
             token: None,
             versions: None,
             key_phase: false,
+            spin_bit: false,
         };
 
         let mut d = [0; 50];
@@ -1153,6 +1670,7 @@ mod tests {
             token: None,
             versions: None,
             key_phase: false,
+            spin_bit: false,
         };
 
         let mut d = [0; 50];
@@ -1164,6 +1682,269 @@ mod tests {
         assert_eq!(Header::from_bytes(&mut b, 9).unwrap(), hdr);
     }
 
+    #[test]
+    fn application_spin_bit() {
+        let hdr = Header {
+            ty: Type::Short,
+            version: 0,
+            dcid: vec![0xba, 0xba, 0xba, 0xba, 0xba, 0xba, 0xba, 0xba, 0xba]
+                .into(),
+            scid: ConnectionId::default(),
+            pkt_num: 0,
+            pkt_num_len: 0,
+            token: None,
+            versions: None,
+            key_phase: false,
+            spin_bit: true,
+        };
+
+        let mut d = [0; 50];
+
+        let mut b = octets::OctetsMut::with_slice(&mut d);
+        assert!(hdr.to_bytes(&mut b).is_ok());
+        assert_eq!(d[0] & SPIN_BIT, SPIN_BIT);
+
+        let mut b = octets::OctetsMut::with_slice(&mut d);
+        assert_eq!(Header::from_bytes(&mut b, 9).unwrap(), hdr);
+    }
+
+    #[test]
+    fn from_slice_immutable() {
+        let hdr = Header {
+            ty: Type::Initial,
+            version: crate::PROTOCOL_VERSION,
+            dcid: vec![0xba; 9].into(),
+            scid: vec![0xbb; 7].into(),
+            pkt_num: 0,
+            pkt_num_len: 0,
+            token: Some(vec![0x05, 0x06, 0x07, 0x08]),
+            versions: None,
+            key_phase: false,
+            spin_bit: false,
+        };
+
+        let mut d = [0; 50];
+
+        let mut b = octets::OctetsMut::with_slice(&mut d);
+        assert!(hdr.to_bytes(&mut b).is_ok());
+
+        let d = d;
+
+        assert_eq!(Header::from_slice(&d, 9).unwrap(), hdr);
+
+        // Parsing the same buffer again yields the same result.
+        assert_eq!(Header::from_slice(&d, 9).unwrap(), hdr);
+    }
+
+    #[test]
+    fn from_slice_borrowed() {
+        let hdr = Header {
+            ty: Type::Short,
+            version: 0,
+            dcid: vec![0xba; 9].into(),
+            scid: ConnectionId::default(),
+            pkt_num: 0,
+            pkt_num_len: 0,
+            token: None,
+            versions: None,
+            key_phase: false,
+            spin_bit: false,
+        };
+
+        let mut d = [0; 50];
+
+        let mut b = octets::OctetsMut::with_slice(&mut d);
+        assert!(hdr.to_bytes(&mut b).is_ok());
+
+        let parsed = Header::from_slice_borrowed(&d, 9).unwrap();
+        assert_eq!(parsed, hdr);
+
+        // The connection ID points into the original buffer.
+        assert_eq!(parsed.dcid.as_ptr(), d[1..].as_ptr());
+
+        let owned = parsed.into_owned();
+        assert_eq!(owned, hdr);
+        assert_ne!(owned.dcid.as_ptr(), d[1..].as_ptr());
+    }
+
+    #[test]
+    fn split_datagram() {
+        let mut d = [0; 128];
+
+        let mut b = octets::OctetsMut::with_slice(&mut d);
+
+        let mut lens = Vec::new();
+
+        for (ty, payload_len) in
+            [(Type::Initial, 20), (Type::Handshake, 10), (Type::Short, 5)]
+        {
+            let start = b.off();
+
+            let hdr = Header {
+                ty,
+                version: crate::PROTOCOL_VERSION,
+                dcid: vec![0xba; 9].into(),
+                scid: vec![0xbb; 5].into(),
+                pkt_num: 0,
+                pkt_num_len: 0,
+                token: None,
+                versions: None,
+                key_phase: false,
+                spin_bit: false,
+            };
+
+            hdr.to_bytes(&mut b).unwrap();
+
+            if ty != Type::Short {
+                b.put_varint(payload_len as u64).unwrap();
+            }
+
+            b.put_bytes(&[0xaa; 20][..payload_len]).unwrap();
+
+            lens.push(b.off() - start);
+        }
+
+        let len = b.off();
+
+        let pkts: Vec<&[u8]> = super::split_datagram(&d[..len])
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(pkts.iter().map(|p| p.len()).collect::<Vec<_>>(), lens);
+
+        let hdr = Header::from_slice(pkts[0], 9).unwrap();
+        assert_eq!(hdr.ty, Type::Initial);
+
+        let hdr = Header::from_slice(pkts[1], 9).unwrap();
+        assert_eq!(hdr.ty, Type::Handshake);
+
+        let hdr = Header::from_slice(pkts[2], 9).unwrap();
+        assert_eq!(hdr.ty, Type::Short);
+
+        // The length of the first packet exceeds the datagram.
+        let mut iter = super::split_datagram(&d[..lens[0] - 1]);
+        assert_eq!(iter.next(), Some(Err(Error::InvalidPacket)));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn invariant_header() {
+        // Long header of an unknown version, with an oversized DCID and a body
+        // that doesn't follow the v1 format.
+        let mut d = vec![0xc0, 0x1a, 0x2a, 0x3a, 0x4a, 30];
+        d.extend_from_slice(&[0xba; 30]);
+        d.push(5);
+        d.extend_from_slice(&[0xbb; 5]);
+        d.extend_from_slice(&[0xff; 10]);
+
+        assert!(Header::from_slice(&d, 9).is_err());
+
+        let hdr = InvariantHeader::from_slice(&d, 9).unwrap();
+        assert_eq!(hdr.version, Some(0x1a2a3a4a));
+        assert_eq!(hdr.dcid.as_ref(), &[0xba; 30]);
+        assert_eq!(hdr.scid.as_ref(), &[0xbb; 5]);
+
+        // Short header.
+        let mut d = vec![0x40];
+        d.extend_from_slice(&[0xba; 9]);
+        d.extend_from_slice(&[0xff; 10]);
+
+        let hdr = InvariantHeader::from_slice(&d, 9).unwrap();
+        assert_eq!(hdr.version, None);
+        assert_eq!(hdr.dcid.as_ref(), &[0xba; 9]);
+        assert!(hdr.scid.is_empty());
+
+        // Truncated connection ID.
+        assert_eq!(
+            InvariantHeader::from_slice(&d[..5], 9),
+            Err(Error::BufferTooShort)
+        );
+    }
+
+    #[test]
+    fn negotiate_version() {
+        let scid = [0xba; 9];
+        let dcid = [0xbb; 7];
+
+        let mut d = [0; 50];
+
+        let len =
+            super::negotiate_version(&scid, &dcid, &[0x1, 0xff00001d], &mut d)
+                .unwrap();
+
+        let hdr = Header::from_slice(&d[..len], 0).unwrap();
+        assert_eq!(hdr.ty, Type::VersionNegotiation);
+        assert_eq!(hdr.dcid, ConnectionId::from_ref(&scid));
+        assert_eq!(hdr.scid, ConnectionId::from_ref(&dcid));
+
+        let versions = hdr.versions.unwrap();
+        assert_eq!(versions.len(), 3);
+
+        // A reserved version is advertised first.
+        assert_eq!(versions[0] & 0x0f0f0f0f, 0x0a0a0a0a);
+        assert_eq!(&versions[1..], &[0x1, 0xff00001d]);
+    }
+
+    #[test]
+    fn stateless_reset() {
+        let token = 0xba5eba11_u128;
+
+        let mut d = [0; 50];
+
+        // The packet is smaller than the one that triggered it.
+        let len = super::stateless_reset(token, 40, &mut d).unwrap();
+        assert_eq!(len, 39);
+
+        // It looks like a short header packet.
+        assert_eq!(d[0] & FORM_BIT, 0);
+        assert_eq!(d[0] & FIXED_BIT, FIXED_BIT);
+
+        let hdr = Header::from_slice(&d[..len], 16).unwrap();
+        assert_eq!(hdr.ty, Type::Short);
+
+        assert_eq!(&d[len - 16..len], &token.to_be_bytes());
+
+        // The packet is capped to the output buffer size.
+        let len = super::stateless_reset(token, 1200, &mut d).unwrap();
+        assert_eq!(len, 50);
+        assert_eq!(&d[len - 16..len], &token.to_be_bytes());
+
+        // Triggering packet is too small.
+        assert_eq!(
+            super::stateless_reset(token, MIN_STATELESS_RESET_LEN, &mut d),
+            Err(Error::Done)
+        );
+
+        // Output buffer is too small.
+        assert_eq!(
+            super::stateless_reset(token, 1200, &mut d[..20]),
+            Err(Error::Done)
+        );
+    }
+
+    #[test]
+    fn stateless_reset_token() {
+        // Test vectors from RFC 4231, truncated to 16 bytes.
+        let token = super::stateless_reset_token(&[0x0b; 20], b"Hi There");
+        assert_eq!(token, 0xb0344c61d8db38535ca8afceaf0bf12b);
+
+        let token = super::stateless_reset_token(
+            b"Jefe",
+            b"what do ya want for nothing?",
+        );
+        assert_eq!(token, 0x5bdcc146bf60754e6a042426089575c7);
+
+        // Tokens differ across connection IDs and keys.
+        let key = [0xaa; 32];
+        let token = super::stateless_reset_token(&key, &[0xba; 16]);
+
+        assert_ne!(token, super::stateless_reset_token(&key, &[0xbb; 16]));
+        assert_ne!(
+            token,
+            super::stateless_reset_token(&[0xab; 32], &[0xba; 16])
+        );
+    }
+
     #[test]
     fn pkt_num_encode_decode() {
         let num_len = pkt_num_len(0, 0);
@@ -1192,6 +1973,24 @@ mod tests {
         assert_eq!(pn, 0xace9fe);
     }
 
+    #[test]
+    fn pkt_num_len_range() {
+        // A single byte can only cover 128 unacked packets.
+        assert_eq!(pkt_num_len(128, 0), 1);
+        assert_eq!(pkt_num_len(129, 0), 2);
+        assert_eq!(pkt_num_len(1128, 1000), 1);
+        assert_eq!(pkt_num_len(1129, 1000), 2);
+
+        assert_eq!(pkt_num_len(32768, 0), 2);
+        assert_eq!(pkt_num_len(32769, 0), 3);
+
+        assert_eq!(pkt_num_len(8388608, 0), 3);
+        assert_eq!(pkt_num_len(8388609, 0), 4);
+
+        // Packet numbers can't be encoded in more than 4 bytes.
+        assert_eq!(pkt_num_len(u64::MAX, 0), 4);
+    }
+
     #[test]
     fn pkt_num_window() {
         let mut win = PktNumWindow::default();
@@ -1544,6 +2343,74 @@ mod tests {
         assert_eq!(&payload, &[0x01]);
     }
 
+    #[test]
+    fn decrypt_packet_initial() {
+        let mut pkt = [
+            0xcf, 0x00, 0x00, 0x00, 0x01, 0x00, 0x08, 0xf0, 0x67, 0xa5, 0x50,
+            0x2a, 0x42, 0x62, 0xb5, 0x00, 0x40, 0x75, 0xc0, 0xd9, 0x5a, 0x48,
+            0x2c, 0xd0, 0x99, 0x1c, 0xd2, 0x5b, 0x0a, 0xac, 0x40, 0x6a, 0x58,
+            0x16, 0xb6, 0x39, 0x41, 0x00, 0xf3, 0x7a, 0x1c, 0x69, 0x79, 0x75,
+            0x54, 0x78, 0x0b, 0xb3, 0x8c, 0xc5, 0xa9, 0x9f, 0x5e, 0xde, 0x4c,
+            0xf7, 0x3c, 0x3e, 0xc2, 0x49, 0x3a, 0x18, 0x39, 0xb3, 0xdb, 0xcb,
+            0xa3, 0xf6, 0xea, 0x46, 0xc5, 0xb7, 0x68, 0x4d, 0xf3, 0x54, 0x8e,
+            0x7d, 0xde, 0xb9, 0xc3, 0xbf, 0x9c, 0x73, 0xcc, 0x3f, 0x3b, 0xde,
+            0xd7, 0x4b, 0x56, 0x2b, 0xfb, 0x19, 0xfb, 0x84, 0x02, 0x2f, 0x8e,
+            0xf4, 0xcd, 0xd9, 0x37, 0x95, 0xd7, 0x7d, 0x06, 0xed, 0xbb, 0x7a,
+            0xaf, 0x2f, 0x58, 0x89, 0x18, 0x50, 0xab, 0xbd, 0xca, 0x3d, 0x20,
+            0x39, 0x8c, 0x27, 0x64, 0x56, 0xcb, 0xc4, 0x21, 0x58, 0x40, 0x7d,
+            0xd0, 0x74, 0xee,
+        ];
+
+        let pkt_len = pkt.len();
+
+        let odcid = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+
+        // The client's key can't decrypt the server's packet.
+        let key =
+            PacketKey::initial(&odcid, crate::PROTOCOL_VERSION, false).unwrap();
+        assert!(super::decrypt_packet(&mut pkt.clone(), 0, 0, &key).is_err());
+
+        let key =
+            PacketKey::initial(&odcid, crate::PROTOCOL_VERSION, true).unwrap();
+
+        let (hdr, payload, len) =
+            super::decrypt_packet(&mut pkt, 0, 0, &key).unwrap();
+
+        assert_eq!(hdr.ty, Type::Initial);
+        assert_eq!(hdr.pkt_num, 1);
+        assert_eq!(hdr.pkt_num_len, 2);
+        assert_eq!(len, pkt_len);
+
+        // ACK frame followed by a CRYPTO frame.
+        assert_eq!(&payload[..6], &[0x02, 0x00, 0x00, 0x00, 0x00, 0x06]);
+    }
+
+    #[test]
+    fn decrypt_packet_secret() {
+        let secret = [
+            0x9a, 0xc3, 0x12, 0xa7, 0xf8, 0x77, 0x46, 0x8e, 0xbe, 0x69, 0x42,
+            0x27, 0x48, 0xad, 0x00, 0xa1, 0x54, 0x43, 0xf1, 0x82, 0x03, 0xa0,
+            0x7d, 0x60, 0x60, 0xf6, 0x88, 0xf3, 0x0f, 0x21, 0x63, 0x2b,
+        ];
+
+        let mut pkt = [
+            0x4c, 0xfe, 0x41, 0x89, 0x65, 0x5e, 0x5c, 0xd5, 0x5c, 0x41, 0xf6,
+            0x90, 0x80, 0x57, 0x5d, 0x79, 0x99, 0xc2, 0x5a, 0x5b, 0xfb,
+        ];
+
+        let alg = crypto::Algorithm::ChaCha20_Poly1305;
+
+        let key = PacketKey::from_secret(alg, &secret).unwrap();
+
+        let (hdr, payload, len) =
+            super::decrypt_packet(&mut pkt, 0, 654_360_564, &key).unwrap();
+
+        assert_eq!(hdr.ty, Type::Short);
+        assert_eq!(hdr.pkt_num, 654_360_564);
+        assert_eq!(payload, &[0x01]);
+        assert_eq!(len, 21);
+    }
+
     fn assert_encrypt_initial_pkt(
         header: &mut [u8], dcid: &[u8], frames: &[u8], pn: u64, pn_len: usize,
         is_server: bool, expected_pkt: &[u8],
@@ -1935,6 +2802,7 @@ mod tests {
             token: None,
             versions: None,
             key_phase: false,
+            spin_bit: false,
         };
 
         hdr.to_bytes(&mut b).unwrap();
@@ -1967,6 +2835,7 @@ mod tests {
             token: None,
             versions: None,
             key_phase: false,
+            spin_bit: false,
         };
 
         hdr.to_bytes(&mut b).unwrap();
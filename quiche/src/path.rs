@@ -484,9 +484,14 @@ impl Path {
             lost: self.recovery.lost_count(),
             retrans: self.retrans_count,
             rtt: self.recovery.rtt(),
+            latest_rtt: self.recovery.latest_rtt(),
             min_rtt: self.recovery.min_rtt(),
             rttvar: self.recovery.rttvar(),
             cwnd: self.recovery.cwnd(),
+            ssthresh: self.recovery.ssthresh(),
+            bytes_in_flight: self.recovery.bytes_in_flight(),
+            pto_count: self.recovery.total_pto_count,
+            spurious_lost: self.recovery.lost_spurious_count,
             sent_bytes: self.sent_bytes,
             recv_bytes: self.recv_bytes,
             lost_bytes: self.recovery.bytes_lost,
@@ -859,6 +864,9 @@ pub struct PathStats {
     /// The estimated round-trip time of the connection.
     pub rtt: time::Duration,
 
+    /// The most recent round-trip time sample.
+    pub latest_rtt: time::Duration,
+
     /// The minimum round-trip time observed.
     pub min_rtt: Option<time::Duration>,
 
@@ -869,6 +877,20 @@ pub struct PathStats {
     /// The size of the connection's congestion window in bytes.
     pub cwnd: usize,
 
+    /// The slow start threshold in bytes.
+    ///
+    /// This is `usize::MAX` until the first congestion event.
+    pub ssthresh: usize,
+
+    /// The number of bytes in flight.
+    pub bytes_in_flight: usize,
+
+    /// The number of times the probe timeout expired.
+    pub pto_count: usize,
+
+    /// The number of QUIC packets declared lost that were later acked.
+    pub spurious_lost: usize,
+
     /// The number of sent bytes.
     pub sent_bytes: u64,
 
@@ -910,8 +932,17 @@ impl std::fmt::Debug for PathStats {
         )?;
         write!(
             f,
-            "recv={} sent={} lost={} retrans={} rtt={:?} min_rtt={:?} rttvar={:?} cwnd={}",
-            self.recv, self.sent, self.lost, self.retrans, self.rtt, self.min_rtt, self.rttvar, self.cwnd,
+            "recv={} sent={} lost={} retrans={} rtt={:?} latest_rtt={:?} min_rtt={:?} rttvar={:?} cwnd={}",
+            self.recv, self.sent, self.lost, self.retrans, self.rtt, self.latest_rtt, self.min_rtt, self.rttvar, self.cwnd,
+        )?;
+
+        write!(
+            f,
+            " ssthresh={} bytes_in_flight={} pto_count={} spurious_lost={}",
+            self.ssthresh,
+            self.bytes_in_flight,
+            self.pto_count,
+            self.spurious_lost,
         )?;
 
         write!(
@@ -38,6 +38,14 @@ pub fn rand_u8() -> u8 {
     buf[0]
 }
 
+pub fn rand_u32() -> u32 {
+    let mut buf = [0; 4];
+
+    rand_bytes(&mut buf);
+
+    u32::from_ne_bytes(buf)
+}
+
 pub fn rand_u64() -> u64 {
     let mut buf = [0; 8];
 
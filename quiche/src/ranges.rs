@@ -38,7 +38,10 @@ const MIN_TO_INLINE: usize = 2;
 
 /// A sorted collection of non overlapping [`u64`] ranges
 #[derive(Clone, PartialEq, Eq, PartialOrd)]
-pub enum RangeSet {
+pub struct RangeSet(Inner);
+
+#[derive(Clone, PartialEq, Eq, PartialOrd)]
+enum Inner {
     Inline(InlineRangeSet),
     BTree(BTreeRangeSet),
 }
@@ -46,7 +49,7 @@ pub enum RangeSet {
 /// A [`RangeSet`] variant backed by a [`SmallVec`] that is capable of storing
 /// [`MAX_INLINE_CAPACITY`] of ranges without allocation
 #[derive(Clone, PartialEq, Eq, PartialOrd)]
-pub struct InlineRangeSet {
+struct InlineRangeSet {
     inner: SmallVec<[(u64, u64); MAX_INLINE_CAPACITY]>,
     capacity: usize,
 }
@@ -54,7 +57,7 @@ pub struct InlineRangeSet {
 /// A [`RangeSet`] variant backed by a [`BTreeMap`] that is capable of storing
 /// an arbitrary number of ranges
 #[derive(Clone, PartialEq, Eq, PartialOrd)]
-pub struct BTreeRangeSet {
+struct BTreeRangeSet {
     inner: BTreeMap<u64, u64>,
     capacity: usize,
 }
@@ -65,36 +68,41 @@ impl RangeSet {
     /// When the length of a [`RangeSet`] overflows `capacity` it will remove
     /// the smallest range.
     pub fn new(capacity: usize) -> Self {
-        RangeSet::Inline(InlineRangeSet {
+        RangeSet(Inner::Inline(InlineRangeSet {
             inner: Default::default(),
             capacity,
-        })
+        }))
     }
 
     /// The number of nonoverlapping ranges stored in this [`RangeSet`].
     pub fn len(&self) -> usize {
-        match self {
-            RangeSet::Inline(set) => set.inner.len(),
-            RangeSet::BTree(set) => set.inner.len(),
+        match &self.0 {
+            Inner::Inline(set) => set.inner.len(),
+            Inner::BTree(set) => set.inner.len(),
         }
     }
 
+    /// Returns true if this [`RangeSet`] doesn't contain any range.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// Converts the inner representation from a BTree to Inline and vice versa
     /// when the proper conditions are met. Keeps the stored data intact.
     #[inline(always)]
     fn fixup(&mut self) {
-        match self {
-            RangeSet::Inline(set) if set.inner.len() == MAX_INLINE_CAPACITY => {
+        match &mut self.0 {
+            Inner::Inline(set) if set.inner.len() == MAX_INLINE_CAPACITY => {
                 let old_inner = std::mem::take(&mut set.inner);
-                *self = RangeSet::BTree(BTreeRangeSet {
+                self.0 = Inner::BTree(BTreeRangeSet {
                     inner: old_inner.into_inner().expect("At capacity").into(),
                     capacity: set.capacity,
                 });
             },
 
-            RangeSet::BTree(set) if set.inner.len() <= MIN_TO_INLINE => {
+            Inner::BTree(set) if set.inner.len() <= MIN_TO_INLINE => {
                 let old_inner = std::mem::take(&mut set.inner);
-                *self = RangeSet::Inline(InlineRangeSet {
+                self.0 = Inner::Inline(InlineRangeSet {
                     inner: SmallVec::from_iter(old_inner),
                     capacity: set.capacity,
                 })
@@ -111,9 +119,9 @@ impl RangeSet {
     /// stored ranges overflows capacity, the smalles range will be removed.
     #[inline]
     pub fn insert(&mut self, item: Range<u64>) {
-        match self {
-            RangeSet::Inline(set) => set.insert(item),
-            RangeSet::BTree(set) => set.insert(item),
+        match &mut self.0 {
+            Inner::Inline(set) => set.insert(item),
+            Inner::BTree(set) => set.insert(item),
         }
 
         self.fixup();
@@ -124,11 +132,11 @@ impl RangeSet {
         &self,
     ) -> impl DoubleEndedIterator<Item = Range<u64>> + ExactSizeIterator + '_
     {
-        match self {
-            RangeSet::BTree(set) =>
+        match &self.0 {
+            Inner::BTree(set) =>
                 Either::Left(set.inner.iter().map(|(k, v)| *k..*v)),
 
-            RangeSet::Inline(set) =>
+            Inner::Inline(set) =>
                 Either::Right(set.inner.iter().map(|(s, e)| *s..*e)),
         }
     }
@@ -136,44 +144,45 @@ impl RangeSet {
     /// Iterate over every single [`u64`] value covered by the ranges in this
     /// [`RangeSet`] in incremental order.
     pub fn flatten(&self) -> impl DoubleEndedIterator<Item = u64> + '_ {
-        match self {
-            RangeSet::BTree(set) =>
+        match &self.0 {
+            Inner::BTree(set) =>
                 Either::Left(set.inner.iter().flat_map(|(k, v)| *k..*v)),
 
-            RangeSet::Inline(set) =>
+            Inner::Inline(set) =>
                 Either::Right(set.inner.iter().flat_map(|(s, e)| *s..*e)),
         }
     }
 
     /// The smallest value covered by ranges in this collection.
     pub fn first(&self) -> Option<u64> {
-        match self {
-            RangeSet::Inline(set) => set.inner.first().map(|(s, _)| *s),
+        match &self.0 {
+            Inner::Inline(set) => set.inner.first().map(|(s, _)| *s),
 
-            RangeSet::BTree(set) => set.inner.first_key_value().map(|(k, _)| *k),
+            Inner::BTree(set) => set.inner.first_key_value().map(|(k, _)| *k),
         }
     }
 
     /// The largest value covered by ranges in this collection.
     pub fn last(&self) -> Option<u64> {
-        match self {
-            RangeSet::Inline(set) => set.inner.last().map(|(_, e)| *e - 1),
+        match &self.0 {
+            Inner::Inline(set) => set.inner.last().map(|(_, e)| *e - 1),
 
-            RangeSet::BTree(set) =>
-                set.inner.last_key_value().map(|(_, v)| *v - 1),
+            Inner::BTree(set) => set.inner.last_key_value().map(|(_, v)| *v - 1),
         }
     }
 
+    /// Removes all the values smaller than or equal to `largest`.
     #[inline]
     pub fn remove_until(&mut self, largest: u64) {
-        match self {
-            RangeSet::Inline(set) => set.remove_until(largest),
-            RangeSet::BTree(set) => set.remove_until(largest),
+        match &mut self.0 {
+            Inner::Inline(set) => set.remove_until(largest),
+            Inner::BTree(set) => set.remove_until(largest),
         }
 
         self.fixup();
     }
 
+    /// Insert a single value into the collection.
     pub fn push_item(&mut self, item: u64) {
         self.insert(item..item + 1)
     }
@@ -343,10 +352,10 @@ impl BTreeRangeSet {
 
 impl Default for RangeSet {
     fn default() -> Self {
-        RangeSet::Inline(InlineRangeSet {
+        RangeSet(Inner::Inline(InlineRangeSet {
             inner: Default::default(),
             capacity: usize::MAX,
-        })
+        }))
     }
 }
 
@@ -489,7 +498,7 @@ mod tests {
             3, 4, 5, 10, 16, 17, 18, 19
         ]);
 
-        assert!(matches!(r, RangeSet::Inline(_)));
+        assert!(matches!(r.0, Inner::Inline(_)));
 
         r.insert(13..14);
         assert_eq!(r.len(), 4);
@@ -498,7 +507,7 @@ mod tests {
         ]);
 
         // Make sure it converted to a btree at capacity
-        assert!(matches!(r, RangeSet::BTree(_)));
+        assert!(matches!(r.0, Inner::BTree(_)));
 
         r.insert(4..17);
         assert_eq!(r.len(), 1);
@@ -507,7 +516,7 @@ mod tests {
         ]);
 
         // Make sure it converted back to inline
-        assert!(matches!(r, RangeSet::Inline(_)));
+        assert!(matches!(r.0, Inner::Inline(_)));
     }
 
     #[test]
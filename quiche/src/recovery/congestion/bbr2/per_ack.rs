@@ -26,7 +26,6 @@
 
 use super::*;
 use crate::rand;
-use crate::recovery;
 
 /// 1.2Mbps in bytes/sec
 const PACING_RATE_1_2MBPS: u64 = 1200 * 1000 / 8;
@@ -708,11 +707,11 @@ fn bbr2_modulate_cwnd_for_recovery(r: &mut Congestion, in_flight: usize) {
     let lost_bytes = r.bbr2_state.newly_lost_bytes;
 
     if lost_bytes > 0 {
-        // QUIC mininum cwnd is 2 x MSS.
+        // Don't go below the configured minimum cwnd.
         r.congestion_window = r
             .congestion_window
             .saturating_sub(lost_bytes)
-            .max(r.max_datagram_size * recovery::MINIMUM_WINDOW_PACKETS);
+            .max(r.max_datagram_size * r.minimum_congestion_window_packets);
     }
 
     if r.bbr2_state.packet_conservation {
@@ -0,0 +1,453 @@
+// Copyright (C) 2024, Cloudflare, Inc.
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//
+//     * Redistributions in binary form must reproduce the above copyright
+//       notice, this list of conditions and the following disclaimer in the
+//       documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS
+// IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO,
+// THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR
+// PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Built-in congestion control algorithms.
+//!
+//! Drives a built-in algorithm from [`CongestionControl`] events, so it can
+//! be used in place of an application-provided one.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::packet;
+use crate::recovery::rtt::RttStats;
+use crate::recovery::Acked;
+use crate::recovery::RecoveryConfig;
+use crate::recovery::Sent;
+use crate::CongestionControlAlgorithm;
+
+use super::AckedPacket;
+use super::Congestion;
+use super::CongestionControl;
+use super::PacketNumberSpace;
+use super::RttEstimate;
+
+/// A built-in congestion control algorithm.
+///
+/// This implements [`CongestionControl`] for the algorithms listed in
+/// [`CongestionControlAlgorithm`], so they can be wrapped or compared with
+/// experimental algorithms set with [`set_custom_cc()`].
+///
+/// The algorithm runs on the same code as when it's selected with
+/// [`set_cc_algorithm()`], and it uses the RTT estimate and bytes in flight
+/// reported by quiche.
+///
+/// [`CongestionControl`]: trait.CongestionControl.html
+/// [`CongestionControlAlgorithm`]: enum.CongestionControlAlgorithm.html
+/// [`set_custom_cc()`]: struct.Config.html#method.set_custom_cc
+/// [`set_cc_algorithm()`]: struct.Config.html#method.set_cc_algorithm
+pub struct BuiltinCongestionControl {
+    cc: Congestion,
+
+    // Mirrors the RTT estimate reported by quiche.
+    rtt_stats: RttStats,
+
+    // Packets that were sent and not acknowledged or lost yet, for each
+    // packet number space, in the order they were sent. They are needed for
+    // delivery rate sampling.
+    sent_packets: [VecDeque<Sent>; packet::Epoch::count()],
+
+    bytes_lost: u64,
+}
+
+impl BuiltinCongestionControl {
+    /// Creates a new instance of the given algorithm.
+    ///
+    /// `max_datagram_size` is the maximum size of the packets sent on the
+    /// path. Other parameters, such as the initial congestion window, have
+    /// their default values.
+    pub fn new(
+        algo: CongestionControlAlgorithm, max_datagram_size: usize,
+    ) -> Self {
+        let recovery_config = RecoveryConfig {
+            max_send_udp_payload_size: max_datagram_size,
+            max_ack_delay: Duration::ZERO,
+            cc_algorithm: algo,
+            hystart: true,
+            pacing: true,
+            max_pacing_rate: None,
+            initial_congestion_window_packets:
+                crate::DEFAULT_INITIAL_CONGESTION_WINDOW_PACKETS,
+            minimum_congestion_window_packets:
+                crate::DEFAULT_MINIMUM_CONGESTION_WINDOW_PACKETS,
+            ecn: false,
+            custom_cc: None,
+            pto_backoff_multiplier: crate::DEFAULT_PTO_BACKOFF_MULTIPLIER,
+            max_pto_backoffs: crate::DEFAULT_MAX_PTO_BACKOFFS,
+        };
+
+        BuiltinCongestionControl {
+            cc: Congestion::from_config(&recovery_config),
+            rtt_stats: RttStats::new(Duration::ZERO),
+            sent_packets: Default::default(),
+            bytes_lost: 0,
+        }
+    }
+
+    fn update_rtt(&mut self, rtt: &RttEstimate, now: Instant) {
+        self.rtt_stats.latest_rtt = rtt.latest_rtt;
+        self.rtt_stats.smoothed_rtt = rtt.smoothed_rtt;
+        self.rtt_stats.rttvar = rtt.rttvar;
+
+        if let Some(min_rtt) = rtt.min_rtt {
+            self.rtt_stats.min_rtt.reset(now, min_rtt);
+
+            if self.rtt_stats.first_rtt_sample.is_none() {
+                self.rtt_stats.first_rtt_sample = Some(now);
+            }
+        }
+    }
+
+    // Returns the index of the given packet in the list of sent packets of
+    // its packet number space, which is sorted by packet number.
+    fn find_sent(&self, space: PacketNumberSpace, pkt_num: u64) -> Option<usize> {
+        self.sent_packets[space as usize]
+            .binary_search_by_key(&pkt_num, |p| p.pkt_num)
+            .ok()
+    }
+}
+
+impl CongestionControl for BuiltinCongestionControl {
+    fn on_packet_sent(
+        &mut self, space: PacketNumberSpace, pkt_num: u64, sent_bytes: usize,
+        bytes_in_flight: usize, now: Instant,
+    ) {
+        let mut pkt = Sent {
+            pkt_num,
+            frames: Default::default(),
+            time_sent: now,
+            time_acked: None,
+            time_lost: None,
+            size: sent_bytes,
+            ack_eliciting: true,
+            in_flight: true,
+            delivered: 0,
+            delivered_time: now,
+            first_sent_time: now,
+            is_app_limited: false,
+            tx_in_flight: 0,
+            lost: 0,
+            has_data: false,
+            pmtud: false,
+        };
+
+        self.cc.on_packet_sent(
+            bytes_in_flight,
+            sent_bytes,
+            now,
+            &mut pkt,
+            &self.rtt_stats,
+            self.bytes_lost,
+            true,
+            space.into(),
+        );
+
+        self.sent_packets[space as usize].push_back(pkt);
+    }
+
+    fn on_packets_acked(
+        &mut self, space: PacketNumberSpace, acked: &[AckedPacket],
+        rtt: &RttEstimate, bytes_in_flight: usize, now: Instant,
+    ) {
+        self.update_rtt(rtt, now);
+
+        let mut newly_acked = Vec::with_capacity(acked.len());
+
+        for pkt in acked {
+            let sent = self
+                .find_sent(space, pkt.pkt_num)
+                .map(|i| &mut self.sent_packets[space as usize][i]);
+
+            let acked = match sent {
+                Some(sent) => {
+                    sent.time_acked = Some(now);
+
+                    Acked {
+                        pkt_num: pkt.pkt_num,
+                        time_sent: sent.time_sent,
+                        size: pkt.size,
+
+                        rtt: now.saturating_duration_since(sent.time_sent),
+                        delivered: sent.delivered,
+                        delivered_time: sent.delivered_time,
+                        first_sent_time: sent.first_sent_time,
+                        is_app_limited: sent.is_app_limited,
+                    }
+                },
+
+                // The packet was already considered lost, so it can't be used
+                // for a delivery rate sample.
+                None => Acked {
+                    pkt_num: pkt.pkt_num,
+                    time_sent: pkt.time_sent,
+                    size: pkt.size,
+
+                    rtt: now.saturating_duration_since(pkt.time_sent),
+                    delivered: 0,
+                    delivered_time: pkt.time_sent,
+                    first_sent_time: pkt.time_sent,
+                    is_app_limited: true,
+                },
+            };
+
+            newly_acked.push(acked);
+        }
+
+        // Acknowledged packets are marked rather than removed, so only the
+        // ones at the front of the list need to be dropped.
+        let sent_packets = &mut self.sent_packets[space as usize];
+
+        while sent_packets
+            .front()
+            .map_or(false, |p| p.time_acked.is_some())
+        {
+            sent_packets.pop_front();
+        }
+
+        self.cc.on_packets_acked(
+            bytes_in_flight,
+            &mut newly_acked,
+            &self.rtt_stats,
+            now,
+            space.into(),
+        );
+    }
+
+    fn on_congestion_event(
+        &mut self, space: PacketNumberSpace, pkt_num: u64, lost_bytes: usize,
+        time_sent: Instant, bytes_in_flight: usize, now: Instant,
+    ) {
+        let pkt = match self.find_sent(space, pkt_num) {
+            Some(i) => {
+                let sent_packets = &mut self.sent_packets[space as usize];
+
+                let pkt = sent_packets[i].clone();
+
+                // Packets sent before a lost packet and not acknowledged yet
+                // are either lost as well or too old to be useful for
+                // delivery rate sampling, so stop tracking them.
+                if lost_bytes > 0 {
+                    sent_packets.drain(..=i);
+                }
+
+                pkt
+            },
+
+            // ECN-CE marks are reported for packets that were already
+            // acknowledged.
+            None => Sent {
+                pkt_num,
+                frames: Default::default(),
+                time_sent,
+                time_acked: None,
+                time_lost: None,
+                size: 0,
+                ack_eliciting: true,
+                in_flight: true,
+                delivered: 0,
+                delivered_time: time_sent,
+                first_sent_time: time_sent,
+                is_app_limited: false,
+                tx_in_flight: 0,
+                lost: 0,
+                has_data: false,
+                pmtud: false,
+            },
+        };
+
+        self.cc.on_congestion_event(
+            bytes_in_flight,
+            lost_bytes,
+            &pkt,
+            now,
+            space.into(),
+        );
+
+        self.bytes_lost += lost_bytes as u64;
+    }
+
+    fn on_packet_number_space_discarded(&mut self, space: PacketNumberSpace) {
+        self.sent_packets[space as usize].clear();
+    }
+
+    fn on_persistent_congestion(&mut self) {
+        self.cc.on_persistent_congestion();
+    }
+
+    fn cwnd(&self) -> usize {
+        self.cc.congestion_window()
+    }
+
+    fn pacing_rate(&self) -> Option<u64> {
+        // Algorithms without their own pacing use the rate quiche derives
+        // from the congestion window.
+        (self.cc.cc_ops.has_custom_pacing)().then(|| self.cc.pacer.rate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::recovery::congestion::test_sender::TestSender;
+
+    fn test_sender(algo: CongestionControlAlgorithm) -> TestSender {
+        let mut cfg = crate::Config::new(crate::PROTOCOL_VERSION).unwrap();
+        cfg.set_custom_cc(move || {
+            Box::new(BuiltinCongestionControl::new(
+                algo,
+                crate::MAX_SEND_UDP_PAYLOAD_SIZE,
+            ))
+        });
+
+        TestSender::with_config(&cfg)
+    }
+
+    #[test]
+    fn builtin_init() {
+        for algo in [
+            CongestionControlAlgorithm::Reno,
+            CongestionControlAlgorithm::CUBIC,
+            CongestionControlAlgorithm::BBR,
+            CongestionControlAlgorithm::BBR2,
+        ] {
+            let sender = test_sender(algo);
+            let expected = TestSender::new(algo, true);
+
+            assert_eq!(sender.congestion_window, expected.congestion_window);
+        }
+    }
+
+    #[test]
+    fn builtin_reno() {
+        let mut sender = test_sender(CongestionControlAlgorithm::Reno);
+        let mut expected =
+            TestSender::new(CongestionControlAlgorithm::Reno, true);
+        let size = sender.max_datagram_size;
+        let rtt = Duration::from_millis(50);
+
+        for s in [&mut sender, &mut expected] {
+            for _ in 0..10 {
+                s.send_packet(size);
+            }
+
+            s.advance_time(rtt);
+            s.update_rtt(rtt);
+            s.ack_n_packets(10, size);
+        }
+
+        // Slow start.
+        assert_eq!(sender.congestion_window, expected.congestion_window);
+        assert_eq!(sender.congestion_window, size * 20);
+
+        for s in [&mut sender, &mut expected] {
+            s.send_packet(size);
+            s.lose_n_packets(1, size, None);
+        }
+
+        assert_eq!(sender.congestion_window, expected.congestion_window);
+        assert_eq!(sender.congestion_window, size * 10);
+    }
+
+    #[test]
+    fn builtin_bbr_pacing() {
+        let mut sender = test_sender(CongestionControlAlgorithm::BBR);
+        let size = sender.max_datagram_size;
+        let rtt = Duration::from_millis(50);
+
+        sender.send_packet(size);
+        assert_eq!(sender.pacer.rate(), 0);
+
+        sender.advance_time(rtt);
+        sender.update_rtt(rtt);
+        sender.ack_n_packets(1, size);
+
+        // The pacing rate set by BBR is used for the next packet.
+        sender.send_packet(size);
+        assert!(sender.pacer.rate() > 0);
+    }
+
+    #[test]
+    fn builtin_packet_number_spaces() {
+        let mut cc = BuiltinCongestionControl::new(
+            CongestionControlAlgorithm::Reno,
+            crate::MAX_SEND_UDP_PAYLOAD_SIZE,
+        );
+        let size = crate::MAX_SEND_UDP_PAYLOAD_SIZE;
+        let now = Instant::now();
+
+        // The same packet number is used in two packet number spaces.
+        cc.on_packet_sent(PacketNumberSpace::Initial, 0, size, 0, now);
+        cc.on_packet_sent(PacketNumberSpace::Application, 0, size, size, now);
+        cc.on_packet_sent(PacketNumberSpace::Application, 1, size, size, now);
+
+        let rtt = RttEstimate {
+            latest_rtt: Duration::from_millis(80),
+            smoothed_rtt: Duration::from_millis(60),
+            rttvar: Duration::from_millis(10),
+            min_rtt: Some(Duration::from_millis(50)),
+        };
+
+        let acked = [
+            AckedPacket {
+                pkt_num: 0,
+                size,
+                time_sent: now,
+            },
+            AckedPacket {
+                pkt_num: 1,
+                size,
+                time_sent: now,
+            },
+        ];
+
+        cc.on_packets_acked(
+            PacketNumberSpace::Application,
+            &acked,
+            &rtt,
+            size * 3,
+            now + rtt.latest_rtt,
+        );
+
+        assert!(
+            cc.sent_packets[PacketNumberSpace::Application as usize].is_empty()
+        );
+        assert_eq!(
+            cc.sent_packets[PacketNumberSpace::Initial as usize].len(),
+            1
+        );
+
+        // The RTT estimate is used as is.
+        assert_eq!(cc.rtt_stats.rtt(), rtt.smoothed_rtt);
+        assert_eq!(cc.rtt_stats.min_rtt(), rtt.min_rtt);
+        assert_eq!(cc.rtt_stats.latest_rtt, rtt.latest_rtt);
+
+        cc.on_packet_number_space_discarded(PacketNumberSpace::Initial);
+
+        assert!(cc.sent_packets[PacketNumberSpace::Initial as usize].is_empty());
+    }
+}
@@ -36,7 +36,6 @@ use std::cmp;
 use std::time::Duration;
 use std::time::Instant;
 
-use crate::recovery;
 use crate::recovery::rtt::RttStats;
 use crate::recovery::Acked;
 use crate::recovery::Sent;
@@ -350,7 +349,7 @@ fn congestion_event(
         r.ssthresh = (r.congestion_window as f64 * BETA_CUBIC) as usize;
         r.ssthresh = cmp::max(
             r.ssthresh,
-            r.max_datagram_size * recovery::MINIMUM_WINDOW_PACKETS,
+            r.max_datagram_size * r.minimum_congestion_window_packets,
         );
         r.congestion_window = r.ssthresh;
 
@@ -421,6 +420,7 @@ mod tests {
 
     use crate::CongestionControlAlgorithm;
 
+    use crate::recovery;
     use crate::recovery::congestion::hystart;
     use crate::recovery::congestion::test_sender::TestSender;
     use crate::recovery::Recovery;
@@ -0,0 +1,239 @@
+// Copyright (C) 2024, Cloudflare, Inc.
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//
+//     * Redistributions in binary form must reproduce the above copyright
+//       notice, this list of conditions and the following disclaimer in the
+//       documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS
+// IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO,
+// THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR
+// PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Application-provided congestion control.
+//!
+//! Forwards congestion control events to a [`CongestionControl`]
+//! implementation, and keeps the congestion window in sync with it.
+
+use std::time::Instant;
+
+use crate::recovery::rtt::RttStats;
+use crate::recovery::Acked;
+use crate::recovery::Sent;
+
+use super::Congestion;
+use super::CongestionControlOps;
+
+pub(crate) static CUSTOM: CongestionControlOps = CongestionControlOps {
+    on_init,
+    on_packet_sent,
+    on_packets_acked,
+    congestion_event,
+    checkpoint,
+    rollback,
+    has_custom_pacing,
+    debug_fmt,
+};
+
+fn sync_cwnd(r: &mut Congestion) {
+    if let Some(cc) = r.custom.as_ref() {
+        r.congestion_window = cc.cwnd();
+    }
+}
+
+pub fn on_init(r: &mut Congestion) {
+    sync_cwnd(r);
+}
+
+pub fn on_packet_sent(
+    r: &mut Congestion, _sent_bytes: usize, _bytes_in_flight: usize,
+    _now: Instant,
+) {
+    // The packet itself is forwarded by `Congestion::on_packet_sent()`, as
+    // the algorithm needs its packet number space.
+    sync_cwnd(r);
+}
+
+// The acknowledged packets are forwarded by `Congestion::on_packets_acked()`
+// and the lost ones by `Congestion::on_congestion_event()`, as the algorithm
+// needs their packet number space.
+fn on_packets_acked(
+    r: &mut Congestion, _bytes_in_flight: usize, packets: &mut Vec<Acked>,
+    _now: Instant, _rtt_stats: &RttStats,
+) {
+    packets.clear();
+
+    sync_cwnd(r);
+}
+
+fn congestion_event(
+    r: &mut Congestion, _bytes_in_flight: usize, _lost_bytes: usize,
+    _largest_lost_pkt: &Sent, _now: Instant,
+) {
+    sync_cwnd(r);
+}
+
+fn checkpoint(_r: &mut Congestion) {}
+
+fn rollback(_r: &mut Congestion) -> bool {
+    false
+}
+
+fn has_custom_pacing() -> bool {
+    false
+}
+
+fn debug_fmt(_r: &Congestion, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "custom")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic;
+    use std::sync::Arc;
+
+    use crate::recovery::congestion::test_sender::TestSender;
+    use crate::recovery::congestion::AckedPacket;
+    use crate::recovery::congestion::CongestionControl;
+    use crate::recovery::congestion::PacketNumberSpace;
+    use crate::recovery::congestion::RttEstimate;
+
+    #[derive(Default)]
+    struct Counters {
+        sent_bytes: atomic::AtomicUsize,
+        ack_frames: atomic::AtomicUsize,
+    }
+
+    /// Halves the window on every congestion event, and grows it by the
+    /// number of acknowledged bytes otherwise.
+    struct Halving {
+        cwnd: usize,
+        counters: Arc<Counters>,
+    }
+
+    impl CongestionControl for Halving {
+        fn on_packet_sent(
+            &mut self, space: PacketNumberSpace, _pkt_num: u64,
+            sent_bytes: usize, _bytes_in_flight: usize, _now: Instant,
+        ) {
+            assert_eq!(space, PacketNumberSpace::Application);
+
+            self.counters
+                .sent_bytes
+                .fetch_add(sent_bytes, atomic::Ordering::Relaxed);
+        }
+
+        fn on_packets_acked(
+            &mut self, _space: PacketNumberSpace, acked: &[AckedPacket],
+            _rtt: &RttEstimate, _bytes_in_flight: usize, _now: Instant,
+        ) {
+            self.counters
+                .ack_frames
+                .fetch_add(1, atomic::Ordering::Relaxed);
+
+            self.cwnd += acked.iter().map(|p| p.size).sum::<usize>();
+        }
+
+        fn on_congestion_event(
+            &mut self, _space: PacketNumberSpace, _pkt_num: u64,
+            _lost_bytes: usize, _time_sent: Instant, _bytes_in_flight: usize,
+            _now: Instant,
+        ) {
+            self.cwnd /= 2;
+        }
+
+        fn on_persistent_congestion(&mut self) {
+            self.cwnd = 1;
+        }
+
+        fn cwnd(&self) -> usize {
+            self.cwnd
+        }
+
+        fn pacing_rate(&self) -> Option<u64> {
+            Some(1_000_000)
+        }
+    }
+
+    fn test_sender(counters: Arc<Counters>) -> TestSender {
+        let mut cfg = crate::Config::new(crate::PROTOCOL_VERSION).unwrap();
+        cfg.set_custom_cc(move || {
+            Box::new(Halving {
+                cwnd: 10_000,
+                counters: counters.clone(),
+            })
+        });
+
+        TestSender::with_config(&cfg)
+    }
+
+    #[test]
+    fn custom_init() {
+        let sender = test_sender(Default::default());
+
+        assert_eq!(sender.congestion_window, 10_000);
+    }
+
+    #[test]
+    fn custom_send_and_ack() {
+        let counters = Arc::new(Counters::default());
+
+        let mut sender = test_sender(counters.clone());
+        let size = sender.max_datagram_size;
+
+        sender.send_packet(size);
+        sender.send_packet(size);
+
+        assert_eq!(
+            counters.sent_bytes.load(atomic::Ordering::Relaxed),
+            size * 2
+        );
+
+        sender.ack_n_packets(2, size);
+
+        // Both packets are reported at once.
+        assert_eq!(counters.ack_frames.load(atomic::Ordering::Relaxed), 1);
+        assert_eq!(sender.congestion_window, 10_000 + size * 2);
+    }
+
+    #[test]
+    fn custom_congestion_event() {
+        let mut sender = test_sender(Default::default());
+        let size = sender.max_datagram_size;
+
+        sender.send_packet(size);
+        sender.lose_n_packets(1, size, None);
+
+        assert_eq!(sender.congestion_window, 5_000);
+
+        sender.on_persistent_congestion();
+
+        assert_eq!(sender.congestion_window, 1);
+    }
+
+    #[test]
+    fn custom_pacing_rate() {
+        let mut sender = test_sender(Default::default());
+        let size = sender.max_datagram_size;
+
+        sender.send_packet(size);
+
+        assert_eq!(sender.pacer.rate(), 1_000_000);
+    }
+}
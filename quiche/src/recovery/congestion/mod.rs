@@ -25,13 +25,19 @@
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use std::time::Instant;
 
+use crate::packet;
+
 use super::rtt::RttStats;
 use super::Acked;
 use super::RecoveryConfig;
 use super::Sent;
 
+pub use self::builtin::BuiltinCongestionControl;
+
 pub const PACING_MULTIPLIER: f64 = 1.25;
 pub struct Congestion {
     // Congestion control.
@@ -58,6 +64,9 @@ pub struct Congestion {
     // BBRv2 state.
     bbr2_state: bbr2::State,
 
+    // Application-provided congestion control.
+    custom: Option<Box<dyn CongestionControl>>,
+
     pub(crate) congestion_window: usize,
 
     pub(crate) ssthresh: usize,
@@ -75,6 +84,9 @@ pub struct Congestion {
     /// Initial congestion window size in terms of packet count.
     pub(crate) initial_congestion_window_packets: usize,
 
+    /// Minimum congestion window size in terms of packet count.
+    pub(crate) minimum_congestion_window_packets: usize,
+
     max_datagram_size: usize,
 
     pub(crate) lost_count: usize,
@@ -85,6 +97,14 @@ impl Congestion {
         let initial_congestion_window = recovery_config.max_send_udp_payload_size *
             recovery_config.initial_congestion_window_packets;
 
+        let custom = recovery_config.custom_cc.as_ref().map(|f| f());
+
+        let cc_ops = if custom.is_some() {
+            &custom::CUSTOM
+        } else {
+            recovery_config.cc_algorithm.into()
+        };
+
         let mut cc = Congestion {
             congestion_window: initial_congestion_window,
 
@@ -96,7 +116,7 @@ impl Congestion {
 
             congestion_recovery_start_time: None,
 
-            cc_ops: recovery_config.cc_algorithm.into(),
+            cc_ops,
 
             cubic_state: cubic::State::default(),
 
@@ -107,6 +127,9 @@ impl Congestion {
             initial_congestion_window_packets: recovery_config
                 .initial_congestion_window_packets,
 
+            minimum_congestion_window_packets: recovery_config
+                .minimum_congestion_window_packets,
+
             max_datagram_size: recovery_config.max_send_udp_payload_size,
 
             send_quantum: initial_congestion_window,
@@ -128,6 +151,8 @@ impl Congestion {
             bbr_state: bbr::State::new(),
 
             bbr2_state: bbr2::State::new(),
+
+            custom,
         };
 
         (cc.cc_ops.on_init)(&mut cc);
@@ -160,6 +185,23 @@ impl Congestion {
         self.congestion_window
     }
 
+    /// Collapses the congestion window to the minimum, as losses over a long
+    /// period of time established persistent congestion.
+    pub(crate) fn on_persistent_congestion(&mut self) {
+        if let Some(cc) = self.custom.as_mut() {
+            cc.on_persistent_congestion();
+
+            self.congestion_window = cc.cwnd();
+
+            return;
+        }
+
+        self.congestion_window =
+            self.max_datagram_size * self.minimum_congestion_window_packets;
+
+        self.bytes_acked_ca = 0;
+    }
+
     fn update_app_limited(&mut self, v: bool) {
         self.app_limited = v;
     }
@@ -168,12 +210,23 @@ impl Congestion {
     pub(crate) fn on_packet_sent(
         &mut self, bytes_in_flight: usize, sent_bytes: usize, now: Instant,
         pkt: &mut Sent, rtt_stats: &RttStats, bytes_lost: u64, in_flight: bool,
+        epoch: packet::Epoch,
     ) {
         if in_flight {
             self.update_app_limited(
                 (bytes_in_flight + sent_bytes) < self.congestion_window,
             );
 
+            if let Some(cc) = self.custom.as_mut() {
+                cc.on_packet_sent(
+                    epoch.into(),
+                    pkt.pkt_num,
+                    sent_bytes,
+                    bytes_in_flight,
+                    now,
+                );
+            }
+
             (self.cc_ops.on_packet_sent)(self, sent_bytes, bytes_in_flight, now);
 
             self.prr.on_packet_sent(sent_bytes);
@@ -185,7 +238,12 @@ impl Congestion {
         }
 
         // Pacing: Set the pacing rate if CC doesn't do its own.
-        if !(self.cc_ops.has_custom_pacing)() &&
+        let custom_pacing_rate =
+            self.custom.as_ref().and_then(|cc| cc.pacing_rate());
+
+        if let Some(rate) = custom_pacing_rate {
+            self.set_pacing_rate(rate, now);
+        } else if !(self.cc_ops.has_custom_pacing)() &&
             rtt_stats.first_rtt_sample.is_some()
         {
             let rate = PACING_MULTIPLIER * self.congestion_window as f64 /
@@ -204,7 +262,7 @@ impl Congestion {
 
     pub(crate) fn on_packets_acked(
         &mut self, bytes_in_flight: usize, acked: &mut Vec<Acked>,
-        rtt_stats: &RttStats, now: Instant,
+        rtt_stats: &RttStats, now: Instant, epoch: packet::Epoch,
     ) {
         // Update delivery rate sample per acked packet.
         for pkt in acked.iter() {
@@ -214,6 +272,25 @@ impl Congestion {
         // Fill in a rate sample.
         self.delivery_rate.generate_rate_sample(*rtt_stats.min_rtt);
 
+        if let Some(cc) = self.custom.as_mut() {
+            let packets: Vec<AckedPacket> = acked
+                .iter()
+                .map(|pkt| AckedPacket {
+                    pkt_num: pkt.pkt_num,
+                    size: pkt.size,
+                    time_sent: pkt.time_sent,
+                })
+                .collect();
+
+            cc.on_packets_acked(
+                epoch.into(),
+                &packets,
+                &rtt_stats.estimate(),
+                bytes_in_flight,
+                now,
+            );
+        }
+
         // Call congestion control hooks.
         (self.cc_ops.on_packets_acked)(
             self,
@@ -224,6 +301,45 @@ impl Congestion {
         );
     }
 
+    /// Notifies the algorithm that packets were lost, or that the peer
+    /// reported new ECN-CE marks, with `largest_lost_pkt` being the most
+    /// recently sent of the affected packets.
+    pub(crate) fn on_congestion_event(
+        &mut self, bytes_in_flight: usize, lost_bytes: usize,
+        largest_lost_pkt: &Sent, now: Instant, epoch: packet::Epoch,
+    ) {
+        if !self.in_congestion_recovery(largest_lost_pkt.time_sent) {
+            (self.cc_ops.checkpoint)(self);
+        }
+
+        if let Some(cc) = self.custom.as_mut() {
+            cc.on_congestion_event(
+                epoch.into(),
+                largest_lost_pkt.pkt_num,
+                lost_bytes,
+                largest_lost_pkt.time_sent,
+                bytes_in_flight,
+                now,
+            );
+        }
+
+        (self.cc_ops.congestion_event)(
+            self,
+            bytes_in_flight,
+            lost_bytes,
+            largest_lost_pkt,
+            now,
+        );
+    }
+
+    /// Notifies the algorithm that the packets sent in the given packet
+    /// number space will neither be acknowledged nor declared lost.
+    pub(crate) fn on_pkt_num_space_discarded(&mut self, epoch: packet::Epoch) {
+        if let Some(cc) = self.custom.as_mut() {
+            cc.on_packet_number_space_discarded(epoch.into());
+        }
+    }
+
     fn schedule_next_packet(&mut self, now: Instant, packet_size: usize) {
         // Don't pace in any of these cases:
         //   * Packet contains no data.
@@ -281,6 +397,144 @@ impl FromStr for CongestionControlAlgorithm {
     }
 }
 
+/// A packet number space, as defined in [RFC 9000].
+///
+/// [RFC 9000]: https://www.rfc-editor.org/rfc/rfc9000.html#section-12.3
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PacketNumberSpace {
+    /// Initial packets.
+    Initial     = 0,
+    /// Handshake packets.
+    Handshake   = 1,
+    /// 0-RTT and 1-RTT packets.
+    Application = 2,
+}
+
+impl From<packet::Epoch> for PacketNumberSpace {
+    fn from(epoch: packet::Epoch) -> Self {
+        match epoch {
+            packet::Epoch::Initial => PacketNumberSpace::Initial,
+            packet::Epoch::Handshake => PacketNumberSpace::Handshake,
+            packet::Epoch::Application => PacketNumberSpace::Application,
+        }
+    }
+}
+
+impl From<PacketNumberSpace> for packet::Epoch {
+    fn from(space: PacketNumberSpace) -> Self {
+        match space {
+            PacketNumberSpace::Initial => packet::Epoch::Initial,
+            PacketNumberSpace::Handshake => packet::Epoch::Handshake,
+            PacketNumberSpace::Application => packet::Epoch::Application,
+        }
+    }
+}
+
+/// A newly acknowledged packet.
+#[derive(Clone, Copy, Debug)]
+pub struct AckedPacket {
+    /// The packet number, unique within its packet number space.
+    pub pkt_num: u64,
+
+    /// The size of the packet, in bytes.
+    pub size: usize,
+
+    /// The time the packet was sent.
+    pub time_sent: Instant,
+}
+
+/// The RTT estimate of a path, as defined in [RFC 9002].
+///
+/// [RFC 9002]: https://www.rfc-editor.org/rfc/rfc9002.html#section-5
+#[derive(Clone, Copy, Debug)]
+pub struct RttEstimate {
+    /// The most recent RTT sample.
+    pub latest_rtt: Duration,
+
+    /// The smoothed RTT.
+    pub smoothed_rtt: Duration,
+
+    /// The RTT variation.
+    pub rttvar: Duration,
+
+    /// The minimum RTT, or `None` if no RTT sample was taken yet.
+    pub min_rtt: Option<Duration>,
+}
+
+/// A congestion control algorithm provided by the application.
+///
+/// A custom algorithm is used instead of the built-in ones when a factory is
+/// set with [`set_custom_cc()`]. A new instance is created for every path.
+///
+/// Loss detection, RTT estimation, pacing, and the accounting of bytes in
+/// flight are still done by quiche: the algorithm is only notified of the
+/// relevant events and decides the size of the congestion window.
+///
+/// Packet numbers are only unique within a packet number space, so the
+/// space is passed along with them.
+///
+/// The built-in algorithms implement this trait with
+/// [`BuiltinCongestionControl`].
+///
+/// [`set_custom_cc()`]: struct.Config.html#method.set_custom_cc
+/// [`BuiltinCongestionControl`]: struct.BuiltinCongestionControl.html
+pub trait CongestionControl: Send + Sync {
+    /// Called when a packet that counts towards bytes in flight is sent.
+    ///
+    /// `bytes_in_flight` doesn't include the packet being sent.
+    fn on_packet_sent(
+        &mut self, space: PacketNumberSpace, pkt_num: u64, sent_bytes: usize,
+        bytes_in_flight: usize, now: Instant,
+    );
+
+    /// Called once for each ACK frame that newly acknowledges packets.
+    ///
+    /// `acked` is sorted by packet number. `rtt` already includes the RTT
+    /// sample taken from the ACK frame, if any, and `bytes_in_flight` still
+    /// includes the acknowledged packets.
+    fn on_packets_acked(
+        &mut self, space: PacketNumberSpace, acked: &[AckedPacket],
+        rtt: &RttEstimate, bytes_in_flight: usize, now: Instant,
+    );
+
+    /// Called when packets are declared lost, or when the peer reports new
+    /// ECN-CE marks.
+    ///
+    /// `pkt_num` and `time_sent` are the number and the time the most recent
+    /// of the affected packets was sent. The latter can be used to react only
+    /// once per round trip. `bytes_in_flight` still includes the lost
+    /// packets.
+    fn on_congestion_event(
+        &mut self, space: PacketNumberSpace, pkt_num: u64, lost_bytes: usize,
+        time_sent: Instant, bytes_in_flight: usize, now: Instant,
+    );
+
+    /// Called when a packet number space is discarded.
+    ///
+    /// Packets sent in `space` that are still outstanding will neither be
+    /// acknowledged nor declared lost, and no longer count towards bytes in
+    /// flight.
+    fn on_packet_number_space_discarded(&mut self, _space: PacketNumberSpace) {}
+
+    /// Called when persistent congestion is established.
+    fn on_persistent_congestion(&mut self);
+
+    /// Returns the congestion window, in bytes.
+    fn cwnd(&self) -> usize;
+
+    /// Returns the pacing rate, in bytes per second.
+    ///
+    /// When `None` is returned, the pacing rate is derived from the
+    /// congestion window and the smoothed RTT.
+    fn pacing_rate(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Creates new instances of a custom congestion control algorithm.
+pub(crate) type CongestionControlFactory =
+    Arc<dyn Fn() -> Box<dyn CongestionControl> + Send + Sync>;
+
 pub(crate) struct CongestionControlOps {
     pub on_init: fn(r: &mut Congestion),
 
@@ -332,7 +586,9 @@ impl From<CongestionControlAlgorithm> for &'static CongestionControlOps {
 
 mod bbr;
 mod bbr2;
+mod builtin;
 mod cubic;
+mod custom;
 mod delivery_rate;
 mod hystart;
 pub(crate) mod pacer;
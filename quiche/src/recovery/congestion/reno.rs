@@ -122,7 +122,7 @@ fn congestion_event(
 
         r.congestion_window = cmp::max(
             r.congestion_window,
-            r.max_datagram_size * recovery::MINIMUM_WINDOW_PACKETS,
+            r.max_datagram_size * r.minimum_congestion_window_packets,
         );
 
         r.bytes_acked_ca = (r.congestion_window as f64 *
@@ -224,6 +224,41 @@ mod tests {
         assert_eq!(prev_cwnd / 2, sender.congestion_window);
     }
 
+    #[test]
+    fn reno_congestion_event_minimum_window() {
+        let mut sender = test_sender();
+        let size = sender.max_datagram_size;
+
+        sender.minimum_congestion_window_packets = 8;
+
+        sender.send_packet(size);
+        sender.lose_n_packets(1, size, None);
+
+        // The cwnd is not cut below the minimum window.
+        assert_eq!(sender.congestion_window, size * 8);
+    }
+
+    #[test]
+    fn reno_congestion_event_minimum_window_clamped() {
+        let mut cfg = crate::Config::new(crate::PROTOCOL_VERSION).unwrap();
+        cfg.set_cc_algorithm(recovery::CongestionControlAlgorithm::Reno);
+        cfg.set_minimum_congestion_window_packets(0);
+
+        let mut sender = TestSender::with_config(&cfg);
+        let size = sender.max_datagram_size;
+
+        assert_eq!(sender.minimum_congestion_window_packets, 1);
+
+        // Keep losing packets until the cwnd stops shrinking.
+        for _ in 0..10 {
+            sender.advance_time(Duration::from_millis(1));
+            sender.send_packet(size);
+            sender.lose_n_packets(1, size, None);
+        }
+
+        assert_eq!(sender.congestion_window, size);
+    }
+
     #[test]
     fn reno_congestion_avoidance() {
         let mut sender = test_sender();
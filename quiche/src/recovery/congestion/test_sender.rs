@@ -30,6 +30,7 @@ use std::ops::DerefMut;
 use std::time::Duration;
 use std::time::Instant;
 
+use crate::packet;
 use crate::recovery::congestion::Congestion;
 use crate::recovery::rtt::RttStats;
 use crate::recovery::Acked;
@@ -53,13 +54,17 @@ impl TestSender {
         cfg.set_cc_algorithm(algo);
         cfg.enable_hystart(hystart);
 
+        TestSender::with_config(&cfg)
+    }
+
+    pub(crate) fn with_config(cfg: &crate::Config) -> Self {
         TestSender {
             next_pkt: 0,
             next_ack: 0,
             bytes_in_flight: 0,
             time: Instant::now(),
             rtt_stats: RttStats::new(Duration::from_micros(0)),
-            cc: Congestion::from_config(&RecoveryConfig::from_config(&cfg)),
+            cc: Congestion::from_config(&RecoveryConfig::from_config(cfg)),
             sent_packets: VecDeque::new(),
         }
     }
@@ -92,6 +97,7 @@ impl TestSender {
             &self.rtt_stats,
             0,
             true,
+            packet::Epoch::Application,
         );
 
         self.sent_packets.push_back(sent);
@@ -108,6 +114,7 @@ impl TestSender {
             &mut vec![acked],
             &self.rtt_stats,
             now,
+            packet::Epoch::Application,
         );
     }
 
@@ -137,6 +144,7 @@ impl TestSender {
             &mut acked,
             &self.rtt_stats,
             self.time,
+            packet::Epoch::Application,
         );
 
         self.bytes_in_flight -= n * bytes;
@@ -157,16 +165,12 @@ impl TestSender {
             unacked.time_sent = time;
         }
 
-        if !self.cc.in_congestion_recovery(unacked.time_sent) {
-            (self.cc.cc_ops.checkpoint)(&mut self.cc);
-        }
-
-        (self.cc_ops.congestion_event)(
-            &mut self.cc,
+        self.cc.on_congestion_event(
             self.bytes_in_flight,
             n * bytes,
             &unacked,
             self.time,
+            packet::Epoch::Application,
         );
 
         self.cc.lost_count += n;
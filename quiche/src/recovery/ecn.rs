@@ -0,0 +1,339 @@
+// Copyright (C) 2024, Cloudflare, Inc.
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//
+//     * Redistributions in binary form must reproduce the above copyright
+//       notice, this list of conditions and the following disclaimer in the
+//       documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS
+// IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO,
+// THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR
+// PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! ECN validation.
+//!
+//! This implements the validation of the path's support for Explicit
+//! Congestion Notification, as described in [RFC 9000 Section 13.4.2].
+//!
+//! [RFC 9000 Section 13.4.2]: https://www.rfc-editor.org/rfc/rfc9000#section-13.4.2
+
+use crate::frame::EcnCounts;
+use crate::packet;
+use crate::ranges::RangeSet;
+use crate::Ecn;
+
+/// The number of packets marked with ECT(0) while testing the path.
+const TESTING_PACKETS: usize = 10;
+
+/// The maximum number of ranges of ECT(0) marked packets tracked.
+const MAX_MARKED_RANGES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EcnValidation {
+    /// Marked packets are being sent to test the path.
+    Testing,
+
+    /// All testing packets were sent, but none of them were acked yet.
+    Unknown,
+
+    /// The path supports ECN.
+    Capable,
+
+    /// The path doesn't support ECN, or ECN is disabled.
+    Failed,
+}
+
+pub struct EcnState {
+    validation: EcnValidation,
+
+    /// The number of marked packets sent while testing.
+    testing_sent: usize,
+
+    /// The ECT(0) marked packets not yet acked, for each packet number space.
+    marked: [RangeSet; packet::Epoch::count()],
+
+    /// The number of ECT(0) marked packets sent in each packet number space.
+    sent_ect0: [u64; packet::Epoch::count()],
+
+    /// The largest ECN counts reported by the peer in each packet number
+    /// space.
+    peer_counts: [EcnCounts; packet::Epoch::count()],
+}
+
+impl EcnState {
+    pub fn new(enabled: bool) -> Self {
+        let validation = if enabled {
+            EcnValidation::Testing
+        } else {
+            EcnValidation::Failed
+        };
+
+        EcnState {
+            validation,
+
+            testing_sent: 0,
+
+            marked: [
+                RangeSet::new(MAX_MARKED_RANGES),
+                RangeSet::new(MAX_MARKED_RANGES),
+                RangeSet::new(MAX_MARKED_RANGES),
+            ],
+
+            sent_ect0: [0; packet::Epoch::count()],
+
+            peer_counts: Default::default(),
+        }
+    }
+
+    /// Returns the codepoint outgoing packets should be marked with.
+    pub fn codepoint(&self) -> Ecn {
+        match self.validation {
+            EcnValidation::Testing | EcnValidation::Capable => Ecn::Ect0,
+
+            EcnValidation::Unknown | EcnValidation::Failed => Ecn::NotEct,
+        }
+    }
+
+    /// Records a packet sent with the given codepoint.
+    ///
+    /// Note that the codepoint is the one of the datagram the packet was
+    /// coalesced in, which might differ from the current one if the state
+    /// changed while the datagram was being built.
+    pub fn on_packet_sent(
+        &mut self, pkt_num: u64, epoch: packet::Epoch, ecn: Ecn,
+    ) {
+        if ecn != Ecn::Ect0 {
+            return;
+        }
+
+        self.marked[epoch].insert(pkt_num..pkt_num + 1);
+        self.sent_ect0[epoch] += 1;
+
+        if self.validation == EcnValidation::Testing {
+            self.testing_sent += 1;
+
+            if self.testing_sent >= TESTING_PACKETS {
+                self.validation = EcnValidation::Unknown;
+            }
+        }
+    }
+
+    /// Processes the ECN counts of an ACK frame acknowledging the given
+    /// packet number ranges.
+    ///
+    /// Returns true if the peer reported new ECN-CE marks.
+    pub fn on_ack_received(
+        &mut self, ranges: &RangeSet, counts: Option<&EcnCounts>,
+        epoch: packet::Epoch,
+    ) -> bool {
+        if self.validation == EcnValidation::Failed {
+            return false;
+        }
+
+        let newly_acked = self.newly_acked(ranges, epoch);
+
+        let counts = match counts {
+            Some(v) => v,
+
+            // Marked packets were acked without ECN feedback, so either the
+            // peer or the path don't support ECN.
+            None if newly_acked > 0 => {
+                self.validation = EcnValidation::Failed;
+                return false;
+            },
+
+            None => return false,
+        };
+
+        let prev = &self.peer_counts[epoch];
+
+        // Ignore reordered ACK frames carrying stale counts.
+        if counts.ect0_count < prev.ect0_count ||
+            counts.ect1_count < prev.ect1_count ||
+            counts.ecn_ce_count < prev.ecn_ce_count
+        {
+            return false;
+        }
+
+        let ect0_increase = counts.ect0_count - prev.ect0_count;
+        let ce_increase = counts.ecn_ce_count - prev.ecn_ce_count;
+
+        // Only ECT(0) is ever sent, so the counts must not exceed the number
+        // of marked packets, and all newly acked marked packets must be
+        // accounted for.
+        if counts.ect1_count > 0 ||
+            counts.ect0_count + counts.ecn_ce_count > self.sent_ect0[epoch] ||
+            ect0_increase + ce_increase < newly_acked
+        {
+            self.validation = EcnValidation::Failed;
+            return false;
+        }
+
+        self.peer_counts[epoch] = counts.clone();
+
+        if newly_acked > 0 && self.validation != EcnValidation::Capable {
+            self.validation = EcnValidation::Capable;
+        }
+
+        ce_increase > 0
+    }
+
+    /// Returns the number of marked packets acked for the first time, and
+    /// stops tracking them.
+    fn newly_acked(&mut self, ranges: &RangeSet, epoch: packet::Epoch) -> u64 {
+        let marked = &mut self.marked[epoch];
+
+        let mut newly_acked = 0;
+
+        for r in ranges.iter() {
+            for m in marked.iter() {
+                let start = std::cmp::max(r.start, m.start);
+                let end = std::cmp::min(r.end, m.end);
+
+                newly_acked += end.saturating_sub(start);
+            }
+        }
+
+        // Marked packets below the largest acked one are either acked or
+        // lost, so they don't need to be tracked anymore.
+        if let Some(largest_acked) = ranges.last() {
+            marked.remove_until(largest_acked);
+        }
+
+        newly_acked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ack(ranges: std::ops::Range<u64>) -> RangeSet {
+        let mut r = RangeSet::default();
+        r.insert(ranges);
+        r
+    }
+
+    fn counts(ect0_count: u64, ecn_ce_count: u64) -> EcnCounts {
+        EcnCounts {
+            ect0_count,
+            ect1_count: 0,
+            ecn_ce_count,
+        }
+    }
+
+    #[test]
+    fn disabled() {
+        let mut ecn = EcnState::new(false);
+
+        assert_eq!(ecn.codepoint(), Ecn::NotEct);
+
+        ecn.on_packet_sent(0, packet::Epoch::Application, ecn.codepoint());
+        assert_eq!(ecn.sent_ect0[packet::Epoch::Application], 0);
+    }
+
+    #[test]
+    fn validation() {
+        let mut ecn = EcnState::new(true);
+
+        let epoch = packet::Epoch::Application;
+
+        for pn in 0..TESTING_PACKETS as u64 {
+            assert_eq!(ecn.codepoint(), Ecn::Ect0);
+            ecn.on_packet_sent(pn, epoch, Ecn::Ect0);
+        }
+
+        // Stop marking until the testing packets are acked.
+        assert_eq!(ecn.codepoint(), Ecn::NotEct);
+
+        assert!(!ecn.on_ack_received(&ack(0..5), Some(&counts(5, 0)), epoch));
+        assert_eq!(ecn.validation, EcnValidation::Capable);
+        assert_eq!(ecn.codepoint(), Ecn::Ect0);
+
+        // New CE marks are reported.
+        assert!(ecn.on_ack_received(&ack(0..8), Some(&counts(6, 2)), epoch));
+
+        // Duplicate ACK.
+        assert!(!ecn.on_ack_received(&ack(0..8), Some(&counts(6, 2)), epoch));
+        assert_eq!(ecn.validation, EcnValidation::Capable);
+    }
+
+    #[test]
+    fn validation_coalesced() {
+        let mut ecn = EcnState::new(true);
+
+        let epoch = packet::Epoch::Application;
+
+        for pn in 0..TESTING_PACKETS as u64 - 1 {
+            ecn.on_packet_sent(pn, epoch, Ecn::Ect0);
+        }
+
+        // The last testing packet is coalesced with another one in a marked
+        // datagram, so both are marked even though the state changed.
+        let codepoint = ecn.codepoint();
+        assert_eq!(codepoint, Ecn::Ect0);
+
+        ecn.on_packet_sent(9, epoch, codepoint);
+        assert_eq!(ecn.codepoint(), Ecn::NotEct);
+
+        ecn.on_packet_sent(10, epoch, codepoint);
+        assert_eq!(ecn.sent_ect0[epoch], 11);
+
+        assert!(!ecn.on_ack_received(&ack(0..11), Some(&counts(11, 0)), epoch));
+        assert_eq!(ecn.validation, EcnValidation::Capable);
+    }
+
+    #[test]
+    fn validation_missing_counts() {
+        let mut ecn = EcnState::new(true);
+
+        let epoch = packet::Epoch::Application;
+
+        ecn.on_packet_sent(0, epoch, Ecn::Ect0);
+        ecn.on_packet_sent(1, epoch, Ecn::Ect0);
+
+        assert!(!ecn.on_ack_received(&ack(0..2), None, epoch));
+        assert_eq!(ecn.validation, EcnValidation::Failed);
+        assert_eq!(ecn.codepoint(), Ecn::NotEct);
+    }
+
+    #[test]
+    fn validation_bleached() {
+        let mut ecn = EcnState::new(true);
+
+        let epoch = packet::Epoch::Application;
+
+        ecn.on_packet_sent(0, epoch, Ecn::Ect0);
+        ecn.on_packet_sent(1, epoch, Ecn::Ect0);
+
+        // Only one of the two marked packets is reported.
+        assert!(!ecn.on_ack_received(&ack(0..2), Some(&counts(1, 0)), epoch));
+        assert_eq!(ecn.validation, EcnValidation::Failed);
+    }
+
+    #[test]
+    fn validation_too_many_marks() {
+        let mut ecn = EcnState::new(true);
+
+        let epoch = packet::Epoch::Application;
+
+        ecn.on_packet_sent(0, epoch, Ecn::Ect0);
+
+        assert!(!ecn.on_ack_received(&ack(0..1), Some(&counts(2, 0)), epoch));
+        assert_eq!(ecn.validation, EcnValidation::Failed);
+    }
+}
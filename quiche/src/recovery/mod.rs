@@ -26,6 +26,8 @@
 
 use std::cmp;
 
+use std::net::SocketAddr;
+
 use std::time::Duration;
 use std::time::Instant;
 
@@ -48,6 +50,9 @@ use smallvec::SmallVec;
 
 use self::congestion::pacer;
 use self::congestion::Congestion;
+use self::ecn::EcnState;
+use self::resume::CarefulResume;
+pub use self::resume::CarefulResumeParams;
 use self::rtt::RttStats;
 
 // Loss Recovery
@@ -61,10 +66,13 @@ const GRANULARITY: Duration = Duration::from_millis(1);
 
 const MAX_PTO_PROBES_COUNT: usize = 2;
 
-const MINIMUM_WINDOW_PACKETS: usize = 2;
+// Upper bound to the PTO backoff factor, to keep timeouts representable.
+const MAX_PTO_BACKOFF: f64 = 1_000_000.0;
 
 const LOSS_REDUCTION_FACTOR: f64 = 0.5;
 
+const PERSISTENT_CONGESTION_THRESHOLD: u32 = 3;
+
 // How many non ACK eliciting packets we send before including a PING to solicit
 // an ACK.
 pub(super) const MAX_OUTSTANDING_NON_ACK_ELICITING: usize = 24;
@@ -89,8 +97,8 @@ struct RecoveryEpoch {
     loss_probes: usize,
     in_flight_count: usize,
 
-    acked_frames: Vec<frame::Frame>,
-    lost_frames: Vec<frame::Frame>,
+    acked_frames: Vec<frame::SentFrame>,
+    lost_frames: Vec<frame::SentFrame>,
 }
 
 struct AckedDetectionResult {
@@ -106,6 +114,10 @@ struct LossDetectionResult {
     lost_packets: usize,
     lost_bytes: usize,
     pmtud_lost_bytes: usize,
+
+    /// The longest time between the send times of two lost ack-eliciting
+    /// packets, with no packet sent in between them being acked.
+    lost_period: Duration,
 }
 
 impl RecoveryEpoch {
@@ -197,9 +209,11 @@ impl RecoveryEpoch {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn detect_lost_packets(
-        &mut self, loss_delay: Duration, pkt_thresh: u64, now: Instant,
-        trace_id: &str, epoch: Epoch,
+        &mut self, loss_delay: Duration, pkt_thresh: u64,
+        first_rtt_sample: Option<Instant>, now: Instant, trace_id: &str,
+        epoch: Epoch,
     ) -> LossDetectionResult {
         self.loss_time = None;
 
@@ -216,14 +230,25 @@ impl RecoveryEpoch {
 
         let mut largest_lost_pkt = None;
 
+        // Send time of the first ack-eliciting packet in the current sequence
+        // of lost packets.
+        let mut lost_period_start = None;
+        let mut lost_period = Duration::ZERO;
+
         let unacked_iter = self.sent_packets
         .iter_mut()
         // Skip packets that follow the largest acked packet.
         .take_while(|p| p.pkt_num <= largest_acked)
-        // Skip packets that have already been acked or lost.
-        .filter(|p| p.time_acked.is_none() && p.time_lost.is_none());
+        // Skip packets that have already been lost.
+        .filter(|p| p.time_lost.is_none());
 
         for unacked in unacked_iter {
+            // An acked packet ends the current sequence of lost packets.
+            if unacked.time_acked.is_some() {
+                lost_period_start = None;
+                continue;
+            }
+
             // Mark packet as lost, or set time when it should be marked.
             if unacked.time_sent <= lost_send_time ||
                 largest_acked >= unacked.pkt_num + pkt_thresh
@@ -243,6 +268,19 @@ impl RecoveryEpoch {
                 if unacked.in_flight {
                     lost_bytes += unacked.size;
 
+                    // Only consider packets sent after an RTT sample was taken
+                    // for persistent congestion.
+                    if unacked.ack_eliciting &&
+                        first_rtt_sample
+                            .map_or(false, |t| unacked.time_sent > t)
+                    {
+                        let start =
+                            *lost_period_start.get_or_insert(unacked.time_sent);
+
+                        lost_period =
+                            cmp::max(lost_period, unacked.time_sent - start);
+                    }
+
                     // Frames have already been removed from the packet, so
                     // cloning the whole packet should be relatively cheap.
                     largest_lost_pkt = Some(unacked.clone());
@@ -276,6 +314,7 @@ impl RecoveryEpoch {
             lost_packets,
             lost_bytes,
             pmtud_lost_bytes,
+            lost_period,
         }
     }
 
@@ -329,10 +368,16 @@ pub struct Recovery {
 
     pub lost_spurious_count: usize,
 
+    pub total_pto_count: usize,
+
     pkt_thresh: u64,
 
     time_thresh: f64,
 
+    pto_backoff_multiplier: f64,
+
+    max_pto_backoffs: u32,
+
     bytes_in_flight: usize,
 
     bytes_sent: usize,
@@ -349,6 +394,10 @@ pub struct Recovery {
 
     congestion: Congestion,
 
+    ecn: EcnState,
+
+    careful_resume: CarefulResume,
+
     /// A resusable list of acks.
     newly_acked: Vec<Acked>,
 }
@@ -361,6 +410,11 @@ pub struct RecoveryConfig {
     pacing: bool,
     max_pacing_rate: Option<u64>,
     initial_congestion_window_packets: usize,
+    minimum_congestion_window_packets: usize,
+    ecn: bool,
+    custom_cc: Option<congestion::CongestionControlFactory>,
+    pto_backoff_multiplier: f64,
+    max_pto_backoffs: u32,
 }
 
 impl RecoveryConfig {
@@ -374,6 +428,12 @@ impl RecoveryConfig {
             max_pacing_rate: config.max_pacing_rate,
             initial_congestion_window_packets: config
                 .initial_congestion_window_packets,
+            minimum_congestion_window_packets: config
+                .minimum_congestion_window_packets,
+            ecn: config.ecn,
+            custom_cc: config.custom_cc.clone(),
+            pto_backoff_multiplier: config.pto_backoff_multiplier,
+            max_pto_backoffs: config.max_pto_backoffs,
         }
     }
 }
@@ -391,10 +451,16 @@ impl Recovery {
 
             lost_spurious_count: 0,
 
+            total_pto_count: 0,
+
             pkt_thresh: INITIAL_PACKET_THRESHOLD,
 
             time_thresh: INITIAL_TIME_THRESHOLD,
 
+            pto_backoff_multiplier: recovery_config.pto_backoff_multiplier,
+
+            max_pto_backoffs: recovery_config.max_pto_backoffs,
+
             bytes_in_flight: 0,
 
             bytes_sent: 0,
@@ -410,6 +476,10 @@ impl Recovery {
 
             congestion: Congestion::from_config(recovery_config),
 
+            ecn: EcnState::new(recovery_config.ecn),
+
+            careful_resume: CarefulResume::default(),
+
             newly_acked: Vec::new(),
         }
     }
@@ -427,15 +497,87 @@ impl Recovery {
                 MAX_OUTSTANDING_NON_ACK_ELICITING
     }
 
+    /// Returns the ECN codepoint outgoing packets should be marked with.
+    pub fn ecn_codepoint(&self) -> crate::Ecn {
+        self.ecn.codepoint()
+    }
+
+    /// Records a packet sent with the given ECN codepoint.
+    pub fn on_ecn_packet_sent(
+        &mut self, pkt_num: u64, epoch: packet::Epoch, ecn: crate::Ecn,
+    ) {
+        self.ecn.on_packet_sent(pkt_num, epoch, ecn);
+    }
+
+    /// Processes the ECN counts carried by an ACK frame.
+    ///
+    /// This needs to be called before [`on_ack_received()`], as new ECN-CE
+    /// marks trigger a congestion event based on the largest newly acked
+    /// packet.
+    ///
+    /// [`on_ack_received()`]: struct.Recovery.html#method.on_ack_received
+    pub fn on_ecn_counts_received(
+        &mut self, ranges: &RangeSet, ecn_counts: Option<&frame::EcnCounts>,
+        epoch: packet::Epoch, now: Instant, trace_id: &str,
+    ) {
+        if !self.ecn.on_ack_received(ranges, ecn_counts, epoch) {
+            return;
+        }
+
+        let largest_acked = match ranges.last() {
+            Some(v) => v,
+
+            None => return,
+        };
+
+        let pkt = match self.epochs[epoch]
+            .sent_packets
+            .iter()
+            .find(|p| p.pkt_num == largest_acked && p.time_acked.is_none())
+        {
+            Some(v) => v.clone(),
+
+            None => return,
+        };
+
+        trace!("{} ECN-CE reported on epoch {}", trace_id, epoch);
+
+        self.congestion.on_congestion_event(
+            self.bytes_in_flight,
+            0,
+            &pkt,
+            now,
+            epoch,
+        );
+
+        self.careful_resume_on_congestion_event();
+    }
+
+    /// Starts using congestion control parameters saved from a previous
+    /// connection on the same path.
+    pub fn set_careful_resume_params(&mut self, params: &CarefulResumeParams) {
+        self.careful_resume.set_params(params);
+    }
+
+    fn careful_resume_on_congestion_event(&mut self) {
+        let min_cwnd = self.max_datagram_size *
+            self.congestion.minimum_congestion_window_packets;
+
+        if let Some(cwnd) = self.careful_resume.on_congestion_event(min_cwnd) {
+            self.congestion.congestion_window = cwnd;
+            self.congestion.ssthresh = cwnd;
+        }
+    }
+
     pub fn get_acked_frames(
         &mut self, epoch: packet::Epoch,
-    ) -> impl Iterator<Item = frame::Frame> + '_ {
+    ) -> impl Iterator<Item = frame::SentFrame> + '_ {
         self.epochs[epoch].acked_frames.drain(..)
     }
 
     pub fn get_lost_frames(
         &mut self, epoch: packet::Epoch,
-    ) -> impl Iterator<Item = frame::Frame> + '_ {
+    ) -> impl Iterator<Item = frame::SentFrame> + '_ {
         self.epochs[epoch].lost_frames.drain(..)
     }
 
@@ -471,6 +613,10 @@ impl Recovery {
         let in_flight = pkt.in_flight;
         let sent_bytes = pkt.size;
 
+        if in_flight && epoch == packet::Epoch::Application {
+            self.careful_resume.on_packet_sent(pkt.pkt_num);
+        }
+
         if ack_eliciting {
             self.outstanding_non_ack_eliciting = 0;
         } else {
@@ -489,6 +635,7 @@ impl Recovery {
             &self.rtt_stats,
             self.bytes_lost,
             in_flight,
+            epoch,
         );
 
         if in_flight {
@@ -565,6 +712,21 @@ impl Recovery {
                 now,
                 handshake_status.completed,
             );
+
+            if let Some(cwnd) =
+                self.careful_resume.on_rtt_sample(latest_rtt, self.cwnd())
+            {
+                trace!("{} careful resume: jump cwnd to {}", trace_id, cwnd);
+
+                self.congestion.congestion_window = cwnd;
+            }
+        }
+
+        for pkt in &self.newly_acked {
+            let pkt_num =
+                (epoch == packet::Epoch::Application).then_some(pkt.pkt_num);
+
+            self.careful_resume.on_packet_acked(pkt_num, pkt.size);
         }
 
         // Detect and mark lost packets without removing them from the sent
@@ -576,6 +738,7 @@ impl Recovery {
             &mut self.newly_acked,
             &self.rtt_stats,
             now,
+            epoch,
         );
 
         self.bytes_in_flight -= acked_bytes;
@@ -624,6 +787,7 @@ impl Recovery {
         };
 
         self.pto_count += 1;
+        self.total_pto_count += 1;
 
         let epoch = &mut self.epochs[epoch];
 
@@ -661,6 +825,8 @@ impl Recovery {
         &mut self, epoch: packet::Epoch, handshake_status: HandshakeStatus,
         now: Instant,
     ) {
+        self.congestion.on_pkt_num_space_discarded(epoch);
+
         let epoch = &mut self.epochs[epoch];
 
         let unacked_bytes = epoch
@@ -715,6 +881,22 @@ impl Recovery {
         self.rtt_stats.rtt()
     }
 
+    pub fn ssthresh(&self) -> usize {
+        self.congestion.ssthresh
+    }
+
+    pub fn bytes_in_flight(&self) -> usize {
+        self.bytes_in_flight
+    }
+
+    pub fn latest_rtt(&self) -> Duration {
+        self.rtt_stats.latest_rtt
+    }
+
+    pub fn rtt_sample_count(&self) -> u64 {
+        self.rtt_stats.sample_count
+    }
+
     pub fn min_rtt(&self) -> Option<Duration> {
         self.rtt_stats.min_rtt()
     }
@@ -727,6 +909,12 @@ impl Recovery {
         self.rtt() + cmp::max(self.rtt_stats.rttvar * 4, GRANULARITY)
     }
 
+    /// Returns the duration over which losses establish persistent congestion.
+    fn persistent_congestion_duration(&self) -> Duration {
+        (self.pto() + self.rtt_stats.max_ack_delay) *
+            PERSISTENT_CONGESTION_THRESHOLD
+    }
+
     pub fn delivery_rate(&self) -> u64 {
         self.congestion.delivery_rate()
     }
@@ -784,7 +972,7 @@ impl Recovery {
     fn pto_time_and_space(
         &self, handshake_status: HandshakeStatus, now: Instant,
     ) -> (Option<Instant>, packet::Epoch) {
-        let mut duration = self.pto() * 2_u32.pow(self.pto_count);
+        let mut duration = self.pto_backoff(self.pto());
 
         // Arm PTO from now when there are no inflight packets.
         if self.bytes_in_flight == 0 {
@@ -816,8 +1004,7 @@ impl Recovery {
                 }
 
                 // Include max_ack_delay and backoff for Application Data.
-                duration +=
-                    self.rtt_stats.max_ack_delay * 2_u32.pow(self.pto_count);
+                duration += self.pto_backoff(self.rtt_stats.max_ack_delay);
             }
 
             let new_time = epoch
@@ -833,6 +1020,24 @@ impl Recovery {
         (pto_timeout, pto_space)
     }
 
+    /// Applies the PTO backoff for the current number of consecutive timeouts
+    /// to the given duration.
+    fn pto_backoff(&self, duration: Duration) -> Duration {
+        let backoffs = self.pto_count.min(self.max_pto_backoffs);
+
+        let backoff = self
+            .pto_backoff_multiplier
+            .powi(backoffs as i32)
+            .min(MAX_PTO_BACKOFF);
+
+        // Avoid rounding errors with the default, integer, multiplier.
+        if backoff.fract() == 0.0 {
+            return duration * backoff as u32;
+        }
+
+        duration.mul_f64(backoff)
+    }
+
     fn set_loss_detection_timer(
         &mut self, handshake_status: HandshakeStatus, now: Instant,
     ) {
@@ -865,25 +1070,30 @@ impl Recovery {
         let loss = self.epochs[epoch].detect_lost_packets(
             loss_delay,
             self.pkt_thresh,
+            self.rtt_stats.first_rtt_sample,
             now,
             trace_id,
             epoch,
         );
 
         if let Some(pkt) = loss.largest_lost_pkt {
-            if !self.congestion.in_congestion_recovery(pkt.time_sent) {
-                (self.congestion.cc_ops.checkpoint)(&mut self.congestion);
-            }
-
-            (self.congestion.cc_ops.congestion_event)(
-                &mut self.congestion,
+            self.congestion.on_congestion_event(
                 self.bytes_in_flight,
                 loss.lost_bytes,
                 &pkt,
                 now,
+                epoch,
             );
 
+            self.careful_resume_on_congestion_event();
+
             self.bytes_in_flight -= loss.lost_bytes;
+
+            if loss.lost_period > self.persistent_congestion_duration() {
+                trace!("{} persistent congestion on epoch {}", trace_id, epoch);
+
+                self.congestion.on_persistent_congestion();
+            }
         };
 
         self.bytes_in_flight -= loss.pmtud_lost_bytes;
@@ -988,7 +1198,7 @@ impl std::fmt::Debug for Recovery {
 pub struct Sent {
     pub pkt_num: u64,
 
-    pub frames: SmallVec<[frame::Frame; 1]>,
+    pub frames: SmallVec<[frame::SentFrame; 1]>,
 
     pub time_sent: Instant,
 
@@ -1078,6 +1288,61 @@ impl Default for HandshakeStatus {
     }
 }
 
+/// A loss recovery or congestion control event on a path.
+///
+/// Events are delivered to the handler set with
+/// [`set_recovery_event_handler()`].
+///
+/// [`set_recovery_event_handler()`]: struct.Connection.html#method.set_recovery_event_handler
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecoveryEvent {
+    /// A new RTT sample was taken on the path.
+    RttUpdated {
+        /// The local address of the path.
+        local_addr: SocketAddr,
+
+        /// The peer address of the path.
+        peer_addr: SocketAddr,
+
+        /// The RTT sample.
+        latest_rtt: Duration,
+
+        /// The smoothed RTT estimate, including the new sample.
+        rtt: Duration,
+    },
+
+    /// Packets sent on the path were declared lost.
+    PacketsLost {
+        /// The local address of the path.
+        local_addr: SocketAddr,
+
+        /// The peer address of the path.
+        peer_addr: SocketAddr,
+
+        /// The number of packets declared lost.
+        packets: usize,
+
+        /// The number of bytes declared lost.
+        bytes: usize,
+    },
+
+    /// The congestion window of the path changed.
+    ///
+    /// This covers changes made by the congestion controller, including on
+    /// persistent congestion and careful resume, as well as changes caused by
+    /// a new path MTU.
+    CongestionWindowUpdated {
+        /// The local address of the path.
+        local_addr: SocketAddr,
+
+        /// The peer address of the path.
+        peer_addr: SocketAddr,
+
+        /// The new congestion window, in bytes.
+        cwnd: usize,
+    },
+}
+
 // We don't need to log all qlog metrics every time there is a recovery event.
 // Instead, we can log only the MetricsUpdated event data fields that we care
 // about, only when they change. To support this, the QLogMetrics structure
@@ -1373,6 +1638,7 @@ mod tests {
         assert_eq!(r.epochs[packet::Epoch::Application].loss_probes, 1);
         assert_eq!(r.congestion.lost_count, 0);
         assert_eq!(r.pto_count, 1);
+        assert_eq!(r.total_pto_count, 1);
 
         let p = Sent {
             pkt_num: 4,
@@ -1637,6 +1903,249 @@ mod tests {
         assert_eq!(r.epochs[packet::Epoch::Application].sent_packets.len(), 0);
     }
 
+    #[test]
+    fn persistent_congestion() {
+        let mut cfg = crate::Config::new(crate::PROTOCOL_VERSION).unwrap();
+        cfg.set_cc_algorithm(CongestionControlAlgorithm::Reno);
+
+        let mut r = Recovery::new(&cfg);
+
+        let mut now = Instant::now();
+
+        let sent = |pkt_num, now| Sent {
+            pkt_num,
+            frames: smallvec![],
+            time_sent: now,
+            time_acked: None,
+            time_lost: None,
+            size: 1000,
+            ack_eliciting: true,
+            in_flight: true,
+            delivered: 0,
+            delivered_time: now,
+            first_sent_time: now,
+            is_app_limited: false,
+            tx_in_flight: 0,
+            lost: 0,
+            has_data: false,
+            pmtud: false,
+        };
+
+        // Get a first RTT sample.
+        r.on_packet_sent(
+            sent(0, now),
+            packet::Epoch::Application,
+            HandshakeStatus::default(),
+            now,
+            "",
+        );
+
+        now += Duration::from_millis(10);
+
+        let mut acked = ranges::RangeSet::default();
+        acked.insert(0..1);
+
+        assert_eq!(
+            r.on_ack_received(
+                &acked,
+                0,
+                packet::Epoch::Application,
+                HandshakeStatus::default(),
+                now,
+                "",
+            ),
+            Ok((0, 0, 1000))
+        );
+
+        let cwnd_prev = r.cwnd();
+
+        // Send packets over a period longer than the persistent congestion
+        // duration.
+        for pn in 1..5 {
+            now += Duration::from_millis(100);
+
+            r.on_packet_sent(
+                sent(pn, now),
+                packet::Epoch::Application,
+                HandshakeStatus::default(),
+                now,
+                "",
+            );
+        }
+
+        assert!(Duration::from_millis(300) > r.persistent_congestion_duration());
+
+        now += Duration::from_millis(100);
+
+        r.on_packet_sent(
+            sent(5, now),
+            packet::Epoch::Application,
+            HandshakeStatus::default(),
+            now,
+            "",
+        );
+
+        now += Duration::from_millis(10);
+
+        // Only the last packet is acked, all the others are lost.
+        let mut acked = ranges::RangeSet::default();
+        acked.insert(5..6);
+
+        assert_eq!(
+            r.on_ack_received(
+                &acked,
+                0,
+                packet::Epoch::Application,
+                HandshakeStatus::default(),
+                now,
+                "",
+            ),
+            Ok((4, 4000, 1000))
+        );
+
+        assert!(r.cwnd() < cwnd_prev);
+        assert_eq!(
+            r.cwnd(),
+            r.max_datagram_size() *
+                r.congestion.minimum_congestion_window_packets
+        );
+    }
+
+    #[test]
+    fn pto_backoff() {
+        let cfg = crate::Config::new(crate::PROTOCOL_VERSION).unwrap();
+
+        let mut r = Recovery::new(&cfg);
+
+        let pto = Duration::from_millis(100);
+
+        assert_eq!(r.pto_backoff(pto), pto);
+
+        r.pto_count = 3;
+        assert_eq!(r.pto_backoff(pto), pto * 8);
+
+        // The backoff is capped by default.
+        r.pto_count = 100;
+        assert_eq!(r.pto_backoff(pto), pto * 2_u32.pow(16));
+
+        let mut cfg = crate::Config::new(crate::PROTOCOL_VERSION).unwrap();
+        cfg.set_pto_backoff_multiplier(1.5);
+        cfg.set_max_pto_backoffs(2);
+
+        let mut r = Recovery::new(&cfg);
+
+        r.pto_count = 1;
+        let backoff = r.pto_backoff(pto);
+        assert!(backoff > Duration::from_micros(149_999));
+        assert!(backoff < Duration::from_micros(150_001));
+
+        r.pto_count = 5;
+        let backoff = r.pto_backoff(pto);
+        assert!(backoff > Duration::from_micros(224_999));
+        assert!(backoff < Duration::from_micros(225_001));
+    }
+
+    #[test]
+    fn careful_resume() {
+        let mut cfg = crate::Config::new(crate::PROTOCOL_VERSION).unwrap();
+        cfg.set_cc_algorithm(CongestionControlAlgorithm::Reno);
+
+        let mut r = Recovery::new(&cfg);
+
+        r.set_careful_resume_params(&CarefulResumeParams {
+            cwnd: 100_000,
+            rtt: Duration::from_millis(50),
+            delivery_rate: 0,
+        });
+
+        let mut now = Instant::now();
+
+        let sent = |pkt_num, now| Sent {
+            pkt_num,
+            frames: smallvec![],
+            time_sent: now,
+            time_acked: None,
+            time_lost: None,
+            size: 1000,
+            ack_eliciting: true,
+            in_flight: true,
+            delivered: 0,
+            delivered_time: now,
+            first_sent_time: now,
+            is_app_limited: false,
+            tx_in_flight: 0,
+            lost: 0,
+            has_data: false,
+            pmtud: false,
+        };
+
+        r.on_packet_sent(
+            sent(0, now),
+            packet::Epoch::Application,
+            HandshakeStatus::default(),
+            now,
+            "",
+        );
+
+        now += Duration::from_millis(50);
+
+        let mut acked = ranges::RangeSet::default();
+        acked.insert(0..1);
+
+        assert_eq!(
+            r.on_ack_received(
+                &acked,
+                0,
+                packet::Epoch::Application,
+                HandshakeStatus::default(),
+                now,
+                "",
+            ),
+            Ok((0, 0, 1000))
+        );
+
+        // The RTT sample matches the saved RTT, so the congestion window
+        // jumps to half of the saved one.
+        assert_eq!(r.cwnd(), 50_000);
+
+        for pn in 1..7 {
+            r.on_packet_sent(
+                sent(pn, now),
+                packet::Epoch::Application,
+                HandshakeStatus::default(),
+                now,
+                "",
+            );
+        }
+
+        now += Duration::from_millis(50);
+
+        let mut acked = ranges::RangeSet::default();
+        acked.insert(1..2);
+        acked.insert(5..6);
+
+        // Packet 2 is lost while validating the jump.
+        assert_eq!(
+            r.on_ack_received(
+                &acked,
+                0,
+                packet::Epoch::Application,
+                HandshakeStatus::default(),
+                now,
+                "",
+            ),
+            Ok((1, 1000, 2000))
+        );
+
+        // The congestion window retreats to half of the acked data, but not
+        // below the minimum.
+        assert_eq!(
+            r.cwnd(),
+            r.max_datagram_size() *
+                r.congestion.minimum_congestion_window_packets
+        );
+    }
+
     #[test]
     fn loss_on_reordering() {
         let mut cfg = crate::Config::new(crate::PROTOCOL_VERSION).unwrap();
@@ -2152,4 +2661,6 @@ mod tests {
 }
 
 pub mod congestion;
+mod ecn;
+mod resume;
 mod rtt;
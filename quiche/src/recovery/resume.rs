@@ -0,0 +1,324 @@
+// Copyright (C) 2024, Cloudflare, Inc.
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//
+//     * Redistributions in binary form must reproduce the above copyright
+//       notice, this list of conditions and the following disclaimer in the
+//       documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS
+// IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO,
+// THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR
+// PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Careful resume.
+//!
+//! This implements the reuse of congestion control parameters observed by a
+//! previous connection on the same path, as described in
+//! [draft-ietf-tsvwg-careful-resume].
+//!
+//! [draft-ietf-tsvwg-careful-resume]: https://datatracker.ietf.org/doc/html/draft-ietf-tsvwg-careful-resume
+
+use std::time::Duration;
+
+/// Congestion control parameters observed on a path.
+///
+/// They can be obtained from a connection with [`careful_resume_params()`]
+/// and used to seed a later connection to the same peer with
+/// [`set_careful_resume_params()`].
+///
+/// [`careful_resume_params()`]: struct.Connection.html#method.careful_resume_params
+/// [`set_careful_resume_params()`]: struct.Connection.html#method.set_careful_resume_params
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CarefulResumeParams {
+    /// The congestion window, in bytes.
+    pub cwnd: usize,
+
+    /// The minimum RTT.
+    pub rtt: Duration,
+
+    /// The delivery rate estimate, in bytes per second.
+    ///
+    /// This is informational only: it is not used when resuming, but it can
+    /// help applications decide whether saved parameters are worth reusing.
+    pub delivery_rate: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    /// Waiting for an RTT sample to confirm the saved parameters.
+    Reconnaissance,
+
+    /// The congestion window was raised, but no packet sent with it was acked
+    /// yet.
+    Unvalidated,
+
+    /// Packets sent with the raised congestion window are being acked.
+    Validating,
+
+    /// Congestion was detected while using the raised congestion window.
+    SafeRetreat,
+
+    /// Saved parameters are not used.
+    Normal,
+}
+
+pub struct CarefulResume {
+    phase: Phase,
+
+    /// The saved congestion window.
+    saved_cwnd: usize,
+
+    /// The saved minimum RTT.
+    saved_rtt: Duration,
+
+    /// The number of bytes acked since the connection started.
+    pipesize: usize,
+
+    /// The first packet sent with the raised congestion window.
+    first_unvalidated_pkt: Option<u64>,
+
+    /// The last packet sent with the raised congestion window.
+    last_unvalidated_pkt: Option<u64>,
+}
+
+impl Default for CarefulResume {
+    fn default() -> Self {
+        CarefulResume {
+            phase: Phase::Normal,
+
+            saved_cwnd: 0,
+
+            saved_rtt: Duration::ZERO,
+
+            pipesize: 0,
+
+            first_unvalidated_pkt: None,
+
+            last_unvalidated_pkt: None,
+        }
+    }
+}
+
+impl CarefulResume {
+    /// Starts using the given saved parameters.
+    pub fn set_params(&mut self, params: &CarefulResumeParams) {
+        self.phase = Phase::Reconnaissance;
+        self.saved_cwnd = params.cwnd;
+        self.saved_rtt = params.rtt;
+    }
+
+    /// Called on the first RTT sample. Returns the congestion window to jump
+    /// to, if the sample confirms the saved parameters.
+    pub fn on_rtt_sample(&mut self, rtt: Duration, cwnd: usize) -> Option<usize> {
+        if self.phase != Phase::Reconnaissance {
+            return None;
+        }
+
+        // The path is unlikely to be the same if the RTT changed a lot.
+        if rtt < self.saved_rtt / 2 || rtt > self.saved_rtt * 10 {
+            self.phase = Phase::Normal;
+            return None;
+        }
+
+        let jump_cwnd = self.saved_cwnd / 2;
+
+        if jump_cwnd <= cwnd {
+            self.phase = Phase::Normal;
+            return None;
+        }
+
+        self.phase = Phase::Unvalidated;
+
+        Some(jump_cwnd)
+    }
+
+    /// Records an application data packet sent.
+    pub fn on_packet_sent(&mut self, pkt_num: u64) {
+        if self.phase != Phase::Unvalidated {
+            return;
+        }
+
+        if self.first_unvalidated_pkt.is_none() {
+            self.first_unvalidated_pkt = Some(pkt_num);
+        }
+
+        self.last_unvalidated_pkt = Some(pkt_num);
+    }
+
+    /// Records an acked packet. `pkt_num` is only set for application data
+    /// packets.
+    pub fn on_packet_acked(&mut self, pkt_num: Option<u64>, size: usize) {
+        if self.phase == Phase::Normal {
+            return;
+        }
+
+        self.pipesize += size;
+
+        let pkt_num = match pkt_num {
+            Some(v) => v,
+
+            None => return,
+        };
+
+        if self.phase == Phase::Unvalidated &&
+            self.first_unvalidated_pkt.map_or(false, |pn| pkt_num >= pn)
+        {
+            self.phase = Phase::Validating;
+        }
+
+        if matches!(self.phase, Phase::Validating | Phase::SafeRetreat) &&
+            self.last_unvalidated_pkt.map_or(false, |pn| pkt_num >= pn)
+        {
+            self.phase = Phase::Normal;
+        }
+    }
+
+    /// Called on a congestion event. Returns the congestion window to retreat
+    /// to, if congestion was detected while using the raised congestion
+    /// window.
+    pub fn on_congestion_event(&mut self, min_cwnd: usize) -> Option<usize> {
+        match self.phase {
+            Phase::Reconnaissance => {
+                self.phase = Phase::Normal;
+
+                None
+            },
+
+            Phase::Unvalidated | Phase::Validating => {
+                self.phase = Phase::SafeRetreat;
+
+                Some((self.pipesize / 2).max(min_cwnd))
+            },
+
+            Phase::SafeRetreat | Phase::Normal => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> CarefulResumeParams {
+        CarefulResumeParams {
+            cwnd: 100_000,
+            rtt: Duration::from_millis(50),
+            delivery_rate: 0,
+        }
+    }
+
+    #[test]
+    fn not_enabled() {
+        let mut cr = CarefulResume::default();
+
+        assert_eq!(cr.on_rtt_sample(Duration::from_millis(50), 12_000), None);
+        assert_eq!(cr.on_congestion_event(2_400), None);
+    }
+
+    #[test]
+    fn jump_and_validate() {
+        let mut cr = CarefulResume::default();
+        cr.set_params(&params());
+
+        assert_eq!(
+            cr.on_rtt_sample(Duration::from_millis(60), 12_000),
+            Some(50_000)
+        );
+        assert_eq!(cr.phase, Phase::Unvalidated);
+
+        cr.on_packet_sent(10);
+        cr.on_packet_sent(11);
+        cr.on_packet_sent(12);
+
+        cr.on_packet_acked(Some(9), 1_000);
+        assert_eq!(cr.phase, Phase::Unvalidated);
+
+        cr.on_packet_acked(Some(10), 1_000);
+        assert_eq!(cr.phase, Phase::Validating);
+
+        cr.on_packet_acked(Some(12), 1_000);
+        assert_eq!(cr.phase, Phase::Normal);
+
+        // The saved parameters are only used once.
+        assert_eq!(cr.on_congestion_event(2_400), None);
+    }
+
+    #[test]
+    fn rtt_mismatch() {
+        let mut cr = CarefulResume::default();
+        cr.set_params(&params());
+
+        assert_eq!(cr.on_rtt_sample(Duration::from_millis(10), 12_000), None);
+        assert_eq!(cr.phase, Phase::Normal);
+
+        let mut cr = CarefulResume::default();
+        cr.set_params(&params());
+
+        assert_eq!(cr.on_rtt_sample(Duration::from_millis(600), 12_000), None);
+        assert_eq!(cr.phase, Phase::Normal);
+    }
+
+    #[test]
+    fn small_saved_cwnd() {
+        let mut cr = CarefulResume::default();
+        cr.set_params(&CarefulResumeParams {
+            cwnd: 20_000,
+            ..params()
+        });
+
+        assert_eq!(cr.on_rtt_sample(Duration::from_millis(50), 12_000), None);
+        assert_eq!(cr.phase, Phase::Normal);
+    }
+
+    #[test]
+    fn loss_before_jump() {
+        let mut cr = CarefulResume::default();
+        cr.set_params(&params());
+
+        assert_eq!(cr.on_congestion_event(2_400), None);
+        assert_eq!(cr.on_rtt_sample(Duration::from_millis(50), 12_000), None);
+    }
+
+    #[test]
+    fn safe_retreat() {
+        let mut cr = CarefulResume::default();
+        cr.set_params(&params());
+
+        cr.on_packet_acked(None, 12_000);
+
+        assert_eq!(
+            cr.on_rtt_sample(Duration::from_millis(50), 12_000),
+            Some(50_000)
+        );
+
+        cr.on_packet_sent(5);
+        cr.on_packet_sent(6);
+
+        cr.on_packet_acked(Some(5), 4_000);
+        assert_eq!(cr.phase, Phase::Validating);
+
+        // Retreat to half of what was acked so far.
+        assert_eq!(cr.on_congestion_event(2_400), Some(8_000));
+        assert_eq!(cr.phase, Phase::SafeRetreat);
+
+        // Further congestion is left to congestion control.
+        assert_eq!(cr.on_congestion_event(2_400), None);
+
+        cr.on_packet_acked(Some(6), 1_000);
+        assert_eq!(cr.phase, Phase::Normal);
+    }
+}
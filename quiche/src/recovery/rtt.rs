@@ -29,6 +29,8 @@ use std::time::Instant;
 
 use crate::minmax::Minmax;
 
+use super::congestion::RttEstimate;
+
 pub(crate) const INITIAL_RTT: Duration = Duration::from_millis(333);
 
 pub(crate) const RTT_WINDOW: Duration = Duration::from_secs(300);
@@ -45,6 +47,8 @@ pub struct RttStats {
     pub(super) max_ack_delay: Duration,
 
     pub(super) first_rtt_sample: Option<Instant>,
+
+    pub(super) sample_count: u64,
 }
 
 impl std::fmt::Debug for RttStats {
@@ -66,6 +70,7 @@ impl RttStats {
             smoothed_rtt: INITIAL_RTT,
             rttvar: INITIAL_RTT / 2,
             first_rtt_sample: None,
+            sample_count: 0,
             max_ack_delay,
         }
     }
@@ -75,6 +80,7 @@ impl RttStats {
         handshake_confirmed: bool,
     ) {
         self.latest_rtt = latest_rtt;
+        self.sample_count += 1;
 
         if self.first_rtt_sample.is_none() {
             self.min_rtt.reset(now, latest_rtt);
@@ -116,4 +122,13 @@ impl RttStats {
     pub(crate) fn min_rtt(&self) -> Option<Duration> {
         self.min_rtt.ne(&Duration::ZERO).then_some(*self.min_rtt)
     }
+
+    pub(crate) fn estimate(&self) -> RttEstimate {
+        RttEstimate {
+            latest_rtt: self.latest_rtt,
+            smoothed_rtt: self.smoothed_rtt,
+            rttvar: self.rttvar,
+            min_rtt: self.first_rtt_sample.map(|_| *self.min_rtt),
+        }
+    }
 }
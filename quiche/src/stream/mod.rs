@@ -28,9 +28,12 @@ use std::cmp;
 
 use std::sync::Arc;
 
+use std::time;
+
 use std::collections::hash_map;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 
 use intrusive_collections::intrusive_adapter;
 use intrusive_collections::KeyAdapter;
@@ -50,6 +53,9 @@ const DEFAULT_STREAM_WINDOW: u64 = 32 * 1024;
 /// The maximum size of the receiver stream flow control window.
 pub const MAX_STREAM_WINDOW: u64 = 16 * 1024 * 1024;
 
+// The number of collected streams whose final statistics are kept.
+const MAX_COLLECTED_STATS: usize = 1024;
+
 /// A simple no-op hasher for Stream IDs.
 ///
 /// The QUIC protocol and quiche library guarantees stream ID uniqueness, so
@@ -96,6 +102,13 @@ pub struct StreamMap {
     /// created streams, to prevent peers from re-creating them.
     collected: StreamIdHashSet,
 
+    /// Final statistics of the most recently collected streams.
+    collected_stats: StreamIdHashMap<StreamStats>,
+
+    /// IDs of the streams in `collected_stats`, in the order they were
+    /// collected, so that the oldest statistics can be evicted.
+    collected_stats_order: VecDeque<u64>,
+
     /// Peer's maximum bidirectional stream count limit.
     peer_max_streams_bidi: u64,
 
@@ -150,9 +163,10 @@ pub struct StreamMap {
     blocked: StreamIdHashMap<u64>,
 
     /// Set of stream IDs corresponding to streams that are reset. The value
-    /// of the map elements is a tuple of the error code and final size values
-    /// to include in the RESET_STREAM frame.
-    reset: StreamIdHashMap<(u64, u64)>,
+    /// of the map elements is a tuple of the error code, final size and
+    /// reliable size values to include in the RESET_STREAM frame, or in the
+    /// RESET_STREAM_AT frame when the reliable size is not zero.
+    reset: StreamIdHashMap<(u64, u64, u64)>,
 
     /// Set of stream IDs corresponding to streams that are shutdown on the
     /// receive side, and need to send a STOP_SENDING frame. The value of the
@@ -161,6 +175,12 @@ pub struct StreamMap {
 
     /// The maximum size of a stream window.
     max_stream_window: u64,
+
+    /// Whether stream events should be queued for the application.
+    events_enabled: bool,
+
+    /// Stream lifecycle events to be notified to the application.
+    events: VecDeque<StreamEvent>,
 }
 
 impl StreamMap {
@@ -205,6 +225,7 @@ impl StreamMap {
     pub(crate) fn get_or_create(
         &mut self, id: u64, local_params: &crate::TransportParams,
         peer_params: &crate::TransportParams, local: bool, is_server: bool,
+        now: time::Instant,
     ) -> Result<&mut Stream> {
         let (stream, is_new_and_writable) = match self.streams.entry(id) {
             hash_map::Entry::Vacant(v) => {
@@ -298,6 +319,10 @@ impl StreamMap {
                     },
                 };
 
+                if !local && self.events_enabled {
+                    self.events.push_back(StreamEvent::Opened(id));
+                }
+
                 let s = Stream::new(
                     id,
                     max_rx_data,
@@ -305,6 +330,7 @@ impl StreamMap {
                     is_bidi(id),
                     local,
                     self.max_stream_window,
+                    now,
                 );
 
                 let is_writable = s.is_writable();
@@ -449,13 +475,15 @@ impl StreamMap {
     }
 
     /// Adds the stream ID to the reset streams set with the
-    /// given error code and final size values.
+    /// given error code, final size and reliable size values.
     ///
     /// If the stream was already in the list, this does nothing.
     pub fn insert_reset(
         &mut self, stream_id: u64, error_code: u64, final_size: u64,
+        reliable_size: u64,
     ) {
-        self.reset.insert(stream_id, (error_code, final_size));
+        self.reset
+            .insert(stream_id, (error_code, final_size, reliable_size));
     }
 
     /// Removes the stream ID from the reset streams set.
@@ -511,6 +539,20 @@ impl StreamMap {
         self.local_max_streams_uni_next
     }
 
+    /// Returns the ID of the next stream to be opened by the local endpoint.
+    ///
+    /// The ID follows the highest local stream ID of the given type that was
+    /// used so far, so that IDs are allocated in order.
+    pub fn next_local_stream_id(&self, bidi: bool, is_server: bool) -> u64 {
+        let (opened, ty) = if bidi {
+            (self.local_opened_streams_bidi, 0x0)
+        } else {
+            (self.local_opened_streams_uni, 0x2)
+        };
+
+        (opened << 2) | ty | u64::from(is_server)
+    }
+
     /// Returns the number of bidirectional streams that can be created
     /// before the peer's stream count limit is reached.
     pub fn peer_streams_left_bidi(&self) -> u64 {
@@ -523,11 +565,11 @@ impl StreamMap {
         self.peer_max_streams_uni - self.local_opened_streams_uni
     }
 
-    /// Drops completed stream.
+    /// Drops completed stream, keeping only its final statistics.
     ///
     /// This should only be called when Stream::is_complete() returns true for
     /// the given stream.
-    pub fn collect(&mut self, stream_id: u64, local: bool) {
+    pub fn collect(&mut self, stream_id: u64, local: bool, now: time::Instant) {
         if !local {
             // If the stream was created by the peer, give back a max streams
             // credit.
@@ -549,6 +591,15 @@ impl StreamMap {
         self.remove_flushable(&s.priority_key);
 
         self.collected.insert(stream_id);
+
+        if self.collected_stats_order.len() == MAX_COLLECTED_STATS {
+            if let Some(oldest) = self.collected_stats_order.pop_front() {
+                self.collected_stats.remove(&oldest);
+            }
+        }
+
+        self.collected_stats.insert(stream_id, s.stats(now));
+        self.collected_stats_order.push_back(stream_id);
     }
 
     /// Creates an iterator over streams that have outstanding data to read.
@@ -578,7 +629,7 @@ impl StreamMap {
     }
 
     /// Creates an iterator over streams that need to send RESET_STREAM.
-    pub fn reset(&self) -> hash_map::Iter<u64, (u64, u64)> {
+    pub fn reset(&self) -> hash_map::Iter<u64, (u64, u64, u64)> {
         self.reset.iter()
     }
 
@@ -587,11 +638,34 @@ impl StreamMap {
         self.stopped.iter()
     }
 
+    /// Configures whether stream events should be queued.
+    pub fn enable_events(&mut self, v: bool) {
+        self.events_enabled = v;
+    }
+
+    /// Queues a stream event for the application, if events are enabled.
+    pub fn notify_event(&mut self, ev: StreamEvent) {
+        if self.events_enabled {
+            self.events.push_back(ev);
+        }
+    }
+
+    /// Returns the oldest queued stream event, if any.
+    pub fn pop_event(&mut self) -> Option<StreamEvent> {
+        self.events.pop_front()
+    }
+
     /// Returns true if the stream has been collected.
     pub fn is_collected(&self, stream_id: u64) -> bool {
         self.collected.contains(&stream_id)
     }
 
+    /// Returns the final statistics of a collected stream, if it's one of the
+    /// most recently collected ones.
+    pub fn collected_stats(&self, stream_id: u64) -> Option<&StreamStats> {
+        self.collected_stats.get(&stream_id)
+    }
+
     /// Returns true if there are any streams that have data to write.
     pub fn has_flushable(&self) -> bool {
         !self.flushable.is_empty()
@@ -646,6 +720,97 @@ impl StreamMap {
     }
 }
 
+/// A stream lifecycle event.
+///
+/// Stream events can be collected using the [`stream_event_next()`] method,
+/// once enabled with [`enable_stream_events()`].
+///
+/// [`stream_event_next()`]: struct.Connection.html#method.stream_event_next
+/// [`enable_stream_events()`]: struct.Config.html#method.enable_stream_events
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// The peer opened a new stream with the given ID.
+    Opened(u64),
+
+    /// New data became available to read on the stream.
+    DataAvailable(u64),
+
+    /// The peer signalled the end of the stream, so its final size is now
+    /// known. Some data might still need to be received and read.
+    Finished(u64),
+
+    /// The peer reset the stream with the given error code. Reading from the
+    /// stream will return [`StreamReset`], once any data the peer still
+    /// guarantees to deliver has been read.
+    ///
+    /// [`StreamReset`]: enum.Error.html#variant.StreamReset
+    Reset(u64, u64),
+
+    /// The peer asked to stop sending on the stream with the given error code.
+    /// Writing to the stream will return [`StreamStopped`].
+    ///
+    /// [`StreamStopped`]: enum.Error.html#variant.StreamStopped
+    StopSending(u64, u64),
+
+    /// The peer raised the stream's or the connection's flow control limit,
+    /// and the stream became writable.
+    Writable(u64),
+}
+
+/// Statistics about a stream.
+///
+/// A stream's statistics can be collected using the [`stream_stats()`] method.
+///
+/// [`stream_stats()`]: struct.Connection.html#method.stream_stats
+#[derive(Clone, Debug)]
+pub struct StreamStats {
+    /// The number of bytes written to the stream by the application.
+    pub written: u64,
+
+    /// The largest offset sent to the peer.
+    pub sent: u64,
+
+    /// The number of bytes acknowledged by the peer, contiguously from the
+    /// start of the stream.
+    pub acked: u64,
+
+    /// The number of bytes that were lost and marked for retransmission.
+    pub retransmitted: u64,
+
+    /// The largest offset received from the peer.
+    pub received: u64,
+
+    /// The number of bytes read by the application.
+    pub consumed: u64,
+
+    /// The final size of the send side, if known.
+    pub send_final_size: Option<u64>,
+
+    /// The final size of the receive side, if known.
+    pub recv_final_size: Option<u64>,
+
+    /// The error code of the `RESET_STREAM` frame received from the peer, if
+    /// any.
+    pub peer_reset_error: Option<u64>,
+
+    /// The error code of the `STOP_SENDING` frame received from the peer, if
+    /// any.
+    pub peer_stop_sending_error: Option<u64>,
+
+    /// The error code the send side was reset with by the local endpoint, if
+    /// any. This includes resets in response to a `STOP_SENDING` frame.
+    pub local_reset_error: Option<u64>,
+
+    /// The error code the receive side was shut down with by the local
+    /// endpoint, if any. A `STOP_SENDING` frame is only sent if the final size
+    /// wasn't known yet.
+    pub local_stop_sending_error: Option<u64>,
+
+    /// The time elapsed since the stream was created, until it was collected
+    /// if it's complete.
+    pub age: time::Duration,
+}
+
 /// A QUIC stream.
 pub struct Stream {
     /// Receive-side stream buffer.
@@ -669,13 +834,22 @@ pub struct Stream {
     pub incremental: bool,
 
     pub priority_key: Arc<StreamPriorityKey>,
+
+    /// The time the stream was created.
+    pub created: time::Instant,
+
+    /// The error code the local endpoint reset the stream with, if any.
+    pub local_reset_error: Option<u64>,
+
+    /// The error code the local endpoint stopped the stream with, if any.
+    pub local_stop_sending_error: Option<u64>,
 }
 
 impl Stream {
     /// Creates a new stream with the given flow control limits.
     pub fn new(
         id: u64, max_rx_data: u64, max_tx_data: u64, bidi: bool, local: bool,
-        max_window: u64,
+        max_window: u64, now: time::Instant,
     ) -> Stream {
         let priority_key = Arc::new(StreamPriorityKey {
             id,
@@ -691,6 +865,28 @@ impl Stream {
             urgency: priority_key.urgency,
             incremental: priority_key.incremental,
             priority_key,
+            created: now,
+            local_reset_error: None,
+            local_stop_sending_error: None,
+        }
+    }
+
+    /// Collects statistics about the stream.
+    pub fn stats(&self, now: time::Instant) -> StreamStats {
+        StreamStats {
+            written: self.send.off_back(),
+            sent: self.send.emit_off(),
+            acked: self.send.ack_off(),
+            retransmitted: self.send.retransmitted(),
+            received: self.recv.max_off(),
+            consumed: self.recv.off_front(),
+            send_final_size: self.send.fin_off(),
+            recv_final_size: self.recv.fin_off(),
+            peer_reset_error: self.recv.error(),
+            peer_stop_sending_error: self.send.error(),
+            local_reset_error: self.local_reset_error,
+            local_stop_sending_error: self.local_stop_sending_error,
+            age: now.saturating_duration_since(self.created),
         }
     }
 
@@ -975,6 +1171,44 @@ impl RangeBuf {
         self.pos += count;
     }
 
+    /// Converts `self` into a vector holding the remaining data.
+    ///
+    /// The internal buffer is reused when it isn't shared with other
+    /// `RangeBuf` objects, otherwise the data is copied.
+    pub fn into_vec(self) -> Vec<u8> {
+        let end = self.start + self.len;
+
+        match Arc::try_unwrap(self.data) {
+            Ok(mut data) => {
+                data.truncate(end);
+                data.drain(..self.pos);
+                data
+            },
+
+            Err(data) => data[self.pos..end].to_vec(),
+        }
+    }
+
+    /// Appends the given slice at the end of `self`.
+    ///
+    /// This is only possible when the internal buffer isn't shared with other
+    /// `RangeBuf` objects and ends with `self`, otherwise `false` is returned
+    /// and nothing is appended.
+    pub fn try_append(&mut self, buf: &[u8]) -> bool {
+        let end = self.start + self.len;
+
+        match Arc::get_mut(&mut self.data) {
+            Some(data) if data.len() == end => {
+                data.extend_from_slice(buf);
+                self.len += buf.len();
+
+                true
+            },
+
+            _ => false,
+        }
+    }
+
     /// Splits the buffer into two at the given index.
     pub fn split_off(&mut self, at: usize) -> RangeBuf {
         assert!(
@@ -1034,7 +1268,10 @@ mod tests {
 
     #[test]
     fn recv_flow_control() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
         assert!(!stream.recv.almost_full());
 
         let mut buf = [0; 32];
@@ -1065,7 +1302,10 @@ mod tests {
 
     #[test]
     fn recv_past_fin() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
         assert!(!stream.recv.almost_full());
 
         let first = RangeBuf::from(b"hello", 0, true);
@@ -1077,7 +1317,10 @@ mod tests {
 
     #[test]
     fn recv_fin_dup() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
         assert!(!stream.recv.almost_full());
 
         let first = RangeBuf::from(b"hello", 0, true);
@@ -1095,7 +1338,10 @@ mod tests {
 
     #[test]
     fn recv_fin_change() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
         assert!(!stream.recv.almost_full());
 
         let first = RangeBuf::from(b"hello", 0, true);
@@ -1107,7 +1353,10 @@ mod tests {
 
     #[test]
     fn recv_fin_lower_than_received() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
         assert!(!stream.recv.almost_full());
 
         let first = RangeBuf::from(b"hello", 0, true);
@@ -1119,7 +1368,10 @@ mod tests {
 
     #[test]
     fn recv_fin_flow_control() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
         assert!(!stream.recv.almost_full());
 
         let mut buf = [0; 32];
@@ -1139,7 +1391,10 @@ mod tests {
 
     #[test]
     fn recv_fin_reset_mismatch() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
         assert!(!stream.recv.almost_full());
 
         let first = RangeBuf::from(b"hello", 0, true);
@@ -1150,7 +1405,10 @@ mod tests {
 
     #[test]
     fn recv_reset_dup() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
         assert!(!stream.recv.almost_full());
 
         let first = RangeBuf::from(b"hello", 0, false);
@@ -1162,7 +1420,10 @@ mod tests {
 
     #[test]
     fn recv_reset_change() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
         assert!(!stream.recv.almost_full());
 
         let first = RangeBuf::from(b"hello", 0, false);
@@ -1174,7 +1435,10 @@ mod tests {
 
     #[test]
     fn recv_reset_lower_than_received() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
         assert!(!stream.recv.almost_full());
 
         let first = RangeBuf::from(b"hello", 0, false);
@@ -1185,9 +1449,12 @@ mod tests {
 
     #[test]
     fn send_flow_control() {
+        let now = time::Instant::now();
+
         let mut buf = [0; 25];
 
-        let mut stream = Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW);
+        let mut stream =
+            Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW, now);
 
         let first = b"hello";
         let second = b"world";
@@ -1230,7 +1497,10 @@ mod tests {
 
     #[test]
     fn send_past_fin() {
-        let mut stream = Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW, now);
 
         let first = b"hello";
         let second = b"world";
@@ -1246,7 +1516,10 @@ mod tests {
 
     #[test]
     fn send_fin_dup() {
-        let mut stream = Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW, now);
 
         assert_eq!(stream.send.write(b"hello", true), Ok(5));
         assert!(stream.send.is_fin());
@@ -1257,7 +1530,10 @@ mod tests {
 
     #[test]
     fn send_undo_fin() {
-        let mut stream = Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW, now);
 
         assert_eq!(stream.send.write(b"hello", true), Ok(5));
         assert!(stream.send.is_fin());
@@ -1270,9 +1546,12 @@ mod tests {
 
     #[test]
     fn send_fin_max_data_match() {
+        let now = time::Instant::now();
+
         let mut buf = [0; 15];
 
-        let mut stream = Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW);
+        let mut stream =
+            Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW, now);
 
         let slice = b"hellohellohello";
 
@@ -1286,9 +1565,12 @@ mod tests {
 
     #[test]
     fn send_fin_zero_length() {
+        let now = time::Instant::now();
+
         let mut buf = [0; 5];
 
-        let mut stream = Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW);
+        let mut stream =
+            Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW, now);
 
         assert_eq!(stream.send.write(b"hello", false), Ok(5));
         assert_eq!(stream.send.write(b"", true), Ok(0));
@@ -1302,9 +1584,12 @@ mod tests {
 
     #[test]
     fn send_ack() {
+        let now = time::Instant::now();
+
         let mut buf = [0; 5];
 
-        let mut stream = Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW);
+        let mut stream =
+            Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW, now);
 
         assert_eq!(stream.send.write(b"hello", false), Ok(5));
         assert_eq!(stream.send.write(b"world", false), Ok(5));
@@ -1332,9 +1617,12 @@ mod tests {
 
     #[test]
     fn send_ack_reordering() {
+        let now = time::Instant::now();
+
         let mut buf = [0; 5];
 
-        let mut stream = Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW);
+        let mut stream =
+            Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW, now);
 
         assert_eq!(stream.send.write(b"hello", false), Ok(5));
         assert_eq!(stream.send.write(b"world", false), Ok(5));
@@ -1371,7 +1659,10 @@ mod tests {
 
     #[test]
     fn recv_data_below_off() {
-        let mut stream = Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW);
+        let now = time::Instant::now();
+
+        let mut stream =
+            Stream::new(0, 15, 0, true, true, DEFAULT_STREAM_WINDOW, now);
 
         let first = RangeBuf::from(b"hello", 0, false);
 
@@ -1393,8 +1684,10 @@ mod tests {
 
     #[test]
     fn stream_complete() {
+        let now = time::Instant::now();
+
         let mut stream =
-            Stream::new(0, 30, 30, true, true, DEFAULT_STREAM_WINDOW);
+            Stream::new(0, 30, 30, true, true, DEFAULT_STREAM_WINDOW, now);
 
         assert_eq!(stream.send.write(b"hello", false), Ok(5));
         assert_eq!(stream.send.write(b"world", false), Ok(5));
@@ -1435,9 +1728,12 @@ mod tests {
 
     #[test]
     fn send_fin_zero_length_output() {
+        let now = time::Instant::now();
+
         let mut buf = [0; 5];
 
-        let mut stream = Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW);
+        let mut stream =
+            Stream::new(0, 0, 15, true, true, DEFAULT_STREAM_WINDOW, now);
 
         assert_eq!(stream.send.write(b"hello", false), Ok(5));
         assert_eq!(stream.send.off_front(), 0);
@@ -1465,9 +1761,12 @@ mod tests {
 
     #[test]
     fn send_emit() {
+        let now = time::Instant::now();
+
         let mut buf = [0; 5];
 
-        let mut stream = Stream::new(0, 0, 20, true, true, DEFAULT_STREAM_WINDOW);
+        let mut stream =
+            Stream::new(0, 0, 20, true, true, DEFAULT_STREAM_WINDOW, now);
 
         assert_eq!(stream.send.write(b"hello", false), Ok(5));
         assert_eq!(stream.send.write(b"world", false), Ok(5));
@@ -1517,9 +1816,12 @@ mod tests {
 
     #[test]
     fn send_emit_ack() {
+        let now = time::Instant::now();
+
         let mut buf = [0; 5];
 
-        let mut stream = Stream::new(0, 0, 20, true, true, DEFAULT_STREAM_WINDOW);
+        let mut stream =
+            Stream::new(0, 0, 20, true, true, DEFAULT_STREAM_WINDOW, now);
 
         assert_eq!(stream.send.write(b"hello", false), Ok(5));
         assert_eq!(stream.send.write(b"world", false), Ok(5));
@@ -1584,9 +1886,12 @@ mod tests {
 
     #[test]
     fn send_emit_retransmit() {
+        let now = time::Instant::now();
+
         let mut buf = [0; 5];
 
-        let mut stream = Stream::new(0, 0, 20, true, true, DEFAULT_STREAM_WINDOW);
+        let mut stream =
+            Stream::new(0, 0, 20, true, true, DEFAULT_STREAM_WINDOW, now);
 
         assert_eq!(stream.send.write(b"hello", false), Ok(5));
         assert_eq!(stream.send.write(b"world", false), Ok(5));
@@ -1813,6 +2118,8 @@ mod tests {
     /// streams of that type with lower-numbered stream IDs also being opened.
     #[test]
     fn stream_limit_auto_open() {
+        let now = time::Instant::now();
+
         let local_tp = crate::TransportParams::default();
         let peer_tp = crate::TransportParams::default();
 
@@ -1823,7 +2130,7 @@ mod tests {
         assert!(is_bidi(stream_id), "stream id is bidirectional");
         assert_eq!(
             streams
-                .get_or_create(stream_id, &local_tp, &peer_tp, false, true)
+                .get_or_create(stream_id, &local_tp, &peer_tp, false, true, now)
                 .err(),
             Some(Error::StreamLimit),
             "stream limit should be exceeded"
@@ -1834,6 +2141,8 @@ mod tests {
     /// streams
     #[test]
     fn stream_create_out_of_order() {
+        let now = time::Instant::now();
+
         let local_tp = crate::TransportParams::default();
         let peer_tp = crate::TransportParams::default();
 
@@ -1843,7 +2152,7 @@ mod tests {
             assert!(is_local(stream_id, false), "stream id is client initiated");
             assert!(is_bidi(stream_id), "stream id is bidirectional");
             assert!(streams
-                .get_or_create(stream_id, &local_tp, &peer_tp, false, true)
+                .get_or_create(stream_id, &local_tp, &peer_tp, false, true, now)
                 .is_ok());
         }
     }
@@ -1851,6 +2160,8 @@ mod tests {
     /// Check stream limit boundary cases
     #[test]
     fn stream_limit_edge() {
+        let now = time::Instant::now();
+
         let local_tp = crate::TransportParams::default();
         let peer_tp = crate::TransportParams::default();
 
@@ -1859,19 +2170,52 @@ mod tests {
         // Highest permitted
         let stream_id = 8;
         assert!(streams
-            .get_or_create(stream_id, &local_tp, &peer_tp, false, true)
+            .get_or_create(stream_id, &local_tp, &peer_tp, false, true, now)
             .is_ok());
 
         // One more than highest permitted
         let stream_id = 12;
         assert_eq!(
             streams
-                .get_or_create(stream_id, &local_tp, &peer_tp, false, true)
+                .get_or_create(stream_id, &local_tp, &peer_tp, false, true, now)
                 .err(),
             Some(Error::StreamLimit)
         );
     }
 
+    #[test]
+    fn collected_stats_bounded() {
+        let now = time::Instant::now();
+
+        let local_tp = crate::TransportParams::default();
+        let peer_tp = crate::TransportParams::default();
+
+        let count = MAX_COLLECTED_STATS as u64 + 1;
+
+        let mut streams = StreamMap::new(count, count, count);
+
+        for i in 0..count {
+            let stream_id = i * 4;
+
+            assert!(streams
+                .get_or_create(stream_id, &local_tp, &peer_tp, false, true, now)
+                .is_ok());
+
+            streams.collect(stream_id, false, now);
+        }
+
+        // The oldest statistics were evicted, but the stream is still known
+        // to be collected.
+        assert!(streams.is_collected(0));
+        assert!(streams.collected_stats(0).is_none());
+
+        assert!(streams.is_collected(4));
+        assert!(streams.collected_stats(4).is_some());
+
+        assert_eq!(streams.collected_stats.len(), MAX_COLLECTED_STATS);
+        assert_eq!(streams.collected_stats_order.len(), MAX_COLLECTED_STATS);
+    }
+
     fn cycle_stream_priority(stream_id: u64, streams: &mut StreamMap) {
         let key = streams.get(stream_id).unwrap().priority_key.clone();
         streams.update_priority(&key.clone(), &key);
@@ -1879,6 +2223,8 @@ mod tests {
 
     #[test]
     fn writable_prioritized_default_priority() {
+        let now = time::Instant::now();
+
         let local_tp = crate::TransportParams::default();
         let peer_tp = crate::TransportParams {
             initial_max_stream_data_bidi_local: 100,
@@ -1890,7 +2236,7 @@ mod tests {
 
         for id in [0, 4, 8, 12] {
             assert!(streams
-                .get_or_create(id, &local_tp, &peer_tp, false, true)
+                .get_or_create(id, &local_tp, &peer_tp, false, true, now)
                 .is_ok());
         }
 
@@ -1915,6 +2261,8 @@ mod tests {
 
     #[test]
     fn writable_prioritized_insert_order() {
+        let now = time::Instant::now();
+
         let local_tp = crate::TransportParams::default();
         let peer_tp = crate::TransportParams {
             initial_max_stream_data_bidi_local: 100,
@@ -1928,7 +2276,7 @@ mod tests {
         // same order to start with.
         for id in [12, 4, 8, 0] {
             assert!(streams
-                .get_or_create(id, &local_tp, &peer_tp, false, true)
+                .get_or_create(id, &local_tp, &peer_tp, false, true, now)
                 .is_ok());
         }
 
@@ -1950,6 +2298,8 @@ mod tests {
 
     #[test]
     fn writable_prioritized_mixed_urgency() {
+        let now = time::Instant::now();
+
         let local_tp = crate::TransportParams::default();
         let peer_tp = crate::TransportParams {
             initial_max_stream_data_bidi_local: 100,
@@ -1979,7 +2329,7 @@ mod tests {
             // this duplicates some code from stream_priority in order to access
             // streams and the collection they're in
             let stream = streams
-                .get_or_create(id, &local_tp, &peer_tp, false, true)
+                .get_or_create(id, &local_tp, &peer_tp, false, true, now)
                 .unwrap();
 
             stream.urgency = urgency;
@@ -2007,7 +2357,7 @@ mod tests {
             // this duplicates some code from stream_priority in order to access
             // streams and the collection they're in
             let stream = streams
-                .get_or_create(id, &local_tp, &peer_tp, false, true)
+                .get_or_create(id, &local_tp, &peer_tp, false, true, now)
                 .unwrap();
 
             stream.urgency = urgency;
@@ -2031,20 +2381,20 @@ mod tests {
         assert_eq!(walk_2, vec![40, 36, 32, 28, 24, 20, 16, 12, 8, 4, 0]);
 
         // Removing streams doesn't break expected ordering.
-        streams.collect(24, true);
+        streams.collect(24, true, now);
 
         let walk_3: Vec<u64> = streams.writable().collect();
         assert_eq!(walk_3, vec![40, 36, 32, 28, 20, 16, 12, 8, 4, 0]);
 
-        streams.collect(40, true);
-        streams.collect(0, true);
+        streams.collect(40, true, now);
+        streams.collect(0, true, now);
 
         let walk_4: Vec<u64> = streams.writable().collect();
         assert_eq!(walk_4, vec![36, 32, 28, 20, 16, 12, 8, 4]);
 
         // Adding streams doesn't break expected ordering.
         streams
-            .get_or_create(44, &local_tp, &peer_tp, false, true)
+            .get_or_create(44, &local_tp, &peer_tp, false, true, now)
             .unwrap();
 
         let walk_5: Vec<u64> = streams.writable().collect();
@@ -2053,6 +2403,8 @@ mod tests {
 
     #[test]
     fn writable_prioritized_mixed_urgencies_incrementals() {
+        let now = time::Instant::now();
+
         let local_tp = crate::TransportParams::default();
         let peer_tp = crate::TransportParams {
             initial_max_stream_data_bidi_local: 100,
@@ -2081,7 +2433,7 @@ mod tests {
             // this duplicates some code from stream_priority in order to access
             // streams and the collection they're in
             let stream = streams
-                .get_or_create(id, &local_tp, &peer_tp, false, true)
+                .get_or_create(id, &local_tp, &peer_tp, false, true, now)
                 .unwrap();
 
             stream.urgency = urgency;
@@ -2149,14 +2501,14 @@ mod tests {
         assert_eq!(walk_9, vec![40, 36, 4, 12, 20, 28, 32, 16, 24, 0, 8]);
 
         // Removing streams doesn't break expected ordering.
-        streams.collect(20, true);
+        streams.collect(20, true, now);
 
         let walk_10: Vec<u64> = streams.writable().collect();
         assert_eq!(walk_10, vec![40, 4, 12, 36, 28, 32, 24, 16, 8, 0]);
 
         // Adding streams doesn't break expected ordering.
         let stream = streams
-            .get_or_create(44, &local_tp, &peer_tp, false, true)
+            .get_or_create(44, &local_tp, &peer_tp, false, true, now)
             .unwrap();
 
         stream.urgency = 20;
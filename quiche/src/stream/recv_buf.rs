@@ -64,6 +64,10 @@ pub struct RecvBuf {
     /// The error code received via RESET_STREAM.
     error: Option<u64>,
 
+    /// The offset up to which data is still delivered after the stream was
+    /// reset via RESET_STREAM_AT.
+    reliable_off: Option<u64>,
+
     /// Whether incoming data is validated but not buffered.
     drain: bool,
 }
@@ -86,7 +90,7 @@ impl RecvBuf {
     /// This also takes care of enforcing stream flow control limits, as well
     /// as handling incoming data that overlaps data that is already in the
     /// buffer.
-    pub fn write(&mut self, buf: RangeBuf) -> Result<()> {
+    pub fn write(&mut self, mut buf: RangeBuf) -> Result<()> {
         if buf.max_off() > self.max_data() {
             return Err(Error::FlowControl);
         }
@@ -114,6 +118,17 @@ impl RecvBuf {
             return Ok(());
         }
 
+        // The stream was reset, so discard data past the reliable size.
+        if let Some(reliable_off) = self.reliable_off {
+            if buf.off() >= reliable_off {
+                return Ok(());
+            }
+
+            if buf.max_off() > reliable_off {
+                buf.split_off((reliable_off - buf.off()) as usize);
+            }
+        }
+
         if buf.fin() {
             self.fin_off = Some(buf.max_off());
         }
@@ -211,8 +226,7 @@ impl RecvBuf {
 
         // The stream was reset, so clear its data and return the error code
         // instead.
-        if let Some(e) = self.error {
-            self.data.clear();
+        if let Some(e) = self.reset_error() {
             return Err(Error::StreamReset(e));
         }
 
@@ -249,6 +263,38 @@ impl RecvBuf {
         Ok((len, self.is_fin()))
     }
 
+    /// Removes the next contiguous chunk of data from the receive buffer.
+    ///
+    /// This is the same as [`emit()`], except that the data is returned as
+    /// it was buffered, in order to avoid copying it. If there is no data at
+    /// the expected read offset, the `Done` error is returned.
+    ///
+    /// [`emit()`]: struct.RecvBuf.html#method.emit
+    pub fn emit_owned(&mut self) -> Result<(Vec<u8>, bool)> {
+        if !self.ready() {
+            return Err(Error::Done);
+        }
+
+        // The stream was reset, so clear its data and return the error code
+        // instead.
+        if let Some(e) = self.reset_error() {
+            return Err(Error::StreamReset(e));
+        }
+
+        let buf = match self.data.pop_first() {
+            Some((_, buf)) => buf.into_vec(),
+
+            None => return Err(Error::Done),
+        };
+
+        self.off += buf.len() as u64;
+
+        // Update consumed bytes for flow control.
+        self.flow_control.add_consumed(buf.len() as u64);
+
+        Ok((buf, self.is_fin()))
+    }
+
     /// Resets the stream at the given offset.
     pub fn reset(&mut self, error_code: u64, final_size: u64) -> Result<usize> {
         // Stream's size is already known, forbid changing it.
@@ -286,6 +332,76 @@ impl RecvBuf {
         Ok(max_data_delta as usize)
     }
 
+    /// Resets the stream at the given offset, but keeps delivering data up to
+    /// `reliable_size` to the application.
+    pub fn reset_at(
+        &mut self, error_code: u64, final_size: u64, reliable_size: u64,
+    ) -> Result<usize> {
+        // All reliable data was already read, so there is no difference with
+        // a regular reset.
+        if reliable_size <= self.off || self.drain {
+            return self.reset(error_code, final_size);
+        }
+
+        // Stream's size is already known, forbid changing it.
+        if let Some(fin_off) = self.fin_off {
+            if fin_off != final_size {
+                return Err(Error::FinalSize);
+            }
+        }
+
+        // Stream's known size is lower than data already received.
+        if final_size < self.len {
+            return Err(Error::FinalSize);
+        }
+
+        // Calculate how many bytes need to be removed from the connection flow
+        // control.
+        let max_data_delta = final_size - self.len;
+
+        if self.error.is_some() {
+            return Ok(max_data_delta as usize);
+        }
+
+        self.error = Some(error_code);
+        self.reliable_off = Some(reliable_size);
+
+        self.fin_off = Some(final_size);
+        self.len = final_size;
+
+        // Drop buffered data past the reliable size.
+        let mut tail = self.data.split_off(&(reliable_size + 1));
+
+        if let Some((_, mut buf)) = tail.pop_first() {
+            if buf.off() < reliable_size {
+                buf.split_off((reliable_size - buf.off()) as usize);
+                self.data.insert(buf.max_off(), buf);
+            }
+        }
+
+        Ok(max_data_delta as usize)
+    }
+
+    /// Returns the reset error code once all data that must be delivered
+    /// after a reset was read, and clears the buffered data.
+    fn reset_error(&mut self) -> Option<u64> {
+        let e = self.error?;
+
+        if let Some(reliable_off) = self.reliable_off {
+            if self.off < reliable_off {
+                return None;
+            }
+
+            // No more data will be read, so skip to the final size.
+            self.off = self.fin_off.unwrap_or(reliable_off);
+            self.reliable_off = None;
+        }
+
+        self.data.clear();
+
+        Some(e)
+    }
+
     /// Commits the new max_data limit.
     pub fn update_max_data(&mut self, now: time::Instant) {
         self.flow_control.update_max_data(now);
@@ -353,6 +469,16 @@ impl RecvBuf {
         false
     }
 
+    /// Returns the final stream offset received from the peer, if any.
+    pub fn fin_off(&self) -> Option<u64> {
+        self.fin_off
+    }
+
+    /// Returns the error code received via RESET_STREAM, if any.
+    pub fn error(&self) -> Option<u64> {
+        self.error
+    }
+
     /// Returns true if the stream is not storing incoming data.
     pub fn is_draining(&self) -> bool {
         self.drain
@@ -360,6 +486,11 @@ impl RecvBuf {
 
     /// Returns true if the stream has data to be read.
     pub fn ready(&self) -> bool {
+        // The reset can be reported once the reliable data was read.
+        if self.reliable_off == Some(self.off) {
+            return true;
+        }
+
         let (_, buf) = match self.data.first_key_value() {
             Some(v) => v,
             None => return false,
@@ -516,6 +647,90 @@ mod tests {
         assert_eq!(recv.off, 19);
     }
 
+    #[test]
+    fn owned_read() {
+        let mut recv = RecvBuf::new(u64::MAX, DEFAULT_STREAM_WINDOW);
+        assert_eq!(recv.len, 0);
+
+        let mut buf = [0; 32];
+
+        let first = RangeBuf::from(b"something", 0, false);
+        let second = RangeBuf::from(b"helloworld", 9, true);
+
+        assert!(recv.write(second).is_ok());
+        assert_eq!(recv.emit_owned(), Err(Error::Done));
+
+        assert!(recv.write(first).is_ok());
+        assert_eq!(recv.len, 19);
+        assert_eq!(recv.off, 0);
+
+        // Partially read the first buffer.
+        let (len, fin) = recv.emit(&mut buf[..4]).unwrap();
+        assert_eq!(len, 4);
+        assert!(!fin);
+        assert_eq!(&buf[..len], b"some");
+
+        let (data, fin) = recv.emit_owned().unwrap();
+        assert!(!fin);
+        assert_eq!(&data, b"thing");
+        assert_eq!(recv.off, 9);
+
+        let (data, fin) = recv.emit_owned().unwrap();
+        assert!(fin);
+        assert_eq!(&data, b"helloworld");
+        assert_eq!(recv.off, 19);
+
+        assert_eq!(recv.emit_owned(), Err(Error::Done));
+    }
+
+    #[test]
+    fn reset_at_read() {
+        let mut recv = RecvBuf::new(u64::MAX, DEFAULT_STREAM_WINDOW);
+        assert_eq!(recv.len, 0);
+
+        let mut buf = [0; 32];
+
+        let first = RangeBuf::from(b"something", 0, false);
+        let second = RangeBuf::from(b"world", 9, false);
+        let third = RangeBuf::from(b"hello", 14, false);
+        let fourth = RangeBuf::from(b"xyz", 16, false);
+
+        assert!(recv.write(first).is_ok());
+        assert!(recv.write(third).is_ok());
+        assert_eq!(recv.len, 19);
+
+        // Data past the reliable size is dropped.
+        assert_eq!(recv.reset_at(7, 30, 16), Ok(11));
+        assert_eq!(recv.len, 30);
+        assert_eq!(recv.fin_off(), Some(30));
+        assert_eq!(recv.error(), Some(7));
+
+        let (len, fin) = recv.emit(&mut buf).unwrap();
+        assert_eq!(len, 9);
+        assert!(!fin);
+        assert_eq!(&buf[..len], b"something");
+
+        assert_eq!(recv.emit(&mut buf), Err(Error::Done));
+
+        assert!(recv.write(second).is_ok());
+        assert!(recv.write(fourth).is_ok());
+
+        let (len, fin) = recv.emit(&mut buf).unwrap();
+        assert_eq!(len, 7);
+        assert!(!fin);
+        assert_eq!(&buf[..len], b"worldhe");
+        assert_eq!(recv.off, 16);
+
+        // All reliable data was read, so the reset is reported.
+        assert!(recv.ready());
+        assert_eq!(recv.emit(&mut buf), Err(Error::StreamReset(7)));
+        assert_eq!(recv.off, 30);
+        assert!(recv.is_fin());
+
+        assert!(!recv.ready());
+        assert_eq!(recv.emit(&mut buf), Err(Error::Done));
+    }
+
     #[test]
     fn incomplete_read() {
         let mut recv = RecvBuf::new(u64::MAX, DEFAULT_STREAM_WINDOW);
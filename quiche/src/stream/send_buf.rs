@@ -85,6 +85,9 @@ pub struct SendBuf {
 
     /// The error code received via STOP_SENDING.
     error: Option<u64>,
+
+    /// The amount of data that was marked for retransmission.
+    retransmitted: u64,
 }
 
 impl SendBuf {
@@ -146,9 +149,26 @@ impl SendBuf {
 
         let mut len = 0;
 
+        // Fill the last buffer first, so that small writes don't each
+        // allocate a new buffer. This is only done if the buffer wasn't fully
+        // sent yet, as otherwise the new data would be skipped.
+        if let Some(buf) = self.data.back_mut().filter(|buf| !buf.is_empty()) {
+            let room = SEND_BUFFER_SIZE.saturating_sub(buf.len);
+            let chunk = &data[..cmp::min(room, data.len())];
+
+            if !chunk.is_empty() && buf.try_append(chunk) {
+                len += chunk.len();
+
+                buf.fin = len == data.len() && fin;
+
+                self.off += chunk.len() as u64;
+                self.len += chunk.len() as u64;
+            }
+        }
+
         // Split the remaining input data into consistently-sized buffers to
         // avoid fragmentation.
-        for chunk in data.chunks(SEND_BUFFER_SIZE) {
+        for chunk in data[len..].chunks(SEND_BUFFER_SIZE) {
             len += chunk.len();
 
             let fin = len == data.len() && fin;
@@ -307,17 +327,20 @@ impl SendBuf {
             return;
         }
 
-        for i in 0..self.data.len() {
+        // Buffers are contiguous and ordered by offset, so find the first one
+        // that overlaps the retransmit range with a binary search. Note that
+        // buffers don't all have the same size, as they can be split when only
+        // part of them is retransmitted, so the index can't be derived from
+        // the offset directly.
+        let first = self.data.partition_point(|buf| buf.max_off() < off);
+
+        for i in first..self.data.len() {
             let buf = &mut self.data[i];
 
             if buf.off >= max_off {
                 break;
             }
 
-            if off > buf.max_off() {
-                continue;
-            }
-
             // Split the buffer into 2 if the retransmit range ends before the
             // buffer's final offset.
             let new_buf = if buf.off < max_off && max_off < buf.max_off() {
@@ -339,6 +362,7 @@ impl SendBuf {
             self.pos = cmp::min(self.pos, i);
 
             self.len += (prev_pos - buf.pos) as u64;
+            self.retransmitted += (prev_pos - buf.pos) as u64;
 
             if let Some(b) = new_buf {
                 self.data.insert(i + 1, b);
@@ -366,6 +390,44 @@ impl SendBuf {
         (self.emit_off, unsent_len)
     }
 
+    /// Resets the stream, but keeps data up to `reliable_size` so that it is
+    /// still delivered to the peer.
+    ///
+    /// The final size of the stream and the amount of buffered data that was
+    /// never sent are returned.
+    pub fn reset_at(&mut self, reliable_size: u64) -> (u64, u64) {
+        let final_size = cmp::max(self.emit_off, reliable_size);
+        let unsent_len = self.off_back().saturating_sub(final_size);
+
+        self.fin_off = Some(final_size);
+
+        // Drop buffered data past the reliable size.
+        while let Some(buf) = self.data.back_mut() {
+            if buf.off >= reliable_size {
+                self.data.pop_back();
+                continue;
+            }
+
+            if buf.off + buf.len as u64 > reliable_size {
+                buf.split_off((reliable_size - buf.off) as usize);
+            }
+
+            break;
+        }
+
+        // Data past the reliable size doesn't need to be acked.
+        self.off = final_size;
+
+        if final_size > reliable_size {
+            self.ack(reliable_size, (final_size - reliable_size) as usize);
+        }
+
+        self.pos = cmp::min(self.pos, self.data.len());
+        self.len = self.data.iter().map(|b| b.len() as u64).sum();
+
+        (final_size, unsent_len)
+    }
+
     /// Resets the streams and records the received error code.
     ///
     /// Calling this again after the first time has no effect.
@@ -392,6 +454,17 @@ impl SendBuf {
         Ok(self.reset())
     }
 
+    /// Shuts down sending data, except for data up to `reliable_size`.
+    pub fn shutdown_at(&mut self, reliable_size: u64) -> Result<(u64, u64)> {
+        if self.shutdown {
+            return Err(Error::Done);
+        }
+
+        self.shutdown = true;
+
+        Ok(self.reset_at(reliable_size))
+    }
+
     /// Returns the largest offset of data buffered.
     pub fn off_back(&self) -> u64 {
         self.off
@@ -444,6 +517,26 @@ impl SendBuf {
         false
     }
 
+    /// Returns the largest offset sent to the peer.
+    pub fn emit_off(&self) -> u64 {
+        self.emit_off
+    }
+
+    /// Returns the final stream offset, if known.
+    pub fn fin_off(&self) -> Option<u64> {
+        self.fin_off
+    }
+
+    /// Returns the error code received via STOP_SENDING, if any.
+    pub fn error(&self) -> Option<u64> {
+        self.error
+    }
+
+    /// Returns the amount of data that was marked for retransmission.
+    pub fn retransmitted(&self) -> u64 {
+        self.retransmitted
+    }
+
     /// Returns true if the stream was stopped before completion.
     pub fn is_stopped(&self) -> bool {
         self.error.is_some()
@@ -526,6 +619,60 @@ mod tests {
         assert_eq!(send.len, 0);
     }
 
+    #[test]
+    fn small_writes() {
+        let mut buf = [0; 10];
+
+        let mut send = SendBuf::new(u64::MAX);
+
+        assert_eq!(send.write(b"ab", false), Ok(2));
+        assert_eq!(send.write(b"c", false), Ok(1));
+        assert_eq!(send.bufs_count(), 1);
+
+        // The last buffer is filled before a new one is created.
+        assert_eq!(send.write(b"defg", false), Ok(4));
+        assert_eq!(send.bufs_count(), 2);
+        assert_eq!(send.len, 7);
+
+        let (written, fin) = send.emit(&mut buf[..6]).unwrap();
+        assert_eq!(written, 6);
+        assert!(!fin);
+        assert_eq!(&buf[..written], b"abcdef");
+
+        // The last buffer was partially sent, so it can still be filled.
+        assert_eq!(send.write(b"h", true), Ok(1));
+        assert_eq!(send.bufs_count(), 2);
+
+        let (written, fin) = send.emit(&mut buf[..10]).unwrap();
+        assert_eq!(written, 2);
+        assert!(fin);
+        assert_eq!(&buf[..written], b"gh");
+        assert_eq!(send.len, 0);
+    }
+
+    #[test]
+    fn small_writes_after_emit() {
+        let mut buf = [0; 10];
+
+        let mut send = SendBuf::new(u64::MAX);
+
+        assert_eq!(send.write(b"ab", false), Ok(2));
+
+        let (written, fin) = send.emit(&mut buf[..10]).unwrap();
+        assert_eq!(written, 2);
+        assert!(!fin);
+
+        // The last buffer was fully sent, so a new one is created.
+        assert_eq!(send.write(b"cd", false), Ok(2));
+        assert_eq!(send.bufs_count(), 2);
+
+        let (written, fin) = send.emit(&mut buf[..10]).unwrap();
+        assert_eq!(written, 2);
+        assert!(!fin);
+        assert_eq!(&buf[..written], b"cd");
+        assert_eq!(send.len, 0);
+    }
+
     #[test]
     fn split_write() {
         let mut buf = [0; 10];
@@ -632,6 +779,100 @@ mod tests {
         assert_eq!(send.off_front(), 19);
     }
 
+    #[test]
+    fn resend_middle() {
+        let mut buf = [0; 50];
+
+        let mut send = SendBuf::new(u64::MAX);
+
+        let data: Vec<u8> = (0..50).collect();
+
+        assert_eq!(send.write(&data, false), Ok(50));
+        assert_eq!(send.bufs_count(), 10);
+
+        let (written, fin) = send.emit(&mut buf).unwrap();
+        assert_eq!(written, 50);
+        assert!(!fin);
+        assert_eq!(send.len, 0);
+
+        // Retransmit a range spanning two buffers in the middle.
+        send.retransmit(23, 4);
+        assert_eq!(send.len, 4);
+        assert_eq!(send.off_front(), 23);
+        assert_eq!(send.retransmitted(), 4);
+
+        let (written, fin) = send.emit(&mut buf).unwrap();
+        assert_eq!(written, 4);
+        assert!(!fin);
+        assert_eq!(&buf[..written], &data[23..27]);
+        assert_eq!(send.len, 0);
+        assert_eq!(send.off_front(), 50);
+    }
+
+    #[test]
+    fn reset_at() {
+        let mut buf = [0; 50];
+
+        let mut send = SendBuf::new(u64::MAX);
+
+        let data: Vec<u8> = (0..20).collect();
+
+        assert_eq!(send.write(&data, false), Ok(20));
+        assert_eq!(send.bufs_count(), 4);
+
+        let (written, fin) = send.emit(&mut buf[..5]).unwrap();
+        assert_eq!(written, 5);
+        assert!(!fin);
+
+        // Data up to offset 12 must still be delivered, the rest is dropped.
+        assert_eq!(send.shutdown_at(12), Ok((12, 8)));
+        assert_eq!(send.shutdown_at(12), Err(Error::Done));
+        assert_eq!(send.bufs_count(), 3);
+        assert_eq!(send.len, 7);
+        assert_eq!(send.off_back(), 12);
+        assert_eq!(send.fin_off(), Some(12));
+
+        let (written, fin) = send.emit(&mut buf).unwrap();
+        assert_eq!(written, 7);
+        assert!(fin);
+        assert_eq!(&buf[..written], &data[5..12]);
+
+        assert!(!send.is_complete());
+
+        send.ack_and_drop(0, 12);
+        assert!(send.is_complete());
+    }
+
+    #[test]
+    fn reset_at_below_sent() {
+        let mut buf = [0; 50];
+
+        let mut send = SendBuf::new(u64::MAX);
+
+        let data: Vec<u8> = (0..20).collect();
+
+        assert_eq!(send.write(&data, false), Ok(20));
+
+        let (written, fin) = send.emit(&mut buf[..10]).unwrap();
+        assert_eq!(written, 10);
+        assert!(!fin);
+
+        // The final size covers all data that was already sent.
+        assert_eq!(send.shutdown_at(3), Ok((10, 10)));
+        assert_eq!(send.len, 0);
+        assert_eq!(send.fin_off(), Some(10));
+
+        send.retransmit(0, 10);
+        assert_eq!(send.len, 3);
+
+        let (written, _) = send.emit(&mut buf).unwrap();
+        assert_eq!(written, 3);
+        assert_eq!(&buf[..written], &data[..3]);
+
+        send.ack_and_drop(0, 3);
+        assert!(send.is_complete());
+    }
+
     #[test]
     fn write_blocked_by_off() {
         let mut buf = [0; 10];
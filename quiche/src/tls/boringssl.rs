@@ -8,6 +8,52 @@ struct CRYPTO_BUFFER {
     _unused: c_void,
 }
 
+#[allow(non_camel_case_types)]
+#[repr(transparent)]
+struct CBB {
+    _unused: c_void,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(transparent)]
+struct BIO {
+    _unused: c_void,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(transparent)]
+struct X509 {
+    _unused: c_void,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(transparent)]
+struct EVP_PKEY {
+    _unused: c_void,
+}
+
+// Only the first field is needed, so the rest of the struct is omitted.
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct SSL_CLIENT_HELLO {
+    ssl: *mut SSL,
+}
+
+type CertCompressCb = extern fn(
+    ssl: *mut SSL,
+    out: *mut CBB,
+    input: *const u8,
+    len: usize,
+) -> c_int;
+
+type CertDecompressCb = extern fn(
+    ssl: *mut SSL,
+    out: *mut *mut CRYPTO_BUFFER,
+    uncompressed_len: usize,
+    input: *const u8,
+    len: usize,
+) -> c_int;
+
 #[repr(C)]
 #[allow(non_camel_case_types)]
 pub(super) struct SSL_QUIC_METHOD {
@@ -101,9 +147,195 @@ impl Context {
             );
         }
     }
+
+    pub fn enable_ocsp_response(&mut self) -> Result<()> {
+        // The response is set on each handshake by select_certificate(), once
+        // the certificate to present is known.
+        self.enable_certificate_selection();
+
+        Ok(())
+    }
+
+    pub fn enable_certificate_selection(&mut self) {
+        unsafe {
+            SSL_CTX_set_select_certificate_cb(
+                self.as_mut_ptr(),
+                Some(select_certificate_cb),
+            );
+        }
+    }
+
+    pub fn enable_ocsp_stapling(&mut self) {
+        unsafe { SSL_CTX_enable_ocsp_stapling(self.as_mut_ptr()) };
+    }
+
+    pub fn add_cert_compression_alg(&mut self, alg: u16) -> Result<()> {
+        // The callbacks don't receive the algorithm, so instantiate a
+        // separate pair for each of the ones defined in RFC 8879.
+        let callbacks: (CertCompressCb, CertDecompressCb) = match alg {
+            1 => (compress_cert::<1>, decompress_cert::<1>),
+            2 => (compress_cert::<2>, decompress_cert::<2>),
+            3 => (compress_cert::<3>, decompress_cert::<3>),
+
+            _ => return Err(Error::TlsFail),
+        };
+
+        let (compress, decompress) = callbacks;
+
+        map_result(unsafe {
+            SSL_CTX_add_cert_compression_alg(
+                self.as_mut_ptr(),
+                alg,
+                Some(compress),
+                Some(decompress),
+            )
+        })
+    }
+
+    pub fn set_ciphersuites(&mut self, _ciphersuites: &str) -> Result<()> {
+        // BoringSSL doesn't allow configuring TLS 1.3 cipher suites, so the
+        // negotiated one is checked when the handshake secrets are set
+        // instead.
+        Ok(())
+    }
+}
+
+/// The certificate chain and private key of a [`Certificate`].
+pub(super) struct CertChain {
+    certs: Vec<*mut CRYPTO_BUFFER>,
+
+    key: *mut EVP_PKEY,
+
+    ocsp_response: Option<Vec<u8>>,
+}
+
+impl CertChain {
+    pub(super) fn from_pem(cert: &[u8], key: &[u8]) -> Result<CertChain> {
+        let mut chain = CertChain {
+            certs: Vec::new(),
+            key: ptr::null_mut(),
+            ocsp_response: None,
+        };
+
+        unsafe {
+            let bio = BIO_new_mem_buf(cert.as_ptr(), cert.len() as isize);
+            if bio.is_null() {
+                return Err(Error::TlsFail);
+            }
+
+            loop {
+                let x509 = PEM_read_bio_X509(
+                    bio,
+                    ptr::null_mut(),
+                    ptr::null(),
+                    ptr::null_mut(),
+                );
+
+                if x509.is_null() {
+                    break;
+                }
+
+                let mut der: *mut u8 = ptr::null_mut();
+                let der_len = i2d_X509(x509, &mut der);
+
+                X509_free(x509);
+
+                if der_len <= 0 {
+                    BIO_free(bio);
+                    return Err(Error::TlsFail);
+                }
+
+                let buffer =
+                    CRYPTO_BUFFER_new(der, der_len as usize, ptr::null_mut());
+
+                OPENSSL_free(der as *mut c_void);
+
+                if buffer.is_null() {
+                    BIO_free(bio);
+                    return Err(Error::TlsFail);
+                }
+
+                chain.certs.push(buffer);
+            }
+
+            BIO_free(bio);
+
+            // Reading the certificates stops with an error once the end of
+            // the input is reached.
+            ERR_clear_error();
+
+            if chain.certs.is_empty() {
+                return Err(Error::TlsFail);
+            }
+
+            let bio = BIO_new_mem_buf(key.as_ptr(), key.len() as isize);
+            if bio.is_null() {
+                return Err(Error::TlsFail);
+            }
+
+            chain.key = PEM_read_bio_PrivateKey(
+                bio,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null_mut(),
+            );
+
+            BIO_free(bio);
+
+            if chain.key.is_null() {
+                return Err(Error::TlsFail);
+            }
+        }
+
+        Ok(chain)
+    }
+
+    pub(super) fn set_ocsp_response(&mut self, response: &[u8]) -> Result<()> {
+        self.ocsp_response = Some(response.to_vec());
+
+        Ok(())
+    }
+
+    pub(super) fn ocsp_response(&self) -> Option<&[u8]> {
+        self.ocsp_response.as_deref()
+    }
+}
+
+impl Drop for CertChain {
+    fn drop(&mut self) {
+        unsafe {
+            for buffer in &self.certs {
+                CRYPTO_BUFFER_free(*buffer);
+            }
+
+            EVP_PKEY_free(self.key);
+        }
+    }
 }
 
 impl Handshake {
+    pub(super) fn set_certificate(&mut self, chain: &CertChain) -> Result<()> {
+        map_result(unsafe {
+            SSL_set_chain_and_key(
+                self.as_mut_ptr(),
+                chain.certs.as_ptr(),
+                chain.certs.len(),
+                chain.key,
+                ptr::null(),
+            )
+        })
+    }
+
+    pub fn set_ocsp_response(&mut self, response: &[u8]) -> Result<()> {
+        map_result(unsafe {
+            SSL_set_ocsp_response(
+                self.as_mut_ptr(),
+                response.as_ptr(),
+                response.len(),
+            )
+        })
+    }
+
     pub fn set_quic_early_data_context(&mut self, context: &[u8]) -> Result<()> {
         map_result(unsafe {
             SSL_set_quic_early_data_context(
@@ -209,6 +441,21 @@ impl Handshake {
         Some(cert_chain)
     }
 
+    pub fn ocsp_response(&self) -> Option<&[u8]> {
+        let mut ptr: *const u8 = ptr::null();
+        let mut len: usize = 0;
+
+        unsafe {
+            SSL_get0_ocsp_response(self.as_ptr(), &mut ptr, &mut len);
+        }
+
+        if len == 0 {
+            return None;
+        }
+
+        Some(unsafe { slice::from_raw_parts(ptr, len) })
+    }
+
     pub fn peer_cert(&self) -> Option<&[u8]> {
         let peer_cert = unsafe {
             let chain =
@@ -276,6 +523,82 @@ impl Handshake {
     }
 }
 
+extern fn select_certificate_cb(client_hello: *const SSL_CLIENT_HELLO) -> c_int {
+    // ssl_select_cert_success 1
+    // ssl_select_cert_error -1
+    let ssl = unsafe { (*client_hello).ssl };
+
+    match select_certificate(ssl) {
+        Ok(_) => 1,
+
+        Err(_) => -1,
+    }
+}
+
+extern fn compress_cert<const ALG: u16>(
+    ssl: *mut SSL, out: *mut CBB, input: *const u8, len: usize,
+) -> c_int {
+    let ex_data = match get_ex_data_from_ptr::<ExData>(ssl, *QUICHE_EX_DATA_INDEX)
+    {
+        Some(v) => v,
+
+        None => return 0,
+    };
+
+    let cert_compression =
+        match ex_data.cert_compression.iter().find(|c| c.alg == ALG) {
+            Some(v) => v,
+
+            None => return 0,
+        };
+
+    let input = unsafe { slice::from_raw_parts(input, len) };
+
+    match (cert_compression.compress)(input) {
+        Some(v) => unsafe { CBB_add_bytes(out, v.as_ptr(), v.len()) },
+
+        None => 0,
+    }
+}
+
+extern fn decompress_cert<const ALG: u16>(
+    ssl: *mut SSL, out: *mut *mut CRYPTO_BUFFER, uncompressed_len: usize,
+    input: *const u8, len: usize,
+) -> c_int {
+    let ex_data = match get_ex_data_from_ptr::<ExData>(ssl, *QUICHE_EX_DATA_INDEX)
+    {
+        Some(v) => v,
+
+        None => return 0,
+    };
+
+    let cert_compression =
+        match ex_data.cert_compression.iter().find(|c| c.alg == ALG) {
+            Some(v) => v,
+
+            None => return 0,
+        };
+
+    let input = unsafe { slice::from_raw_parts(input, len) };
+
+    let cert = match (cert_compression.decompress)(input, uncompressed_len) {
+        Some(v) if v.len() == uncompressed_len => v,
+
+        _ => return 0,
+    };
+
+    let buffer =
+        unsafe { CRYPTO_BUFFER_new(cert.as_ptr(), cert.len(), ptr::null_mut()) };
+
+    if buffer.is_null() {
+        return 0;
+    }
+
+    unsafe { *out = buffer };
+
+    1
+}
+
 pub(super) fn get_session_bytes(session: *mut SSL_SESSION) -> Result<Vec<u8>> {
     let session_bytes = unsafe {
         let mut out: *mut u8 = std::ptr::null_mut();
@@ -300,9 +623,30 @@ extern {
     ) -> c_int;
     fn SSL_CTX_set_early_data_enabled(ctx: *mut SSL_CTX, enabled: i32);
 
+    fn SSL_CTX_set_select_certificate_cb(
+        ctx: *mut SSL_CTX,
+        cb: Option<extern fn(*const SSL_CLIENT_HELLO) -> c_int>,
+    );
+
+    fn SSL_CTX_enable_ocsp_stapling(ctx: *mut SSL_CTX);
+
+    fn SSL_CTX_add_cert_compression_alg(
+        ctx: *mut SSL_CTX, alg_id: u16, compress: Option<CertCompressCb>,
+        decompress: Option<CertDecompressCb>,
+    ) -> c_int;
+
+    pub(super) fn SSL_CTX_set1_curves_list(
+        ctx: *mut SSL_CTX, curves: *const c_char,
+    ) -> c_int;
+
+    pub(super) fn SSL_CTX_set1_sigalgs_list(
+        ctx: *mut SSL_CTX, sigalgs: *const c_char,
+    ) -> c_int;
+
     pub(super) fn SSL_CTX_set_session_cache_mode(
         ctx: *mut SSL_CTX, mode: c_int,
     ) -> c_int;
+
     pub(super) fn SSL_get_ex_new_index(
         argl: c_long, argp: *const c_void, unused: *const c_void,
         dup_unused: *const c_void, free_func: *const c_void,
@@ -318,6 +662,10 @@ extern {
 
     fn SSL_get0_peer_certificates(ssl: *const SSL) -> *const STACK_OF;
 
+    fn SSL_get0_ocsp_response(
+        ssl: *const SSL, out: *mut *const u8, out_len: *mut usize,
+    );
+
     pub(super) fn SSL_set_min_proto_version(ssl: *mut SSL, version: u16)
         -> c_int;
 
@@ -328,6 +676,15 @@ extern {
         ssl: *mut SSL, name: *const c_char,
     ) -> c_int;
 
+    fn SSL_set_chain_and_key(
+        ssl: *mut SSL, certs: *const *mut CRYPTO_BUFFER, num_certs: usize,
+        privkey: *mut EVP_PKEY, privkey_method: *const c_void,
+    ) -> c_int;
+
+    fn SSL_set_ocsp_response(
+        ssl: *mut SSL, response: *const u8, response_len: usize,
+    ) -> c_int;
+
     fn SSL_set_quic_early_data_context(
         ssl: *mut SSL, context: *const u8, context_len: usize,
     ) -> c_int;
@@ -360,4 +717,44 @@ extern {
     fn CRYPTO_BUFFER_len(buffer: *const CRYPTO_BUFFER) -> usize;
 
     fn CRYPTO_BUFFER_data(buffer: *const CRYPTO_BUFFER) -> *const u8;
+
+    fn CRYPTO_BUFFER_new(
+        data: *const u8, len: usize, pool: *mut c_void,
+    ) -> *mut CRYPTO_BUFFER;
+
+    fn CRYPTO_BUFFER_free(buffer: *mut CRYPTO_BUFFER);
+
+    // BIO
+
+    fn BIO_new_mem_buf(buf: *const u8, len: isize) -> *mut BIO;
+
+    fn BIO_free(bio: *mut BIO) -> c_int;
+
+    // PEM
+
+    fn PEM_read_bio_X509(
+        bio: *mut BIO, out: *mut *mut X509, cb: *const c_void, u: *mut c_void,
+    ) -> *mut X509;
+
+    fn PEM_read_bio_PrivateKey(
+        bio: *mut BIO, out: *mut *mut EVP_PKEY, cb: *const c_void, u: *mut c_void,
+    ) -> *mut EVP_PKEY;
+
+    // X509
+
+    fn i2d_X509(x509: *mut X509, out: *mut *mut u8) -> c_int;
+
+    fn X509_free(x509: *mut X509);
+
+    // EVP_PKEY
+
+    fn EVP_PKEY_free(pkey: *mut EVP_PKEY);
+
+    // ERR
+
+    fn ERR_clear_error();
+
+    // CBB
+
+    fn CBB_add_bytes(cbb: *mut CBB, data: *const u8, len: usize) -> c_int;
 }
@@ -28,6 +28,8 @@ use std::ffi;
 use std::ptr;
 use std::slice;
 
+use std::sync::Arc;
+
 use std::io::Write;
 
 use once_cell::sync::Lazy;
@@ -272,13 +274,30 @@ impl Context {
         };
     }
 
-    pub fn set_verify(&mut self, verify: bool) {
-        // true  -> 0x01 SSL_VERIFY_PEER
-        // false -> 0x00 SSL_VERIFY_NONE
-        let mode = i32::from(verify);
+    /// Returns whether the peer's certificate is verified, and whether the
+    /// handshake fails when the peer doesn't present one.
+    pub fn verify(&self) -> (bool, bool) {
+        let mode = unsafe { SSL_CTX_get_verify_mode(self.as_ptr()) };
+
+        // 0x01 SSL_VERIFY_PEER
+        // 0x02 SSL_VERIFY_FAIL_IF_NO_PEER_CERT
+        (mode & 0x01 != 0, mode & 0x02 != 0)
+    }
+
+    pub fn set_verify(&mut self, verify: bool, require_peer_cert: bool) {
+        // require_peer_cert -> 0x03 SSL_VERIFY_PEER |
+        //                           SSL_VERIFY_FAIL_IF_NO_PEER_CERT
+        // verify            -> 0x01 SSL_VERIFY_PEER
+        // otherwise         -> 0x00 SSL_VERIFY_NONE
+        //
+        // A peer certificate can't be required without verifying it.
+        let mode = if require_peer_cert {
+            0x03
+        } else {
+            i32::from(verify)
+        };
 
-        // Note: Base on two used modes(see above), it seems ok for both, bssl and
-        // ossl. If mode needs to be ored then it may need to be adjusted.
+        // Note: the values above are the same for both bssl and ossl.
         unsafe {
             SSL_CTX_set_verify(self.as_mut_ptr(), mode, None);
         }
@@ -317,6 +336,22 @@ impl Context {
         })
     }
 
+    pub fn set_groups(&mut self, groups: &str) -> Result<()> {
+        let cstr = ffi::CString::new(groups).map_err(|_| Error::TlsFail)?;
+
+        map_result(unsafe {
+            SSL_CTX_set1_curves_list(self.as_mut_ptr(), cstr.as_ptr())
+        })
+    }
+
+    pub fn set_sigalgs(&mut self, sigalgs: &str) -> Result<()> {
+        let cstr = ffi::CString::new(sigalgs).map_err(|_| Error::TlsFail)?;
+
+        map_result(unsafe {
+            SSL_CTX_set1_sigalgs_list(self.as_mut_ptr(), cstr.as_ptr())
+        })
+    }
+
     pub fn set_ticket_key(&mut self, key: &[u8]) -> Result<()> {
         map_result(unsafe {
             SSL_CTX_set_tlsext_ticket_keys(
@@ -327,6 +362,10 @@ impl Context {
         })
     }
 
+    fn as_ptr(&self) -> *const SSL_CTX {
+        self.0
+    }
+
     fn as_mut_ptr(&mut self) -> *mut SSL_CTX {
         self.0
     }
@@ -346,6 +385,64 @@ impl Drop for Context {
     }
 }
 
+/// A certificate chain and private key that a server can present.
+///
+/// This is used to select a certificate based on the server name requested
+/// by the client, see [`Config::set_certificate_selector()`].
+///
+/// [`Config::set_certificate_selector()`]: struct.Config.html#method.set_certificate_selector
+pub struct Certificate(CertChain);
+
+impl Certificate {
+    /// Loads a certificate chain and private key from the given files.
+    ///
+    /// The content of `cert_file` is parsed as a PEM-encoded leaf
+    /// certificate, followed by optional intermediate certificates, and the
+    /// content of `key_file` as a PEM-encoded private key.
+    ///
+    /// ## Examples:
+    ///
+    /// ```no_run
+    /// let cert = quiche::Certificate::from_pem_files(
+    ///     "/path/to/cert.pem",
+    ///     "/path/to/key.pem",
+    /// )?;
+    /// # Ok::<(), quiche::Error>(())
+    /// ```
+    pub fn from_pem_files(cert_file: &str, key_file: &str) -> Result<Self> {
+        let cert = std::fs::read(cert_file).map_err(|_| Error::TlsFail)?;
+        let key = std::fs::read(key_file).map_err(|_| Error::TlsFail)?;
+
+        Ok(Certificate(CertChain::from_pem(&cert, &key)?))
+    }
+
+    /// Configures the OCSP response to staple when presenting this
+    /// certificate.
+    ///
+    /// The OCSP response configured with [`Config::set_ocsp_response()`] only
+    /// applies to the default certificate, so certificates selected by server
+    /// name need their own.
+    ///
+    /// This is only supported with BoringSSL, otherwise [`TlsFail`] is
+    /// returned.
+    ///
+    /// [`Config::set_ocsp_response()`]: struct.Config.html#method.set_ocsp_response
+    /// [`TlsFail`]: enum.Error.html#variant.TlsFail
+    pub fn set_ocsp_response(&mut self, response: &[u8]) -> Result<()> {
+        self.0.set_ocsp_response(response)
+    }
+}
+
+// NOTE: These traits are not automatically implemented for Certificate due to
+// the raw pointers it wraps. However, the underlying objects are reference
+// counted by the TLS library, and they are never modified after being loaded.
+unsafe impl std::marker::Send for Certificate {}
+unsafe impl std::marker::Sync for Certificate {}
+
+/// Selects the certificate to present for a given server name.
+pub(crate) type CertificateSelector =
+    Arc<dyn Fn(&str) -> Option<Arc<Certificate>> + Send + Sync>;
+
 pub struct Handshake {
     /// Raw pointer
     ptr: *mut SSL,
@@ -509,6 +606,23 @@ impl Handshake {
         s.to_str().ok()
     }
 
+    pub fn export_keying_material(
+        &self, out: &mut [u8], label: &[u8], context: &[u8],
+    ) -> Result<()> {
+        map_result(unsafe {
+            SSL_export_keying_material(
+                self.as_ptr(),
+                out.as_mut_ptr(),
+                out.len(),
+                label.as_ptr() as *const c_char,
+                label.len(),
+                context.as_ptr(),
+                context.len(),
+                1, // use_context
+            )
+        })
+    }
+
     pub fn provide_data(
         &mut self, level: crypto::Level, buf: &[u8],
     ) -> Result<()> {
@@ -531,6 +645,7 @@ impl Handshake {
 
         self.set_transport_error(ex_data, rc);
         self.map_result_ssl(rc)
+            .map_err(|e| map_tls_alert(e, ex_data))
     }
 
     pub fn process_post_handshake(&mut self, ex_data: &mut ExData) -> Result<()> {
@@ -547,6 +662,7 @@ impl Handshake {
 
         self.set_transport_error(ex_data, rc);
         self.map_result_ssl(rc)
+            .map_err(|e| map_tls_alert(e, ex_data))
     }
 
     pub fn write_level(&self) -> crypto::Level {
@@ -652,6 +768,7 @@ impl Handshake {
                     is_app: false,
                     error_code: INTERNAL_ERROR,
                     reason: Vec::new(),
+                    frame_type: None,
                 })
             }
         }
@@ -679,6 +796,16 @@ impl Drop for Handshake {
     }
 }
 
+/// Application-provided certificate compression functions.
+#[derive(Clone, Copy)]
+pub struct CertCompression {
+    pub alg: u16,
+
+    pub compress: fn(&[u8]) -> Option<Vec<u8>>,
+
+    pub decompress: fn(&[u8], usize) -> Option<Vec<u8>>,
+}
+
 pub struct ExData<'a> {
     pub application_protos: &'a Vec<Vec<u8>>,
 
@@ -690,6 +817,14 @@ pub struct ExData<'a> {
 
     pub keylog: Option<&'a mut Box<dyn std::io::Write + Send + Sync>>,
 
+    pub cert_selector: Option<&'a CertificateSelector>,
+
+    pub ocsp_response: Option<&'a [u8]>,
+
+    pub cert_compression: &'a [CertCompression],
+
+    pub cipher_suites: &'a [crypto::Algorithm],
+
     pub trace_id: &'a str,
 
     pub is_server: bool,
@@ -715,6 +850,25 @@ fn get_cipher_from_ptr(cipher: *const SSL_CIPHER) -> Result<crypto::Algorithm> {
     Ok(alg)
 }
 
+/// Returns the AEAD of the TLS 1.3 cipher suite with the given name.
+pub fn cipher_from_name(name: &str) -> Option<crypto::Algorithm> {
+    match name {
+        "TLS_AES_128_GCM_SHA256" => Some(crypto::Algorithm::AES128_GCM),
+        "TLS_AES_256_GCM_SHA384" => Some(crypto::Algorithm::AES256_GCM),
+        "TLS_CHACHA20_POLY1305_SHA256" =>
+            Some(crypto::Algorithm::ChaCha20_Poly1305),
+        _ => None,
+    }
+}
+
+/// Returns whether the negotiated cipher suite is allowed by the
+/// configuration.
+fn is_cipher_allowed(
+    cipher_suites: &[crypto::Algorithm], aead: crypto::Algorithm,
+) -> bool {
+    cipher_suites.is_empty() || cipher_suites.contains(&aead)
+}
+
 extern fn set_read_secret(
     ssl: *mut SSL, level: crypto::Level, cipher: *const SSL_CIPHER,
     secret: *const u8, secret_len: usize,
@@ -745,6 +899,12 @@ extern fn set_read_secret(
         Err(_) => return 0,
     };
 
+    if !is_cipher_allowed(ex_data.cipher_suites, aead) {
+        trace!("{} cipher {:?} not allowed", ex_data.trace_id, aead);
+
+        return 0;
+    }
+
     // 0-RTT read secrets are present only on the server.
     if level != crypto::Level::ZeroRTT || ex_data.is_server {
         let secret = unsafe { slice::from_raw_parts(secret, secret_len) };
@@ -796,6 +956,12 @@ extern fn set_write_secret(
         Err(_) => return 0,
     };
 
+    if !is_cipher_allowed(ex_data.cipher_suites, aead) {
+        trace!("{} cipher {:?} not allowed", ex_data.trace_id, aead);
+
+        return 0;
+    }
+
     // 0-RTT write secrets are present only on the client.
     if level != crypto::Level::ZeroRTT || !ex_data.is_server {
         let secret = unsafe { slice::from_raw_parts(secret, secret_len) };
@@ -863,11 +1029,16 @@ extern fn send_alert(ssl: *mut SSL, level: crypto::Level, alert: u8) -> c_int {
         None => return 0,
     };
 
+    let desc = unsafe {
+        ffi::CStr::from_ptr(SSL_alert_desc_string_long(c_int::from(alert)))
+    };
+
     trace!(
-        "{} send alert lvl={:?} alert={:x}",
+        "{} send alert lvl={:?} alert={:x} desc={:?}",
         ex_data.trace_id,
         level,
-        alert
+        alert,
+        desc
     );
 
     let error: u64 = TLS_ALERT_ERROR + u64::from(alert);
@@ -875,11 +1046,21 @@ extern fn send_alert(ssl: *mut SSL, level: crypto::Level, alert: u8) -> c_int {
         is_app: false,
         error_code: error,
         reason: Vec::new(),
+        frame_type: None,
     });
 
     1
 }
 
+/// Reports a handshake failure as `TlsAlert` when an alert was sent.
+fn map_tls_alert(e: Error, ex_data: &ExData) -> Error {
+    match ex_data.local_error.as_ref().and_then(|err| err.tls_alert()) {
+        Some(alert) if e == Error::TlsFail => Error::TlsAlert(alert),
+
+        _ => e,
+    }
+}
+
 extern fn keylog(ssl: *const SSL, line: *const c_char) {
     let ex_data = match get_ex_data_from_ptr::<ExData>(ssl, *QUICHE_EX_DATA_INDEX)
     {
@@ -900,6 +1081,45 @@ extern fn keylog(ssl: *const SSL, line: *const c_char) {
     }
 }
 
+/// Presents the certificate selected by the application for the server name
+/// requested by the client, if any, along with the matching OCSP response.
+///
+/// This is called by each vendor's certificate selection callback, before
+/// the certificate is used.
+fn select_certificate(ssl: *mut SSL) -> Result<()> {
+    let ex_data = get_ex_data_from_ptr::<ExData>(ssl, *QUICHE_EX_DATA_INDEX)
+        .ok_or(Error::TlsFail)?;
+
+    // The handshake is owned by the connection, so it must not be freed here.
+    let mut handshake = std::mem::ManuallyDrop::new(Handshake::new(ssl));
+
+    let cert = match (ex_data.cert_selector, handshake.server_name()) {
+        (Some(selector), Some(name)) => selector(name),
+
+        _ => None,
+    };
+
+    // The OCSP response configured for the default certificate must not be
+    // stapled to another one, so it's only set once the certificate is known.
+    let ocsp_response = match &cert {
+        Some(cert) => {
+            trace!("{} using selected certificate", ex_data.trace_id);
+
+            handshake.set_certificate(&cert.0)?;
+
+            cert.0.ocsp_response()
+        },
+
+        None => ex_data.ocsp_response,
+    };
+
+    if let Some(response) = ocsp_response {
+        handshake.set_ocsp_response(response)?;
+    }
+
+    Ok(())
+}
+
 extern fn select_alpn(
     ssl: *mut SSL, out: *mut *const u8, out_len: *mut u8, inp: *mut u8,
     in_len: c_uint, _arg: *mut c_void,
@@ -1073,6 +1293,8 @@ extern {
     #[cfg(windows)]
     fn SSL_CTX_get_cert_store(ctx: *mut SSL_CTX) -> *mut X509_STORE;
 
+    fn SSL_CTX_get_verify_mode(ctx: *const SSL_CTX) -> c_int;
+
     fn SSL_CTX_set_verify(
         ctx: *mut SSL_CTX, mode: c_int,
         cb: Option<
@@ -1154,6 +1376,12 @@ extern {
 
     fn SSL_get_servername(ssl: *const SSL, ty: c_int) -> *const c_char;
 
+    fn SSL_export_keying_material(
+        ssl: *const SSL, out: *mut u8, out_len: usize, label: *const c_char,
+        label_len: usize, context: *const u8, context_len: usize,
+        use_context: c_int,
+    ) -> c_int;
+
     fn SSL_provide_quic_data(
         ssl: *mut SSL, level: crypto::Level, data: *const u8, len: usize,
     ) -> c_int;
@@ -1172,6 +1400,8 @@ extern {
 
     fn SSL_free(ssl: *mut SSL);
 
+    fn SSL_alert_desc_string_long(value: c_int) -> *const c_char;
+
     // SSL_CIPHER
     fn SSL_CIPHER_get_id(cipher: *const SSL_CIPHER) -> c_uint;
 
@@ -15,6 +15,18 @@ struct X509 {
     _unused: c_void,
 }
 
+#[allow(non_camel_case_types)]
+#[repr(transparent)]
+struct BIO {
+    _unused: c_void,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(transparent)]
+struct EVP_PKEY {
+    _unused: c_void,
+}
+
 #[repr(C)]
 #[allow(non_camel_case_types)]
 pub(super) struct SSL_QUIC_METHOD {
@@ -55,9 +67,176 @@ impl Context {
     pub fn set_early_data_enabled(&mut self, _enabled: bool) {
         // not yet supported
     }
+
+    pub fn enable_ocsp_response(&mut self) -> Result<()> {
+        // not yet supported
+        Err(Error::TlsFail)
+    }
+
+    pub fn enable_certificate_selection(&mut self) {
+        unsafe {
+            SSL_CTX_set_tlsext_servername_callback(
+                self.as_mut_ptr(),
+                Some(select_certificate_cb),
+            );
+        }
+    }
+
+    pub fn enable_ocsp_stapling(&mut self) {
+        // not yet supported
+    }
+
+    pub fn add_cert_compression_alg(&mut self, _alg: u16) -> Result<()> {
+        // not yet supported
+        Err(Error::TlsFail)
+    }
+
+    pub fn set_ciphersuites(&mut self, ciphersuites: &str) -> Result<()> {
+        let cstr = ffi::CString::new(ciphersuites).map_err(|_| Error::TlsFail)?;
+
+        map_result(unsafe {
+            SSL_CTX_set_ciphersuites(self.as_mut_ptr(), cstr.as_ptr())
+        })
+    }
+}
+
+/// The certificate chain and private key of a [`Certificate`].
+pub(super) struct CertChain {
+    leaf: *mut X509,
+
+    chain: *mut OPENSSL_STACK,
+
+    key: *mut EVP_PKEY,
+}
+
+impl CertChain {
+    pub(super) fn from_pem(cert: &[u8], key: &[u8]) -> Result<CertChain> {
+        let mut chain = CertChain {
+            leaf: ptr::null_mut(),
+            chain: ptr::null_mut(),
+            key: ptr::null_mut(),
+        };
+
+        unsafe {
+            chain.chain = OPENSSL_sk_new_null();
+            if chain.chain.is_null() {
+                return Err(Error::TlsFail);
+            }
+
+            let bio = BIO_new_mem_buf(cert.as_ptr(), cert.len() as c_int);
+            if bio.is_null() {
+                return Err(Error::TlsFail);
+            }
+
+            chain.leaf = PEM_read_bio_X509(
+                bio,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null_mut(),
+            );
+
+            while !chain.leaf.is_null() {
+                let x509 = PEM_read_bio_X509(
+                    bio,
+                    ptr::null_mut(),
+                    ptr::null(),
+                    ptr::null_mut(),
+                );
+
+                if x509.is_null() {
+                    break;
+                }
+
+                if OPENSSL_sk_push(chain.chain, x509 as *const c_void) == 0 {
+                    X509_free(x509);
+                    BIO_free(bio);
+                    return Err(Error::TlsFail);
+                }
+            }
+
+            BIO_free(bio);
+
+            // Reading the certificates stops with an error once the end of
+            // the input is reached.
+            ERR_clear_error();
+
+            if chain.leaf.is_null() {
+                return Err(Error::TlsFail);
+            }
+
+            let bio = BIO_new_mem_buf(key.as_ptr(), key.len() as c_int);
+            if bio.is_null() {
+                return Err(Error::TlsFail);
+            }
+
+            chain.key = PEM_read_bio_PrivateKey(
+                bio,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null_mut(),
+            );
+
+            BIO_free(bio);
+
+            if chain.key.is_null() {
+                return Err(Error::TlsFail);
+            }
+        }
+
+        Ok(chain)
+    }
+
+    pub(super) fn set_ocsp_response(&mut self, _response: &[u8]) -> Result<()> {
+        // not yet supported
+        Err(Error::TlsFail)
+    }
+
+    pub(super) fn ocsp_response(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl Drop for CertChain {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.chain.is_null() {
+                loop {
+                    let x509 = OPENSSL_sk_pop(self.chain) as *mut X509;
+
+                    if x509.is_null() {
+                        break;
+                    }
+
+                    X509_free(x509);
+                }
+
+                OPENSSL_sk_free(self.chain);
+            }
+
+            X509_free(self.leaf);
+            EVP_PKEY_free(self.key);
+        }
+    }
 }
 
 impl Handshake {
+    pub(super) fn set_certificate(&mut self, chain: &CertChain) -> Result<()> {
+        map_result(unsafe {
+            SSL_use_cert_and_key(
+                self.as_mut_ptr(),
+                chain.leaf,
+                chain.key,
+                chain.chain,
+                1,
+            )
+        })
+    }
+
+    pub fn set_ocsp_response(&mut self, _response: &[u8]) -> Result<()> {
+        // not yet supported
+        Err(Error::TlsFail)
+    }
+
     pub fn set_quic_early_data_context(&mut self, _context: &[u8]) -> Result<()> {
         // not supported for now.
         map_result(1)
@@ -141,6 +320,11 @@ impl Handshake {
         false
     }
 
+    pub fn ocsp_response(&self) -> Option<&[u8]> {
+        // not yet supported
+        None
+    }
+
     pub fn set_session(&mut self, session: &[u8]) -> Result<()> {
         unsafe {
             let ctx = SSL_get_SSL_CTX(self.as_ptr());
@@ -177,6 +361,18 @@ impl Handshake {
     }
 }
 
+extern fn select_certificate_cb(
+    ssl: *mut SSL, _alert: *mut c_int, _arg: *mut c_void,
+) -> c_int {
+    // SSL_TLSEXT_ERR_OK 0
+    // SSL_TLSEXT_ERR_ALERT_FATAL 2
+    match select_certificate(ssl) {
+        Ok(_) => 0,
+
+        Err(_) => 2,
+    }
+}
+
 extern fn set_encryption_secrets(
     ssl: *mut SSL, level: crypto::Level, read_secret: *const u8,
     write_secret: *const u8, secret_len: usize,
@@ -219,6 +415,42 @@ pub(super) unsafe fn SSL_CTX_set_session_cache_mode(
     ) as c_int
 }
 
+#[allow(non_snake_case)]
+pub(super) unsafe fn SSL_CTX_set_tlsext_servername_callback(
+    ctx: *mut SSL_CTX,
+    cb: Option<extern fn(*mut SSL, *mut c_int, *mut c_void) -> c_int>,
+) -> c_int {
+    const SSL_CTRL_SET_TLSEXT_SERVERNAME_CB: c_int = 53;
+
+    SSL_CTX_callback_ctrl(
+        ctx,
+        SSL_CTRL_SET_TLSEXT_SERVERNAME_CB,
+        std::mem::transmute::<
+            Option<extern fn(*mut SSL, *mut c_int, *mut c_void) -> c_int>,
+            Option<extern fn()>,
+        >(cb),
+    ) as c_int
+}
+
+#[allow(non_snake_case)]
+pub(super) unsafe fn SSL_CTX_set1_curves_list(
+    ctx: *mut SSL_CTX, curves: *const c_char,
+) -> c_int {
+    const SSL_CTRL_SET_GROUPS_LIST: c_int = 92;
+
+    SSL_CTX_ctrl(ctx, SSL_CTRL_SET_GROUPS_LIST, 0, curves as *mut c_void) as c_int
+}
+
+#[allow(non_snake_case)]
+pub(super) unsafe fn SSL_CTX_set1_sigalgs_list(
+    ctx: *mut SSL_CTX, sigalgs: *const c_char,
+) -> c_int {
+    const SSL_CTRL_SET_SIGALGS_LIST: c_int = 98;
+
+    SSL_CTX_ctrl(ctx, SSL_CTRL_SET_SIGALGS_LIST, 0, sigalgs as *mut c_void)
+        as c_int
+}
+
 #[allow(non_snake_case)]
 pub(super) unsafe fn SSL_CTX_set_tlsext_ticket_keys(
     ctx: *mut SSL_CTX, key: *const u8, key_len: usize,
@@ -325,6 +557,12 @@ extern {
         ctx: *mut SSL_CTX, cmd: c_int, larg: c_long, parg: *mut c_void,
     ) -> c_long;
 
+    fn SSL_CTX_set_ciphersuites(ctx: *mut SSL_CTX, str: *const c_char) -> c_int;
+
+    fn SSL_CTX_callback_ctrl(
+        ctx: *mut SSL_CTX, cmd: c_int, fp: Option<extern fn()>,
+    ) -> c_long;
+
     fn SSL_get_peer_cert_chain(ssl: *const SSL) -> *mut STACK_OF;
 
     fn SSL_get0_peer_certificate(ssl: *const SSL) -> *mut X509;
@@ -339,6 +577,47 @@ extern {
 
     fn OPENSSL_sk_value(stack: *const OPENSSL_STACK, idx: usize) -> *mut c_void;
 
+    fn OPENSSL_sk_new_null() -> *mut OPENSSL_STACK;
+
+    fn OPENSSL_sk_push(stack: *mut OPENSSL_STACK, data: *const c_void) -> c_int;
+
+    fn OPENSSL_sk_pop(stack: *mut OPENSSL_STACK) -> *mut c_void;
+
+    fn OPENSSL_sk_free(stack: *mut OPENSSL_STACK);
+
+    fn SSL_use_cert_and_key(
+        ssl: *mut SSL, x509: *mut X509, pkey: *mut EVP_PKEY,
+        chain: *mut OPENSSL_STACK, overwrite: c_int,
+    ) -> c_int;
+
+    // BIO
+
+    fn BIO_new_mem_buf(buf: *const u8, len: c_int) -> *mut BIO;
+
+    fn BIO_free(bio: *mut BIO) -> c_int;
+
+    // PEM
+
+    fn PEM_read_bio_X509(
+        bio: *mut BIO, out: *mut *mut X509, cb: *const c_void, u: *mut c_void,
+    ) -> *mut X509;
+
+    fn PEM_read_bio_PrivateKey(
+        bio: *mut BIO, out: *mut *mut EVP_PKEY, cb: *const c_void, u: *mut c_void,
+    ) -> *mut EVP_PKEY;
+
+    // X509
+
+    fn X509_free(x509: *mut X509);
+
+    // EVP_PKEY
+
+    fn EVP_PKEY_free(pkey: *mut EVP_PKEY);
+
+    // ERR
+
+    fn ERR_clear_error();
+
     // CRYPTO
 
     fn CRYPTO_get_ex_new_index(